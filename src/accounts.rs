@@ -0,0 +1,182 @@
+use crate::api_budget::{api_call, ApiCallKind};
+use crate::config::{Config, ReplyVisibility, TwitterAccountConfig, TwitterFetchMode};
+use crate::errors::SyncError;
+use crate::history::HistoryNetwork;
+use crate::mastodon::mastodon_account_gone;
+use crate::media::MediaOptions;
+use crate::plan::SyncAccount;
+use crate::post::{
+    post_to_mastodon, post_to_twitter, twitter_is_rejected_error, PostError, PostedStatus,
+};
+use crate::registration::{twitter_account_token, twitter_token};
+use crate::sync::NewStatus;
+use crate::twitter_rss::twitter_rss_tweets;
+use crate::twitter_search::{twitter_search_query, twitter_search_tweets};
+use anyhow::{Context, Result};
+use egg_mode::tweet::Tweet;
+use egg_mode::Token;
+use elefren::entities::status::Status;
+use elefren::prelude::*;
+use elefren::{Mastodon, StatusesRequest};
+use tokio::runtime::Runtime;
+use tracing::{info_span, warn};
+
+/// The Mastodon account of a sync run.
+pub struct MastodonAccount {
+    mastodon: Mastodon,
+    // The account whose statuses are fetched, empty for accounts that are
+    // only posted to.
+    account_id: String,
+    media_options: MediaOptions,
+    reply_visibility: ReplyVisibility,
+}
+
+impl MastodonAccount {
+    pub fn new(config: &Config, account_id: &str, media_options: MediaOptions) -> MastodonAccount {
+        MastodonAccount {
+            mastodon: Mastodon::from(config.mastodon.app.clone()),
+            account_id: account_id.to_string(),
+            media_options,
+            reply_visibility: config.twitter.reply_visibility,
+        }
+    }
+}
+
+impl SyncAccount for MastodonAccount {
+    type Status = Status;
+
+    fn fetch(&self) -> Result<Vec<Status>> {
+        // Get most recent 50 toots with replies.
+        api_call(HistoryNetwork::Mastodon, ApiCallKind::Read);
+        match self
+            .mastodon
+            .statuses(&self.account_id, StatusesRequest::new().limit(50))
+        {
+            Ok(statuses) => Ok(statuses.initial_items),
+            Err(e) if mastodon_account_gone(&e) => Err(SyncError::MastodonAccountGone {
+                instance: self.mastodon.base.to_string(),
+            }
+            .into()),
+            Err(e) => Err(anyhow::Error::new(SyncError::MastodonApi(e))
+                .context("Failed to fetch toots from Mastodon")),
+        }
+    }
+
+    fn post(&mut self, status: &NewStatus, dry_run: bool) -> Result<Vec<PostedStatus>, PostError> {
+        post_to_mastodon(
+            &self.mastodon,
+            status,
+            &self.media_options,
+            self.reply_visibility,
+            dry_run,
+        )
+    }
+}
+
+/// The Twitter account of a sync run, with the backup account that posts
+/// when Twitter rejects posting with it.
+pub struct TwitterAccount<'a> {
+    rt: &'a Runtime,
+    config: &'a Config,
+    token: Token,
+    backup: Option<(&'a TwitterAccountConfig, Token)>,
+    media_options: MediaOptions,
+}
+
+impl<'a> TwitterAccount<'a> {
+    pub fn new(rt: &'a Runtime, config: &'a Config, media_options: MediaOptions) -> Self {
+        TwitterAccount {
+            rt,
+            config,
+            token: twitter_token(&config.twitter),
+            backup: config
+                .twitter
+                .backup
+                .as_ref()
+                .map(|backup| (backup, twitter_account_token(backup))),
+            media_options,
+        }
+    }
+}
+
+impl SyncAccount for TwitterAccount<'_> {
+    type Status = Tweet;
+
+    // Fetches the recent tweets of the user, from the RSS feed if Twitter is
+    // read-only.
+    fn fetch(&self) -> Result<Vec<Tweet>> {
+        let (rt, config, token) = (self.rt, self.config, &self.token);
+        if config.twitter.fetch_mode == TwitterFetchMode::Rss {
+            return rt
+                .block_on(twitter_rss_tweets(&config.twitter))
+                .context("Failed to fetch tweets from the RSS feed");
+        }
+        // With hashtag-only syncing the candidate tweets can be searched.
+        let search_query = match config.twitter.fetch_mode {
+            TwitterFetchMode::Search => {
+                let query = twitter_search_query(&config.twitter);
+                if query.is_none() {
+                    warn!("twitter.fetch_mode = \"search\" needs sync_hashtags, fetching the timeline instead");
+                }
+                query
+            }
+            TwitterFetchMode::Timeline | TwitterFetchMode::Rss => None,
+        };
+        if let Some(query) = search_query {
+            return rt
+                .block_on(twitter_search_tweets(&config.twitter, &query, token))
+                .context("Failed to search tweets on Twitter");
+        }
+        // @todo Exclude retweets directly here if config option set.
+        let timeline = egg_mode::tweet::user_timeline(config.twitter.user_id, true, true, token)
+            .with_page_size(50);
+
+        api_call(HistoryNetwork::Twitter, ApiCallKind::Read);
+        let (timeline, first_tweets) = rt
+            .block_on(timeline.start())
+            .map_err(SyncError::TwitterApi)
+            .context("Failed to fetch tweets from Twitter")?;
+        let mut tweets = (*first_tweets).to_vec();
+        // We might have only one tweet because of filtering out reply
+        // tweets. Fetch some more tweets to make sure we have enough for
+        // comparing.
+        if tweets.len() < 50 {
+            api_call(HistoryNetwork::Twitter, ApiCallKind::Read);
+            let (_, next_tweets) = rt
+                .block_on(timeline.older(None))
+                .map_err(SyncError::TwitterApi)
+                .context("Failed to fetch older tweets from Twitter")?;
+            tweets.append(&mut (*next_tweets).to_vec());
+        }
+        Ok(tweets)
+    }
+
+    fn post(&mut self, status: &NewStatus, dry_run: bool) -> Result<Vec<PostedStatus>, PostError> {
+        let result = self.rt.block_on(post_to_twitter(
+            &self.token,
+            status,
+            &self.media_options,
+            dry_run,
+        ));
+        match (result, &self.backup) {
+            // Try again with the backup account, unless a part of the thread
+            // is posted with this account already.
+            (Err(e), Some((backup, backup_token)))
+                if e.created.is_empty() && twitter_is_rejected_error(&e.error) =>
+            {
+                warn!(
+                    "Twitter rejected posting with @{} ({e:#}), posting with backup account @{}",
+                    self.config.twitter.user_name, backup.user_name
+                );
+                let _span = info_span!("backup", account = %backup.user_name).entered();
+                self.rt.block_on(post_to_twitter(
+                    backup_token,
+                    status,
+                    &self.media_options,
+                    dry_run,
+                ))
+            }
+            (result, _) => result,
+        }
+    }
+}
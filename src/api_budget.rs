@@ -0,0 +1,260 @@
+// Without both networks only the deletions count API calls.
+#![cfg_attr(not(all(feature = "mastodon", feature = "twitter")), allow(dead_code))]
+
+use crate::history::HistoryNetwork;
+use crate::run_report::run_report;
+use anyhow::{bail, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::Mutex;
+use tracing::info;
+
+// API calls and budgets of the current run, counting starts once the config is
+// loaded.
+static API_BUDGET: Mutex<Option<BudgetState>> = Mutex::new(None);
+
+/// Whether an API call only reads or changes something, the paid Twitter API
+/// tiers limit both separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiCallKind {
+    Read,
+    Write,
+}
+
+/// Number of API calls to a network.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiCalls {
+    pub read: u64,
+    pub write: u64,
+}
+
+impl ApiCalls {
+    fn add(&mut self, kind: ApiCallKind) {
+        match kind {
+            ApiCallKind::Read => self.read += 1,
+            ApiCallKind::Write => self.write += 1,
+        }
+    }
+
+    fn get(&self, kind: ApiCallKind) -> u64 {
+        match kind {
+            ApiCallKind::Read => self.read,
+            ApiCallKind::Write => self.write,
+        }
+    }
+}
+
+/// Maximum number of API calls per calendar month, None for no limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ApiBudget {
+    pub read: Option<u64>,
+    pub write: Option<u64>,
+}
+
+impl ApiBudget {
+    fn get(&self, kind: ApiCallKind) -> Option<u64> {
+        match kind {
+            ApiCallKind::Read => self.read,
+            ApiCallKind::Write => self.write,
+        }
+    }
+}
+
+/// API calls of a calendar month, kept in a file in the cache directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ApiUsage {
+    month: String,
+    calls: BTreeMap<HistoryNetwork, ApiCalls>,
+}
+
+impl ApiUsage {
+    // Loads the usage of the month, a new month starts from zero.
+    fn load(file: &str, month: &str) -> ApiUsage {
+        match fs::read_to_string(file)
+            .ok()
+            .and_then(|json| serde_json::from_str::<ApiUsage>(&json).ok())
+        {
+            Some(usage) if usage.month == month => usage,
+            _ => ApiUsage {
+                month: month.to_string(),
+                calls: BTreeMap::new(),
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BudgetState {
+    usage: ApiUsage,
+    // Calls of this run.
+    run: BTreeMap<HistoryNetwork, ApiCalls>,
+    budgets: BTreeMap<HistoryNetwork, ApiBudget>,
+}
+
+impl BudgetState {
+    fn left(&self, network: HistoryNetwork, kind: ApiCallKind) -> bool {
+        let used = self
+            .usage
+            .calls
+            .get(&network)
+            .map_or(0, |calls| calls.get(kind));
+        self.budgets
+            .get(&network)
+            .and_then(|budget| budget.get(kind))
+            .is_none_or(|budget| used < budget)
+    }
+}
+
+/// Starts counting API calls with the usage of the current month from the
+/// file.
+pub fn api_budget_start(file: &str, budgets: BTreeMap<HistoryNetwork, ApiBudget>) {
+    let month = Utc::now().format("%Y-%m").to_string();
+    *API_BUDGET.lock().unwrap() = Some(BudgetState {
+        usage: ApiUsage::load(file, &month),
+        run: BTreeMap::new(),
+        budgets,
+    });
+}
+
+/// Counts a call to the API of the network, for the monthly budget and the
+/// run report.
+pub fn api_call(network: HistoryNetwork, kind: ApiCallKind) {
+    if let Some(state) = API_BUDGET.lock().unwrap().as_mut() {
+        state.usage.calls.entry(network).or_default().add(kind);
+        state.run.entry(network).or_default().add(kind);
+    }
+    run_report(|report| report.api_calls.entry(network).or_default().add(kind));
+}
+
+/// Counts a write call to the API of the network like api_call(), but fails
+/// without counting if the monthly write budget is used up already.
+pub fn api_write(network: HistoryNetwork) -> Result<()> {
+    if !api_budget_left(network, ApiCallKind::Write) {
+        bail!("The monthly {network:?} API write budget is used up");
+    }
+    api_call(network, ApiCallKind::Write);
+    Ok(())
+}
+
+/// Returns false if the monthly budget of the network for this kind of calls
+/// is used up.
+pub fn api_budget_left(network: HistoryNetwork, kind: ApiCallKind) -> bool {
+    API_BUDGET
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_none_or(|state| state.left(network, kind))
+}
+
+/// Logs the API calls of the run and writes the usage of the month to the
+/// file. Counting goes on with the next run, streaming saves after each sync.
+pub fn api_budget_save(file: &str) -> Result<()> {
+    let mut budget_state = API_BUDGET.lock().unwrap();
+    let state = match budget_state.as_mut() {
+        Some(state) => state,
+        None => return Ok(()),
+    };
+    for (network, calls) in &state.run {
+        let month = state.usage.calls.get(network).copied().unwrap_or_default();
+        let budget = state.budgets.get(network).copied().unwrap_or_default();
+        info!(
+            "{network:?} API calls in this run: {} read, {} write. This month: {} read, {} write",
+            calls.read,
+            calls.write,
+            used_of(month.read, budget.read),
+            used_of(month.write, budget.write),
+        );
+    }
+    fs::write(file, serde_json::to_string_pretty(&state.usage)?)?;
+    state.run.clear();
+    // A stream can go on into the next month, which starts from zero.
+    let month = Utc::now().format("%Y-%m").to_string();
+    if state.usage.month != month {
+        state.usage = ApiUsage {
+            month,
+            calls: BTreeMap::new(),
+        };
+    }
+    Ok(())
+}
+
+fn used_of(used: u64, budget: Option<u64>) -> String {
+    match budget {
+        Some(budget) => format!("{used} of {budget}"),
+        None => used.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monthly_budget() {
+        let mut state = BudgetState {
+            usage: ApiUsage::default(),
+            run: BTreeMap::new(),
+            budgets: BTreeMap::from([(
+                HistoryNetwork::Twitter,
+                ApiBudget {
+                    read: Some(2),
+                    write: None,
+                },
+            )]),
+        };
+        state
+            .usage
+            .calls
+            .entry(HistoryNetwork::Twitter)
+            .or_default()
+            .add(ApiCallKind::Read);
+        assert!(state.left(HistoryNetwork::Twitter, ApiCallKind::Read));
+        state
+            .usage
+            .calls
+            .entry(HistoryNetwork::Twitter)
+            .or_default()
+            .add(ApiCallKind::Read);
+        assert!(!state.left(HistoryNetwork::Twitter, ApiCallKind::Read));
+        // Without a budget there is no limit.
+        assert!(state.left(HistoryNetwork::Twitter, ApiCallKind::Write));
+        assert!(state.left(HistoryNetwork::Mastodon, ApiCallKind::Read));
+    }
+
+    #[test]
+    fn save_after_each_sync() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("api_usage.json");
+        let file = file.to_str().unwrap();
+        api_budget_start(file, BTreeMap::new());
+        api_call(HistoryNetwork::Twitter, ApiCallKind::Read);
+        api_budget_save(file).unwrap();
+        // The next sync of a stream is counted on top.
+        api_call(HistoryNetwork::Twitter, ApiCallKind::Read);
+        api_budget_save(file).unwrap();
+        let month = Utc::now().format("%Y-%m").to_string();
+        assert_eq!(
+            ApiUsage::load(file, &month).calls[&HistoryNetwork::Twitter].read,
+            2
+        );
+        *API_BUDGET.lock().unwrap() = None;
+    }
+
+    #[test]
+    fn usage_of_new_month() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("api_usage.json");
+        let file = file.to_str().unwrap();
+        fs::write(
+            file,
+            r#"{"month":"2023-04","calls":{"twitter":{"read":1500,"write":20}}}"#,
+        )
+        .unwrap();
+        let usage = ApiUsage::load(file, "2023-04");
+        assert_eq!(usage.calls[&HistoryNetwork::Twitter].read, 1500);
+        let usage = ApiUsage::load(file, "2023-05");
+        assert!(usage.calls.is_empty());
+    }
+}
@@ -1,19 +1,187 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 
-#[derive(Debug, Parser)]
+use crate::paths::DEFAULT_CONFIG_FILE;
+
+#[derive(Debug, Clone, Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
     /// Config file
-    #[arg(
-        short = 'c',
-        long = "config",
-        default_value = "mastodon-twitter-sync.toml"
-    )]
+    #[arg(short = 'c', long = "config", default_value = DEFAULT_CONFIG_FILE)]
     pub config: String,
+    /// Print where the config file and the cache files are and exit
+    #[arg(long = "print-paths")]
+    pub print_paths: bool,
     /// Dry run
     #[arg(short = 'n', long = "dry-run")]
     pub dry_run: bool,
     /// Skip all existing posts, use this if you only want to sync future posts
     #[arg(long = "skip-existing-posts")]
     pub skip_existing_posts: bool,
+    /// Post at most this many statuses per network in this run
+    #[arg(long = "limit")]
+    pub limit: Option<usize>,
+    /// Delete at most this many old statuses and favourites per network and
+    /// kind in this run, overrides delete_batch_size
+    #[arg(long = "max-deletions")]
+    pub max_deletions: Option<usize>,
+    /// Print the posts that would be synced as JSON and exit without posting
+    #[arg(long = "print-json")]
+    pub print_json: bool,
+    /// Only add new posts to the queue, post them later with the flush command
+    #[arg(long = "queue")]
+    pub queue: bool,
+    /// Ask whether to post statuses that are similar to a recent status on the
+    /// other network, instead of skipping them
+    #[arg(long = "interactive")]
+    pub interactive: bool,
+    /// List every skipped source post with the reason in the summary, not
+    /// only the number of posts per reason
+    #[arg(long = "show-skipped")]
+    pub show_skipped: bool,
+    /// Keep downloaded media attachments in this directory and reuse them in
+    /// later runs
+    #[arg(long = "media-cache-dir")]
+    pub media_cache_dir: Option<String>,
+    /// Sync with Mastodon and Twitter, or rehearse with two fake accounts in
+    /// the mock_mastodon.json and mock_twitter.json files
+    #[arg(long = "target", value_enum, default_value_t = Target::Live)]
+    pub target: Target,
+    /// Show debug output
+    #[arg(short = 'v', long = "verbose", conflicts_with = "quiet")]
+    pub verbose: bool,
+    /// Only show warnings and errors
+    #[arg(short = 'q', long = "quiet")]
+    pub quiet: bool,
+    /// Do not color the output, also switched off by the NO_COLOR
+    /// environment variable and when the output is not a terminal
+    #[arg(long = "no-color")]
+    pub no_color: bool,
+    /// Log output format
+    #[arg(long = "log-format", value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Post the queued statuses
+    Flush,
+    /// Create a new config file with both accounts and the most common
+    /// options, use --dry-run to only print the config
+    Init,
+    /// Register Mastodon and Twitter accounts in the config file, use
+    /// --dry-run to only print the new config
+    Register {
+        /// URL of the Mastodon instance for --mastodon-token
+        #[arg(long = "mastodon-instance", requires = "mastodon_token")]
+        mastodon_instance: Option<String>,
+        /// Access token created in the Mastodon web interface, skips the
+        /// authorization of a new app
+        #[arg(long = "mastodon-token", requires = "mastodon_instance")]
+        mastodon_token: Option<String>,
+        /// OAuth 2.0 client ID of the Twitter app, authorizes with OAuth 2.0
+        /// instead of OAuth 1.0a
+        #[arg(long = "twitter-client-id")]
+        twitter_client_id: Option<String>,
+    },
+    /// Export Twitter blocks and mutes and import them into Mastodon
+    Moderation {
+        #[command(subcommand)]
+        command: ModerationCommand,
+    },
+    /// Report which followed Twitter accounts are on Mastodon and write a CSV
+    /// file for the Mastodon follow importer
+    FollowersReport {
+        #[arg(long = "file", default_value = "following.csv")]
+        file: String,
+    },
+    /// Keep running and sync as soon as a new status is posted on Mastodon,
+    /// using the Mastodon streaming API
+    Stream {
+        /// Seconds between syncs while the stream is disconnected
+        #[arg(long = "poll-interval", default_value_t = 300)]
+        poll_interval: u64,
+    },
+    /// Inspect and repair the local state files
+    State {
+        #[command(subcommand)]
+        command: StateCommand,
+    },
+    /// Show, clear or change what is recorded as synced
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// Report synced posts per month, delays, deletions and failures of
+    /// previous runs
+    Stats,
+    /// Write a systemd user service and timer, or a launchd agent on macOS,
+    /// that runs the sync periodically, use --dry-run to only print them
+    InstallService {
+        /// Minutes between two runs
+        #[arg(long = "interval", default_value_t = 10)]
+        interval: u64,
+        /// Name of the service, use different names for several config files
+        #[arg(long = "name", default_value = "mastodon-twitter-sync")]
+        name: String,
+    },
+    /// Inspect the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum ConfigCommand {
+    /// Report unknown, deprecated and missing options in the config file
+    Check,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum CacheCommand {
+    /// Print the synced statuses, posted texts and queued posts
+    Show,
+    /// Remove the ID map and post cache files, for example if they are broken
+    Clear,
+    /// Mark a toot or tweet as synced, so that it is not posted to the other
+    /// network
+    MarkSynced {
+        /// URL of the toot or tweet
+        url: String,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum StateCommand {
+    /// Rebuild the ID map and post cache by matching recent posts on both networks
+    Rebuild,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum ModerationCommand {
+    /// Write blocked and muted Twitter accounts with their guessed Mastodon
+    /// accounts to a CSV file for review
+    Export {
+        #[arg(long = "file", default_value = "moderation.csv")]
+        file: String,
+    },
+    /// Block and mute the Mastodon accounts of the reviewed CSV file
+    Import {
+        #[arg(long = "file", default_value = "moderation.csv")]
+        file: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Target {
+    Live,
+    Mock,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
 }
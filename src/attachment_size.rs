@@ -0,0 +1,220 @@
+use crate::config::OversizedMediaPolicy;
+use crate::sync::*;
+use anyhow::Result;
+use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use std::mem;
+use std::time::Duration;
+use tracing::{debug, info};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Size and type of an attachment as announced by its server.
+#[derive(Debug, Clone, Default)]
+pub struct RemoteMedia {
+    pub size: Option<u64>,
+    pub content_type: Option<String>,
+}
+
+impl RemoteMedia {
+    // The kind of media for the note in the text, "video" for "video/mp4".
+    fn kind(&self) -> &str {
+        match self
+            .content_type
+            .as_deref()
+            .and_then(|t| t.split('/').next())
+        {
+            Some(kind @ ("image" | "video" | "audio")) => kind,
+            _ => "attachment",
+        }
+    }
+}
+
+/// Looks up the size of an attachment with a HEAD request, without
+/// downloading it.
+pub fn remote_media(url: &str) -> Result<RemoteMedia> {
+    let response = reqwest::blocking::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?
+        .head(url)
+        .send()?
+        .error_for_status()?;
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    Ok(RemoteMedia {
+        size: header(CONTENT_LENGTH).and_then(|length| length.parse().ok()),
+        content_type: header(CONTENT_TYPE),
+    })
+}
+
+/// Removes attachments larger than max_mb from the statuses and their
+/// replies. Depending on the policy the text links the removed attachments
+/// or the whole status is skipped, together with its replies.
+///
+/// Attachments of unknown size are kept. The text is shortened if the links do
+/// not fit otherwise.
+pub fn limit_attachment_size(
+    statuses: Vec<NewStatus>,
+    max_mb: u64,
+    policy: OversizedMediaPolicy,
+    media: &impl Fn(&str) -> Option<RemoteMedia>,
+    fits: &impl Fn(&str) -> bool,
+    skipped: &mut Vec<NewStatus>,
+) -> Vec<NewStatus> {
+    let max_bytes = max_mb.saturating_mul(1024 * 1024);
+    let mut kept = Vec::new();
+    for mut status in statuses {
+        let mut notes = Vec::new();
+        let mut attachments = Vec::new();
+        for attachment in mem::take(&mut status.attachments) {
+            match media(&attachment.attachment_url) {
+                Some(remote) if remote.size.map_or(false, |size| size > max_bytes) => {
+                    info!(
+                        "Attachment {} of status {} is larger than {max_mb} MB",
+                        attachment.attachment_url, status.original_id
+                    );
+                    notes.push(format!("{}: {}", remote.kind(), attachment.attachment_url));
+                }
+                _ => attachments.push(attachment),
+            }
+        }
+        status.attachments = attachments;
+        if !notes.is_empty() {
+            if policy == OversizedMediaPolicy::Skip {
+                skipped.push(status);
+                continue;
+            }
+            status.text = text_with_notes(&status.text, &notes, fits);
+        }
+        status.replies = limit_attachment_size(
+            mem::take(&mut status.replies),
+            max_mb,
+            policy,
+            media,
+            fits,
+            skipped,
+        );
+        kept.push(status);
+    }
+    kept
+}
+
+// Appends the notes about removed attachments on their own lines.
+fn text_with_notes(text: &str, notes: &[String], fits: &impl Fn(&str) -> bool) -> String {
+    let suffix = format!("\n\n{}", notes.join("\n"));
+    if fits(&format!("{text}{suffix}")) {
+        return format!("{text}{suffix}");
+    }
+    debug!("Shortening text to make room for attachment links: {text}");
+    let shortened = shorten_text(text, |shortened| fits(&format!("{shortened}…{suffix}")));
+    format!("{shortened}…{suffix}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::tests::new_status;
+
+    fn status(id: u64, text: &str, urls: &[&str]) -> NewStatus {
+        NewStatus {
+            attachments: urls
+                .iter()
+                .map(|url| NewMedia {
+                    attachment_url: url.to_string(),
+                    alt_text: None,
+                    focus: None,
+                })
+                .collect(),
+            ..new_status(id, text)
+        }
+    }
+
+    fn media(url: &str) -> Option<RemoteMedia> {
+        match url {
+            "https://example.com/video.mp4" => Some(RemoteMedia {
+                size: Some(200 * 1024 * 1024),
+                content_type: Some("video/mp4".to_string()),
+            }),
+            "https://example.com/image.jpg" => Some(RemoteMedia {
+                size: Some(300 * 1024),
+                content_type: Some("image/jpeg".to_string()),
+            }),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn oversized_attachments_linked() {
+        let statuses = vec![
+            status(
+                1,
+                "My holiday",
+                &[
+                    "https://example.com/video.mp4",
+                    "https://example.com/image.jpg",
+                ],
+            ),
+            status(2, "Unknown size", &["https://example.com/unknown.mp4"]),
+        ];
+        let mut skipped = Vec::new();
+        let fits = |text: &str| text.chars().count() <= 500;
+        let kept = limit_attachment_size(
+            statuses,
+            100,
+            OversizedMediaPolicy::Link,
+            &media,
+            &fits,
+            &mut skipped,
+        );
+        assert!(skipped.is_empty());
+        assert_eq!(
+            kept[0].text,
+            "My holiday\n\nvideo: https://example.com/video.mp4"
+        );
+        assert_eq!(kept[0].attachments.len(), 1);
+        assert_eq!(kept[1].attachments.len(), 1);
+
+        // The text makes room for the link.
+        let fits = |text: &str| text.chars().count() <= 50;
+        let kept = limit_attachment_size(
+            vec![status(
+                3,
+                "A long text about my holiday",
+                &["https://example.com/video.mp4"],
+            )],
+            100,
+            OversizedMediaPolicy::Link,
+            &media,
+            &fits,
+            &mut skipped,
+        );
+        assert_eq!(
+            kept[0].text,
+            "A long text…\n\nvideo: https://example.com/video.mp4"
+        );
+    }
+
+    #[test]
+    fn oversized_attachments_skipped() {
+        let mut parent = status(1, "Parent", &["https://example.com/image.jpg"]);
+        parent
+            .replies
+            .push(status(2, "Reply", &["https://example.com/video.mp4"]));
+        let mut skipped = Vec::new();
+        let kept = limit_attachment_size(
+            vec![parent],
+            100,
+            OversizedMediaPolicy::Skip,
+            &media,
+            &|_: &str| true,
+            &mut skipped,
+        );
+        assert_eq!(kept.len(), 1);
+        assert!(kept[0].replies.is_empty());
+        assert_eq!(skipped[0].original_id, StatusId::Number(2));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::api_budget::{api_call, ApiCallKind};
+use crate::cache_file;
+use crate::history::HistoryNetwork;
+use crate::id_map::IdMap;
+use crate::mastodon::mastodon_bookmark;
+use anyhow::Result;
+use egg_mode::tweet::Tweet;
+use elefren::status_builder::{StatusBuilder, Visibility};
+use elefren::{Mastodon, MastodonClient};
+use std::collections::BTreeSet;
+use std::fs;
+use tracing::{error, info};
+
+// Only bookmark this many likes in one run to not run into API limits, the
+// rest follows on the next runs.
+const MAX_BOOKMARKS_PER_RUN: usize = 10;
+
+/// Fetches the most recent likes of the Twitter user, newest first.
+pub async fn twitter_fetch_recent_likes(
+    user_id: u64,
+    token: &egg_mode::Token,
+) -> Result<Vec<Tweet>> {
+    let timeline = egg_mode::tweet::liked_by(user_id, token).with_page_size(50);
+    api_call(HistoryNetwork::Twitter, ApiCallKind::Read);
+    let (_, likes) = timeline.start().await?;
+    Ok(likes.to_vec())
+}
+
+/// Turns new Twitter likes into Mastodon bookmarks. Liked tweets that were
+/// synced with Mastodon bookmark the synced toot, for other tweets a direct
+/// toot with the tweet URL is posted and bookmarked.
+pub fn likes_to_bookmarks(
+    mastodon: &Mastodon,
+    likes: &[Tweet],
+    id_map: &IdMap,
+    dry_run: bool,
+) -> Result<()> {
+    // Remember which likes were handled already in a cache file.
+    let cache_file = &cache_file("likes_bookmark_cache.json");
+    let mut handled: BTreeSet<u64> = match fs::read_to_string(cache_file) {
+        Ok(json) => serde_json::from_str(&json)?,
+        Err(_) => {
+            // Only likes from now on are bookmarked, not all existing ones.
+            info!("Starting to turn new Twitter likes into Mastodon bookmarks");
+            if !dry_run {
+                save_handled_likes(cache_file, &likes.iter().map(|t| t.id).collect())?;
+            }
+            return Ok(());
+        }
+    };
+
+    let new_likes = new_likes(likes, &handled);
+    if new_likes.len() > MAX_BOOKMARKS_PER_RUN {
+        info!("Bookmarking only {MAX_BOOKMARKS_PER_RUN} Twitter likes to not run into API limits. Just run me again!");
+    }
+    for tweet in new_likes.into_iter().take(MAX_BOOKMARKS_PER_RUN) {
+        let tweet_url = format!("https://twitter.com/twitter/status/{}", tweet.id);
+        info!("Bookmarking Twitter like {tweet_url} on Mastodon");
+        if dry_run {
+            continue;
+        }
+
+        // A failed like is tried again on the next run, the handled likes are
+        // saved anyway.
+        match bookmark_like(mastodon, tweet, &tweet_url, id_map) {
+            Ok(()) => {
+                handled.insert(tweet.id);
+            }
+            Err(e) => error!("Failed to bookmark Twitter like {tweet_url}: {e:#}"),
+        }
+    }
+
+    // Older likes are not fetched anymore, so they can be removed from the
+    // cache.
+    handled.retain(|id| likes.iter().any(|t| t.id == *id));
+    if !dry_run {
+        save_handled_likes(cache_file, &handled)?;
+    }
+    Ok(())
+}
+
+// Bookmarks the synced toot of a liked tweet, or a new direct toot with the
+// tweet URL.
+fn bookmark_like(
+    mastodon: &Mastodon,
+    tweet: &Tweet,
+    tweet_url: &str,
+    id_map: &IdMap,
+) -> Result<()> {
+    let toot_id = match id_map.toot_id(tweet.id) {
+        Some(toot_id) => toot_id.to_string(),
+        None => {
+            let status = StatusBuilder::new()
+                .status(tweet_url)
+                .visibility(Visibility::Direct)
+                .build()?;
+            mastodon.new_status(status)?.id
+        }
+    };
+    mastodon_bookmark(mastodon, &toot_id)
+}
+
+// Returns the likes that were not bookmarked yet, oldest first.
+fn new_likes<'a>(likes: &'a [Tweet], handled: &BTreeSet<u64>) -> Vec<&'a Tweet> {
+    likes
+        .iter()
+        .rev()
+        .filter(|tweet| !handled.contains(&tweet.id))
+        .collect()
+}
+
+fn save_handled_likes(cache_file: &str, handled: &BTreeSet<u64>) -> Result<()> {
+    let json = serde_json::to_string_pretty(handled)?;
+    fs::write(cache_file, json.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::tests::get_twitter_status;
+
+    #[test]
+    fn only_new_likes() {
+        let mut newest = get_twitter_status();
+        newest.id = 3;
+        let mut handled_like = get_twitter_status();
+        handled_like.id = 2;
+        let mut oldest = get_twitter_status();
+        oldest.id = 1;
+        let likes = vec![newest, handled_like, oldest];
+
+        let handled = BTreeSet::from([2]);
+        let ids: Vec<u64> = new_likes(&likes, &handled).iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![1, 3]);
+    }
+}
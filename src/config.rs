@@ -1,5 +1,8 @@
+#[cfg(feature = "mastodon")]
+use anyhow::Context;
 use anyhow::Result;
 use chrono::prelude::*;
+#[cfg(feature = "mastodon")]
 use elefren::data::Data;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
@@ -7,50 +10,661 @@ use serde_with::NoneAsEmptyString;
 use std::collections::BTreeMap;
 use std::fs;
 use std::fs::remove_file;
+#[cfg(feature = "mastodon")]
+use std::path::Path;
 
+use crate::errors::SyncError;
+
+#[cfg(feature = "mastodon")]
 #[inline]
 pub fn config_load(config: &str) -> Result<Config> {
-    toml::from_str(config).map_err(anyhow::Error::from)
+    toml::from_str(config).map_err(|e| SyncError::Config(e).into())
+}
+
+/// Writes the config as TOML to the given file.
+#[cfg(feature = "mastodon")]
+pub fn config_save(config: &Config, config_file: &str) -> Result<()> {
+    let toml = toml::to_string(config)?;
+    // The config directory of the platform might not exist yet.
+    if let Some(dir) = Path::new(config_file).parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create config directory {}", dir.display()))?;
+    }
+    fs::write(config_file, toml.as_bytes())
+        .with_context(|| format!("Failed to write config file {config_file}"))?;
+    Ok(())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[cfg(feature = "mastodon")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    // Without a Mastodon account only the Twitter mirror runs.
+    #[serde(default)]
     pub mastodon: MastodonConfig,
     pub twitter: TwitterConfig,
+    // Where to send notifications when a run fails.
+    #[serde(default = "config_none_default")]
+    pub notify: Option<NotifyConfig>,
+    // Where to report panics and failed runs, needs the sentry feature.
+    #[serde(default = "config_none_default")]
+    pub sentry: Option<SentryConfig>,
+    // Where to write a machine readable report of each run.
+    #[serde(default = "config_none_default")]
+    pub report: Option<ReportConfig>,
 }
 
+/// Notification channels for failed runs, all configured channels are used.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NotifyConfig {
+    #[serde(default = "config_none_default")]
+    pub email: Option<EmailNotifyConfig>,
+    #[serde(default = "config_none_default")]
+    pub ntfy: Option<NtfyNotifyConfig>,
+    #[serde(default = "config_none_default")]
+    pub pushover: Option<PushoverNotifyConfig>,
+}
+
+/// Sends an email over SMTP with STARTTLS.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailNotifyConfig {
+    pub smtp_server: String,
+    #[serde(default = "config_smtp_port_default")]
+    pub smtp_port: u16,
+    #[serde(default = "config_none_default")]
+    pub smtp_user: Option<String>,
+    #[serde(default = "config_none_default")]
+    pub smtp_password: Option<String>,
+    pub from: String,
+    pub to: String,
+}
+
+/// Publishes a message to a topic of ntfy.sh or a self-hosted ntfy server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NtfyNotifyConfig {
+    // Full topic URL like https://ntfy.sh/my-topic.
+    pub url: String,
+    // Access token for protected topics.
+    #[serde(default = "config_none_default")]
+    pub token: Option<String>,
+}
+
+/// Sends a Pushover message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushoverNotifyConfig {
+    // API token of the Pushover application.
+    pub token: String,
+    // User or group key that receives the message.
+    pub user: String,
+}
+
+/// Writes a JSON report of every sync run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportConfig {
+    pub file: String,
+}
+
+/// Reports panics and failed runs to Sentry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SentryConfig {
+    pub dsn: String,
+    // Optional environment name to tell apart several installations.
+    #[serde(default = "config_none_default")]
+    pub environment: Option<String>,
+}
+
+#[cfg(feature = "mastodon")]
 #[serde_as]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MastodonConfig {
     pub delete_older_statuses: bool,
     #[serde(default = "config_false_default")]
     pub delete_older_favs: bool,
+    // Always keep this many of the latest favourites, even if they are older
+    // than 90 days.
+    #[serde(default = "config_none_default")]
+    pub keep_latest_favs: Option<usize>,
+    // Delete at most this many old statuses and favourites in one run, the
+    // rest is deleted in the next runs.
+    #[serde(default = "config_none_default")]
+    pub delete_batch_size: Option<usize>,
+    // Pause between two delete calls in milliseconds.
+    #[serde(default)]
+    pub delete_delay_ms: u64,
     #[serde(default = "config_true_default")]
     pub sync_reblogs: bool,
+    // Copy the text of boosts or only post a link to the boosted status.
+    #[serde(default)]
+    pub retweet_sync_mode: RetweetSyncMode,
+    // Skip boosts of own toots or retweet their synced tweets.
+    #[serde(default)]
+    pub self_boosts: SelfBoostMode,
     #[serde_as(as = "NoneAsEmptyString")]
     #[serde(default = "config_none_default")]
     pub sync_hashtag: Option<String>,
+    // Additional sync hashtags, hashtags starting with "-" exclude statuses.
+    #[serde(default)]
+    pub sync_hashtags: Vec<String>,
+    #[serde(default)]
+    pub sync_hashtags_match: HashtagMatch,
+    // Remove the sync hashtags from posts on the other network.
+    #[serde(default = "config_false_default")]
+    pub remove_sync_hashtag: bool,
+    // Statuses posted with these apps are not synced, for example because
+    // another tool cross-posts them already.
+    #[serde(default)]
+    pub skip_sources: Vec<String>,
+    // Statuses that match one of these regular expressions were cross-posted
+    // by another tool and are not synced back.
+    #[serde(default = "config_crosspost_patterns_default")]
+    pub crosspost_patterns: Vec<String>,
+    // Boosts of statuses from these instances are not synced.
+    #[serde(default)]
+    pub boost_deny_instances: Vec<String>,
+    // Sync replies to other accounts as standalone posts with a link to the
+    // replied status.
+    #[serde(default = "config_false_default")]
+    pub sync_replies_to_others: bool,
+    #[serde(default = "config_none_default")]
+    pub max_thread_depth: Option<usize>,
+    // How threads of this network are synced, "thread_mode" is accepted as
+    // well.
+    #[serde(default, alias = "thread_mode")]
+    pub thread_strategy: ThreadStrategy,
+    #[serde(default = "config_none_default")]
+    pub max_posts_per_run: Option<usize>,
+    #[serde(default)]
+    pub direct_posts: DirectPostMode,
+    // Post a summary of the previous month once per month.
+    #[serde(default = "config_false_default")]
+    pub monthly_digest: bool,
+    #[serde(default = "config_none_default")]
+    pub monthly_digest_template: Option<String>,
+    // Queue scheduled statuses as tweets that are posted at the scheduled
+    // time.
+    #[serde(default = "config_false_default")]
+    pub sync_scheduled: bool,
+    // Only post statuses from this network during this time of the day.
+    #[serde(default = "config_none_default")]
+    pub posting_window: Option<PostingWindow>,
+    // Post statuses from this network this many minutes after they were
+    // created, they wait in the queue until then.
+    #[serde(default = "config_none_default")]
+    pub publish_delay: Option<u64>,
+    // External command that can change statuses from this network before
+    // posting them.
+    #[serde(default = "config_none_default")]
+    pub transform_cmd: Option<String>,
+    // External command that gets each status created from this network,
+    // including its URL, as JSON on stdin.
+    #[serde(default = "config_none_default")]
+    pub posted_cmd: Option<String>,
+    // Statuses from this network that are at least this similar (0.0 to 1.0)
+    // to a recent status on the other network are not posted automatically.
+    #[serde(default = "config_none_default")]
+    pub similarity_threshold: Option<f64>,
+    // Remove EXIF metadata like GPS positions from images before uploading
+    // them to the other network.
+    #[serde(default = "config_false_default")]
+    pub strip_metadata: bool,
+    // Warn about or skip statuses with media that has no alt text.
+    #[serde(default)]
+    pub require_alt_text: AltTextPolicy,
+    // Attach the image of the link preview card to posts without media.
+    #[serde(default = "config_false_default")]
+    pub link_preview_images: bool,
+    // Attachments larger than this are not downloaded.
+    #[serde(default = "config_none_default")]
+    pub max_attachment_mb: Option<u64>,
+    // Link oversized attachments in the text or skip the whole status.
+    #[serde(default)]
+    pub oversized_attachments: OversizedMediaPolicy,
+    // Image that is put on top of images before uploading them to the other
+    // network.
+    #[serde(default = "config_none_default")]
+    pub watermark: Option<WatermarkConfig>,
+    // Filter pipeline for statuses from this network.
+    #[serde(default)]
+    pub filters: Vec<FilterConfig>,
+    // Second account, for example on a new instance, that gets copies of the
+    // statuses of this account.
+    #[serde(default = "config_none_default")]
+    pub mirror: Option<MastodonMirrorConfig>,
+    pub app: Data,
+}
+
+#[cfg(feature = "mastodon")]
+impl Default for MastodonConfig {
+    /// The options of an empty config section, with empty app credentials.
+    /// The defaults are only defined once, in the serde attributes.
+    fn default() -> Self {
+        toml::from_str(
+            r#"
+delete_older_statuses = false
+[app]
+base = ""
+client_id = ""
+client_secret = ""
+redirect = ""
+token = ""
+"#,
+        )
+        .expect("Defaults of the Mastodon config")
+    }
+}
+
+/// Mastodon account that mirrors the statuses of the configured account.
+#[cfg(feature = "mastodon")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MastodonMirrorConfig {
     pub app: Data,
 }
 
 #[serde_as]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TwitterConfig {
+    // OAuth 1.0a keys, they can be empty if only an OAuth 2.0 token is
+    // configured or tweets are fetched from an RSS feed.
+    #[serde(default)]
     pub consumer_key: String,
+    #[serde(default)]
     pub consumer_secret: String,
+    #[serde(default)]
     pub access_token: String,
+    #[serde(default)]
     pub access_token_secret: String,
+    #[serde(default)]
     pub user_id: u64,
     pub user_name: String,
     #[serde(default = "config_false_default")]
     pub delete_older_statuses: bool,
     #[serde(default = "config_false_default")]
     pub delete_older_favs: bool,
+    // Always keep this many of the latest favourites, even if they are older
+    // than 90 days.
+    #[serde(default = "config_none_default")]
+    pub keep_latest_favs: Option<usize>,
+    // Only delete old tweets that were posted by this tool.
+    #[serde(default = "config_false_default")]
+    pub delete_only_synced: bool,
+    // Delete at most this many old statuses and favourites in one run, the
+    // rest is deleted in the next runs.
+    #[serde(default = "config_none_default")]
+    pub delete_batch_size: Option<usize>,
+    // Pause between two delete calls in milliseconds.
+    #[serde(default)]
+    pub delete_delay_ms: u64,
+    // Bookmark newly liked tweets on Mastodon.
+    #[serde(default = "config_false_default")]
+    pub likes_to_bookmarks: bool,
     #[serde(default = "config_true_default")]
     pub sync_retweets: bool,
+    // Copy the text of retweets or only post a link to the retweeted tweet.
+    #[serde(default)]
+    pub retweet_sync_mode: RetweetSyncMode,
+    // Skip retweets of own tweets or boost their synced toots.
+    #[serde(default)]
+    pub self_boosts: SelfBoostMode,
+    // Boost the original toot instead of posting a copy of a retweet if the
+    // retweeted account is on Mastodon as well.
+    #[serde(default = "config_false_default")]
+    pub native_boosts: bool,
+    // Mastodon accounts of Twitter users for native boosts. Other users are
+    // looked up in their Twitter profile.
+    #[serde(default)]
+    pub native_boost_accounts: BTreeMap<String, String>,
     #[serde_as(as = "NoneAsEmptyString")]
     #[serde(default = "config_none_default")]
     pub sync_hashtag: Option<String>,
+    // Additional sync hashtags, hashtags starting with "-" exclude statuses.
+    #[serde(default)]
+    pub sync_hashtags: Vec<String>,
+    #[serde(default)]
+    pub sync_hashtags_match: HashtagMatch,
+    // Fetch the user timeline or only search recent tweets with the sync
+    // hashtags, which needs fewer API calls.
+    #[serde(default)]
+    pub fetch_mode: TwitterFetchMode,
+    // RSS feed of the user's tweets for fetch_mode = "rss".
+    #[serde(default = "config_none_default")]
+    pub rss_url: Option<String>,
+    // Remove the sync hashtags from posts on the other network.
+    #[serde(default = "config_false_default")]
+    pub remove_sync_hashtag: bool,
+    // Statuses posted with these apps are not synced, for example because
+    // another tool cross-posts them already.
+    #[serde(default)]
+    pub skip_sources: Vec<String>,
+    // Statuses that match one of these regular expressions were cross-posted
+    // by another tool and are not synced back.
+    #[serde(default = "config_crosspost_patterns_default")]
+    pub crosspost_patterns: Vec<String>,
+    // Sync replies to other accounts as standalone posts with a link to the
+    // replied status.
+    #[serde(default = "config_false_default")]
+    pub sync_replies_to_others: bool,
+    #[serde(default = "config_none_default")]
+    pub max_thread_depth: Option<usize>,
+    #[serde(default, alias = "thread_mode")]
+    pub thread_strategy: ThreadStrategy,
+    #[serde(default)]
+    pub reply_visibility: ReplyVisibility,
+    #[serde(default = "config_none_default")]
+    pub max_posts_per_run: Option<usize>,
+    #[serde(default = "config_true_default")]
+    pub expand_urls: bool,
+    #[serde(default = "config_true_default")]
+    pub escape_mentions: bool,
+    #[serde(default = "config_true_default")]
+    pub decode_html_entities: bool,
+    // Resolve links of link shorteners like bit.ly with HEAD requests when
+    // comparing tweets and toots.
+    #[serde(default = "config_false_default")]
+    pub resolve_short_links: bool,
+    // Characters of the 280 that are kept free when shortening toots for
+    // Twitter.
+    #[serde(default = "config_character_reserve_default")]
+    pub character_reserve: usize,
+    // Appended to shortened toots.
+    #[serde(default = "config_ellipsis_default")]
+    pub shorten_ellipsis: String,
+    // Append the URL of the toot to shortened toots.
+    #[serde(default = "config_true_default")]
+    pub shorten_with_link: bool,
+    // Only post statuses from this network during this time of the day.
+    #[serde(default = "config_none_default")]
+    pub posting_window: Option<PostingWindow>,
+    // Post statuses from this network this many minutes after they were
+    // created, they wait in the queue until then.
+    #[serde(default = "config_none_default")]
+    pub publish_delay: Option<u64>,
+    // External command that can change statuses from this network before
+    // posting them.
+    #[serde(default = "config_none_default")]
+    pub transform_cmd: Option<String>,
+    // External command that gets each status created from this network,
+    // including its URL, as JSON on stdin.
+    #[serde(default = "config_none_default")]
+    pub posted_cmd: Option<String>,
+    // Statuses from this network that are at least this similar (0.0 to 1.0)
+    // to a recent status on the other network are not posted automatically.
+    #[serde(default = "config_none_default")]
+    pub similarity_threshold: Option<f64>,
+    // Remove EXIF metadata like GPS positions from images before uploading
+    // them to the other network.
+    #[serde(default = "config_false_default")]
+    pub strip_metadata: bool,
+    // Warn about or skip statuses with media that has no alt text.
+    #[serde(default)]
+    pub require_alt_text: AltTextPolicy,
+    // Attachments larger than this are not downloaded.
+    #[serde(default = "config_none_default")]
+    pub max_attachment_mb: Option<u64>,
+    // Link oversized attachments in the text or skip the whole status.
+    #[serde(default)]
+    pub oversized_attachments: OversizedMediaPolicy,
+    // Maximum number of Twitter API calls per calendar month that read or
+    // write, for the limits of the paid API tiers.
+    #[serde(default = "config_none_default")]
+    pub monthly_read_budget: Option<u64>,
+    #[serde(default = "config_none_default")]
+    pub monthly_write_budget: Option<u64>,
+    // Image that is put on top of images before uploading them to the other
+    // network.
+    #[serde(default = "config_none_default")]
+    pub watermark: Option<WatermarkConfig>,
+    // Filter pipeline for statuses from this network.
+    #[serde(default)]
+    pub filters: Vec<FilterConfig>,
+    // Second Twitter account that gets the posts when the API rejects posting
+    // with this account.
+    #[serde(default = "config_none_default")]
+    pub backup: Option<TwitterAccountConfig>,
+    // Second Twitter account that gets copies of the tweets of this account.
+    #[serde(default = "config_none_default")]
+    pub mirror: Option<TwitterAccountConfig>,
+    // OAuth 2.0 user context token, needed for endpoints of the v2 API.
+    #[serde(default = "config_none_default")]
+    pub oauth2: Option<TwitterOAuth2Config>,
+}
+
+impl Default for TwitterConfig {
+    /// The options of an empty config section without credentials, see
+    /// MastodonConfig::default().
+    fn default() -> Self {
+        toml::from_str("user_name = \"\"").expect("Defaults of the Twitter config")
+    }
+}
+
+/// OAuth 2.0 token of the Twitter account, it is refreshed automatically and
+/// written back to the config file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwitterOAuth2Config {
+    pub client_id: String,
+    // Only confidential clients have a secret.
+    #[serde(default = "config_none_default")]
+    pub client_secret: Option<String>,
+    pub access_token: String,
+    pub refresh_token: String,
+    // Unix timestamp when the access token expires.
+    pub expires_at: i64,
+}
+
+/// Credentials of a second Twitter account, for posting when the main account
+/// is rejected or for mirroring its tweets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwitterAccountConfig {
+    pub consumer_key: String,
+    pub consumer_secret: String,
+    pub access_token: String,
+    pub access_token_secret: String,
+    pub user_name: String,
+}
+
+/// How threads are synced to the other network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThreadStrategy {
+    /// Post all replies of the thread as thread on the other network.
+    #[default]
+    #[serde(alias = "full")]
+    Mirror,
+    /// Only post the first status with a link to the rest of the thread.
+    #[serde(alias = "first-post-only")]
+    FirstPostWithLink,
+    /// Join the thread into one status if it fits, otherwise like
+    /// FirstPostWithLink.
+    #[serde(alias = "collapsed")]
+    Collapse,
+}
+
+/// Whether a status needs any or all of the sync hashtags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashtagMatch {
+    #[default]
+    Any,
+    All,
+}
+
+/// How tweets are fetched for the sync to Mastodon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TwitterFetchMode {
+    /// The latest tweets of the user timeline.
+    #[default]
+    Timeline,
+    /// Tweets of the last 7 days with the sync hashtags, found with the
+    /// recent search of the v2 API.
+    Search,
+    /// The public tweets from the RSS feed of a Nitter instance, without the
+    /// Twitter API. Nothing is posted to or deleted on Twitter then.
+    Rss,
+}
+
+/// What to do with statuses that have media without alt text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AltTextPolicy {
+    #[default]
+    Ignore,
+    /// Post them, but log a warning.
+    Warn,
+    /// Do not post them.
+    Skip,
+}
+
+/// What to do with attachments that are larger than max_attachment_mb.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OversizedMediaPolicy {
+    /// Post the status without the attachment and with a link to it.
+    #[default]
+    Link,
+    /// Do not post the status.
+    Skip,
+}
+
+/// What to do with statuses that start with a mention, they are meant for the
+/// mentioned accounts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DirectPostMode {
+    #[default]
+    Skip,
+    /// Remove the mentions at the beginning and post the rest.
+    Redact,
+}
+
+/// What to do with boosts or retweets of own statuses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelfBoostMode {
+    /// Post them like other boosts, as "RT user: text".
+    #[default]
+    Copy,
+    Skip,
+    /// Boost or retweet the synced copy of the status on the other network.
+    Native,
+}
+
+/// Visibility of thread replies that are posted to Mastodon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplyVisibility {
+    #[default]
+    Public,
+    /// Only the beginning of a thread shows up on public timelines.
+    Unlisted,
+}
+
+/// How retweets and boosts are posted to the other network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetweetSyncMode {
+    /// Copy the text as "RT user: text" including attachments.
+    #[default]
+    Copy,
+    /// Only post "RT user:" with the URL of the original status, the other
+    /// network shows a preview of it.
+    Link,
+}
+
+/// A daily time window in local time like "08:00-22:00". The window can span
+/// midnight, for example "22:00-06:00".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PostingWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl PostingWindow {
+    /// Returns true if the given time is inside the window, the end is
+    /// exclusive.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl TryFrom<String> for PostingWindow {
+    type Error = String;
+
+    fn try_from(window: String) -> std::result::Result<Self, Self::Error> {
+        let parse_time = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|e| format!("Invalid time \"{time}\" in posting window: {e}"))
+        };
+        match window.split_once('-') {
+            Some((start, end)) => Ok(PostingWindow {
+                start: parse_time(start)?,
+                end: parse_time(end)?,
+            }),
+            None => Err(format!(
+                "Invalid posting window \"{window}\", expected a format like \"08:00-22:00\""
+            )),
+        }
+    }
+}
+
+impl From<PostingWindow> for String {
+    fn from(window: PostingWindow) -> Self {
+        format!(
+            "{}-{}",
+            window.start.format("%H:%M"),
+            window.end.format("%H:%M")
+        )
+    }
+}
+
+/// A PNG image that is put on top of uploaded images.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatermarkConfig {
+    pub image: String,
+    #[serde(default)]
+    pub position: WatermarkPosition,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+    Center,
+}
+
+/// A stage of the filter pipeline that can change or reject new statuses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FilterConfig {
+    /// Only keep statuses containing the hashtag.
+    Hashtag { hashtag: String },
+    /// Replace matches of the pattern, or reject matching statuses if there is
+    /// no replacement.
+    Regex {
+        pattern: String,
+        #[serde(default)]
+        replacement: Option<String>,
+    },
+    /// Replace mentions of user names with their account on the other network.
+    Mentions { map: BTreeMap<String, String> },
+    /// Replace hashtags with the ones used on the other network and append
+    /// hashtags that are missing.
+    HashtagMap {
+        #[serde(default)]
+        map: BTreeMap<String, String>,
+        #[serde(default)]
+        append: Vec<String>,
+    },
+    /// Run an external command that prints the changed status as JSON, or
+    /// nothing to reject it.
+    Command { cmd: String },
 }
 
 fn config_false_default() -> bool {
@@ -65,9 +679,31 @@ fn config_none_default<T>() -> Option<T> {
     None
 }
 
+pub(crate) fn config_crosspost_patterns_default() -> Vec<String> {
+    vec![
+        r"(?i)\(from birdsite\)".to_string(),
+        r"(?i)\bmoa\.party\b".to_string(),
+    ]
+}
+
+fn config_smtp_port_default() -> u16 {
+    587
+}
+
+fn config_character_reserve_default() -> usize {
+    40
+}
+
+fn config_ellipsis_default() -> String {
+    "…".to_string()
+}
+
 pub fn load_dates_from_cache(cache_file: &str) -> Result<Option<BTreeMap<DateTime<Utc>, u64>>> {
     if let Ok(json) = fs::read_to_string(cache_file) {
-        let cache = serde_json::from_str(&json)?;
+        let cache = serde_json::from_str(&json).map_err(|source| SyncError::Cache {
+            file: cache_file.to_string(),
+            source,
+        })?;
         Ok(Some(cache))
     } else {
         Ok(None)
@@ -108,7 +744,7 @@ pub fn remove_dates_from_cache(
     Ok(())
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "mastodon"))]
 mod tests {
 
     use crate::config::*;
@@ -121,8 +757,54 @@ mod tests {
 [mastodon]
 delete_older_statuses = true
 delete_older_favs = true
+keep_latest_favs = 200
+delete_batch_size = 50
+delete_delay_ms = 1000
 sync_reblogs = false
+retweet_sync_mode = "link"
+self_boosts = "native"
 sync_hashtag = "#test"
+sync_hashtags = ["#blog", "-#private"]
+sync_hashtags_match = "all"
+remove_sync_hashtag = true
+skip_sources = ["Buffer", "IFTTT"]
+crosspost_patterns = ["\\(from birdsite\\)"]
+boost_deny_instances = ["example.com"]
+sync_replies_to_others = true
+max_thread_depth = 10
+thread_mode = "collapsed"
+max_posts_per_run = 5
+direct_posts = "redact"
+monthly_digest = true
+monthly_digest_template = "{count} posts in {month}"
+sync_scheduled = true
+posting_window = "08:00-22:00"
+publish_delay = 120
+transform_cmd = "/usr/local/bin/transform-toot"
+posted_cmd = "/usr/local/bin/announce-tweet"
+similarity_threshold = 0.9
+strip_metadata = true
+require_alt_text = "skip"
+link_preview_images = true
+max_attachment_mb = 50
+oversized_attachments = "skip"
+watermark = { image = "/etc/logo.png", position = "top_left" }
+[[mastodon.filters]]
+type = "regex"
+pattern = "(?i)private"
+[[mastodon.filters]]
+type = "mentions"
+map = { "@klausi" = "@klausi_twitter" }
+[[mastodon.filters]]
+type = "hashtag_map"
+map = { "#fediverse" = "#mastodon" }
+append = ["#mastodon"]
+[mastodon.mirror.app]
+base = "https://fosstodon.org"
+client_id = "efgh"
+client_secret = "efgh"
+redirect = "urn:ietf:wg:oauth:2.0:oob"
+token = "5678"
 [mastodon.app]
 base = "https://mastodon.social"
 client_id = "abcd"
@@ -138,8 +820,81 @@ user_id = 0
 user_name = " "
 delete_older_statuses = true
 delete_older_favs = true
+keep_latest_favs = 200
+delete_only_synced = true
+delete_batch_size = 50
+delete_delay_ms = 1000
+likes_to_bookmarks = true
 sync_retweets = false
+retweet_sync_mode = "link"
+self_boosts = "skip"
+native_boosts = true
+native_boost_accounts = { "klausi" = "@klausi@mastodon.social" }
 sync_hashtag = "#test"
+sync_hashtags = ["#blog", "-#private"]
+sync_hashtags_match = "any"
+fetch_mode = "search"
+rss_url = "https://nitter.net/test/rss"
+remove_sync_hashtag = true
+skip_sources = ["Buffer", "IFTTT"]
+crosspost_patterns = ["\\(from birdsite\\)"]
+sync_replies_to_others = true
+max_thread_depth = 10
+thread_strategy = "first_post_with_link"
+reply_visibility = "unlisted"
+max_posts_per_run = 5
+expand_urls = false
+escape_mentions = false
+decode_html_entities = false
+resolve_short_links = true
+character_reserve = 20
+shorten_ellipsis = " [...]"
+shorten_with_link = false
+posting_window = "22:00-06:00"
+publish_delay = 30
+transform_cmd = "/usr/local/bin/transform-tweet"
+strip_metadata = true
+require_alt_text = "warn"
+monthly_read_budget = 10000
+monthly_write_budget = 1500
+watermark = { image = "/etc/logo.png" }
+[[twitter.filters]]
+type = "command"
+cmd = "/usr/local/bin/filter"
+[twitter.backup]
+consumer_key = "efgh"
+consumer_secret = "efgh"
+access_token = "5678"
+access_token_secret = "5678"
+user_name = "backup"
+[twitter.mirror]
+consumer_key = "mnop"
+consumer_secret = "mnop"
+access_token = "7890"
+access_token_secret = "7890"
+user_name = "brand"
+[twitter.oauth2]
+client_id = "ijkl"
+client_secret = "ijkl"
+access_token = "9012"
+refresh_token = "3456"
+expires_at = 1700000000
+[notify.email]
+smtp_server = "smtp.example.com"
+smtp_user = "user"
+smtp_password = "secret"
+from = "sync@example.com"
+to = "me@example.com"
+[notify.ntfy]
+url = "https://ntfy.sh/my-topic"
+[notify.pushover]
+token = "abcd"
+user = "1234"
+[sentry]
+dsn = "https://key@sentry.example.com/1"
+environment = "home-server"
+[report]
+file = "/var/log/mastodon-twitter-sync/report.json"
 "##;
         let config: Config = toml::from_str(toml_config).unwrap();
         toml::to_string(&config).unwrap();
@@ -210,4 +965,40 @@ sync_hashtag = ""
         assert_eq!(config.mastodon.sync_hashtag, None);
         assert_eq!(config.twitter.sync_hashtag, None);
     }
+
+    // Defaults of new configs are the same as of missing options.
+    #[test]
+    fn config_defaults() {
+        let mastodon = MastodonConfig::default();
+        assert!(mastodon.sync_reblogs);
+        assert!(!mastodon.delete_older_statuses);
+        assert_eq!(
+            mastodon.crosspost_patterns,
+            config_crosspost_patterns_default()
+        );
+        let twitter = TwitterConfig::default();
+        assert!(twitter.sync_retweets);
+        assert!(twitter.expand_urls);
+        assert_eq!(twitter.character_reserve, 40);
+        assert_eq!(twitter.shorten_ellipsis, "…");
+    }
+
+    #[test]
+    fn posting_window() {
+        let window = PostingWindow::try_from("08:00-22:00".to_string()).unwrap();
+        assert!(window.contains(NaiveTime::from_hms_opt(8, 0, 0).unwrap()));
+        assert!(window.contains(NaiveTime::from_hms_opt(21, 59, 0).unwrap()));
+        assert!(!window.contains(NaiveTime::from_hms_opt(22, 0, 0).unwrap()));
+        assert!(!window.contains(NaiveTime::from_hms_opt(3, 0, 0).unwrap()));
+        assert_eq!(String::from(window), "08:00-22:00");
+
+        // Windows spanning midnight.
+        let window = PostingWindow::try_from("22:00-06:00".to_string()).unwrap();
+        assert!(window.contains(NaiveTime::from_hms_opt(23, 0, 0).unwrap()));
+        assert!(window.contains(NaiveTime::from_hms_opt(5, 0, 0).unwrap()));
+        assert!(!window.contains(NaiveTime::from_hms_opt(12, 0, 0).unwrap()));
+
+        assert!(PostingWindow::try_from("8-22".to_string()).is_err());
+        assert!(PostingWindow::try_from("08:00".to_string()).is_err());
+    }
 }
@@ -0,0 +1,239 @@
+use crate::config::*;
+use crate::conflicts::text_similarity;
+use elefren::data::Data;
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use std::fmt;
+use toml::Value;
+
+// Options that still work but should be replaced, with the suggested fix.
+const DEPRECATED_OPTIONS: &[(&str, &str, &str)] = &[
+    (
+        "mastodon",
+        "sync_hashtag",
+        "use sync_hashtags = [\"#hashtag\"], it also supports several hashtags",
+    ),
+    (
+        "twitter",
+        "sync_hashtag",
+        "use sync_hashtags = [\"#hashtag\"], it also supports several hashtags",
+    ),
+];
+
+/// Something in the config file that is probably not what the user wants.
+#[derive(Debug, PartialEq)]
+pub enum ConfigProblem {
+    /// The config file cannot be loaded, for example because of a missing
+    /// field.
+    Invalid(String),
+    /// Serde ignores unknown keys, so a typo silently disables an option.
+    UnknownKey {
+        key: String,
+        suggestion: Option<String>,
+    },
+    Deprecated {
+        key: String,
+        fix: &'static str,
+    },
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigProblem::Invalid(error) => write!(f, "Invalid config: {error}"),
+            ConfigProblem::UnknownKey {
+                key,
+                suggestion: Some(suggestion),
+            } => write!(f, "Unknown option {key}, did you mean {suggestion}?"),
+            ConfigProblem::UnknownKey {
+                key,
+                suggestion: None,
+            } => write!(f, "Unknown option {key}"),
+            ConfigProblem::Deprecated { key, fix } => {
+                write!(f, "Deprecated option {key}: {fix}")
+            }
+        }
+    }
+}
+
+/// Checks the TOML of a config file for unknown, deprecated and missing
+/// options.
+pub fn config_check(toml: &str) -> Vec<ConfigProblem> {
+    let value: Value = match toml::from_str(toml) {
+        Ok(value) => value,
+        Err(e) => return vec![ConfigProblem::Invalid(e.to_string())],
+    };
+    let mut problems = Vec::new();
+    if let Err(e) = config_load(toml) {
+        let error = match e.downcast_ref::<crate::errors::SyncError>() {
+            Some(crate::errors::SyncError::Config(e)) => e.to_string(),
+            _ => format!("{e:#}"),
+        };
+        problems.push(ConfigProblem::Invalid(error));
+    }
+
+    check_table(&value, "", struct_fields::<Config>(), &mut problems);
+    let sections = [
+        ("mastodon", struct_fields::<MastodonConfig>()),
+        ("mastodon.app", struct_fields::<Data>()),
+        ("mastodon.watermark", struct_fields::<WatermarkConfig>()),
+        ("mastodon.mirror", struct_fields::<MastodonMirrorConfig>()),
+        ("mastodon.mirror.app", struct_fields::<Data>()),
+        ("twitter", struct_fields::<TwitterConfig>()),
+        ("twitter.watermark", struct_fields::<WatermarkConfig>()),
+        ("twitter.backup", struct_fields::<TwitterAccountConfig>()),
+        ("twitter.mirror", struct_fields::<TwitterAccountConfig>()),
+        ("twitter.oauth2", struct_fields::<TwitterOAuth2Config>()),
+        ("notify", struct_fields::<NotifyConfig>()),
+        ("notify.email", struct_fields::<EmailNotifyConfig>()),
+        ("notify.ntfy", struct_fields::<NtfyNotifyConfig>()),
+        ("notify.pushover", struct_fields::<PushoverNotifyConfig>()),
+        ("sentry", struct_fields::<SentryConfig>()),
+        ("report", struct_fields::<ReportConfig>()),
+    ];
+    for (section, fields) in sections {
+        let table = section
+            .split('.')
+            .try_fold(&value, |value, key| value.get(key));
+        if let Some(table) = table {
+            check_table(table, section, fields, &mut problems);
+        }
+    }
+
+    for (section, key, fix) in DEPRECATED_OPTIONS {
+        if value
+            .get(section)
+            .and_then(|table| table.get(key))
+            .is_some()
+        {
+            problems.push(ConfigProblem::Deprecated {
+                key: format!("{section}.{key}"),
+                fix,
+            });
+        }
+    }
+    problems
+}
+
+fn check_table(table: &Value, section: &str, fields: &[&str], problems: &mut Vec<ConfigProblem>) {
+    let table = match table.as_table() {
+        Some(table) => table,
+        None => return,
+    };
+    for key in table.keys() {
+        if fields.contains(&key.as_str()) {
+            continue;
+        }
+        // Suggest the most similar known option for typos.
+        let suggestion = fields
+            .iter()
+            .map(|field| (text_similarity(key, field), field))
+            .filter(|(similarity, _)| *similarity >= 0.7)
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, field)| prefixed(section, field));
+        problems.push(ConfigProblem::UnknownKey {
+            key: prefixed(section, key),
+            suggestion,
+        });
+    }
+}
+
+fn prefixed(section: &str, key: &str) -> String {
+    if section.is_empty() {
+        key.to_string()
+    } else {
+        format!("{section}.{key}")
+    }
+}
+
+/// Returns the field names of a struct with derived Deserialize.
+fn struct_fields<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+// Deserializer that only records the field names the struct asks for.
+struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+impl<'de, 'a> Deserializer<'de> for FieldNames<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("only struct field names are recorded"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(de::Error::custom("only struct field names are recorded"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCOUNTS: &str = r#"
+[mastodon.app]
+base = "https://mastodon.social"
+client_id = "abcd"
+client_secret = "abcd"
+redirect = "urn:ietf:wg:oauth:2.0:oob"
+token = "1234"
+[twitter]
+consumer_key = "abcd"
+consumer_secret = "abcd"
+access_token = "1234"
+access_token_secret = "1234"
+user_id = 0
+user_name = "example"
+"#;
+
+    #[test]
+    fn valid_config() {
+        let toml = format!("[mastodon]\nsync_reblogs = false\n{ACCOUNTS}");
+        assert_eq!(config_check(&toml), Vec::new());
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let toml = format!("[mastodon]\nsync_reblog = false\nfoo = 1\n{ACCOUNTS}");
+        assert_eq!(
+            config_check(&toml),
+            vec![
+                ConfigProblem::UnknownKey {
+                    key: "mastodon.foo".to_string(),
+                    suggestion: None,
+                },
+                ConfigProblem::UnknownKey {
+                    key: "mastodon.sync_reblog".to_string(),
+                    suggestion: Some("mastodon.sync_reblogs".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_and_deprecated() {
+        let toml = format!("[mastodon]\nsync_hashtag = \"#sync\"\n{ACCOUNTS}")
+            .replace("user_id = 0\n", "");
+        let problems = config_check(&toml);
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].to_string().contains("missing field `user_id`"));
+        assert_eq!(
+            problems[1].to_string(),
+            "Deprecated option mastodon.sync_hashtag: use sync_hashtags = [\"#hashtag\"], it also supports several hashtags"
+        );
+    }
+}
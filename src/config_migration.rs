@@ -0,0 +1,145 @@
+use crate::config::config_load;
+use anyhow::{Context, Result};
+use std::fs;
+use toml::value::Table;
+use toml::Value;
+use tracing::{info, warn};
+
+/// Migrates old option formats of the config file in place and keeps the
+/// original as backup. Returns the TOML to load.
+///
+/// In a dry run the changes are only logged.
+pub fn config_migrate_file(config_file: &str, toml: String, dry_run: bool) -> Result<String> {
+    let (migrated, changes) = config_migrate(&toml);
+    if changes.is_empty() {
+        return Ok(toml);
+    }
+    // Never replace a working config with a broken one.
+    if config_load(&migrated).is_err() {
+        warn!("Could not migrate the old options of config file {config_file}");
+        return Ok(toml);
+    }
+    for change in &changes {
+        info!("Config migration: {change}");
+    }
+    if dry_run {
+        return Ok(migrated);
+    }
+    let backup = format!("{config_file}.bak");
+    fs::write(&backup, &toml).with_context(|| format!("Failed to write config backup {backup}"))?;
+    fs::write(config_file, &migrated)
+        .with_context(|| format!("Failed to write config file {config_file}"))?;
+    info!("Config file {config_file} migrated, the old version is in {backup}");
+    Ok(migrated)
+}
+
+/// Rewrites old option formats line by line, so that comments are kept.
+/// Returns the new TOML and a description of each change.
+pub fn config_migrate(toml: &str) -> (String, Vec<String>) {
+    let mut section = String::new();
+    let mut changes = Vec::new();
+    let mut migrated = Vec::new();
+    // The list option is preferred if a section has both.
+    let has_hashtag_list = |section: &str| {
+        toml::from_str::<Value>(toml)
+            .ok()
+            .and_then(|value| value.get(section)?.get("sync_hashtags").cloned())
+            .is_some()
+    };
+    for line in toml.lines() {
+        if let Some(name) = line.trim().strip_prefix('[') {
+            section = name.trim_matches(|c| c == '[' || c == ']').to_string();
+            migrated.push(line.to_string());
+            continue;
+        }
+        // Only single line key/value pairs are migrated.
+        let (key, value) = match toml::from_str::<Table>(line)
+            .ok()
+            .and_then(|table| table.into_iter().next())
+        {
+            Some(pair) => pair,
+            None => {
+                migrated.push(line.to_string());
+                continue;
+            }
+        };
+        match (section.as_str(), key.as_str(), value) {
+            ("mastodon" | "twitter", "sync_hashtag", Value::String(hashtag))
+                if hashtag.trim().is_empty() =>
+            {
+                changes.push(format!(
+                    "removed empty {section}.sync_hashtag, all posts are synced"
+                ));
+            }
+            ("mastodon" | "twitter", "sync_hashtag", Value::String(hashtag))
+                if !has_hashtag_list(&section) =>
+            {
+                let list = Value::Array(vec![Value::String(hashtag)]);
+                changes.push(format!(
+                    "replaced {section}.sync_hashtag with sync_hashtags = {list}"
+                ));
+                migrated.push(format!("sync_hashtags = {list}"));
+            }
+            ("twitter", "user_id", Value::String(user_id)) if user_id.parse::<u64>().is_ok() => {
+                changes.push(format!("changed twitter.user_id \"{user_id}\" to a number"));
+                migrated.push(format!("user_id = {user_id}"));
+            }
+            _ => migrated.push(line.to_string()),
+        }
+    }
+    let mut migrated = migrated.join("\n");
+    if toml.ends_with('\n') {
+        migrated.push('\n');
+    }
+    (migrated, changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_old_options() {
+        let toml = r##"
+[mastodon]
+# Only sync tagged posts.
+sync_hashtag = "#sync"
+[mastodon.app]
+base = "https://mastodon.social"
+client_id = "abcd"
+client_secret = "abcd"
+redirect = "urn:ietf:wg:oauth:2.0:oob"
+token = "1234"
+[twitter]
+consumer_key = "abcd"
+consumer_secret = "abcd"
+access_token = "1234"
+access_token_secret = "1234"
+user_id = "1234"
+user_name = "example"
+sync_hashtag = ""
+"##;
+        let (migrated, changes) = config_migrate(toml);
+        assert_eq!(changes.len(), 3);
+        assert!(migrated.contains("# Only sync tagged posts.\nsync_hashtags = [\"#sync\"]\n"));
+        assert!(migrated.contains("\nuser_id = 1234\n"));
+        assert!(!migrated.contains("sync_hashtag ="));
+        let config = config_load(&migrated).unwrap();
+        assert_eq!(config.mastodon.sync_hashtags, vec!["#sync".to_string()]);
+        assert_eq!(config.twitter.user_id, 1234);
+
+        // Nothing to do the second time.
+        let (again, changes) = config_migrate(&migrated);
+        assert_eq!(again, migrated);
+        assert!(changes.is_empty());
+    }
+
+    // sync_hashtag is kept if the list exists already, both are used.
+    #[test]
+    fn keep_hashtag_next_to_list() {
+        let toml = "[mastodon]\nsync_hashtag = \"#a\"\nsync_hashtags = [\"#b\"]\n";
+        let (migrated, changes) = config_migrate(toml);
+        assert_eq!(migrated, toml);
+        assert!(changes.is_empty());
+    }
+}
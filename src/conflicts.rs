@@ -0,0 +1,162 @@
+use crate::sync::{
+    mastodon_toot_get_text, tweet_unshorten_decode, NewStatus, Platform, StatusUpdates,
+    TweetTextOptions,
+};
+use anyhow::Result;
+use egg_mode::tweet::Tweet;
+use elefren::entities::status::Status;
+
+/// A new status that is very similar, but not equal, to a recent status on
+/// the target network. Posting it might create a near-duplicate.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub target: Platform,
+    pub status: NewStatus,
+    // Text of the similar status on the target network.
+    pub existing: String,
+    pub similarity: f64,
+}
+
+/// Takes new statuses out of the posts that are at least as similar as the
+/// threshold to a recent status of the target network. No threshold disables
+/// the check for that direction.
+pub fn split_conflicts(
+    posts: StatusUpdates,
+    mastodon_statuses: &[Status],
+    twitter_statuses: &[Tweet],
+    tweet_threshold: Option<f64>,
+    toot_threshold: Option<f64>,
+    text_options: &TweetTextOptions,
+) -> Result<(StatusUpdates, Vec<Conflict>)> {
+    let recent_tweets = twitter_statuses
+        .iter()
+        .map(|tweet| tweet_unshorten_decode(tweet, text_options))
+        .collect::<Result<Vec<String>>>()?;
+    let recent_toots: Vec<String> = mastodon_statuses
+        .iter()
+        .map(mastodon_toot_get_text)
+        .collect();
+
+    let mut conflicts = Vec::new();
+    let skipped = posts.skipped;
+    let tweets = split_direction(
+        posts.tweets,
+        &recent_tweets,
+        tweet_threshold,
+        Platform::Twitter,
+        &mut conflicts,
+    );
+    let toots = split_direction(
+        posts.toots,
+        &recent_toots,
+        toot_threshold,
+        Platform::Mastodon,
+        &mut conflicts,
+    );
+    Ok((
+        StatusUpdates {
+            tweets,
+            toots,
+            skipped,
+            ..StatusUpdates::default()
+        },
+        conflicts,
+    ))
+}
+
+fn split_direction(
+    statuses: Vec<NewStatus>,
+    recent: &[String],
+    threshold: Option<f64>,
+    target: Platform,
+    conflicts: &mut Vec<Conflict>,
+) -> Vec<NewStatus> {
+    let threshold = match threshold {
+        Some(threshold) => threshold,
+        None => return statuses,
+    };
+    let mut keep = Vec::new();
+    for status in statuses {
+        let best = recent
+            .iter()
+            .map(|text| (text, text_similarity(&status.text, text)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        match best {
+            Some((existing, similarity)) if similarity >= threshold => conflicts.push(Conflict {
+                target,
+                status,
+                existing: existing.clone(),
+                similarity,
+            }),
+            _ => keep.push(status),
+        }
+    }
+    keep
+}
+
+/// Returns how similar two texts are from 0.0 (completely different) to 1.0
+/// (equal), ignoring case. Based on the Levenshtein distance of the
+/// characters.
+pub fn text_similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    // Only the previous row of the distance matrix is needed.
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, char_a) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, char_b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(char_a != char_b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    1.0 - previous[b.len()] as f64 / longest as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::tests::new_status;
+
+    #[test]
+    fn similarity() {
+        assert_eq!(text_similarity("Hello", "hello"), 1.0);
+        assert_eq!(text_similarity("abcd", "wxyz"), 0.0);
+        assert_eq!(text_similarity("", ""), 1.0);
+        assert!(text_similarity("Hello world!", "Hello world") > 0.9);
+    }
+
+    #[test]
+    fn split_similar_statuses() {
+        let recent = vec!["My new blog post https://example.com".to_string()];
+        let mut conflicts = Vec::new();
+        let keep = split_direction(
+            vec![
+                new_status(1, "My new blog post: https://example.com"),
+                new_status(1, "Something else entirely"),
+            ],
+            &recent,
+            Some(0.9),
+            Platform::Twitter,
+            &mut conflicts,
+        );
+        assert_eq!(keep.len(), 1);
+        assert_eq!(keep[0].text, "Something else entirely");
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].existing, recent[0]);
+
+        // Without threshold nothing is checked.
+        let keep = split_direction(
+            vec![new_status(1, "My new blog post https://example.com")],
+            &recent,
+            None,
+            Platform::Twitter,
+            &mut conflicts,
+        );
+        assert_eq!(keep.len(), 1);
+    }
+}
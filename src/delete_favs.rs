@@ -1,45 +1,87 @@
 use anyhow::Result;
 use chrono::prelude::*;
 use chrono::Duration;
-use egg_mode::error::Error as EggModeError;
-use egg_mode::error::TwitterErrors;
-use elefren::Error as ElefrenError;
-use elefren::Mastodon;
-use elefren::MastodonClient;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
-use std::str::FromStr;
+use std::fs;
+use std::path::Path;
+use tracing::info;
+#[cfg(feature = "twitter")]
+use {
+    crate::api_budget::{api_call, ApiCallKind},
+    crate::delete_pacing::twitter_delete_result,
+    crate::progress::{Progress, ProgressUnit},
+};
+#[cfg(feature = "mastodon")]
+use {
+    crate::mastodon::{mastodon_fetch_dates, mastodon_remove},
+    elefren::Mastodon,
+    reqwest::Method,
+    std::thread::sleep,
+};
 
 use crate::cache_file;
 use crate::config::*;
+use crate::delete_pacing::{DeletePacing, RateLimit};
+use crate::history::{history_deleted, DeletedKind, HistoryNetwork};
+use crate::run_report::run_report_deleted;
 
-// Delete old favourites of this account that are older than 90 days.
-pub fn mastodon_delete_older_favs(mastodon: &Mastodon, dry_run: bool) -> Result<()> {
+#[cfg(feature = "twitter")]
+const TWITTER_FAV_BATCH_SIZE: usize = 100;
+
+// Delete old favourites of this account that are older than 90 days and not
+// among the latest ones that are kept.
+#[cfg(feature = "mastodon")]
+pub fn mastodon_delete_older_favs(
+    mastodon: &Mastodon,
+    keep_latest: Option<usize>,
+    pacing: DeletePacing,
+    dry_run: bool,
+) -> Result<()> {
     // In order not to fetch old favs every time keep them in a cache file
     // keyed by their dates.
     let cache_file = &cache_file("mastodon_fav_cache.json");
+    let mut cursor = DeleteCursor::load(cache_file);
     let dates = mastodon_load_fav_dates(mastodon, cache_file)?;
-    let mut remove_dates = Vec::new();
-    let three_months_ago = Utc::now() - Duration::days(90);
-    for (date, toot_id) in dates.range(..three_months_ago) {
-        println!("Deleting Mastodon fav {toot_id} from {date}");
+    let delete_before = delete_favs_before(&dates, keep_latest, Utc::now());
+    let (mut remove_dates, old_favs) = cursor.split(&dates, delete_before);
+    let total = cursor.deleted + old_favs.len();
+    let mut deleted = 0;
+    for (index, (date, toot_id)) in old_favs.into_iter().enumerate() {
+        info!(
+            "Deleting Mastodon fav {toot_id} from {date} ({} of {total})",
+            cursor.deleted + index + 1
+        );
         // Do nothing on a dry run, just print what would be done.
         if dry_run {
             continue;
         }
 
-        remove_dates.push(date);
-        // The status could have been deleted already by the user, ignore API
-        // errors in that case.
-        if let Err(error) = mastodon.unfavourite(&format!("{toot_id}")) {
-            match error {
-                ElefrenError::Api(_) => {}
-                _ => return Err(error.into()),
-            }
+        if deleted > 0 {
+            sleep(pacing.delay);
+        }
+        let url = format!("{}/api/v1/statuses/{toot_id}/unfavourite", mastodon.base);
+        let (rate_limit, _) = mastodon_remove(mastodon, Method::POST, &url)?;
+        if rate_limit != RateLimit::Exceeded {
+            remove_dates.push(date);
+            deleted += 1;
+            cursor.advance(*date, cache_file)?;
+        }
+        if let Some(reason) = pacing.stop_reason(deleted, rate_limit) {
+            info!(
+                "Stopping Mastodon fav deletion because {reason}, {} of {total} old favs are left. Just run me again later!",
+                total - cursor.deleted
+            );
+            break;
         }
     }
-    remove_dates_from_cache(remove_dates, &dates, cache_file)
+    history_deleted(HistoryNetwork::Mastodon, DeletedKind::Favs, deleted);
+    run_report_deleted(HistoryNetwork::Mastodon, DeletedKind::Favs, deleted);
+    remove_dates_from_cache(remove_dates, &dates, cache_file)?;
+    DeleteCursor::clear_finished(cache_file)
 }
 
+#[cfg(feature = "mastodon")]
 fn mastodon_load_fav_dates(
     mastodon: &Mastodon,
     cache_file: &str,
@@ -50,80 +92,77 @@ fn mastodon_load_fav_dates(
     }
 }
 
+#[cfg(feature = "mastodon")]
 fn mastodon_fetch_fav_dates(
     mastodon: &Mastodon,
     cache_file: &str,
 ) -> Result<BTreeMap<DateTime<Utc>, u64>> {
-    let mut dates = BTreeMap::new();
-    let mut favourites_pager = mastodon.favourites()?;
-    for status in &favourites_pager.initial_items {
-        let id = u64::from_str(&status.id)?;
-        dates.insert(status.created_at, id);
-    }
-    loop {
-        let statuses = favourites_pager.next_page()?;
-        if let Some(statuses) = statuses {
-            for status in statuses {
-                let id = u64::from_str(&status.id)?;
-                dates.insert(status.created_at, id);
-            }
-        } else {
-            break;
-        }
-    }
-
-    save_dates_to_cache(cache_file, &dates)?;
-
-    Ok(dates)
+    let url = format!("{}/api/v1/favourites?limit=40", mastodon.base);
+    mastodon_fetch_dates(mastodon, &url, cache_file)
 }
 
-// Delete old likes of this account that are older than 90 days.
+// Delete old likes of this account that are older than 90 days and not among
+// the latest ones that are kept.
+#[cfg(feature = "twitter")]
 pub async fn twitter_delete_older_favs(
     user_id: u64,
     token: &egg_mode::Token,
+    keep_latest: Option<usize>,
+    pacing: DeletePacing,
     dry_run: bool,
 ) -> Result<()> {
+    // Only delete 100 likes in one run by default to not run into API limits
+    // or open network port limits.
+    let pacing = DeletePacing {
+        batch_size: pacing.batch_size.or(Some(TWITTER_FAV_BATCH_SIZE)),
+        ..pacing
+    };
     // In order not to fetch old likes every time keep them in a cache file
     // keyed by their dates.
     let cache_file = &cache_file("twitter_fav_cache.json");
+    let mut cursor = DeleteCursor::load(cache_file);
     let dates = twitter_load_fav_dates(user_id, token, cache_file).await?;
-    let mut remove_dates = Vec::new();
-    let three_months_ago = Utc::now() - Duration::days(90);
-    for (delete_count, (date, tweet_id)) in dates.range(..three_months_ago).enumerate() {
-        println!("Deleting Twitter fav {tweet_id} from {date}");
+    let delete_before = delete_favs_before(&dates, keep_latest, Utc::now());
+    let (mut remove_dates, old_favs) = cursor.split(&dates, delete_before);
+    let total = cursor.deleted + old_favs.len();
+    let mut deleted = 0;
+    for (index, (date, tweet_id)) in old_favs.into_iter().enumerate() {
+        info!(
+            "Deleting Twitter fav {tweet_id} from {date} ({} of {total})",
+            cursor.deleted + index + 1
+        );
         // Do nothing on a dry run, just print what would be done.
         if dry_run {
             continue;
         }
 
-        remove_dates.push(date);
-        let delete_result = egg_mode::tweet::unlike(*tweet_id, token).await;
-        // The like could have been deleted already by the user, ignore API
-        // errors in that case.
-        if let Err(EggModeError::TwitterError(headers, TwitterErrors { errors: e })) = delete_result
-        {
-            // Error 144 is "No status found with that ID".
-            if e.len() != 1 || e[0].code != 144 {
-                return Err(anyhow::Error::from(EggModeError::TwitterError(
-                    headers,
-                    TwitterErrors { errors: e },
-                )));
-            }
-        } else {
-            delete_result?;
+        if deleted > 0 {
+            tokio::time::sleep(pacing.delay).await;
         }
-        // Only delete 100 likes in one run to not run into API limits or open
-        // network port limits.
-        if delete_count == 100 {
-            println!(
-                "Stopping Twitter fav deletion to not run into API limits. Just run me again!"
+        // The like could have been deleted already by the user, error 144 is
+        // "No status found with that ID".
+        let (rate_limit, _) =
+            twitter_delete_result(egg_mode::tweet::unlike(*tweet_id, token).await, &[144])?;
+        if rate_limit != RateLimit::Exceeded {
+            remove_dates.push(date);
+            deleted += 1;
+            cursor.advance(*date, cache_file)?;
+        }
+        if let Some(reason) = pacing.stop_reason(deleted, rate_limit) {
+            info!(
+                "Stopping Twitter fav deletion because {reason}, {} of {total} old favs are left. Just run me again later!",
+                total - cursor.deleted
             );
             break;
         }
     }
-    remove_dates_from_cache(remove_dates, &dates, cache_file)
+    history_deleted(HistoryNetwork::Twitter, DeletedKind::Favs, deleted);
+    run_report_deleted(HistoryNetwork::Twitter, DeletedKind::Favs, deleted);
+    remove_dates_from_cache(remove_dates, &dates, cache_file)?;
+    DeleteCursor::clear_finished(cache_file)
 }
 
+#[cfg(feature = "twitter")]
 async fn twitter_load_fav_dates(
     user_id: u64,
     token: &egg_mode::Token,
@@ -135,6 +174,7 @@ async fn twitter_load_fav_dates(
     }
 }
 
+#[cfg(feature = "twitter")]
 async fn twitter_fetch_fav_dates(
     user_id: u64,
     token: &egg_mode::Token,
@@ -145,11 +185,14 @@ async fn twitter_fetch_fav_dates(
     let timeline = egg_mode::tweet::liked_by(user_id, token).with_page_size(200);
     let mut max_id = None;
     let mut dates = BTreeMap::new();
+    let mut bar = Progress::new("Fetching Twitter likes", None, ProgressUnit::Items("likes"));
     loop {
+        api_call(HistoryNetwork::Twitter, ApiCallKind::Read);
         let tweets = timeline.call(None, max_id).await?;
         if tweets.is_empty() {
             break;
         }
+        bar.inc(tweets.len() as u64);
         for tweet in tweets.iter() {
             dates.insert(tweet.created_at, tweet.id);
             if let Some(max) = max_id {
@@ -166,3 +209,129 @@ async fn twitter_fetch_fav_dates(
 
     Ok(dates)
 }
+
+/// Progress of the fav deletion since the cache file was fetched, kept in a
+/// cursor file next to it.
+///
+/// The cache is only updated at the end of a run, the cursor after every
+/// deleted fav. A run that fails in between resumes after the last deleted fav.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DeleteCursor {
+    // Date of the last deleted fav, older favs are deleted already.
+    last_deleted: Option<DateTime<Utc>>,
+    // Number of favs deleted since the cache file was fetched.
+    deleted: usize,
+}
+
+impl DeleteCursor {
+    // A cursor without its cache file is outdated, deleting starts over with
+    // the new cache.
+    fn load(cache_file: &str) -> DeleteCursor {
+        if !Path::new(cache_file).exists() {
+            return DeleteCursor::default();
+        }
+        fs::read_to_string(cursor_file(cache_file))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    // Records a deleted fav.
+    fn advance(&mut self, date: DateTime<Utc>, cache_file: &str) -> Result<()> {
+        self.last_deleted = Some(date);
+        self.deleted += 1;
+        fs::write(cursor_file(cache_file), serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    // Splits the favs before the date into the dates of the ones that were
+    // deleted already and the ones that are left.
+    #[allow(clippy::type_complexity)]
+    fn split<'a>(
+        &self,
+        dates: &'a BTreeMap<DateTime<Utc>, u64>,
+        before: DateTime<Utc>,
+    ) -> (Vec<&'a DateTime<Utc>>, Vec<(&'a DateTime<Utc>, &'a u64)>) {
+        let (deleted, left): (Vec<_>, Vec<_>) = dates
+            .range(..before)
+            .partition(|(date, _)| self.last_deleted.is_some_and(|last| **date <= last));
+        (deleted.into_iter().map(|(date, _)| date).collect(), left)
+    }
+
+    // Removes the cursor once all favs of the cache file are deleted.
+    fn clear_finished(cache_file: &str) -> Result<()> {
+        let cursor_file = cursor_file(cache_file);
+        if !Path::new(cache_file).exists() && Path::new(&cursor_file).exists() {
+            fs::remove_file(cursor_file)?;
+        }
+        Ok(())
+    }
+}
+
+fn cursor_file(cache_file: &str) -> String {
+    format!("{cache_file}.cursor")
+}
+
+// Returns the date before which favs are deleted. They are older than 90 days
+// and the latest keep_latest favs are always kept, regardless of their age.
+fn delete_favs_before(
+    dates: &BTreeMap<DateTime<Utc>, u64>,
+    keep_latest: Option<usize>,
+    now: DateTime<Utc>,
+) -> DateTime<Utc> {
+    let three_months_ago = now - Duration::days(90);
+    match keep_latest.filter(|keep| *keep > 0) {
+        Some(keep) => match dates.keys().rev().nth(keep - 1) {
+            Some(oldest_kept) => *oldest_kept.min(&three_months_ago),
+            // There are not more favs than should be kept, nothing is deleted.
+            None => dates.keys().next().copied().unwrap_or(three_months_ago),
+        },
+        None => three_months_ago,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_latest_favs() {
+        let now = Utc.with_ymd_and_hms(2022, 10, 1, 12, 0, 0).unwrap();
+        let dates: BTreeMap<_, _> = (1..=5)
+            .map(|months| (now - Duration::days(months * 30 + 10), months as u64))
+            .collect();
+        let deleted = |keep_latest| {
+            let before = delete_favs_before(&dates, keep_latest, now);
+            dates.range(..before).map(|(_, id)| *id).collect::<Vec<_>>()
+        };
+        // Favs older than 90 days.
+        assert_eq!(deleted(None), vec![5, 4, 3]);
+        assert_eq!(deleted(Some(0)), vec![5, 4, 3]);
+        // The latest favs are kept even if they are old.
+        assert_eq!(deleted(Some(3)), vec![5, 4]);
+        assert_eq!(deleted(Some(5)), Vec::<u64>::new());
+        assert_eq!(deleted(Some(200)), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn resume_after_cursor() {
+        let now = Utc.with_ymd_and_hms(2022, 10, 1, 12, 0, 0).unwrap();
+        let dates: BTreeMap<_, _> = (1..=3)
+            .map(|days| (now - Duration::days(days), days as u64))
+            .collect();
+        let cursor = DeleteCursor {
+            last_deleted: Some(now - Duration::days(2)),
+            deleted: 2,
+        };
+        let (deleted, left) = cursor.split(&dates, now);
+        assert_eq!(
+            deleted,
+            vec![&(now - Duration::days(3)), &(now - Duration::days(2))]
+        );
+        assert_eq!(left, vec![(&(now - Duration::days(1)), &1)]);
+
+        let (deleted, left) = DeleteCursor::default().split(&dates, now);
+        assert!(deleted.is_empty());
+        assert_eq!(left.len(), 3);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::shutdown::shutdown_requested;
+use std::time::Duration;
+#[cfg(feature = "twitter")]
+use {
+    crate::api_budget::{api_budget_left, api_call, ApiCallKind},
+    crate::history::HistoryNetwork,
+    anyhow::Result,
+    egg_mode::error::Error as EggModeError,
+    egg_mode::error::TwitterErrors,
+    egg_mode::Response,
+};
+
+/// Pacing of the deletion of old statuses and favourites, so that deleting a
+/// long history does not run into API rate limits.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeletePacing {
+    /// Maximum number of deletions in one run, the rest is deleted in the next
+    /// runs.
+    pub batch_size: Option<usize>,
+    /// Pause between two delete calls.
+    pub delay: Duration,
+}
+
+/// The API rate limit after a delete call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimit {
+    /// The call went through, with the number of calls left until the rate
+    /// limit is reset if the response tells it.
+    Remaining(Option<i64>),
+    /// The call was rejected because the rate limit is exceeded.
+    Exceeded,
+    /// The call went through, but used up the monthly API budget.
+    BudgetUsedUp,
+}
+
+impl DeletePacing {
+    pub fn new(batch_size: Option<usize>, delay_ms: u64) -> DeletePacing {
+        DeletePacing {
+            batch_size,
+            delay: Duration::from_millis(delay_ms),
+        }
+    }
+
+    /// Returns why the deletion stops after the given number of deletions and
+    /// the rate limit of the last call, None to go on.
+    ///
+    /// Entries that are not deleted stay in the cache file, so the next run
+    /// resumes where this one stopped.
+    pub fn stop_reason(&self, deleted: usize, rate_limit: RateLimit) -> Option<&'static str> {
+        match rate_limit {
+            RateLimit::Exceeded => Some("the API rate limit is exceeded"),
+            RateLimit::BudgetUsedUp => Some("the monthly API budget is used up"),
+            RateLimit::Remaining(Some(remaining)) if remaining <= 0 => {
+                Some("no API calls are left until the rate limit is reset")
+            }
+            _ if shutdown_requested() => Some("the program is shutting down"),
+            _ if self.batch_size.is_some_and(|size| deleted >= size) => {
+                Some("the batch size is reached")
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Returns the rate limit of a Twitter delete call and the deleted entity.
+///
+/// The status could have been deleted already by the user, Twitter errors with
+/// one of the ignored codes are fine for us.
+#[cfg(feature = "twitter")]
+pub(crate) fn twitter_delete_result<T>(
+    result: std::result::Result<Response<T>, EggModeError>,
+    ignored_codes: &[i32],
+) -> Result<(RateLimit, Option<T>)> {
+    api_call(HistoryNetwork::Twitter, ApiCallKind::Write);
+    match result {
+        Ok(response) if !api_budget_left(HistoryNetwork::Twitter, ApiCallKind::Write) => {
+            Ok((RateLimit::BudgetUsedUp, Some(response.response)))
+        }
+        // egg-mode reports -1 if the response has no rate limit headers.
+        Ok(response) => Ok((
+            RateLimit::Remaining(
+                Some(response.rate_limit_status.remaining.into())
+                    .filter(|remaining| *remaining >= 0),
+            ),
+            Some(response.response),
+        )),
+        Err(EggModeError::RateLimit(_)) => Ok((RateLimit::Exceeded, None)),
+        Err(EggModeError::TwitterError(headers, TwitterErrors { errors: e })) => {
+            // Error 88 is "Rate limit exceeded".
+            if e.len() == 1 && e[0].code == 88 {
+                Ok((RateLimit::Exceeded, None))
+            } else if e.len() == 1 && ignored_codes.contains(&e[0].code) {
+                Ok((RateLimit::Remaining(None), None))
+            } else {
+                Err(EggModeError::TwitterError(headers, TwitterErrors { errors: e }).into())
+            }
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stop_deletion() {
+        let pacing = DeletePacing::new(Some(2), 0);
+        assert_eq!(pacing.stop_reason(1, RateLimit::Remaining(None)), None);
+        assert_eq!(
+            pacing.stop_reason(2, RateLimit::Remaining(Some(100))),
+            Some("the batch size is reached")
+        );
+        assert_eq!(
+            pacing.stop_reason(1, RateLimit::Remaining(Some(0))),
+            Some("no API calls are left until the rate limit is reset")
+        );
+        assert_eq!(
+            pacing.stop_reason(0, RateLimit::Exceeded),
+            Some("the API rate limit is exceeded")
+        );
+        assert_eq!(
+            pacing.stop_reason(0, RateLimit::BudgetUsedUp),
+            Some("the monthly API budget is used up")
+        );
+
+        // Without a batch size only the rate limit stops the deletion.
+        let pacing = DeletePacing::default();
+        assert_eq!(
+            pacing.stop_reason(1000, RateLimit::Remaining(Some(5))),
+            None
+        );
+    }
+}
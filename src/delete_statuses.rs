@@ -1,50 +1,92 @@
 use anyhow::Result;
 use chrono::prelude::*;
 use chrono::Duration;
-use egg_mode::error::Error as EggModeError;
-use egg_mode::error::TwitterErrors;
-use elefren::entities::account::Account;
-use elefren::Error as ElefrenError;
-use elefren::Mastodon;
-use elefren::MastodonClient;
 use std::collections::BTreeMap;
-use std::str::FromStr;
+use tracing::info;
+#[cfg(feature = "twitter")]
+use {
+    crate::api_budget::{api_call, ApiCallKind},
+    crate::delete_pacing::twitter_delete_result,
+    crate::progress::{Progress, ProgressUnit},
+    egg_mode::tweet::Tweet,
+    std::collections::BTreeSet,
+    tracing::debug,
+};
+#[cfg(feature = "mastodon")]
+use {
+    crate::mastodon::{mastodon_fetch_dates, mastodon_remove},
+    elefren::entities::account::Account,
+    elefren::Mastodon,
+    reqwest::Method,
+    std::thread::sleep,
+};
 
 use crate::cache_file;
 use crate::config::*;
+use crate::delete_pacing::{DeletePacing, RateLimit};
+use crate::history::{history_deleted, DeletedKind, HistoryNetwork};
+use crate::run_report::run_report_deleted;
+
+/// A status that was deleted because it was old, to remember that it was
+/// deleted on purpose.
+#[derive(Debug, Clone)]
+pub struct DeletedStatus {
+    pub id: u64,
+    // The text of the status if the API returned it.
+    pub text: Option<String>,
+}
 
 // Delete old statuses of this account that are older than 90 days.
+#[cfg(feature = "mastodon")]
 pub fn mastodon_delete_older_statuses(
     mastodon: &Mastodon,
     account: &Account,
+    pacing: DeletePacing,
     dry_run: bool,
-) -> Result<()> {
+) -> Result<Vec<DeletedStatus>> {
     // In order not to fetch old toots every time keep them in a cache file
     // keyed by their dates.
     let cache_file = &cache_file("mastodon_cache.json");
     let dates = mastodon_load_toot_dates(mastodon, account, cache_file)?;
     let mut remove_dates = Vec::new();
+    let mut deleted = Vec::new();
     let three_months_ago = Utc::now() - Duration::days(90);
     for (date, toot_id) in dates.range(..three_months_ago) {
-        println!("Deleting toot {toot_id} from {date}");
+        info!("Deleting toot {toot_id} from {date}");
         // Do nothing on a dry run, just print what would be done.
         if dry_run {
             continue;
         }
 
-        remove_dates.push(date);
-        // The status could have been deleted already by the user, ignore API
-        // errors in that case.
-        if let Err(error) = mastodon.delete_status(&format!("{toot_id}")) {
-            match error {
-                ElefrenError::Api(_) => {}
-                _ => return Err(error.into()),
-            }
+        if !remove_dates.is_empty() {
+            sleep(pacing.delay);
+        }
+        let url = format!("{}/api/v1/statuses/{toot_id}", mastodon.base);
+        let (rate_limit, text) = mastodon_remove(mastodon, Method::DELETE, &url)?;
+        if rate_limit != RateLimit::Exceeded {
+            remove_dates.push(date);
+            deleted.push(DeletedStatus { id: *toot_id, text });
+        }
+        if let Some(reason) = pacing.stop_reason(remove_dates.len(), rate_limit) {
+            info!("Stopping Mastodon status deletion because {reason}. Just run me again later!");
+            break;
         }
     }
-    remove_dates_from_cache(remove_dates, &dates, cache_file)
+    history_deleted(
+        HistoryNetwork::Mastodon,
+        DeletedKind::Statuses,
+        remove_dates.len(),
+    );
+    run_report_deleted(
+        HistoryNetwork::Mastodon,
+        DeletedKind::Statuses,
+        remove_dates.len(),
+    );
+    remove_dates_from_cache(remove_dates, &dates, cache_file)?;
+    Ok(deleted)
 }
 
+#[cfg(feature = "mastodon")]
 fn mastodon_load_toot_dates(
     mastodon: &Mastodon,
     account: &Account,
@@ -56,75 +98,99 @@ fn mastodon_load_toot_dates(
     }
 }
 
+#[cfg(feature = "mastodon")]
 fn mastodon_fetch_toot_dates(
     mastodon: &Mastodon,
     account: &Account,
     cache_file: &str,
 ) -> Result<BTreeMap<DateTime<Utc>, u64>> {
-    let mut dates = BTreeMap::new();
-    let mut pager = mastodon.statuses(&account.id, None)?;
-    for status in &pager.initial_items {
-        let id = u64::from_str(&status.id)?;
-        dates.insert(status.created_at, id);
-    }
-    loop {
-        let statuses = pager.next_page()?;
-        if let Some(statuses) = statuses {
-            for status in statuses {
-                let id = u64::from_str(&status.id)?;
-                dates.insert(status.created_at, id);
-            }
-        } else {
-            break;
-        }
-    }
-
-    save_dates_to_cache(cache_file, &dates)?;
-
-    Ok(dates)
+    let url = format!(
+        "{}/api/v1/accounts/{}/statuses?limit=40",
+        mastodon.base, account.id
+    );
+    mastodon_fetch_dates(mastodon, &url, cache_file)
 }
 
-// Delete old statuses of this account that are older than 90 days.
+// Delete old statuses of this account that are older than 90 days. If
+// synced_tweets is given only those tweets are deleted.
+#[cfg(feature = "twitter")]
 pub async fn twitter_delete_older_statuses(
     user_id: u64,
     token: &egg_mode::Token,
+    synced_tweets: Option<&BTreeSet<u64>>,
+    pacing: DeletePacing,
     dry_run: bool,
-) -> Result<()> {
+) -> Result<Vec<DeletedStatus>> {
     // In order not to fetch old toots every time keep them in a cache file
     // keyed by their dates.
     let cache_file = &cache_file("twitter_cache.json");
     let dates = twitter_load_tweet_dates(user_id, token, cache_file).await?;
     let mut remove_dates = Vec::new();
+    let mut deleted = Vec::new();
     let three_months_ago = Utc::now() - Duration::days(90);
     for (date, tweet_id) in dates.range(..three_months_ago) {
-        println!("Deleting tweet {tweet_id} from {date}");
+        if let Some(synced_tweets) = synced_tweets {
+            if !synced_tweets.contains(tweet_id) {
+                debug!("Keeping tweet {tweet_id} from {date}, it was not synced from Mastodon");
+                continue;
+            }
+        }
+        info!("Deleting tweet {tweet_id} from {date}");
         // Do nothing on a dry run, just print what would be done.
         if dry_run {
             continue;
         }
 
-        remove_dates.push(date);
-        let delete_result = egg_mode::tweet::delete(*tweet_id, token).await;
-        // The status could have been deleted already by the user, ignore API
-        // errors in that case.
-        if let Err(EggModeError::TwitterError(headers, TwitterErrors { errors: e })) = delete_result
-        {
-            // Error 144 is "No status found with that ID".
-            // Error 63 is "User has been suspended".
-            // Error 179 is "Sorry, you are not authorized to see this status".
-            if e.len() != 1 || (e[0].code != 144 && e[0].code != 63 && e[0].code != 179) {
-                return Err(anyhow::Error::from(EggModeError::TwitterError(
-                    headers,
-                    TwitterErrors { errors: e },
-                )));
-            }
-        } else {
-            delete_result?;
+        if !remove_dates.is_empty() {
+            tokio::time::sleep(pacing.delay).await;
+        }
+        // Error 144 is "No status found with that ID".
+        // Error 63 is "User has been suspended".
+        // Error 179 is "Sorry, you are not authorized to see this status".
+        let (rate_limit, tweet) = twitter_delete_result(
+            egg_mode::tweet::delete(*tweet_id, token).await,
+            &[144, 63, 179],
+        )?;
+        if rate_limit != RateLimit::Exceeded {
+            remove_dates.push(date);
+            deleted.push(DeletedStatus {
+                id: *tweet_id,
+                text: tweet.as_ref().map(tweet_expanded_text),
+            });
+        }
+        if let Some(reason) = pacing.stop_reason(remove_dates.len(), rate_limit) {
+            info!("Stopping Twitter status deletion because {reason}. Just run me again later!");
+            break;
         }
     }
-    remove_dates_from_cache(remove_dates, &dates, cache_file)
+    history_deleted(
+        HistoryNetwork::Twitter,
+        DeletedKind::Statuses,
+        remove_dates.len(),
+    );
+    run_report_deleted(
+        HistoryNetwork::Twitter,
+        DeletedKind::Statuses,
+        remove_dates.len(),
+    );
+    remove_dates_from_cache(remove_dates, &dates, cache_file)?;
+    Ok(deleted)
+}
+
+// Replaces the t.co links in the text of a tweet with the original URLs.
+#[cfg(feature = "twitter")]
+fn tweet_expanded_text(tweet: &Tweet) -> String {
+    tweet
+        .entities
+        .urls
+        .iter()
+        .fold(tweet.text.clone(), |text, url| match &url.expanded_url {
+            Some(expanded_url) => text.replace(&url.url, expanded_url),
+            None => text,
+        })
 }
 
+#[cfg(feature = "twitter")]
 async fn twitter_load_tweet_dates(
     user_id: u64,
     token: &egg_mode::Token,
@@ -136,6 +202,7 @@ async fn twitter_load_tweet_dates(
     }
 }
 
+#[cfg(feature = "twitter")]
 async fn twitter_fetch_tweet_dates(
     user_id: u64,
     token: &egg_mode::Token,
@@ -146,11 +213,14 @@ async fn twitter_fetch_tweet_dates(
     let timeline = egg_mode::tweet::user_timeline(user_id, true, true, token).with_page_size(200);
     let mut max_id = None;
     let mut dates = BTreeMap::new();
+    let mut bar = Progress::new("Fetching tweets", None, ProgressUnit::Items("tweets"));
     loop {
+        api_call(HistoryNetwork::Twitter, ApiCallKind::Read);
         let tweets = timeline.call(None, max_id).await?;
         if tweets.is_empty() {
             break;
         }
+        bar.inc(tweets.len() as u64);
         for tweet in tweets.iter() {
             dates.insert(tweet.created_at, tweet.id);
             if let Some(max) = max_id {
@@ -0,0 +1,199 @@
+use anyhow::Result;
+use chrono::prelude::*;
+use elefren::entities::account::Account;
+use elefren::Mastodon;
+use std::fs;
+use tracing::info;
+
+use crate::cache_file;
+use crate::config::ReplyVisibility;
+use crate::history::{history_load, HistoryEntry, HistoryEvent, HistoryNetwork};
+use crate::media::MediaOptions;
+use crate::post::post_to_mastodon;
+use crate::sync::{NewStatus, StatusId};
+
+pub const DEFAULT_DIGEST_TEMPLATE: &str =
+    "In {month} I published {count} posts, latest post: {last_post}";
+
+// Posts a summary of the previous month to Mastodon, once per month. From
+// there it is synced to Twitter like any other status.
+//
+// The summary is built from the sync history, like the stats command, so it
+// needs no API calls.
+pub fn mastodon_post_monthly_digest(
+    mastodon: &Mastodon,
+    account: &Account,
+    template: &str,
+    dry_run: bool,
+) -> Result<()> {
+    let (start, end) = previous_month(Utc::now());
+    let month_key = start.format("%Y-%m").to_string();
+    // Remember the last month we posted a digest for.
+    let cache_file = &cache_file("digest_cache.json");
+    if let Ok(last_month) = fs::read_to_string(cache_file) {
+        if last_month.trim() == month_key {
+            return Ok(());
+        }
+    }
+
+    let history = history_load()?;
+    if let Some(text) = digest_text(&history, start, end, template, &account.url) {
+        info!("Posting monthly digest for {month_key}");
+        post_to_mastodon(
+            mastodon,
+            &NewStatus::new(text, StatusId::Number(0)),
+            &MediaOptions::default(),
+            ReplyVisibility::Public,
+            dry_run,
+        )?;
+    }
+    if !dry_run {
+        fs::write(cache_file, month_key)?;
+    }
+    Ok(())
+}
+
+// Returns start and end of the month before the given date.
+fn previous_month(now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+    let end = Utc
+        .with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .unwrap();
+    let (year, month) = if now.month() == 1 {
+        (now.year() - 1, 12)
+    } else {
+        (now.year(), now.month() - 1)
+    };
+    let start = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).unwrap();
+    (start, end)
+}
+
+// Fills the digest template with the number of synced posts in the given time
+// span and a link to the latest one, preferably on Mastodon. History entries
+// of older versions have no links, then the profile is linked.
+fn digest_text(
+    history: &[HistoryEntry],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    template: &str,
+    profile_url: &str,
+) -> Option<String> {
+    let posts: Vec<(HistoryNetwork, Option<&str>)> = history
+        .iter()
+        .filter(|entry| (start.timestamp()..end.timestamp()).contains(&entry.time))
+        .filter_map(|entry| match &entry.event {
+            HistoryEvent::Posted { target, url, .. } => Some((*target, url.as_deref())),
+            _ => None,
+        })
+        .collect();
+    if posts.is_empty() {
+        return None;
+    }
+    let latest = |network: Option<HistoryNetwork>| {
+        posts
+            .iter()
+            .rev()
+            .filter(|(target, _)| network.is_none_or(|network| *target == network))
+            .find_map(|(_, url)| *url)
+    };
+    let last_post = latest(Some(HistoryNetwork::Mastodon))
+        .or_else(|| latest(None))
+        .unwrap_or(profile_url);
+
+    // {top_post} is the name of older templates.
+    Some(
+        template
+            .replace("{month}", &start.format("%B %Y").to_string())
+            .replace("{count}", &posts.len().to_string())
+            .replace("{last_post}", last_post)
+            .replace("{top_post}", last_post),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn posted(time: DateTime<Utc>, target: HistoryNetwork, url: Option<&str>) -> HistoryEntry {
+        HistoryEntry {
+            time: time.timestamp(),
+            event: HistoryEvent::Posted {
+                target,
+                delay: None,
+                url: url.map(str::to_string),
+            },
+        }
+    }
+
+    // Test the digest text of a month with three synced posts.
+    #[test]
+    fn monthly_digest_text() {
+        let (start, end) = previous_month(Utc.with_ymd_and_hms(2023, 1, 15, 0, 0, 0).unwrap());
+        assert_eq!(start, Utc.with_ymd_and_hms(2022, 12, 1, 0, 0, 0).unwrap());
+
+        let december = Utc.with_ymd_and_hms(2022, 12, 2, 0, 0, 0).unwrap();
+        let history = vec![
+            posted(
+                Utc.with_ymd_and_hms(2022, 11, 30, 0, 0, 0).unwrap(),
+                HistoryNetwork::Mastodon,
+                Some("https://example.com/@example/1"),
+            ),
+            posted(
+                december,
+                HistoryNetwork::Mastodon,
+                Some("https://example.com/@example/2"),
+            ),
+            posted(
+                december,
+                HistoryNetwork::Twitter,
+                Some("https://twitter.com/example/status/3"),
+            ),
+            HistoryEntry {
+                time: december.timestamp(),
+                event: HistoryEvent::Failed {
+                    target: HistoryNetwork::Mastodon,
+                    reason: "Mastodon API request failed".to_string(),
+                },
+            },
+            posted(december, HistoryNetwork::Twitter, None),
+            posted(
+                Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap(),
+                HistoryNetwork::Mastodon,
+                Some("https://example.com/@example/4"),
+            ),
+        ];
+        let text = digest_text(
+            &history,
+            start,
+            end,
+            DEFAULT_DIGEST_TEMPLATE,
+            "https://example.com/@example",
+        );
+        assert_eq!(
+            text,
+            Some(
+                "In December 2022 I published 3 posts, latest post: https://example.com/@example/2"
+                    .to_string()
+            )
+        );
+
+        // Older templates and history entries without links.
+        let history = vec![posted(december, HistoryNetwork::Twitter, None)];
+        let text = digest_text(
+            &history,
+            start,
+            end,
+            "{count} posts, top post: {top_post}",
+            "https://example.com/@example",
+        );
+        assert_eq!(
+            text.as_deref(),
+            Some("1 posts, top post: https://example.com/@example")
+        );
+
+        assert_eq!(
+            digest_text(&[], start, end, DEFAULT_DIGEST_TEMPLATE, ""),
+            None
+        );
+    }
+}
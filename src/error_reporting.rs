@@ -0,0 +1,75 @@
+use crate::config::SentryConfig;
+use crate::sync::StatusId;
+#[cfg(not(feature = "sentry"))]
+use tracing::warn;
+#[cfg(feature = "sentry")]
+use {
+    sentry::{add_breadcrumb, Breadcrumb, ClientInitGuard, ClientOptions, Hub},
+    std::sync::Arc,
+};
+
+/// Reports panics and failed runs to Sentry as long as it is alive. Events
+/// still in the queue are sent when it is dropped.
+pub struct ErrorReporting {
+    #[cfg(feature = "sentry")]
+    _guard: Option<ClientInitGuard>,
+}
+
+/// Starts error reporting if a Sentry DSN is configured.
+#[cfg(feature = "sentry")]
+pub fn error_reporting_init(config: Option<&SentryConfig>) -> ErrorReporting {
+    let guard = config.map(|config| {
+        sentry::init((
+            config.dsn.as_str(),
+            ClientOptions {
+                release: sentry::release_name!(),
+                environment: config.environment.clone().map(Into::into),
+                ..ClientOptions::default()
+            },
+        ))
+    });
+    ErrorReporting { _guard: guard }
+}
+
+#[cfg(not(feature = "sentry"))]
+pub fn error_reporting_init(config: Option<&SentryConfig>) -> ErrorReporting {
+    if config.is_some() {
+        warn!("Sentry is configured, but the sentry feature is disabled");
+    }
+    ErrorReporting {}
+}
+
+/// Wraps the work of a spawned thread, so that it reports to a copy of the
+/// Sentry hub of the spawning thread. New threads have an own hub without a
+/// client otherwise, their panics and breadcrumbs would be lost.
+#[cfg(feature = "sentry")]
+pub fn bind_hub<R>(f: impl FnOnce() -> R) -> impl FnOnce() -> R {
+    let hub = Arc::new(Hub::new_from_top(Hub::current()));
+    move || Hub::run(hub, f)
+}
+
+#[cfg(not(feature = "sentry"))]
+pub fn bind_hub<R>(f: impl FnOnce() -> R) -> impl FnOnce() -> R {
+    f
+}
+
+/// Sends the error of a failed run with all its causes.
+pub fn report_error(error: &anyhow::Error) {
+    #[cfg(feature = "sentry")]
+    sentry::integrations::anyhow::capture_anyhow(error);
+    #[cfg(not(feature = "sentry"))]
+    let _ = error;
+}
+
+/// Remembers which post is processed, so that a later error report shows
+/// what the run was doing.
+pub fn post_breadcrumb(network: &str, original_id: &StatusId) {
+    #[cfg(feature = "sentry")]
+    add_breadcrumb(Breadcrumb {
+        category: Some("post".into()),
+        message: Some(format!("Posting source status {original_id} to {network}")),
+        ..Breadcrumb::default()
+    });
+    #[cfg(not(feature = "sentry"))]
+    let _ = (network, original_id);
+}
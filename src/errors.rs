@@ -0,0 +1,31 @@
+use thiserror::Error;
+
+/// Errors of this crate that callers might want to handle differently.
+///
+/// They are passed around wrapped in an anyhow::Error like all other errors,
+/// use `error.downcast_ref::<SyncError>()` to match on them.
+#[derive(Debug, Error)]
+pub enum SyncError {
+    #[error("Invalid config file")]
+    Config(#[from] toml::de::Error),
+    #[cfg(feature = "twitter")]
+    #[error("Twitter API request failed")]
+    TwitterApi(#[from] egg_mode::error::Error),
+    #[cfg(feature = "mastodon")]
+    #[error("Mastodon API request failed")]
+    MastodonApi(#[from] elefren::Error),
+    #[error(
+        "Your Mastodon account on {instance} is gone. It was deleted, suspended or moved to \
+        another instance. Start with a new config file to register your new account."
+    )]
+    MastodonAccountGone { instance: String },
+    #[error("Mastodon account has moved to {acct}, refusing to sync with the old account")]
+    MastodonAccountMoved { acct: String },
+    #[error("Media attachment {url} failed: {reason}")]
+    Media { url: String, reason: String },
+    #[error("Cache file {file} is broken")]
+    Cache {
+        file: String,
+        source: serde_json::Error,
+    },
+}
@@ -0,0 +1,397 @@
+use crate::config::FilterConfig;
+use crate::sync::{text_hashtags, NewStatus};
+use crate::transform::run_status_command;
+use anyhow::{Context, Result};
+use regex::{Captures, Regex};
+use std::collections::BTreeMap;
+use tracing::{error, info};
+
+/// A stage of the filter pipeline. It can change a status or reject it by
+/// returning None.
+pub trait Filter {
+    fn name(&self) -> &str;
+    fn apply(&self, status: NewStatus) -> Result<Option<NewStatus>>;
+}
+
+/// Filters that are applied in order to all new statuses of one direction.
+#[derive(Default)]
+pub struct FilterPipeline {
+    stages: Vec<Box<dyn Filter>>,
+}
+
+impl FilterPipeline {
+    pub fn from_config(configs: &[FilterConfig]) -> Result<FilterPipeline> {
+        let mut pipeline = FilterPipeline::default();
+        for config in configs {
+            let stage: Box<dyn Filter> = match config {
+                FilterConfig::Hashtag { hashtag } => Box::new(HashtagFilter {
+                    hashtag: hashtag.trim_start_matches('#').to_lowercase(),
+                }),
+                FilterConfig::Regex {
+                    pattern,
+                    replacement,
+                } => Box::new(RegexFilter {
+                    regex: Regex::new(pattern)
+                        .with_context(|| format!("Invalid filter regex {pattern}"))?,
+                    replacement: replacement.clone(),
+                }),
+                FilterConfig::Mentions { map } => Box::new(MentionFilter::new(map)),
+                FilterConfig::HashtagMap { map, append } => {
+                    Box::new(HashtagMapFilter::new(map, append))
+                }
+                FilterConfig::Command { cmd } => Box::new(CommandFilter { cmd: cmd.clone() }),
+            };
+            pipeline.stages.push(stage);
+        }
+        Ok(pipeline)
+    }
+
+    /// Adds a custom stage at the end of the pipeline.
+    pub fn add(&mut self, stage: Box<dyn Filter>) {
+        self.stages.push(stage);
+    }
+
+    /// Runs all statuses and their thread replies through the pipeline.
+    /// Statuses that are rejected or where a stage fails are left out, the
+    /// replies of a left out status too.
+    pub fn apply(&self, statuses: Vec<NewStatus>) -> Vec<NewStatus> {
+        self.apply_rejected(statuses, &mut Vec::new())
+    }
+
+    /// Like apply(), the left out statuses are added to rejected.
+    pub fn apply_rejected(
+        &self,
+        statuses: Vec<NewStatus>,
+        rejected: &mut Vec<NewStatus>,
+    ) -> Vec<NewStatus> {
+        if self.stages.is_empty() {
+            return statuses;
+        }
+        let mut filtered = Vec::new();
+        'statuses: for mut status in statuses {
+            for stage in &self.stages {
+                let original = status.clone();
+                match stage.apply(status) {
+                    Ok(Some(changed)) => status = changed,
+                    Ok(None) => {
+                        info!("Filter {} rejected status: {}", stage.name(), original.text);
+                        rejected.push(original);
+                        continue 'statuses;
+                    }
+                    Err(e) => {
+                        error!(
+                            "Filter {} failed, skipping status: {} {e:#}",
+                            stage.name(),
+                            original.text
+                        );
+                        rejected.push(original);
+                        continue 'statuses;
+                    }
+                }
+            }
+            status.replies = self.apply_rejected(status.replies, rejected);
+            filtered.push(status);
+        }
+        filtered
+    }
+}
+
+/// Only keeps statuses that contain the hashtag.
+struct HashtagFilter {
+    // Lowercase without "#".
+    hashtag: String,
+}
+
+impl Filter for HashtagFilter {
+    fn name(&self) -> &str {
+        "hashtag"
+    }
+
+    fn apply(&self, status: NewStatus) -> Result<Option<NewStatus>> {
+        if text_hashtags(&status.text).contains(&self.hashtag) {
+            Ok(Some(status))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Replaces matches of the regex in the text, or rejects statuses that match
+/// if there is no replacement.
+struct RegexFilter {
+    regex: Regex,
+    replacement: Option<String>,
+}
+
+impl Filter for RegexFilter {
+    fn name(&self) -> &str {
+        "regex"
+    }
+
+    fn apply(&self, mut status: NewStatus) -> Result<Option<NewStatus>> {
+        match &self.replacement {
+            Some(replacement) => {
+                status.text = self
+                    .regex
+                    .replace_all(&status.text, replacement.as_str())
+                    .into_owned();
+                Ok(Some(status))
+            }
+            None if self.regex.is_match(&status.text) => Ok(None),
+            None => Ok(Some(status)),
+        }
+    }
+}
+
+/// Replaces mentions of accounts with their account on the other network.
+struct MentionFilter {
+    // Lower case user names without the leading @ mapped to the new mention.
+    map: BTreeMap<String, String>,
+    regex: Regex,
+}
+
+impl MentionFilter {
+    fn new(map: &BTreeMap<String, String>) -> MentionFilter {
+        MentionFilter {
+            map: map
+                .iter()
+                .map(|(from, to)| (from.trim_start_matches('@').to_lowercase(), to.clone()))
+                .collect(),
+            // A mention is not part of a word, so that email addresses do not
+            // match.
+            regex: Regex::new(r"(^|[^\w])@(\w+(?:@[\w.-]*\w)?)").unwrap(),
+        }
+    }
+}
+
+impl Filter for MentionFilter {
+    fn name(&self) -> &str {
+        "mentions"
+    }
+
+    fn apply(&self, mut status: NewStatus) -> Result<Option<NewStatus>> {
+        status.text = self
+            .regex
+            .replace_all(&status.text, |caps: &Captures| {
+                match self.map.get(&caps[2].to_lowercase()) {
+                    Some(to) => format!("{}{}", &caps[1], to),
+                    None => caps[0].to_string(),
+                }
+            })
+            .into_owned();
+        Ok(Some(status))
+    }
+}
+
+/// Replaces hashtags with the ones of the other network and appends hashtags
+/// that are only used there.
+struct HashtagMapFilter {
+    // Lower case hashtags without the leading # mapped to the new hashtag.
+    map: BTreeMap<String, String>,
+    append: Vec<String>,
+    regex: Regex,
+}
+
+impl HashtagMapFilter {
+    fn new(map: &BTreeMap<String, String>, append: &[String]) -> HashtagMapFilter {
+        HashtagMapFilter {
+            map: map
+                .iter()
+                .map(|(from, to)| (hashtag_name(from), format!("#{}", hashtag_name(to))))
+                .collect(),
+            append: append
+                .iter()
+                .map(|hashtag| format!("#{}", hashtag.trim_start_matches('#')))
+                .collect(),
+            // A "#" inside of a word or URL does not start a hashtag.
+            regex: Regex::new(r"(^|[^\w&/])#(\w+)").unwrap(),
+        }
+    }
+}
+
+impl Filter for HashtagMapFilter {
+    fn name(&self) -> &str {
+        "hashtag_map"
+    }
+
+    fn apply(&self, mut status: NewStatus) -> Result<Option<NewStatus>> {
+        status.text = self
+            .regex
+            .replace_all(&status.text, |caps: &Captures| {
+                match self.map.get(&caps[2].to_lowercase()) {
+                    Some(to) => format!("{}{}", &caps[1], to),
+                    None => caps[0].to_string(),
+                }
+            })
+            .into_owned();
+        let existing = text_hashtags(&status.text);
+        let missing: Vec<&str> = self
+            .append
+            .iter()
+            .filter(|hashtag| !existing.contains(&hashtag_name(hashtag)))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            status.text = format!("{}\n\n{}", status.text.trim_end(), missing.join(" "));
+        }
+        Ok(Some(status))
+    }
+}
+
+/// Returns the hashtags that hashtag_map filters replace as pairs and the
+/// hashtags that they append, in lower case without "#". They are ignored when
+/// comparing statuses.
+pub fn hashtag_map_changes(configs: &[FilterConfig]) -> (Vec<(String, String)>, Vec<String>) {
+    let mut aliases = Vec::new();
+    let mut appended = Vec::new();
+    for config in configs {
+        if let FilterConfig::HashtagMap { map, append } = config {
+            aliases.extend(
+                map.iter()
+                    .map(|(from, to)| (hashtag_name(from), hashtag_name(to))),
+            );
+            appended.extend(append.iter().map(|hashtag| hashtag_name(hashtag)));
+        }
+    }
+    (aliases, appended)
+}
+
+/// Returns the hashtag in lower case without the leading "#", the form that
+/// hashtags are compared in.
+pub fn hashtag_name(hashtag: &str) -> String {
+    hashtag.trim_start_matches('#').to_lowercase()
+}
+
+/// Runs an external command with the status as JSON on stdin. The command
+/// prints the changed status as JSON, or nothing to reject it.
+struct CommandFilter {
+    cmd: String,
+}
+
+impl Filter for CommandFilter {
+    fn name(&self) -> &str {
+        &self.cmd
+    }
+
+    fn apply(&self, status: NewStatus) -> Result<Option<NewStatus>> {
+        let output = run_status_command(&self.cmd, &status)?;
+        if output.iter().all(|b| b.is_ascii_whitespace()) {
+            return Ok(None);
+        }
+        let status = serde_json::from_slice(&output)
+            .with_context(|| format!("Filter command {} returned invalid JSON", self.cmd))?;
+        Ok(Some(status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::tests::new_status;
+
+    fn pipeline(configs: Vec<FilterConfig>) -> FilterPipeline {
+        FilterPipeline::from_config(&configs).unwrap()
+    }
+
+    #[test]
+    fn hashtag_filter() {
+        let pipeline = pipeline(vec![FilterConfig::Hashtag {
+            hashtag: "#Sync".to_string(),
+        }]);
+        let statuses = pipeline.apply(vec![
+            new_status(1, "Hello #sync"),
+            new_status(1, "Hello"),
+            new_status(1, "Hello #syncing"),
+        ]);
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].text, "Hello #sync");
+    }
+
+    #[test]
+    fn regex_filter() {
+        let pipeline = pipeline(vec![
+            FilterConfig::Regex {
+                pattern: "(?i)secret".to_string(),
+                replacement: None,
+            },
+            FilterConfig::Regex {
+                pattern: "colour".to_string(),
+                replacement: Some("color".to_string()),
+            },
+        ]);
+        let mut rejected = Vec::new();
+        let statuses = pipeline.apply_rejected(
+            vec![new_status(1, "A Secret"), new_status(1, "My colour")],
+            &mut rejected,
+        );
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].text, "My color");
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].text, "A Secret");
+    }
+
+    #[test]
+    fn mention_filter() {
+        let mut map = BTreeMap::new();
+        map.insert("@klausi".to_string(), "@klausi@mastodon.social".to_string());
+        let pipeline = pipeline(vec![FilterConfig::Mentions { map }]);
+        let statuses = pipeline.apply(vec![new_status(
+            1,
+            "Hi @Klausi and @other, mail me at me@klausi",
+        )]);
+        assert_eq!(
+            statuses[0].text,
+            "Hi @klausi@mastodon.social and @other, mail me at me@klausi"
+        );
+    }
+
+    // A rejected status also removes its replies, replies are filtered too.
+    #[test]
+    fn hashtag_map_filter() {
+        let mut map = BTreeMap::new();
+        map.insert("#Fediverse".to_string(), "#mastodon".to_string());
+        let pipeline = pipeline(vec![FilterConfig::HashtagMap {
+            map,
+            append: vec!["#mastodon".to_string(), "#sync".to_string()],
+        }]);
+        let statuses = pipeline.apply(vec![
+            new_status(1, "Hello #fediverse, see https://example.com/#fediverse"),
+            new_status(1, "Hello #Sync "),
+        ]);
+        assert_eq!(
+            statuses[0].text,
+            "Hello #mastodon, see https://example.com/#fediverse\n\n#sync"
+        );
+        assert_eq!(statuses[1].text, "Hello #Sync\n\n#mastodon");
+    }
+
+    #[test]
+    fn filter_replies() {
+        let pipeline = pipeline(vec![FilterConfig::Regex {
+            pattern: "reject".to_string(),
+            replacement: None,
+        }]);
+        let mut status = new_status(1, "Thread");
+        status.replies = vec![new_status(1, "reject me"), new_status(1, "keep me")];
+        let statuses = pipeline.apply(vec![status]);
+        assert_eq!(statuses[0].replies.len(), 1);
+        assert_eq!(statuses[0].replies[0].text, "keep me");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn command_filter() {
+        let statuses = pipeline(vec![FilterConfig::Command {
+            cmd: "cat".to_string(),
+        }])
+        .apply(vec![new_status(1, "Hello")]);
+        assert_eq!(statuses.len(), 1);
+
+        // No output rejects the status.
+        let statuses = pipeline(vec![FilterConfig::Command {
+            cmd: "true".to_string(),
+        }])
+        .apply(vec![new_status(1, "Hello")]);
+        assert!(statuses.is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+use crate::moderation::{twitter_fetch_users, twitter_user_mastodon_account};
+use anyhow::{Context, Result};
+use elefren::{Mastodon, MastodonClient};
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::fs;
+use tokio::runtime::Runtime;
+
+// Header of the CSV format that the Mastodon follow importer understands.
+const CSV_HEADER: &str = "Account address,Show boosts";
+
+/// A followed Twitter account and the guessed Mastodon account of the same
+/// person.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FollowMatch {
+    pub twitter_user: String,
+    // Full address like user@example.com without the leading @.
+    pub mastodon_account: String,
+    pub already_following: bool,
+}
+
+/// Prints which followed Twitter accounts exist on Mastodon and writes the
+/// ones not followed there yet to a CSV file for the Mastodon follow importer.
+pub fn followers_report(
+    rt: &Runtime,
+    mastodon: &Mastodon,
+    twitter_user_id: u64,
+    token: &egg_mode::Token,
+    file: &str,
+) -> Result<()> {
+    let friends = rt.block_on(twitter_fetch_users(egg_mode::user::friends_of(
+        twitter_user_id,
+        token,
+    )))?;
+    let following = mastodon_fetch_following(mastodon)?;
+
+    let matches: Vec<FollowMatch> = friends
+        .iter()
+        .filter_map(|user| {
+            let account = twitter_user_mastodon_account(user)?;
+            Some(follow_match(&user.screen_name, &account, &following))
+        })
+        .collect();
+
+    println!(
+        "You follow {} Twitter accounts, {} of them have a Mastodon account:",
+        friends.len(),
+        matches.len()
+    );
+    for follow in &matches {
+        let state = if follow.already_following {
+            "already following"
+        } else {
+            "not following yet"
+        };
+        println!(
+            "  @{} → @{} ({state})",
+            follow.twitter_user, follow.mastodon_account
+        );
+    }
+
+    let csv = follows_to_csv(&matches);
+    fs::write(file, csv).with_context(|| format!("Failed to write {file}"))?;
+    println!(
+        "Wrote {} accounts to {file}, import it in Mastodon under Preferences → Import and export → Import.",
+        matches.iter().filter(|f| !f.already_following).count()
+    );
+    Ok(())
+}
+
+// Returns the full addresses of all accounts the Mastodon user follows.
+fn mastodon_fetch_following(mastodon: &Mastodon) -> Result<BTreeSet<String>> {
+    let account = mastodon.verify_credentials()?;
+    let domain = mastodon
+        .base
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_end_matches('/')
+        .to_string();
+    let mut following = BTreeSet::new();
+    for followed in mastodon.following(&account.id)?.items_iter() {
+        // Accounts on the same instance have no domain in acct.
+        let address = if followed.acct.contains('@') {
+            followed.acct
+        } else {
+            format!("{}@{domain}", followed.acct)
+        };
+        following.insert(address.to_lowercase());
+    }
+    Ok(following)
+}
+
+fn follow_match(twitter_user: &str, account: &str, following: &BTreeSet<String>) -> FollowMatch {
+    let mastodon_account = account.trim_start_matches('@').to_string();
+    FollowMatch {
+        twitter_user: twitter_user.to_string(),
+        already_following: following.contains(&mastodon_account.to_lowercase()),
+        mastodon_account,
+    }
+}
+
+fn follows_to_csv(matches: &[FollowMatch]) -> String {
+    let mut csv = format!("{CSV_HEADER}\n");
+    for follow in matches.iter().filter(|f| !f.already_following) {
+        let _ = writeln!(csv, "{},true", follow.mastodon_account);
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_only_new_follows() {
+        let following = BTreeSet::from(["klausi@mastodon.social".to_string()]);
+        let matches = vec![
+            follow_match("klausi", "@Klausi@mastodon.social", &following),
+            follow_match("example", "@example@chaos.social", &following),
+        ];
+        assert!(matches[0].already_following);
+        assert!(!matches[1].already_following);
+        assert_eq!(
+            follows_to_csv(&matches),
+            "Account address,Show boosts\nexample@chaos.social,true\n"
+        );
+    }
+}
@@ -0,0 +1,317 @@
+// Without both networks nothing is synced, only deletions are recorded.
+#![cfg_attr(not(all(feature = "mastodon", feature = "twitter")), allow(dead_code))]
+
+use crate::cache_file;
+use anyhow::Result;
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use tracing::warn;
+
+// Twitter IDs contain the milliseconds since this epoch, Mastodon IDs the
+// milliseconds since the Unix epoch.
+const TWITTER_EPOCH_MS: u64 = 1288834974657;
+// IDs that translate to an earlier time are not snowflake IDs, for example
+// the sequential IDs of old Mastodon versions. 2016-01-01.
+const EARLIEST_SNOWFLAKE: i64 = 1451606400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryNetwork {
+    Mastodon,
+    Twitter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletedKind {
+    Statuses,
+    Favs,
+}
+
+/// Something that happened in a run, see `HistoryEntry`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum HistoryEvent {
+    /// A status was posted to the target network. The delay is the number of
+    /// seconds since the original was posted, if it is known. Older versions
+    /// did not record the link to the created status.
+    Posted {
+        target: HistoryNetwork,
+        delay: Option<i64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+    Failed {
+        target: HistoryNetwork,
+        reason: String,
+    },
+    Deleted {
+        network: HistoryNetwork,
+        kind: DeletedKind,
+        count: usize,
+    },
+}
+
+/// One line of the sync history file with a Unix timestamp.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub time: i64,
+    #[serde(flatten)]
+    pub event: HistoryEvent,
+}
+
+impl HistoryEntry {
+    pub fn now(event: HistoryEvent) -> HistoryEntry {
+        HistoryEntry {
+            time: Utc::now().timestamp(),
+            event,
+        }
+    }
+}
+
+fn history_file() -> String {
+    cache_file("sync_history.jsonl")
+}
+
+/// Appends events to the history file. The history is only for statistics,
+/// so errors are only logged.
+pub fn history_append(events: Vec<HistoryEvent>) {
+    if events.is_empty() {
+        return;
+    }
+    let mut lines = String::new();
+    for event in events {
+        match serde_json::to_string(&HistoryEntry::now(event)) {
+            Ok(json) => {
+                lines.push_str(&json);
+                lines.push('\n');
+            }
+            Err(e) => warn!("Failed to serialize history event: {e}"),
+        }
+    }
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(history_file())
+        .and_then(|mut file| file.write_all(lines.as_bytes()));
+    if let Err(e) = result {
+        warn!("Failed to write the sync history: {e}");
+    }
+}
+
+/// Records deleted statuses or favourites.
+pub fn history_deleted(network: HistoryNetwork, kind: DeletedKind, count: usize) {
+    if count > 0 {
+        history_append(vec![HistoryEvent::Deleted {
+            network,
+            kind,
+            count,
+        }]);
+    }
+}
+
+/// Loads the history, broken lines are skipped.
+pub fn history_load() -> Result<Vec<HistoryEntry>> {
+    let content = match fs::read_to_string(history_file()) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Returns the time a status was posted as Unix timestamp from its ID.
+pub fn snowflake_time(id: u64, network: HistoryNetwork) -> Option<i64> {
+    let millis = match network {
+        HistoryNetwork::Twitter => (id >> 22) + TWITTER_EPOCH_MS,
+        HistoryNetwork::Mastodon => id >> 16,
+    };
+    let time = i64::try_from(millis / 1000).ok()?;
+    (EARLIEST_SNOWFLAKE..=Utc::now().timestamp())
+        .contains(&time)
+        .then_some(time)
+}
+
+#[derive(Default)]
+struct MonthStats {
+    to_mastodon: usize,
+    to_twitter: usize,
+}
+
+/// Summarizes the history: posts per month and direction, average delays,
+/// deletions and the most common failure reasons.
+pub fn stats_report(entries: &[HistoryEntry]) -> String {
+    let mut months: BTreeMap<String, MonthStats> = BTreeMap::new();
+    let mut delays: BTreeMap<HistoryNetwork, (i64, i64)> = BTreeMap::new();
+    let mut deleted: BTreeMap<(HistoryNetwork, DeletedKind), usize> = BTreeMap::new();
+    let mut failures: BTreeMap<&str, usize> = BTreeMap::new();
+    for entry in entries {
+        match &entry.event {
+            HistoryEvent::Posted { target, delay, .. } => {
+                let month = match Utc.timestamp_opt(entry.time, 0).single() {
+                    Some(time) => time.format("%Y-%m").to_string(),
+                    None => continue,
+                };
+                let stats = months.entry(month).or_default();
+                match target {
+                    HistoryNetwork::Mastodon => stats.to_mastodon += 1,
+                    HistoryNetwork::Twitter => stats.to_twitter += 1,
+                }
+                if let Some(delay) = delay {
+                    let (sum, count) = delays.entry(*target).or_default();
+                    *sum += delay;
+                    *count += 1;
+                }
+            }
+            HistoryEvent::Failed { reason, .. } => *failures.entry(reason).or_default() += 1,
+            HistoryEvent::Deleted {
+                network,
+                kind,
+                count,
+            } => *deleted.entry((*network, *kind)).or_default() += count,
+        }
+    }
+
+    if entries.is_empty() {
+        return "No sync history recorded yet.\n".to_string();
+    }
+    // Writing to a String cannot fail.
+    let mut report = String::from("Synced posts per month:\n");
+    let _ = writeln!(report, "  Month    To Mastodon  To Twitter");
+    for (month, stats) in &months {
+        let _ = writeln!(
+            report,
+            "  {month}  {:>11}  {:>10}",
+            stats.to_mastodon, stats.to_twitter
+        );
+    }
+    for (target, (sum, count)) in &delays {
+        let _ = writeln!(
+            report,
+            "Average delay to {}: {}",
+            network_name(*target),
+            format_delay(sum / count)
+        );
+    }
+    for ((network, kind), count) in &deleted {
+        let kind = match kind {
+            DeletedKind::Statuses => "statuses",
+            DeletedKind::Favs => "favourites",
+        };
+        let _ = writeln!(
+            report,
+            "Deleted {count} {kind} on {}",
+            network_name(*network)
+        );
+    }
+    if !failures.is_empty() {
+        let mut failures: Vec<_> = failures.into_iter().collect();
+        failures.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        let _ = writeln!(report, "Most common failures:");
+        for (reason, count) in failures.iter().take(5) {
+            let _ = writeln!(report, "  {count} × {reason}");
+        }
+    }
+    report
+}
+
+fn network_name(network: HistoryNetwork) -> &'static str {
+    match network {
+        HistoryNetwork::Mastodon => "Mastodon",
+        HistoryNetwork::Twitter => "Twitter",
+    }
+}
+
+fn format_delay(seconds: i64) -> String {
+    match seconds {
+        s if s < 60 => format!("{s}s"),
+        s if s < 3600 => format!("{}m {}s", s / 60, s % 60),
+        s => format!("{}h {}m", s / 3600, s % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_id_times() {
+        // Both from 2022-10-05.
+        assert_eq!(
+            snowflake_time(1577730467436138524, HistoryNetwork::Twitter),
+            Some(1664995230)
+        );
+        assert_eq!(
+            snowflake_time(109116189799612416, HistoryNetwork::Mastodon),
+            Some(1664980923)
+        );
+        // Old sequential Mastodon ID.
+        assert_eq!(snowflake_time(1234, HistoryNetwork::Mastodon), None);
+    }
+
+    #[test]
+    fn report() {
+        let entry = |time, event| HistoryEntry { time, event };
+        let entries = vec![
+            entry(
+                1664980924,
+                HistoryEvent::Posted {
+                    target: HistoryNetwork::Twitter,
+                    delay: Some(60),
+                    url: None,
+                },
+            ),
+            entry(
+                1664980924,
+                HistoryEvent::Posted {
+                    target: HistoryNetwork::Twitter,
+                    delay: Some(120),
+                    url: None,
+                },
+            ),
+            entry(
+                1669900000,
+                HistoryEvent::Posted {
+                    target: HistoryNetwork::Mastodon,
+                    delay: None,
+                    url: None,
+                },
+            ),
+            entry(
+                1669900000,
+                HistoryEvent::Failed {
+                    target: HistoryNetwork::Twitter,
+                    reason: "Twitter API request failed".to_string(),
+                },
+            ),
+            entry(
+                1669900000,
+                HistoryEvent::Deleted {
+                    network: HistoryNetwork::Mastodon,
+                    kind: DeletedKind::Favs,
+                    count: 3,
+                },
+            ),
+        ];
+        let report = stats_report(&entries);
+        assert!(report.contains("  2022-10            0           2\n"));
+        assert!(report.contains("  2022-12            1           0\n"));
+        assert!(report.contains("Average delay to Twitter: 1m 30s\n"));
+        assert!(!report.contains("Average delay to Mastodon"));
+        assert!(report.contains("Deleted 3 favourites on Mastodon\n"));
+        assert!(report.contains("  1 × Twitter API request failed\n"));
+
+        let json = serde_json::to_string(&entries[0]).unwrap();
+        assert_eq!(
+            json,
+            r#"{"time":1664980924,"event":"posted","target":"twitter","delay":60}"#
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use crate::sync::{unify_post_content, NewStatus, Platform, StatusId};
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+// Remembers which Mastodon status corresponds to which tweet for all synced
+// statuses.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IdMap {
+    // Mastodon status IDs mapped to tweet IDs.
+    pub toots_to_tweets: BTreeMap<StatusId, u64>,
+    // IDs of tweets that were posted by this tool, as opposed to tweets that
+    // were synced to Mastodon.
+    #[serde(default)]
+    pub created_tweets: BTreeSet<u64>,
+    // Content hashes of the synced source statuses by their ID, to notice when
+    // the text of an already synced status changes.
+    #[serde(default)]
+    pub content_hashes: BTreeMap<StatusId, u64>,
+    // IDs of source statuses that were marked as synced by hand.
+    #[serde(default)]
+    pub marked_synced: BTreeSet<StatusId>,
+    // Idempotency keys that toots were posted with by the ID of their source
+    // tweet.
+    #[serde(default)]
+    pub idempotency_keys: BTreeMap<StatusId, String>,
+    // Old statuses that were deleted by their ID, so that their copies on the
+    // other network are not synced back.
+    #[serde(default)]
+    pub tombstones: BTreeMap<StatusId, Tombstone>,
+}
+
+/// Record of a status that was deleted on purpose.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub platform: Platform,
+    // Unix timestamp of the deletion.
+    pub deleted_at: i64,
+    // Content hash of the text, if the API returned the deleted status.
+    pub text_hash: Option<u64>,
+}
+
+impl IdMap {
+    /// Reads the ID map from the JSON file, a missing file is an empty map.
+    pub fn load(file: &str) -> Result<IdMap> {
+        match fs::read_to_string(file) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(_) => Ok(IdMap::default()),
+        }
+    }
+
+    pub fn save(&self, file: &str) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(file, json.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn insert(&mut self, toot_id: StatusId, tweet_id: u64) {
+        self.toots_to_tweets.insert(toot_id, tweet_id);
+    }
+
+    /// Records a tweet that was posted by this tool for a Mastodon status.
+    pub fn insert_created_tweet(&mut self, toot_id: StatusId, tweet_id: u64) {
+        self.insert(toot_id, tweet_id);
+        self.created_tweets.insert(tweet_id);
+    }
+
+    /// Remembers the content hashes of the status and its replies that were
+    /// synced.
+    pub fn insert_content_hashes(&mut self, status: &NewStatus) {
+        if self.is_synced(&status.original_id) {
+            self.content_hashes
+                .insert(status.original_id.clone(), content_hash(&status.text));
+        }
+        self.insert_reply_content_hashes(status);
+    }
+
+    // Parts of a split status have the same ID, the hash of the first part is
+    // kept for it.
+    fn insert_reply_content_hashes(&mut self, status: &NewStatus) {
+        for reply in &status.replies {
+            if reply.original_id == status.original_id {
+                self.insert_reply_content_hashes(reply);
+            } else {
+                self.insert_content_hashes(reply);
+            }
+        }
+    }
+
+    /// Returns true if the source status with this ID was synced already,
+    /// regardless whether it is a toot or a tweet.
+    pub fn is_synced(&self, source_id: &StatusId) -> bool {
+        self.toots_to_tweets.contains_key(source_id)
+            || source_id
+                .as_u64()
+                .map_or(false, |id| self.toot_id(id).is_some())
+            || self.marked_synced.contains(source_id)
+    }
+
+    /// Marks a toot or tweet as synced without knowing its counterpart on the
+    /// other network.
+    pub fn mark_synced(&mut self, source_id: StatusId) {
+        self.marked_synced.insert(source_id);
+    }
+
+    /// Returns true if the text differs from the one the status was synced
+    /// with. Unknown statuses are never changed.
+    pub fn content_changed(&self, source_id: &StatusId, text: &str) -> bool {
+        self.content_hashes
+            .get(source_id)
+            .map_or(false, |hash| *hash != content_hash(text))
+    }
+
+    /// Records that a status was deleted from the network on purpose.
+    pub fn insert_tombstone(&mut self, platform: Platform, id: StatusId, text: Option<&str>) {
+        self.tombstones.insert(
+            id,
+            Tombstone {
+                platform,
+                deleted_at: Utc::now().timestamp(),
+                text_hash: text.map(tombstone_hash),
+            },
+        );
+    }
+
+    /// Returns the tombstone if the status would recreate a deleted status on
+    /// the network, because its source or a status with the same text was
+    /// deleted there.
+    pub fn tombstone(&self, platform: Platform, status: &NewStatus) -> Option<&Tombstone> {
+        if let Some(tombstone) = self.tombstones.get(&status.original_id) {
+            return Some(tombstone);
+        }
+        let hash = tombstone_hash(&status.text);
+        self.tombstones
+            .values()
+            .find(|tombstone| tombstone.platform == platform && tombstone.text_hash == Some(hash))
+    }
+
+    pub fn tweet_id(&self, toot_id: &StatusId) -> Option<u64> {
+        self.toots_to_tweets.get(toot_id).copied()
+    }
+
+    pub fn toot_id(&self, tweet_id: u64) -> Option<&StatusId> {
+        self.toots_to_tweets
+            .iter()
+            .find(|(_, id)| **id == tweet_id)
+            .map(|(toot_id, _)| toot_id)
+    }
+
+    /// Returns the map with the IDs of each pair swapped, only with the IDs.
+    /// A mirror account is on the network it copies from, so its statuses are
+    /// mapped the other way round than the statuses of a sync when posting,
+    /// see post_toots(). Pairs with IDs that are no numbers are left out.
+    pub fn reversed(&self) -> IdMap {
+        IdMap {
+            toots_to_tweets: self
+                .toots_to_tweets
+                .iter()
+                .filter_map(|(toot_id, tweet_id)| {
+                    Some((StatusId::Number(*tweet_id), toot_id.as_u64()?))
+                })
+                .collect(),
+            ..IdMap::default()
+        }
+    }
+}
+
+/// Returns the key for the Idempotency-Key header when posting a status to
+/// Mastodon. Mastodon returns the existing status for a repeated request with
+/// the same key, so retries can not post duplicates. The text is part of the
+/// key, so that a changed source status can be posted again.
+pub fn idempotency_key(original_id: &StatusId, text: &str) -> String {
+    format!(
+        "mastodon-twitter-sync-{original_id}-{:016x}",
+        content_hash(text)
+    )
+}
+
+// Tombstones compare texts like the equality check of toots and tweets.
+fn tombstone_hash(text: &str) -> u64 {
+    content_hash(&unify_post_content(text.to_string()))
+}
+
+// FNV-1a hash, stable between Rust versions unlike the std hasher.
+pub fn content_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::tests::new_status;
+
+    #[test]
+    fn content_hashes() {
+        let mut id_map = IdMap::default();
+        id_map.insert(StatusId::Number(1), 10);
+        let mut status = new_status(1, "Hello");
+        status.replies.push(new_status(2, "Not synced"));
+        id_map.insert_content_hashes(&status);
+
+        assert!(id_map.is_synced(&StatusId::Number(1)));
+        assert!(id_map.is_synced(&StatusId::Number(10)));
+        assert!(!id_map.is_synced(&StatusId::Number(2)));
+        assert!(!id_map.content_changed(&StatusId::Number(1), "Hello"));
+        assert!(id_map.content_changed(&StatusId::Number(1), "Hello, new signature"));
+        assert!(!id_map.content_changed(&StatusId::Number(2), "Anything"));
+    }
+
+    #[test]
+    fn reversed() {
+        let mut id_map = IdMap::default();
+        id_map.insert(StatusId::Number(1), 10);
+        id_map.insert(StatusId::from("abc"), 20);
+        let reversed = id_map.reversed();
+        assert_eq!(reversed.tweet_id(&StatusId::Number(10)), Some(1));
+        assert_eq!(reversed.toot_id(1), Some(&StatusId::Number(10)));
+        assert_eq!(reversed.toots_to_tweets.len(), 1);
+    }
+
+    #[test]
+    fn idempotency_keys() {
+        let id = StatusId::Number(1);
+        assert_eq!(idempotency_key(&id, "Hello"), idempotency_key(&id, "Hello"));
+        assert_ne!(
+            idempotency_key(&id, "Hello"),
+            idempotency_key(&id, "Hello!")
+        );
+        assert_ne!(
+            idempotency_key(&id, "Hello"),
+            idempotency_key(&StatusId::Number(2), "Hello")
+        );
+        assert!(idempotency_key(&id, "Hello").starts_with("mastodon-twitter-sync-1-"));
+    }
+
+    #[test]
+    fn tombstones() {
+        let mut id_map = IdMap::default();
+        id_map.insert_tombstone(Platform::Mastodon, StatusId::Number(1), Some("Old news"));
+        id_map.insert_tombstone(Platform::Twitter, StatusId::Number(10), None);
+
+        // The copy of a deleted toot on Twitter would recreate it.
+        assert!(id_map
+            .tombstone(Platform::Mastodon, &new_status(20, "old   NEWS"))
+            .is_some());
+        assert!(id_map
+            .tombstone(Platform::Twitter, &new_status(20, "Old news"))
+            .is_none());
+        // Deleted statuses are never synced.
+        assert!(id_map
+            .tombstone(Platform::Mastodon, &new_status(10, "Anything"))
+            .is_some());
+        assert!(id_map
+            .tombstone(Platform::Mastodon, &new_status(30, "New news"))
+            .is_none());
+    }
+
+    // Test that numeric IDs of older files and ULIDs of GoToSocial are read.
+    #[test]
+    fn text_status_ids() {
+        let mut id_map: IdMap =
+            serde_json::from_str(r#"{"toots_to_tweets": {"123": 10}}"#).unwrap();
+        id_map.insert(StatusId::from("01GQ8N4TWNFPZHBKTE6X9JBWCP"), 11);
+
+        let json = serde_json::to_string(&id_map).unwrap();
+        let id_map: IdMap = serde_json::from_str(&json).unwrap();
+        assert_eq!(id_map.tweet_id(&StatusId::Number(123)), Some(10));
+        assert_eq!(
+            id_map.toot_id(11),
+            Some(&StatusId::Text("01GQ8N4TWNFPZHBKTE6X9JBWCP".to_string()))
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use crate::config::Config;
+use crate::registration::{console_input, register};
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use tokio::runtime::Runtime;
+
+// Comments for the config keys that the init command writes, by section.
+const CONFIG_COMMENTS: &[(&str, &str, &str)] = &[
+    (
+        "mastodon",
+        "delete_older_statuses",
+        "Delete Mastodon status posts that are older than 90 days",
+    ),
+    (
+        "mastodon",
+        "delete_older_favs",
+        "Delete Mastodon favourites that are older than 90 days",
+    ),
+    ("mastodon", "sync_reblogs", "Also sync reblogs (boosts)."),
+    (
+        "mastodon",
+        "retweet_sync_mode",
+        "\"copy\" posts the text of boosts as \"RT user: text\", \"link\" only posts\n\"RT user:\" with the URL of the original status.",
+    ),
+    (
+        "mastodon",
+        "sync_hashtags",
+        "Only sync posts with these hashtags (leave empty to sync all posts).",
+    ),
+    (
+        "mastodon",
+        "strip_metadata",
+        "Remove EXIF metadata like GPS positions from images before uploading them to\nTwitter.",
+    ),
+    (
+        "mastodon",
+        "require_alt_text",
+        "What to do with posts that have images or videos without alt text: \"ignore\",\n\"warn\" in the log or \"skip\" them.",
+    ),
+    (
+        "mastodon.app",
+        "base",
+        "Registered Mastodon app, use the register command to change the account.",
+    ),
+    (
+        "twitter",
+        "consumer_key",
+        "Twitter app and access tokens, use the register command to change the\naccount.",
+    ),
+    (
+        "twitter",
+        "delete_older_statuses",
+        "Delete Twitter status posts that are older than 90 days",
+    ),
+    (
+        "twitter",
+        "delete_older_favs",
+        "Delete Twitter likes that are older than 90 days",
+    ),
+    (
+        "twitter",
+        "delete_only_synced",
+        "Only delete old tweets that were synced from Mastodon, keep tweets written on\nTwitter.",
+    ),
+    ("twitter", "sync_retweets", "Also sync retweets."),
+    (
+        "twitter",
+        "sync_hashtags",
+        "Only sync posts with these hashtags (leave empty to sync all posts).",
+    ),
+    (
+        "twitter",
+        "expand_urls",
+        "Normalization of tweet texts, switch them off to keep tweets as they are.\nReplace t.co links with the original URLs.",
+    ),
+    ("twitter", "escape_mentions", "Escape @username mentions as @\\username."),
+    (
+        "twitter",
+        "decode_html_entities",
+        "Decode HTML entities like &amp;.",
+    ),
+];
+
+/// Sets up a new config file: registers both accounts, asks for the most common
+/// options and writes the config with comments. Prints a cron entry for
+/// periodic execution on request.
+pub fn init(rt: &Runtime, config_file: &str, dry_run: bool) -> Result<()> {
+    if Path::new(config_file).exists() {
+        bail!(
+            "Config file {config_file} already exists, use the register command to change the accounts"
+        );
+    }
+    let mut config = register(rt, None, None, None)?;
+    ask_options(&mut config)?;
+
+    let toml = commented_config(&config)?;
+    if dry_run {
+        println!("{toml}");
+    } else {
+        fs::write(config_file, toml.as_bytes())
+            .with_context(|| format!("Failed to write config file {config_file}"))?;
+        println!("Config file {config_file} written, see the README for all options.");
+    }
+
+    if confirm("Run the sync every 10 minutes with cron? [y/N]", false)? {
+        println!("Add this line with \"crontab -e\":");
+        println!("{}", cron_entry(config_file)?);
+    }
+    Ok(())
+}
+
+// Asks for the options most users change.
+fn ask_options(config: &mut Config) -> Result<()> {
+    config.mastodon.sync_reblogs =
+        confirm("Sync your boosts from Mastodon to Twitter? [Y/n]", true)?;
+    config.twitter.sync_retweets =
+        confirm("Sync your retweets from Twitter to Mastodon? [Y/n]", true)?;
+    let hashtag =
+        console_input("Only sync posts with this hashtag, leave empty to sync all posts")?;
+    if !hashtag.is_empty() {
+        let hashtag = format!("#{}", hashtag.trim_start_matches('#'));
+        config.mastodon.sync_hashtags = vec![hashtag.clone()];
+        config.twitter.sync_hashtags = vec![hashtag];
+    }
+    let delete = confirm(
+        "Delete your posts and favourites that are older than 90 days on both networks? [y/N]",
+        false,
+    )?;
+    config.mastodon.delete_older_statuses = delete;
+    config.mastodon.delete_older_favs = delete;
+    config.twitter.delete_older_statuses = delete;
+    config.twitter.delete_older_favs = delete;
+    Ok(())
+}
+
+fn confirm(prompt: &str, default: bool) -> Result<bool> {
+    let answer = console_input(prompt)?;
+    Ok(match answer.to_lowercase().as_str() {
+        "y" | "yes" => true,
+        "n" | "no" => false,
+        _ => default,
+    })
+}
+
+/// Serializes the config as TOML with a comment above each known option.
+pub fn commented_config(config: &Config) -> Result<String> {
+    let toml = toml::to_string(config)?;
+    let mut commented = String::from(
+        "# Config of mastodon-twitter-sync, all options are explained in the README:\n\
+        # https://github.com/klausi/mastodon-twitter-sync#configuration\n",
+    );
+    let mut section = "";
+    for line in toml.lines() {
+        if let Some(name) = line.strip_prefix('[') {
+            section = name.trim_end_matches(']');
+            // Empty line before each section, the first one is after the
+            // header.
+            let _ = writeln!(commented);
+        }
+        let key = line.split(" = ").next().unwrap_or_default();
+        let comment = CONFIG_COMMENTS
+            .iter()
+            .find(|(s, k, _)| *s == section && *k == key);
+        if let Some((_, _, comment)) = comment {
+            for comment_line in comment.lines() {
+                let _ = writeln!(commented, "# {comment_line}");
+            }
+        }
+        let _ = writeln!(commented, "{line}");
+    }
+    Ok(commented)
+}
+
+// Returns a crontab line that runs the sync every 10 minutes in the current
+// directory.
+fn cron_entry(config_file: &str) -> Result<String> {
+    let dir = env::current_dir()?;
+    let exe = env::current_exe()?;
+    Ok(format!(
+        "*/10 * * * *   cd {} && {} --config {config_file}",
+        dir.display(),
+        exe.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::config_load;
+
+    #[test]
+    fn commented_config_loads() {
+        let config = config_load(
+            r#"
+[mastodon]
+delete_older_statuses = true
+
+[mastodon.app]
+base = "https://mastodon.social"
+client_id = "XXXXXXXXXXX"
+client_secret = "XXXXXXXXXXX"
+redirect = "urn:ietf:wg:oauth:2.0:oob"
+token = "XXXXXXXXXXX"
+
+[twitter]
+consumer_key = "XXXXXXXXXXX"
+consumer_secret = "XXXXXXXXXXX"
+access_token = "XXXXXXXXXXX"
+access_token_secret = "XXXXXXXXXXX"
+user_id = 1234567890
+user_name = "example"
+"#,
+        )
+        .unwrap();
+        let toml = commented_config(&config).unwrap();
+        assert!(toml.contains(
+            "# Delete Mastodon status posts that are older than 90 days\ndelete_older_statuses = true\n"
+        ));
+        assert!(toml.contains("# Also sync retweets.\nsync_retweets = true\n"));
+        assert!(toml.contains("# Escape @username mentions as @\\username.\n"));
+        let loaded = config_load(&toml).unwrap();
+        assert!(loaded.mastodon.delete_older_statuses);
+        assert_eq!(loaded.twitter.user_name, "example");
+    }
+}
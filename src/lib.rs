@@ -1,210 +1,1820 @@
-use anyhow::Context;
-use anyhow::Result;
-use elefren::prelude::*;
-use elefren::{Mastodon, StatusesRequest};
-use log::debug;
-use std::fs;
-use std::fs::File;
-use std::io::prelude::*;
-use std::process;
-
-use crate::args::*;
-use crate::config::*;
-use crate::delete_favs::*;
-use crate::delete_statuses::mastodon_delete_older_statuses;
-use crate::delete_statuses::twitter_delete_older_statuses;
-use crate::post::*;
-use crate::registration::mastodon_register;
-use crate::registration::twitter_register;
-use crate::sync::*;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+use {
+    crate::accounts::{MastodonAccount, TwitterAccount},
+    crate::api_budget::*,
+    crate::args::*,
+    crate::attachment_size::remote_media,
+    crate::bookmarks::*,
+    crate::config::*,
+    crate::config_check::config_check,
+    crate::config_migration::config_migrate_file,
+    crate::conflicts::{split_conflicts, Conflict},
+    crate::delete_favs::*,
+    crate::delete_pacing::DeletePacing,
+    crate::delete_statuses::*,
+    crate::digest::*,
+    crate::error_reporting::{bind_hub, error_reporting_init, post_breadcrumb, report_error},
+    crate::errors::SyncError,
+    crate::filters::hashtag_map_changes,
+    crate::followers::followers_report,
+    crate::history::*,
+    crate::id_map::IdMap,
+    crate::init::init,
+    crate::logging::color_enabled,
+    crate::mastodon::*,
+    crate::media::{MediaCache, MediaOptions, Watermark},
+    crate::mirror::{mark_mirrored, mirror_config, mirror_posts},
+    crate::mock::mock_run,
+    crate::moderation::*,
+    crate::native_boost::{native_boosts, native_self_boosts, native_self_retweets},
+    crate::notify::notify_failure,
+    crate::paths::paths_init,
+    crate::plan::{plan_posts, SyncAccount},
+    crate::post::*,
+    crate::queue::*,
+    crate::registration::*,
+    crate::report::{dry_run_report, skipped_summary, summary_table, DirectionSummary},
+    crate::run_report::*,
+    crate::scheduled::scheduled_merge,
+    crate::service::install_service,
+    crate::short_links::ShortLinkResolver,
+    crate::shutdown::shutdown_requested,
+    crate::state::*,
+    crate::status::RunStatus,
+    crate::stream::stream_updates,
+    crate::sync::*,
+    crate::transform::{run_status_command, transform_status},
+    crate::twitter_oauth2::twitter_oauth2_refresh,
+    anyhow::bail,
+    anyhow::Context,
+    anyhow::Result,
+    chrono::Local,
+    egg_mode::tweet::Tweet,
+    elefren::entities::status::Status,
+    elefren::prelude::*,
+    elefren::Mastodon,
+    std::collections::{BTreeMap, BTreeSet},
+    std::fs,
+    std::iter,
+    std::mem,
+    std::panic,
+    std::path::{Path, PathBuf},
+    std::sync::{Arc, Mutex},
+    std::thread,
+    std::time::Duration,
+    tracing::{debug, error, info, info_span, warn, Span},
+};
 
+mod api_budget;
 pub mod args;
-mod config;
-mod delete_favs;
-mod delete_statuses;
-mod post;
+pub mod config;
+#[cfg(any(feature = "mastodon", feature = "twitter"))]
+pub mod delete_favs;
+pub mod delete_pacing;
+#[cfg(any(feature = "mastodon", feature = "twitter"))]
+pub mod delete_statuses;
+pub mod errors;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+pub mod filters;
+mod history;
+pub mod logging;
+#[cfg(feature = "mastodon")]
+mod mastodon;
+pub mod paths;
+mod progress;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+pub mod run_mode;
+mod run_report;
+mod shutdown;
+pub mod status;
+
+// Syncing needs both networks.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod accounts;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod attachment_size;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod bookmarks;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod config_check;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod config_migration;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod conflicts;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod digest;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod error_reporting;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod followers;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+pub mod id_map;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod init;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod media;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod mirror;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod mock;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod moderation;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod native_boost;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod notify;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+pub mod plan;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+pub mod post;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod queue;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
 mod registration;
-mod sync;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod report;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod scheduled;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod service;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod short_links;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod state;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod stream;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+pub mod sync;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
 mod thread_replies;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod transform;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod twitter_json;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod twitter_oauth2;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod twitter_rss;
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+mod twitter_search;
 
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
 pub fn run(args: Args) -> Result<()> {
+    run_with_status(args).0
+}
+
+/// Runs the command line and returns the result with the counts of the run.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+pub fn run_with_status(mut args: Args) -> (Result<()>, RunStatus) {
+    args.config = paths_init(&args.config).config;
     debug!("running with args {:?}", args);
 
+    // Only sync runs are recorded in the status file for monitoring, the run
+    // report also covers dry runs.
+    let sync_run = matches!(args.command, None | Some(Command::Flush))
+        && !args.print_json
+        && !args.print_paths
+        && args.target == Target::Live;
+    let record_status = !args.dry_run && sync_run;
+    // The config might be the reason of a failure, then nobody can be
+    // notified.
+    let config = fs::read_to_string(&args.config)
+        .ok()
+        .and_then(|config| config_load(&config).ok());
+    let mut status = RunStatus::start();
+    if let Some(config) = &config {
+        if let Err(e) = set_cache_namespace(config) {
+            return (Err(e), status);
+        }
+        // Dry runs also read from the APIs and count against the budget, as
+        // well as the syncs of streaming.
+        if sync_run || matches!(args.command, Some(Command::Stream { .. })) {
+            api_budget_start(
+                &cache_file("api_usage.json"),
+                BTreeMap::from([(
+                    HistoryNetwork::Twitter,
+                    ApiBudget {
+                        read: config.twitter.monthly_read_budget,
+                        write: config.twitter.monthly_write_budget,
+                    },
+                )]),
+            );
+        }
+    }
+    let (notify, sentry, report) = match config {
+        Some(config) => (config.notify, config.sentry, config.report),
+        None => (None, None, None),
+    };
+    let report = report.filter(|_| sync_run);
+    if report.is_some() {
+        run_report_start(args.dry_run);
+    }
+    // Panics are reported as long as this is alive.
+    let _error_reporting = error_reporting_init(sentry.as_ref());
+    let result = run_command(args, &mut status);
+    status.finish(&result);
+    if let Err(e) = api_budget_save(&cache_file("api_usage.json")) {
+        warn!("Failed to write the API usage file: {e:#}");
+    }
+    if let Some(report) = report {
+        if let Err(e) = run_report_save(&report.file, &status) {
+            warn!("Failed to write the run report {}: {e:#}", report.file);
+        }
+    }
+    if record_status {
+        record_sync_run(&result, &status, notify.as_ref());
+    }
+    (result, status)
+}
+
+// Writes the status file for monitoring and reports a failed sync.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn record_sync_run(result: &Result<()>, status: &RunStatus, notify: Option<&NotifyConfig>) {
+    if let Err(e) = status.save(&cache_file("last_run.json")) {
+        warn!("Failed to write the status file: {e:#}");
+    }
+    if let Err(error) = result {
+        report_error(error);
+        if let Some(notify) = notify {
+            notify_failure(notify, error);
+        }
+    }
+}
+
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn run_command(args: Args, status: &mut RunStatus) -> Result<()> {
     let rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .context("Failed to create tokio runtime")?;
 
-    let config = match fs::read_to_string(&args.config) {
-        Ok(config) => config_load(&config)?,
+    if let Some(Command::Stats) = &args.command {
+        print!("{}", stats_report(&history_load()?));
+        return Ok(());
+    }
+
+    if args.print_paths {
+        return print_paths(&args.config);
+    }
+
+    if let Some(Command::InstallService { interval, name }) = &args.command {
+        return install_service(&args.config, name, *interval, args.dry_run);
+    }
+
+    if let Some(Command::Init) = &args.command {
+        return init(&rt, &args.config, args.dry_run);
+    }
+
+    if let Some(Command::Register {
+        mastodon_instance,
+        mastodon_token,
+        twitter_client_id,
+    }) = &args.command
+    {
+        let existing = match fs::read_to_string(&args.config) {
+            Ok(config) => Some(config_load(&config)?),
+            Err(_) => None,
+        };
+        let mastodon_token = mastodon_instance.as_deref().zip(mastodon_token.as_deref());
+        let config = register(&rt, existing, mastodon_token, twitter_client_id.as_deref())?;
+        if args.dry_run {
+            // Only show the new config, the existing file keeps working.
+            println!("{}", toml::to_string(&config)?);
+        } else {
+            config_save(&config, &args.config).context("Failed to write config file")?;
+            println!("Config file {} updated.", args.config);
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Cache { command }) = &args.command {
+        let id_map_file = &cache_file("id_map.json");
+        let post_cache_file = &cache_file("post_cache.json");
+        return match command {
+            CacheCommand::Show => {
+                print!(
+                    "{}",
+                    cache_show(id_map_file, post_cache_file, &cache_file("post_queue.json"))?
+                );
+                Ok(())
+            }
+            CacheCommand::Clear => cache_clear(&[id_map_file, post_cache_file], args.dry_run),
+            CacheCommand::MarkSynced { url } => {
+                let mut id_map = IdMap::load(id_map_file)?;
+                id_map.mark_synced(status_id_from_url(url)?);
+                println!("Marked {url} as synced");
+                if !args.dry_run {
+                    id_map.save(id_map_file)?;
+                }
+                Ok(())
+            }
+        };
+    }
+
+    if let Some(Command::Config {
+        command: ConfigCommand::Check,
+    }) = &args.command
+    {
+        let toml = fs::read_to_string(&args.config)
+            .with_context(|| format!("Failed to read config file {}", args.config))?;
+        let problems = config_check(&toml);
+        for problem in &problems {
+            println!("{problem}");
+        }
+        if !problems.is_empty() {
+            bail!("Found {} problem(s) in {}", problems.len(), args.config);
+        }
+        println!("Config file {} is valid.", args.config);
+        return Ok(());
+    }
+
+    let mut config = match fs::read_to_string(&args.config) {
+        Ok(toml) => {
+            let toml = config_migrate_file(&args.config, toml, args.dry_run)?;
+            let config = config_load(&toml)?;
+            // Typos in option names are ignored when loading the config.
+            for problem in config_check(&toml) {
+                warn!("Config file {}: {problem}", args.config);
+            }
+            config
+        }
         Err(_) => {
-            let mastodon = mastodon_register().context("Failed to setup mastodon account")?;
-            let twitter_config = rt
-                .block_on(twitter_register())
-                .context("Failed to setup twitter account")?;
-            let config = Config {
-                mastodon: MastodonConfig {
-                    app: (*mastodon).clone(),
-                    // Do not delete older status per default, users should
-                    // enable this explicitly.
-                    delete_older_statuses: false,
-                    delete_older_favs: false,
-                    sync_reblogs: true,
-                    sync_hashtag: None,
-                },
-                twitter: twitter_config,
-            };
+            let config = register(&rt, None, None, None)?;
 
             // Save config for using on the next run.
-            let toml = toml::to_string(&config)?;
-            let mut file = File::create(&args.config).context("Failed to create config file")?;
-            file.write_all(toml.as_bytes())?;
+            config_save(&config, &args.config).context("Failed to create config file")?;
 
             config
         }
     };
+    set_cache_namespace(&config)?;
+
+    // Nothing is sent to Mastodon or Twitter in a mock run.
+    if args.target == Target::Mock {
+        let mastodon_file = cache_file("mock_mastodon.json");
+        let twitter_file = cache_file("mock_twitter.json");
+        set_mock_cache_namespace()?;
+        return mock_run(&args, &config, &mastodon_file, &twitter_file, status);
+    }
+
+    twitter_token_refresh(&mut config, &args.config)?;
 
-    let mastodon = Mastodon::from(config.mastodon.app);
+    let mastodon = Mastodon::from(config.mastodon.app.clone());
+    let token = twitter_token(&config.twitter);
+
+    if let Some(Command::Moderation { command }) = &args.command {
+        return match command {
+            ModerationCommand::Export { file } => {
+                rt.block_on(twitter_export_moderation(&token, file))
+            }
+            ModerationCommand::Import { file } => {
+                mastodon_import_moderation(&mastodon, file, args.dry_run)
+            }
+        };
+    }
+
+    if let Some(Command::FollowersReport { file }) = &args.command {
+        return followers_report(&rt, &mastodon, config.twitter.user_id, &token, file);
+    }
+
+    if let Some(Command::Stream { poll_interval }) = &args.command {
+        let sync_args = Args {
+            command: None,
+            ..args.clone()
+        };
+        let notify = config.notify.clone();
+        return stream_updates(&mastodon, Duration::from_secs(*poll_interval), || {
+            // The access token of Twitter expires while streaming.
+            twitter_token_refresh(&mut config, &args.config)?;
+            let mut status = RunStatus::start();
+            let result = sync(&rt, &sync_args, &mut config, &mut status);
+            status.finish(&result);
+            if let Err(e) = api_budget_save(&cache_file("api_usage.json")) {
+                warn!("Failed to write the API usage file: {e:#}");
+            }
+            if !sync_args.dry_run {
+                record_sync_run(&result, &status, notify.as_ref());
+            }
+            result
+        });
+    }
+
+    sync(&rt, &args, &mut config, status)
+}
+
+/// Syncs the statuses between Mastodon and Twitter once, with the mirrors,
+/// likes and deletions that are configured. Errors are returned, so that
+/// streaming goes on with the next sync.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn sync(
+    rt: &tokio::runtime::Runtime,
+    args: &Args,
+    config: &mut Config,
+    status: &mut RunStatus,
+) -> Result<()> {
+    // There is nothing to sync without a Mastodon account, the tweets can
+    // still be copied to the mirror account.
+    if config.mastodon.app.base.is_empty() {
+        return twitter_mirror_run(rt, args, config, status);
+    }
+    let mastodon = Mastodon::from(config.mastodon.app.clone());
+    let token = twitter_token(&config.twitter);
 
     let account = match mastodon.verify_credentials() {
         Ok(account) => account,
         Err(e) => {
-            eprintln!("Error connecting to Mastodon: {e:#?}");
-            process::exit(1);
+            if mastodon_account_gone(&e) {
+                return Err(SyncError::MastodonAccountGone {
+                    instance: config.mastodon.app.base.to_string(),
+                }
+                .into());
+            }
+            return Err(anyhow::Error::new(SyncError::MastodonApi(e))
+                .context("Failed to connect to Mastodon"));
         }
     };
-    // Get most recent 50 toots with replies.
-    let mastodon_statuses = match mastodon.statuses(&account.id, StatusesRequest::new().limit(50)) {
-        Ok(statuses) => statuses.initial_items,
-        Err(e) => {
-            eprintln!("Error fetching toots from Mastodon: {e:#?}");
-            process::exit(2);
+    // A moved account can still log in, but syncing to it makes no sense
+    // anymore.
+    if let Some(moved) = &account.moved {
+        return mastodon_handle_moved_account(moved, config, &args.config);
+    }
+    // Without API access tweets can only be read from an RSS feed.
+    let twitter_read_only = config.twitter.fetch_mode == TwitterFetchMode::Rss;
+    if twitter_read_only {
+        info!("Twitter is read-only with fetch_mode = \"rss\", nothing is posted to or deleted on Twitter");
+    } else if !api_budget_left(HistoryNetwork::Twitter, ApiCallKind::Read) {
+        warn!("The monthly Twitter API read budget is used up, skipping the sync until next month");
+        return Ok(());
+    }
+    // Attachments are downloaded once, even if they are posted to both
+    // networks or in several posts.
+    let media_cache = media_cache(args);
+    let mut mastodon_account = MastodonAccount::new(
+        config,
+        &account.id,
+        media_options(config, Platform::Twitter, &media_cache)?,
+    );
+    let mut twitter_account = TwitterAccount::new(
+        rt,
+        config,
+        media_options(config, Platform::Mastodon, &media_cache)?,
+    );
+    let clients = ApiClients {
+        rt,
+        mastodon: &mastodon,
+        account_id: &account.id,
+        token: &token,
+    };
+    let synced = sync_timelines(
+        &mut mastodon_account,
+        &mut twitter_account,
+        Some(&clients),
+        args,
+        config,
+        &account.url,
+        status,
+    )?;
+    let SyncedTimelines {
+        mastodon_statuses,
+        tweets,
+        mut id_map,
+        failures,
+    } = match synced {
+        Some(synced) => synced,
+        None => return Ok(()),
+    };
+    media_cache_cleanup(&media_cache, &failures, args);
+
+    if matches!(args.command, Some(Command::Flush)) {
+        return post_failures_result(&failures);
+    }
+    if shutdown_requested() {
+        info!("Shutting down, the rest of the run is skipped");
+        return post_failures_result(&failures);
+    }
+
+    let phase = info_span!("post").entered();
+    for source in [Platform::Mastodon, Platform::Twitter] {
+        mirror(
+            rt,
+            args,
+            config,
+            source,
+            &mastodon_statuses,
+            &tweets,
+            &account.url,
+            status,
+        )?;
+    }
+
+    if config.mastodon.monthly_digest {
+        let template = config
+            .mastodon
+            .monthly_digest_template
+            .as_deref()
+            .unwrap_or(DEFAULT_DIGEST_TEMPLATE);
+        mastodon_post_monthly_digest(&mastodon, &account, template, args.dry_run)
+            .context("Failed to post monthly digest")?;
+    }
+
+    if config.twitter.likes_to_bookmarks && !twitter_read_only {
+        let likes = rt
+            .block_on(twitter_fetch_recent_likes(config.twitter.user_id, &token))
+            .context("Failed to fetch Twitter likes")?;
+        likes_to_bookmarks(&mastodon, &likes, &id_map, args.dry_run)
+            .context("Failed to bookmark Twitter likes on Mastodon")?;
+    }
+
+    phase.exit();
+    let _phase = info_span!("delete").entered();
+    // Delete old mastodon statuses if that option is enabled.
+    let id_map_file = &cache_file("id_map.json");
+    let mut tombstones_changed = false;
+    if config.mastodon.delete_older_statuses {
+        let deleted = mastodon_delete_older_statuses(
+            &mastodon,
+            &account,
+            DeletePacing::new(
+                args.max_deletions.or(config.mastodon.delete_batch_size),
+                config.mastodon.delete_delay_ms,
+            ),
+            args.dry_run,
+        )
+        .context("Failed to delete old mastodon statuses")?;
+        tombstones_changed |= !deleted.is_empty();
+        for status in deleted {
+            id_map.insert_tombstone(Platform::Mastodon, status.id.into(), status.text.as_deref());
         }
+    }
+    let twitter_writes_left =
+        !twitter_read_only && api_budget_left(HistoryNetwork::Twitter, ApiCallKind::Write);
+    if !twitter_writes_left
+        && !twitter_read_only
+        && (config.twitter.delete_older_statuses || config.twitter.delete_older_favs)
+    {
+        warn!("The monthly Twitter API write budget is used up, skipping Twitter deletions");
+    }
+    if config.twitter.delete_older_statuses && twitter_writes_left {
+        let synced_tweets = config
+            .twitter
+            .delete_only_synced
+            .then_some(&id_map.created_tweets);
+        let deleted = rt
+            .block_on(twitter_delete_older_statuses(
+                config.twitter.user_id,
+                &token,
+                synced_tweets,
+                DeletePacing::new(
+                    args.max_deletions.or(config.twitter.delete_batch_size),
+                    config.twitter.delete_delay_ms,
+                ),
+                args.dry_run,
+            ))
+            .context("Failed to delete old twitter statuses")?;
+        tombstones_changed |= !deleted.is_empty();
+        for status in deleted {
+            id_map.insert_tombstone(Platform::Twitter, status.id.into(), status.text.as_deref());
+        }
+    }
+    // Remember the deleted statuses, so that they are not synced back.
+    if tombstones_changed {
+        id_map.save(id_map_file)?;
+    }
+
+    // Delete old mastodon favourites if that option is enabled.
+    if config.mastodon.delete_older_favs {
+        mastodon_delete_older_favs(
+            &mastodon,
+            config.mastodon.keep_latest_favs,
+            DeletePacing::new(
+                args.max_deletions.or(config.mastodon.delete_batch_size),
+                config.mastodon.delete_delay_ms,
+            ),
+            args.dry_run,
+        )
+        .context("Failed to delete old mastodon favs")?;
+    }
+    if config.twitter.delete_older_favs && twitter_writes_left {
+        rt.block_on(twitter_delete_older_favs(
+            config.twitter.user_id,
+            &token,
+            config.twitter.keep_latest_favs,
+            DeletePacing::new(
+                args.max_deletions.or(config.twitter.delete_batch_size),
+                config.twitter.delete_delay_ms,
+            ),
+            args.dry_run,
+        ))
+        .context("Failed to delete old twitter favs")?;
+    }
+
+    post_failures_result(&failures)
+}
+
+/// The timelines and the ID map after the statuses were synced, for the rest
+/// of a sync run.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+struct SyncedTimelines {
+    mastodon_statuses: Vec<Status>,
+    tweets: Vec<Tweet>,
+    id_map: IdMap,
+    failures: Vec<PostFailure>,
+}
+
+/// The API clients of a sync run, for the steps that only exist on the real
+/// networks: looking up attachments, scheduled statuses and native boosts.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+struct ApiClients<'a> {
+    rt: &'a tokio::runtime::Runtime,
+    mastodon: &'a Mastodon,
+    account_id: &'a str,
+    token: &'a egg_mode::Token,
+}
+
+/// Syncs the statuses of two accounts like a sync run, without the mirrors,
+/// likes and deletions. Mock runs and tests sync fake accounts with it.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+pub fn sync_accounts(
+    mastodon: &mut (impl SyncAccount<Status = Status> + Send),
+    twitter: &mut (impl SyncAccount<Status = Tweet> + Sync),
+    args: &Args,
+    config: &Config,
+    mastodon_profile: &str,
+    status: &mut RunStatus,
+) -> Result<()> {
+    match sync_timelines(
+        mastodon,
+        twitter,
+        None,
+        args,
+        config,
+        mastodon_profile,
+        status,
+    )? {
+        Some(synced) => post_failures_result(&synced.failures),
+        None => Ok(()),
+    }
+}
+
+// Fetches the timelines, plans the posts with the queue and the caches of the
+// accounts and posts them. Returns None if there is nothing more to do in
+// this run, for example after printing the planned posts as JSON.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn sync_timelines(
+    mastodon: &mut (impl SyncAccount<Status = Status> + Send),
+    twitter: &mut (impl SyncAccount<Status = Tweet> + Sync),
+    clients: Option<&ApiClients>,
+    args: &Args,
+    config: &Config,
+    mastodon_profile: &str,
+    status: &mut RunStatus,
+) -> Result<Option<SyncedTimelines>> {
+    // Log messages are grouped by the phase of the run.
+    let phase = info_span!("fetch").entered();
+    // Both timelines are fetched at the same time, the Twitter client is async
+    // and the Mastodon client blocks.
+    let (mastodon_statuses, tweets) = thread::scope(|scope| {
+        let span = Span::current();
+        let tweets = scope.spawn(bind_hub(|| {
+            let _span = span.enter();
+            twitter.fetch()
+        }));
+        let mastodon_statuses = mastodon.fetch();
+        let tweets = tweets.join().unwrap_or_else(|e| panic::resume_unwind(e));
+        (mastodon_statuses, tweets)
+    });
+    let mastodon_statuses = mastodon_statuses?;
+    let tweets = tweets?;
+
+    phase.exit();
+    let phase = info_span!("plan").entered();
+    let options = sync_options(config, mastodon_profile)?;
+
+    let id_map_file = &cache_file("id_map.json");
+    if let Some(Command::State {
+        command: StateCommand::Rebuild,
+    }) = args.command
+    {
+        let (id_map, post_cache) = state_rebuild(&mastodon_statuses, &tweets, &options.tweet_text)?;
+        println!(
+            "Found {} synced statuses on Mastodon and Twitter",
+            id_map.toots_to_tweets.len()
+        );
+        if !args.dry_run {
+            id_map.save(id_map_file)?;
+            let json = serde_json::to_string_pretty(&post_cache)?;
+            fs::write(cache_file("post_cache.json"), json.as_bytes())?;
+        }
+        return Ok(None);
+    }
+
+    // Posts held back on previous runs are older, so they are posted first.
+    let queue_file = &cache_file("post_queue.json");
+    let flush = matches!(args.command, Some(Command::Flush));
+    let mut conflicts = Vec::new();
+    let mut id_map = IdMap::load(id_map_file)?;
+    let mut posts = if flush {
+        queue_load(queue_file)?
+    } else {
+        // Only the size is requested, so that big attachments are not
+        // downloaded.
+        let media = |url: &str| {
+            clients?;
+            match remote_media(url) {
+                Ok(media) => Some(media),
+                Err(e) => {
+                    warn!("Failed to look up the size of attachment {url}: {e:#}");
+                    None
+                }
+            }
+        };
+        let mut posts = plan_posts(
+            &mastodon_statuses,
+            &tweets,
+            config,
+            &options,
+            &id_map,
+            &media,
+        )?;
+        // Focal points of images are fetched separately, only if needed.
+        let has_media = mastodon_statuses.iter().any(|toot| {
+            !toot.media_attachments.is_empty()
+                || toot
+                    .reblog
+                    .as_ref()
+                    .map_or(false, |reblog| !reblog.media_attachments.is_empty())
+        });
+        if let Some(clients) = clients.filter(|_| !posts.tweets.is_empty() && has_media) {
+            match mastodon_fetch_media_focus(
+                clients.mastodon,
+                clients.account_id,
+                mastodon_statuses.len(),
+            ) {
+                Ok(focus) => posts.set_media_focus(&focus),
+                Err(e) => warn!("Failed to fetch focal points of Mastodon media: {e:#}"),
+            }
+        }
+        // Queued posts were reviewed already, so only new posts are checked
+        // for near-duplicates.
+        let (mut posts, similar) = split_conflicts(
+            posts,
+            &mastodon_statuses,
+            &tweets,
+            config.mastodon.similarity_threshold,
+            config.twitter.similarity_threshold,
+            &options.tweet_text,
+        )?;
+        for conflict in similar {
+            if args.interactive && !args.dry_run && confirm_conflict(&conflict)? {
+                match conflict.target {
+                    Platform::Mastodon => posts.toots.push(conflict.status),
+                    Platform::Twitter => posts.tweets.push(conflict.status),
+                }
+            } else {
+                if !args.dry_run {
+                    warn!(
+                        "Not posting to {}, {:.0}% similar to a recent status: {}",
+                        conflict.target,
+                        conflict.similarity * 100.0,
+                        conflict.status.text
+                    );
+                }
+                let source = match conflict.target {
+                    Platform::Mastodon => Platform::Twitter,
+                    Platform::Twitter => Platform::Mastodon,
+                };
+                posts.skip(
+                    source,
+                    conflict.status.original_id.clone(),
+                    &conflict.status.text,
+                    SkipReason::Similar,
+                );
+                conflicts.push(conflict);
+            }
+        }
+        // Delays start when the source status was created, not when it is
+        // found.
+        let now = chrono::Utc::now().timestamp();
+        if let Some(minutes) = config.mastodon.publish_delay {
+            let created_at = |id: &StatusId| {
+                mastodon_statuses
+                    .iter()
+                    .find(|toot| StatusId::from(toot.id.as_str()) == *id)
+                    .map(|toot| toot.created_at.timestamp())
+            };
+            queue_delay(&mut posts.tweets, minutes, created_at, now);
+        }
+        if let Some(minutes) = config.twitter.publish_delay {
+            let created_at = |id: &StatusId| {
+                tweets
+                    .iter()
+                    .find(|tweet| StatusId::from(tweet.id) == *id)
+                    .map(|tweet| tweet.created_at.timestamp())
+            };
+            queue_delay(&mut posts.toots, minutes, created_at, now);
+        }
+        queue_merge(queue_load(queue_file)?, posts)
     };
+    if let Some(clients) = clients.filter(|_| config.mastodon.sync_scheduled && !flush) {
+        match mastodon_scheduled_statuses(clients.mastodon) {
+            Ok(scheduled) => scheduled_merge(
+                &mut posts,
+                &scheduled,
+                &options,
+                chrono::Utc::now().timestamp(),
+            ),
+            Err(e) => warn!("Failed to fetch scheduled Mastodon statuses: {e:#}"),
+        }
+    }
+    if let Some(short_links) = &options.tweet_text.short_links {
+        if !args.dry_run {
+            if let Err(e) = short_links.save() {
+                warn!("Failed to save the resolved short links: {e:#}");
+            }
+        }
+    }
+    let skipped = mem::take(&mut posts.skipped);
+    status.skipped_posts = skipped.len();
+    run_report(|report| {
+        report.skipped.extend(skipped.iter().map(|post| {
+            // Skipped posts are reported with the network they were not
+            // synced to.
+            let target = match post.source {
+                Platform::Mastodon => HistoryNetwork::Twitter,
+                Platform::Twitter => HistoryNetwork::Mastodon,
+            };
+            ReportedPost {
+                reason: Some(post.reason.to_string()),
+                ..ReportedPost::new(target, &post.original_id, &post.text)
+            }
+        }))
+    });
 
-    let con_token =
-        egg_mode::KeyPair::new(config.twitter.consumer_key, config.twitter.consumer_secret);
-    let access_token = egg_mode::KeyPair::new(
-        config.twitter.access_token,
-        config.twitter.access_token_secret,
-    );
-    let token = egg_mode::Token::Access {
-        consumer: con_token,
-        access: access_token,
+    // Statuses that are in the ID map were synced already, even if their text
+    // is different now.
+    posts = filter_synced_before(posts, &id_map);
+    // Statuses that were deleted on purpose are not recreated.
+    posts = filter_deleted_before(posts, &id_map);
+
+    // Prevent double posting with a post cache that records each new status
+    // message.
+    let post_cache_file = &cache_file("post_cache.json");
+    let mut cache_changed = false;
+    let mut post_cache = match read_post_cache(post_cache_file) {
+        Some(post_cache) => post_cache,
+        None => {
+            warn!("Rebuilding the post cache from recent statuses, double posting is possible");
+            cache_changed = true;
+            rebuild_post_cache(&mastodon_statuses, &tweets, &options.tweet_text)
+        }
     };
+    posts = filter_posted_before(posts, &post_cache);
+    // Posting a tweet that exists already fails, Twitter might have it from
+    // a run that was interrupted before the ID map was saved.
+    if filter_existing_tweets(
+        &mut posts.tweets,
+        &mastodon_statuses,
+        &tweets,
+        &options.tweet_text,
+        &mut id_map,
+    )? {
+        cache_changed = true;
+    }
+
+    // Scheduled posts wait in the queue until their time, also when flushing.
+    let mut queue = posts.hold_until_publish(chrono::Utc::now().timestamp());
+    if args.queue {
+        info!(
+            "Queueing {} post(s) for Twitter and {} post(s) for Mastodon",
+            posts.tweets.len(),
+            posts.toots.len()
+        );
+        queue.append(&mut posts);
+    }
 
-    // @todo Exclude retweets directly here if config option set.
-    let timeline = egg_mode::tweet::user_timeline(config.twitter.user_id, true, true, &token)
-        .with_page_size(50);
+    // Hold back posts outside of the posting window until a later run. Flushing
+    // the queue is explicitly requested, so it ignores the window.
+    if !flush {
+        queue_outside_window(
+            &mut posts,
+            &mut queue,
+            config.mastodon.posting_window,
+            config.twitter.posting_window,
+            Local::now().time(),
+        );
+    }
 
-    let (timeline, first_tweets) = match rt.block_on(timeline.start()) {
-        Ok(tweets) => tweets,
+    // Do not flood followers with posts, the rest is synced on the next runs.
+    let mut held_back = posts.limit(
+        args.limit.or(config.mastodon.max_posts_per_run),
+        args.limit.or(config.twitter.max_posts_per_run),
+    );
+    queue.append(&mut held_back);
+
+    // Only hand the planned posts over to an external tool.
+    if args.print_json {
+        println!("{}", posts.to_json()?);
+        return Ok(None);
+    }
+
+    // Boosts and retweets need the API clients.
+    if let Some(clients) = clients {
+        if config.twitter.native_boosts {
+            let boosts = native_boosts(
+                clients.mastodon,
+                &mut posts.toots,
+                &tweets,
+                &config.twitter.native_boost_accounts,
+                &options.tweet_text,
+                &mut id_map,
+                args.dry_run,
+            );
+            if boosts > 0 {
+                cache_changed = true;
+            }
+        }
+        if config.mastodon.self_boosts == SelfBoostMode::Native {
+            let retweets = native_self_retweets(
+                clients.rt,
+                clients.token,
+                &mut posts.tweets,
+                &mastodon_statuses,
+                &mut id_map,
+                args.dry_run,
+            );
+            if retweets > 0 {
+                cache_changed = true;
+            }
+        }
+        if config.twitter.self_boosts == SelfBoostMode::Native {
+            let boosts = native_self_boosts(
+                clients.mastodon,
+                &mut posts.toots,
+                &tweets,
+                &mut id_map,
+                args.dry_run,
+            );
+            if boosts > 0 {
+                cache_changed = true;
+            }
+        }
+    }
+
+    if args.dry_run {
+        println!(
+            "{}",
+            dry_run_report(&posts, &conflicts, &config.mastodon.app.base)
+        );
+        if !skipped.is_empty() {
+            println!("{}", skipped_summary(&skipped, args.show_skipped));
+        }
+    } else if !skipped.is_empty() {
+        info!("{}", skipped_summary(&skipped, args.show_skipped));
+    }
+
+    if !posts.tweets.is_empty() || !posts.toots.is_empty() {
+        cache_changed = true;
+    }
+    run_report(|report| {
+        for toot in &posts.toots {
+            let post = ReportedPost::new(HistoryNetwork::Mastodon, &toot.original_id, &toot.text);
+            report.planned.push(post);
+        }
+        for tweet in &posts.tweets {
+            let post = ReportedPost::new(HistoryNetwork::Twitter, &tweet.original_id, &tweet.text);
+            report.planned.push(post);
+        }
+    });
+    phase.exit();
+    let phase = info_span!("post").entered();
+    let (new_toots, new_tweets) = (posts.toots.len(), posts.tweets.len());
+    let (failures, mut unposted) = post_status_updates(
+        mastodon,
+        twitter,
+        config,
+        posts,
+        args,
+        &mut post_cache,
+        &mut id_map,
+    );
+    // Failed posts are retried on the next run, together with the thread
+    // replies that were not tried.
+    for failure in &failures {
+        run_report(|report| {
+            report.failed.push(ReportedPost {
+                reason: Some(format!("{:#}", failure.error)),
+                ..ReportedPost::new(
+                    failure.target.into(),
+                    &failure.status.original_id,
+                    &failure.status.text,
+                )
+            })
+        });
+        let queued = match failure.target {
+            Platform::Mastodon => &mut queue.toots,
+            Platform::Twitter => &mut queue.tweets,
+        };
+        queue_retry(queued, failure.status.clone());
+        queued.extend(failure.remaining.iter().cloned());
+    }
+    let failed_toots = failures
+        .iter()
+        .filter(|f| f.target == Platform::Mastodon)
+        .count();
+    status.posted_toots = new_toots - failed_toots - unposted.toots.len();
+    status.posted_tweets = new_tweets - (failures.len() - failed_toots) - unposted.tweets.len();
+    status.failed_posts = failures.len();
+    // Posts that were not reached before a shutdown follow on the next run.
+    queue.append(&mut unposted);
+    status.queued_posts = queue.toots.len() + queue.tweets.len();
+
+    // Write out the cache file if necessary.
+    if !args.dry_run {
+        queue_save(&queue, queue_file)?;
+        if cache_changed {
+            let json = serde_json::to_string_pretty(&post_cache)?;
+            fs::write(post_cache_file, json.as_bytes())?;
+            id_map.save(id_map_file)?;
+        }
+    }
+    if !args.quiet {
+        let skipped_from = |source| skipped.iter().filter(|post| post.source == source).count();
+        let rows = [
+            DirectionSummary {
+                direction: "Mastodon → Twitter",
+                posted: status.posted_tweets,
+                failed: failures.len() - failed_toots,
+                skipped: skipped_from(Platform::Mastodon),
+                queued: queue.tweets.len(),
+            },
+            DirectionSummary {
+                direction: "Twitter → Mastodon",
+                posted: status.posted_toots,
+                failed: failed_toots,
+                skipped: skipped_from(Platform::Twitter),
+                queued: queue.toots.len(),
+            },
+        ];
+        eprint!("{}", summary_table(&rows, color_enabled(args)));
+    }
+    phase.exit();
+
+    Ok(Some(SyncedTimelines {
+        mastodon_statuses,
+        tweets,
+        id_map,
+        failures,
+    }))
+}
+
+// Copies the tweets to the mirror account, for a config with only a Twitter
+// account.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn twitter_mirror_run(
+    rt: &tokio::runtime::Runtime,
+    args: &Args,
+    config: &Config,
+    status: &mut RunStatus,
+) -> Result<()> {
+    if config.twitter.mirror.is_none() {
+        bail!("No Mastodon account configured, without it only a [twitter.mirror] account can get copies of the tweets");
+    }
+    if !api_budget_left(HistoryNetwork::Twitter, ApiCallKind::Read) {
+        warn!(
+            "The monthly Twitter API read budget is used up, skipping the mirror until next month"
+        );
+        return Ok(());
+    }
+    let phase = info_span!("fetch").entered();
+    // Nothing is posted with this account, so no media options are needed.
+    let tweets = TwitterAccount::new(rt, config, MediaOptions::default()).fetch()?;
+    phase.exit();
+    let _phase = info_span!("post").entered();
+    mirror(
+        rt,
+        args,
+        config,
+        Platform::Twitter,
+        &[],
+        &tweets,
+        "",
+        status,
+    )
+}
+
+/// Copies the new statuses of the source network to the mirror account on the
+/// same network, if one is configured, see mirror_config(). The mirror account
+/// has its own ID map, post cache and queue in the cache directory.
+///
+/// On the first run the statuses are only marked as mirrored, so that the
+/// mirror account does not get a copy of the whole timeline.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+#[allow(clippy::too_many_arguments)]
+fn mirror(
+    rt: &tokio::runtime::Runtime,
+    args: &Args,
+    config: &Config,
+    source: Platform,
+    mastodon_statuses: &[Status],
+    tweets: &[Tweet],
+    mastodon_profile: &str,
+    status: &mut RunStatus,
+) -> Result<()> {
+    let mirror_config = match mirror_config(config, source) {
+        Some(mirror_config) => mirror_config,
+        None => return Ok(()),
+    };
+    let (name, account, statuses, tweets) = match source {
+        Platform::Mastodon => (
+            "mirror",
+            mirror_config.mastodon.app.base.to_string(),
+            mastodon_statuses,
+            &[][..],
+        ),
+        Platform::Twitter => (
+            "twitter_mirror",
+            mirror_config.twitter.user_name.clone(),
+            &[][..],
+            tweets,
+        ),
+    };
+    let _span = info_span!("mirror", account = %account).entered();
+    let mut options = sync_options(config, mastodon_profile)?;
+    if source == Platform::Mastodon {
+        // Statuses are not shortened for Twitter, links count as 23
+        // characters on Mastodon as well.
+        options.tweet_text.shorten.max_characters = 500;
+    }
+
+    let id_map_file = &cache_file(&format!("{name}_id_map.json"));
+    let first_run = !Path::new(id_map_file).exists();
+    let mut id_map = IdMap::load(id_map_file)?;
+    let media = |url: &str| match remote_media(url) {
+        Ok(media) => Some(media),
         Err(e) => {
-            eprintln!("Error fetching tweets from Twitter: {e:#?}");
-            process::exit(3);
+            warn!("Failed to look up the size of attachment {url}: {e:#}");
+            None
         }
     };
-    let mut tweets = (*first_tweets).to_vec();
-    // We might have only one tweet because of filtering out reply tweets. Fetch
-    // some more tweets to make sure we have enough for comparing.
-    if tweets.len() < 50 {
-        let (_, next_tweets) = match rt.block_on(timeline.older(None)) {
-            Ok(tweets) => tweets,
-            Err(e) => {
-                eprintln!("Error fetching older tweets from Twitter: {e:#?}");
-                process::exit(4);
-            }
+    let posts = plan_posts(
+        statuses,
+        tweets,
+        &mirror_config,
+        &options,
+        &id_map.reversed(),
+        &media,
+    )?;
+    let mut posts = mirror_posts(posts, source);
+    if first_run {
+        let existing = posts.toots.len() + posts.tweets.len();
+        info!("Marking {existing} existing status(es) as mirrored on the first run, new statuses are mirrored from the next run on");
+        mark_mirrored(&posts.toots, &mut id_map);
+        mark_mirrored(&posts.tweets, &mut id_map);
+        if !args.dry_run {
+            id_map.save(id_map_file)?;
+        }
+        return Ok(());
+    }
+
+    let queue_file = &cache_file(&format!("{name}_post_queue.json"));
+    let mut posts = queue_merge(queue_load(queue_file)?, posts);
+    posts = filter_synced_before(posts, &id_map);
+    posts = filter_deleted_before(posts, &id_map);
+    let post_cache_file = &cache_file(&format!("{name}_post_cache.json"));
+    let mut post_cache = read_post_cache(post_cache_file).unwrap_or_default();
+    posts = filter_posted_before(posts, &post_cache);
+    let max_posts = args.limit.or(match source {
+        Platform::Mastodon => config.mastodon.max_posts_per_run,
+        Platform::Twitter => config.twitter.max_posts_per_run,
+    });
+    let mut queue = posts.limit(max_posts, max_posts);
+    if args.dry_run {
+        println!(
+            "{}",
+            dry_run_report(&posts, &[], &mirror_config.mastodon.app.base)
+        );
+    }
+
+    let media_cache = media_cache(args);
+    let mut mastodon = MastodonAccount::new(
+        &mirror_config,
+        "",
+        media_options(&mirror_config, Platform::Twitter, &media_cache)?,
+    );
+    let mut twitter = TwitterAccount::new(
+        rt,
+        &mirror_config,
+        media_options(&mirror_config, Platform::Mastodon, &media_cache)?,
+    );
+    let (failures, mut unposted) = post_status_updates(
+        &mut mastodon,
+        &mut twitter,
+        &mirror_config,
+        posts,
+        args,
+        &mut post_cache,
+        &mut id_map,
+    );
+    media_cache_cleanup(&media_cache, &failures, args);
+    for failure in &failures {
+        let queued = match failure.target {
+            Platform::Mastodon => &mut queue.toots,
+            Platform::Twitter => &mut queue.tweets,
         };
-        tweets.append(&mut (*next_tweets).to_vec());
+        queue_retry(queued, failure.status.clone());
+        queued.extend(failure.remaining.iter().cloned());
     }
+    status.failed_posts += failures.len();
+    queue.append(&mut unposted);
+    status.queued_posts += queue.toots.len() + queue.tweets.len();
+    if !args.dry_run {
+        queue_save(&queue, queue_file)?;
+        let json = serde_json::to_string_pretty(&post_cache)?;
+        fs::write(post_cache_file, json.as_bytes())?;
+        id_map.save(id_map_file)?;
+    }
+    Ok(())
+}
 
-    let options = SyncOptions {
-        sync_reblogs: config.mastodon.sync_reblogs,
-        sync_retweets: config.twitter.sync_retweets,
-        sync_hashtag_mastodon: config.mastodon.sync_hashtag,
-        sync_hashtag_twitter: config.twitter.sync_hashtag,
+// Twitter only accepts a refresh token once, so the new token must be saved
+// even in a dry run.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn twitter_token_refresh(config: &mut Config, config_file: &str) -> Result<()> {
+    let refreshed = match config.twitter.oauth2.as_mut() {
+        Some(oauth2) => twitter_oauth2_refresh(oauth2)?,
+        None => false,
+    };
+    if refreshed {
+        config_save(config, config_file).context("Failed to save the refreshed Twitter token")?;
+    }
+    Ok(())
+}
+
+/// Posts the status updates to both networks. A failing post does not stop the
+/// others, the failures are returned for a summary at the end together with
+/// the posts that were not tried because of a shutdown.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn post_status_updates(
+    mastodon: &mut (impl SyncAccount<Status = Status> + Send),
+    twitter: &mut impl SyncAccount<Status = Tweet>,
+    config: &Config,
+    posts: StatusUpdates,
+    args: &Args,
+    post_cache: &mut PostCache,
+    id_map: &mut IdMap,
+) -> (Vec<PostFailure>, StatusUpdates) {
+    // Both networks are posted to at the same time, they share the ID map and
+    // the post cache.
+    let id_map = Mutex::new(id_map);
+    let post_cache = Mutex::new(post_cache);
+    let StatusUpdates { toots, tweets, .. } = posts;
+    let ((mut failures, mut history, toots), (tweet_failures, tweet_history, tweets)) =
+        thread::scope(|scope| {
+            // The thread logs in the phase of the run, too.
+            let span = Span::current();
+            let toots = scope.spawn(bind_hub(|| {
+                let _span = span.enter();
+                post_toots(mastodon, config, toots, args, &post_cache, &id_map)
+            }));
+            let tweets = post_tweets(twitter, config, tweets, args, &post_cache, &id_map);
+            let toots = toots.join().unwrap_or_else(|e| panic::resume_unwind(e));
+            (toots, tweets)
+        });
+    failures.extend(tweet_failures);
+    history.extend(tweet_history);
+    if !args.dry_run {
+        history_append(history);
+    }
+    let unposted = StatusUpdates {
+        toots,
+        tweets,
+        ..StatusUpdates::default()
     };
+    (failures, unposted)
+}
 
-    let mut posts = determine_posts(&mastodon_statuses, &tweets, &options);
+// Attachments are kept in the media cache directory of the arguments or in
+// the cache directory of the accounts.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn media_cache(args: &Args) -> Arc<MediaCache> {
+    let media_cache_dir = match &args.media_cache_dir {
+        Some(dir) => dir.clone(),
+        None => cache_file("media_cache"),
+    };
+    Arc::new(MediaCache::new(Some(PathBuf::from(media_cache_dir))))
+}
 
-    // Prevent double posting with a post cache that records each new status
-    // message.
-    let post_cache_file = &cache_file("post_cache.json");
-    let mut post_cache = read_post_cache(post_cache_file);
-    let mut cache_changed = false;
-    posts = filter_posted_before(posts, &post_cache)?;
+// Media options of the source network apply to the attachments of its
+// statuses.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn media_options(
+    config: &Config,
+    source: Platform,
+    media_cache: &Arc<MediaCache>,
+) -> Result<MediaOptions> {
+    let (strip_metadata, watermark) = match source {
+        Platform::Mastodon => (config.mastodon.strip_metadata, &config.mastodon.watermark),
+        Platform::Twitter => (config.twitter.strip_metadata, &config.twitter.watermark),
+    };
+    let watermark = match watermark {
+        Some(watermark) => Some(Arc::new(Watermark::load(watermark)?)),
+        None => None,
+    };
+    Ok(MediaOptions {
+        strip_metadata,
+        watermark,
+        cache: Some(media_cache.clone()),
+    })
+}
+
+// Without a media cache directory only the verified attachments of failed
+// posts are kept, they are reused when the posts are tried again.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn media_cache_cleanup(media_cache: &MediaCache, failures: &[PostFailure], args: &Args) {
+    if args.media_cache_dir.is_some() || args.dry_run {
+        return;
+    }
+    let mut urls = BTreeSet::new();
+    for failure in failures {
+        for status in iter::once(&failure.status).chain(&failure.remaining) {
+            attachment_urls(status, &mut urls);
+        }
+    }
+    if let Err(e) = media_cache.retain(&urls) {
+        warn!("Failed to clean up the media cache: {e}");
+    }
+}
 
-    for toot in posts.toots {
+// Posts the toots and returns the failures, the history events and the toots
+// that were not posted because of a shutdown.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn post_toots(
+    mastodon: &mut impl SyncAccount<Status = Status>,
+    config: &Config,
+    toots: Vec<NewStatus>,
+    args: &Args,
+    post_cache: &Mutex<&mut PostCache>,
+    id_map: &Mutex<&mut IdMap>,
+) -> (Vec<PostFailure>, Vec<HistoryEvent>, Vec<NewStatus>) {
+    let mut failures = Vec::new();
+    let mut history = Vec::new();
+    let mut unposted = Vec::new();
+    let mut toots = toots.into_iter();
+    for toot in toots.by_ref() {
+        // The current post is finished on shutdown, the others are queued.
+        if shutdown_requested() {
+            unposted.push(toot);
+            break;
+        }
+        let _span =
+            info_span!("post", network = "mastodon", original_id = %toot.original_id).entered();
+        post_breadcrumb("Mastodon", &toot.original_id);
         if !args.skip_existing_posts {
-            if let Err(e) = post_to_mastodon(&mastodon, &toot, args.dry_run) {
-                eprintln!("Error posting toot to Mastodon: {e:#?}");
-                continue;
+            // The post cache keeps the original text, so the transformed
+            // status is only used for posting.
+            let result = match &config.twitter.transform_cmd {
+                Some(cmd) => transform_status(cmd, &toot).map_err(|e| PostError::new(&toot, e)),
+                None => Ok(toot.clone()),
+            }
+            .and_then(|t| mastodon.post(&t, args.dry_run));
+            let (created, failure) = match result {
+                Ok(created) => (created, None),
+                Err(e) => split_post_error(Platform::Mastodon, &toot, e),
+            };
+            let posted_any = !created.is_empty();
+            for posted in created {
+                info!("Posted to Mastodon: {}", posted.url);
+                run_report(|report| {
+                    report.created.push(ReportedPost {
+                        url: Some(posted.url.clone()),
+                        ..ReportedPost::new(
+                            HistoryNetwork::Mastodon,
+                            &posted.original_id,
+                            &posted.text,
+                        )
+                    })
+                });
+                history.push(HistoryEvent::Posted {
+                    target: HistoryNetwork::Mastodon,
+                    delay: sync_delay(&posted.original_id, HistoryNetwork::Twitter),
+                    url: Some(posted.url.clone()),
+                });
+                // The source of toots is always a numeric tweet ID.
+                if let Some(tweet_id) = posted.original_id.as_u64() {
+                    id_map.lock().unwrap().insert(posted.id.clone(), tweet_id);
+                }
+                if let Some(key) = &posted.idempotency_key {
+                    id_map
+                        .lock()
+                        .unwrap()
+                        .idempotency_keys
+                        .insert(posted.original_id.clone(), key.clone());
+                }
+                run_posted_cmd(&config.twitter.posted_cmd, &posted);
+            }
+            id_map.lock().unwrap().insert_content_hashes(&toot);
+            if let Some(failure) = failure {
+                error!("Error posting toot to Mastodon: {:#?}", failure.error);
+                history.push(HistoryEvent::Failed {
+                    target: HistoryNetwork::Mastodon,
+                    reason: failure.error.to_string(),
+                });
+                failures.push(failure);
+                // A thread is in the post cache once its first status is
+                // posted, the rest of it is queued.
+                if !posted_any {
+                    continue;
+                }
             }
         }
         // Posting API call was successful: store text in cache to prevent any
         // double posting next time.
         if !args.dry_run {
-            post_cache.insert(toot.text);
-            cache_changed = true;
+            post_cache.lock().unwrap().insert(Platform::Mastodon, &toot);
         }
     }
+    unposted.extend(toots);
+    (failures, history, unposted)
+}
 
-    for tweet in posts.tweets {
+// Posts the tweets and returns the failures, the history events and the tweets
+// that were not posted because of a shutdown.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn post_tweets(
+    twitter: &mut impl SyncAccount<Status = Tweet>,
+    config: &Config,
+    tweets: Vec<NewStatus>,
+    args: &Args,
+    post_cache: &Mutex<&mut PostCache>,
+    id_map: &Mutex<&mut IdMap>,
+) -> (Vec<PostFailure>, Vec<HistoryEvent>, Vec<NewStatus>) {
+    let mut failures = Vec::new();
+    let mut history = Vec::new();
+    let mut unposted = Vec::new();
+    let mut tweets = tweets.into_iter();
+    for tweet in tweets.by_ref() {
+        // The current post is finished on shutdown, the others are queued.
+        if shutdown_requested() {
+            unposted.push(tweet);
+            break;
+        }
+        if !args.skip_existing_posts
+            && !api_budget_left(HistoryNetwork::Twitter, ApiCallKind::Write)
+        {
+            warn!("The monthly Twitter API write budget is used up, queueing the remaining tweets");
+            unposted.push(tweet);
+            break;
+        }
+        let _span =
+            info_span!("post", network = "twitter", original_id = %tweet.original_id).entered();
+        post_breadcrumb("Twitter", &tweet.original_id);
         if !args.skip_existing_posts {
-            if let Err(e) = rt.block_on(post_to_twitter(&token, &tweet, args.dry_run)) {
-                eprintln!("Error posting tweet to Twitter: {e:#?}");
-                continue;
+            // The post cache keeps the original text, so the transformed
+            // status is only used for posting.
+            let result = match &config.mastodon.transform_cmd {
+                Some(cmd) => transform_status(cmd, &tweet).map_err(|e| PostError::new(&tweet, e)),
+                None => Ok(tweet.clone()),
+            }
+            .and_then(|t| twitter.post(&t, args.dry_run));
+            let (created, failure) = match result {
+                Ok(created) => (created, None),
+                // Twitter already has this status, so consider it synced.
+                Err(e) if twitter_is_duplicate_error(&e.error) => {
+                    warn!(
+                        "Twitter rejected duplicate tweet, skipping: {}",
+                        e.failed.text
+                    );
+                    (e.created, None)
+                }
+                Err(e) => split_post_error(Platform::Twitter, &tweet, e),
+            };
+            let posted_any = !created.is_empty();
+            for posted in created {
+                info!("Posted to Twitter: {}", posted.url);
+                run_report(|report| {
+                    report.created.push(ReportedPost {
+                        url: Some(posted.url.clone()),
+                        ..ReportedPost::new(
+                            HistoryNetwork::Twitter,
+                            &posted.original_id,
+                            &posted.text,
+                        )
+                    })
+                });
+                history.push(HistoryEvent::Posted {
+                    target: HistoryNetwork::Twitter,
+                    delay: sync_delay(&posted.original_id, HistoryNetwork::Mastodon),
+                    url: Some(posted.url.clone()),
+                });
+                if let Some(tweet_id) = posted.id.as_u64() {
+                    id_map
+                        .lock()
+                        .unwrap()
+                        .insert_created_tweet(posted.original_id.clone(), tweet_id);
+                }
+                run_posted_cmd(&config.mastodon.posted_cmd, &posted);
+            }
+            id_map.lock().unwrap().insert_content_hashes(&tweet);
+            if let Some(failure) = failure {
+                error!("Error posting tweet to Twitter: {:#?}", failure.error);
+                history.push(HistoryEvent::Failed {
+                    target: HistoryNetwork::Twitter,
+                    reason: failure.error.to_string(),
+                });
+                failures.push(failure);
+                if !posted_any {
+                    continue;
+                }
             }
         }
         // Posting API call was successful: store text in cache to prevent any
         // double posting next time.
         if !args.dry_run {
-            post_cache.insert(tweet.text);
-            cache_changed = true;
+            post_cache.lock().unwrap().insert(Platform::Twitter, &tweet);
         }
     }
+    unposted.extend(tweets);
+    (failures, history, unposted)
+}
 
-    // Write out the cache file if necessary.
-    if !args.dry_run && cache_changed {
-        let json = serde_json::to_string_pretty(&post_cache)?;
-        fs::write(post_cache_file, json.as_bytes())?;
+// Splits the error of posting a thread into the statuses that were created and
+// the failure with the rest of the thread. If nothing was created the status
+// is retried as it was, without the changes of a transform_cmd.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn split_post_error(
+    target: Platform,
+    status: &NewStatus,
+    error: PostError,
+) -> (Vec<PostedStatus>, Option<PostFailure>) {
+    let PostError {
+        created,
+        failed,
+        remaining,
+        error,
+    } = error;
+    let failure = if created.is_empty() {
+        PostFailure {
+            target,
+            status: status.clone(),
+            remaining: Vec::new(),
+            error,
+        }
+    } else {
+        PostFailure {
+            target,
+            status: failed,
+            remaining,
+            error,
+        }
+    };
+    (created, Some(failure))
+}
+
+// Returns the seconds since the source status was posted.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn sync_delay(original_id: &StatusId, source: HistoryNetwork) -> Option<i64> {
+    let posted_at = snowflake_time(original_id.as_u64()?, source)?;
+    Some(chrono::Utc::now().timestamp() - posted_at)
+}
+
+// Collects the attachment URLs of a status and its thread replies.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn attachment_urls(status: &NewStatus, urls: &mut BTreeSet<String>) {
+    for attachment in &status.attachments {
+        urls.insert(attachment.attachment_url.clone());
     }
+    for reply in &status.replies {
+        attachment_urls(reply, urls);
+    }
+}
 
-    // Delete old mastodon statuses if that option is enabled.
-    if config.mastodon.delete_older_statuses {
-        mastodon_delete_older_statuses(&mastodon, &account, args.dry_run)
-            .context("Failed to delete old mastodon statuses")?;
+/// Passes a created status to the posted_cmd hook. The status is already
+/// posted, so a failing hook is only logged.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn run_posted_cmd(cmd: &Option<String>, posted: &PostedStatus) {
+    if let Some(cmd) = cmd {
+        if let Err(e) = run_status_command(cmd, posted) {
+            error!("Posted command failed for {}: {e:#}", posted.url);
+        }
     }
-    if config.twitter.delete_older_statuses {
-        rt.block_on(twitter_delete_older_statuses(
-            config.twitter.user_id,
-            &token,
-            args.dry_run,
-        ))
-        .context("Failed to delete old twitter statuses")?;
+}
+
+/// Asks on the console whether a status that is similar to a recent status
+/// should be posted anyway.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn confirm_conflict(conflict: &Conflict) -> Result<bool> {
+    println!(
+        "This status is {:.0}% similar to a recent {} status:\n{}\n\nExisting status:\n{}\n",
+        conflict.similarity * 100.0,
+        conflict.target,
+        conflict.status.text,
+        conflict.existing
+    );
+    let answer = console_input(&format!("Post it to {} anyway? [y/N]", conflict.target))?;
+    Ok(answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"))
+}
+
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn post_failures_result(failures: &[PostFailure]) -> Result<()> {
+    if !failures.is_empty() {
+        print_post_failures(failures);
+        bail!("{} post(s) failed to sync", failures.len());
     }
+    Ok(())
+}
 
-    // Delete old mastodon favourites if that option is enabled.
-    if config.mastodon.delete_older_favs {
-        mastodon_delete_older_favs(&mastodon, args.dry_run)
-            .context("Failed to delete old mastodon favs")?;
+/// Builds the options for comparing and converting statuses from the config.
+/// The Mastodon profile URL marks statuses that link back to it as crossposts.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+pub fn sync_options(config: &Config, mastodon_profile: &str) -> Result<SyncOptions> {
+    let mut sync_hashtags_mastodon = HashtagFilter::new(
+        config
+            .mastodon
+            .sync_hashtag
+            .iter()
+            .chain(&config.mastodon.sync_hashtags)
+            .cloned(),
+        config.mastodon.sync_hashtags_match,
+    );
+    sync_hashtags_mastodon.remove = config.mastodon.remove_sync_hashtag;
+    let mut sync_hashtags_twitter = HashtagFilter::new(
+        config
+            .twitter
+            .sync_hashtag
+            .iter()
+            .chain(&config.twitter.sync_hashtags)
+            .cloned(),
+        config.twitter.sync_hashtags_match,
+    );
+    sync_hashtags_twitter.remove = config.twitter.remove_sync_hashtag;
+    let mut removed_hashtags: Vec<String> = [&sync_hashtags_mastodon, &sync_hashtags_twitter]
+        .into_iter()
+        .filter(|filter| filter.remove)
+        .flat_map(|filter| filter.required.clone())
+        .collect();
+    // Hashtags of hashtag_map filters are only on one network.
+    let mut hashtag_aliases = Vec::new();
+    for filters in [&config.mastodon.filters, &config.twitter.filters] {
+        let (aliases, appended) = hashtag_map_changes(filters);
+        hashtag_aliases.extend(aliases);
+        removed_hashtags.extend(appended);
     }
-    if config.twitter.delete_older_favs {
-        rt.block_on(twitter_delete_older_favs(
-            config.twitter.user_id,
-            &token,
-            args.dry_run,
-        ))
-        .context("Failed to delete old twitter favs")?;
+
+    Ok(SyncOptions {
+        sync_reblogs: config.mastodon.sync_reblogs,
+        sync_retweets: config.twitter.sync_retweets,
+        retweet_sync_mode_mastodon: config.mastodon.retweet_sync_mode,
+        retweet_sync_mode_twitter: config.twitter.retweet_sync_mode,
+        sync_hashtags_mastodon,
+        sync_hashtags_twitter,
+        boost_deny_instances: config.mastodon.boost_deny_instances.clone(),
+        skip_sources_twitter: config.twitter.skip_sources.clone(),
+        skip_sources_mastodon: config.mastodon.skip_sources.clone(),
+        crosspost_patterns_twitter: crosspost_patterns(
+            &config.twitter.crosspost_patterns,
+            mastodon_profile
+                .trim_start_matches("https://")
+                .trim_start_matches("http://"),
+        )?,
+        crosspost_patterns_mastodon: crosspost_patterns(
+            &config.mastodon.crosspost_patterns,
+            &format!("twitter.com/{}", config.twitter.user_name),
+        )?,
+        sync_replies_to_others_mastodon: config.mastodon.sync_replies_to_others,
+        sync_replies_to_others_twitter: config.twitter.sync_replies_to_others,
+        max_thread_depth_mastodon: config.mastodon.max_thread_depth,
+        max_thread_depth_twitter: config.twitter.max_thread_depth,
+        thread_strategy_twitter: config.twitter.thread_strategy,
+        thread_strategy_mastodon: config.mastodon.thread_strategy,
+        direct_posts_mastodon: config.mastodon.direct_posts,
+        self_boosts_twitter: config.twitter.self_boosts,
+        self_boosts_mastodon: config.mastodon.self_boosts,
+        link_preview_images_mastodon: config.mastodon.link_preview_images,
+        tweet_text: TweetTextOptions {
+            expand_urls: config.twitter.expand_urls,
+            escape_mentions: config.twitter.escape_mentions,
+            decode_html_entities: config.twitter.decode_html_entities,
+            removed_hashtags,
+            hashtag_aliases,
+            short_links: config.twitter.resolve_short_links.then(|| {
+                Arc::new(ShortLinkResolver::load(Some(&cache_file(
+                    "short_links.json",
+                ))))
+            }),
+            shorten: ShortenOptions {
+                max_characters: 280,
+                character_reserve: config.twitter.character_reserve,
+                ellipsis: config.twitter.shorten_ellipsis.clone().into(),
+                link_to_source: config.twitter.shorten_with_link,
+            },
+        },
+    })
+}
+
+// Subdirectory for the cache files of the synced accounts, so that several
+// config files can be used in the same directory.
+static CACHE_NAMESPACE: std::sync::Mutex<Option<String>> = std::sync::Mutex::new(None);
+
+// Cache files that were shared by all accounts before they got their own
+// subdirectory.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+const LEGACY_CACHE_FILES: &[&str] = &[
+    "id_map.json",
+    "post_cache.json",
+    "post_queue.json",
+    "likes_bookmark_cache.json",
+    "last_run.json",
+    "digest_cache.json",
+    "mastodon_cache.json",
+    "twitter_cache.json",
+    "mastodon_fav_cache.json",
+    "twitter_fav_cache.json",
+    "media_cache",
+];
+
+/// Returns the full path for a cache file name, in the subdirectory of the
+/// accounts once the config is loaded.
+fn cache_file(name: &str) -> String {
+    let cache_dir = crate::paths::cache_dir();
+    let namespace = CACHE_NAMESPACE.lock().unwrap().clone();
+    match (cache_dir, namespace) {
+        (Some(cache_dir), Some(namespace)) => format!("{cache_dir}/{namespace}/{name}"),
+        (Some(dir), None) | (None, Some(dir)) => format!("{dir}/{name}"),
+        (None, None) => name.into(),
     }
+}
 
+// Prints the absolute paths of the config file and of the cache directory of
+// the accounts, which is only known once the config file exists.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn print_paths(config_file: &str) -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let config = current_dir.join(config_file);
+    if config.exists() {
+        println!("Config file: {}", config.display());
+    } else {
+        println!("Config file: {} (not created yet)", config.display());
+    }
+    println!(
+        "Cache directory: {}",
+        current_dir.join(cache_file("")).display()
+    );
     Ok(())
 }
 
-/// Returns the full path for a cache file name.
-fn cache_file(name: &str) -> String {
-    if let Ok(cache_dir) = std::env::var("MTS_CACHE_DIR") {
-        return format!("{cache_dir}/{name}");
+/// Puts the cache files into a subdirectory named after the Mastodon instance
+/// and the Twitter user ID. Cache files of older versions are moved there.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn set_cache_namespace(config: &Config) -> Result<()> {
+    let namespace = cache_namespace(&config.mastodon.app.base, config.twitter.user_id);
+    *CACHE_NAMESPACE.lock().unwrap() = None;
+    let legacy_dir = PathBuf::from(cache_file(""));
+    let dir = legacy_dir.join(&namespace);
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+    for name in LEGACY_CACHE_FILES {
+        let legacy = legacy_dir.join(name);
+        if legacy.exists() && !dir.join(name).exists() {
+            info!(
+                "Moving cache file {} to {}",
+                legacy.display(),
+                dir.display()
+            );
+            fs::rename(&legacy, dir.join(name))?;
+        }
+    }
+    *CACHE_NAMESPACE.lock().unwrap() = Some(namespace);
+    Ok(())
+}
+
+// Keeps the ID map, queue and caches of mock runs in a subdirectory, apart
+// from the cache files of the real accounts.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn set_mock_cache_namespace() -> Result<()> {
+    let dir = cache_file("mock");
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create cache directory {dir}"))?;
+    if let Some(namespace) = CACHE_NAMESPACE.lock().unwrap().as_mut() {
+        namespace.push_str("/mock");
     }
-    name.into()
+    Ok(())
+}
+
+// Returns a directory name like "mastodon.social-1234" for the accounts.
+#[cfg(all(feature = "mastodon", feature = "twitter"))]
+fn cache_namespace(mastodon_base: &str, twitter_user_id: u64) -> String {
+    let instance = mastodon_base
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_end_matches('/');
+    let instance: String = instance
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{instance}-{twitter_user_id}")
 }
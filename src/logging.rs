@@ -0,0 +1,60 @@
+use std::env;
+use std::io::{stderr, IsTerminal};
+use tracing::Subscriber;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+use crate::args::{Args, LogFormat};
+use crate::progress::progress_init;
+
+/// Sets up log output on stderr.
+///
+/// The RUST_LOG environment variable overrides the level given on the command
+/// line and can set levels per module, for example
+/// RUST_LOG=mastodon_twitter_sync::post=debug.
+pub fn logging_init(args: &Args) {
+    let level = if args.quiet {
+        "warn"
+    } else if args.verbose {
+        "debug"
+    } else {
+        "info"
+    };
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("warn,mastodon_twitter_sync={level}")));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    // Progress bars would garble JSON log lines.
+    progress_init(!args.quiet && matches!(args.log_format, LogFormat::Text));
+    match args.log_format {
+        LogFormat::Text => init(
+            builder
+                .without_time()
+                .with_target(false)
+                .with_ansi(color_enabled(args))
+                .finish(),
+        ),
+        LogFormat::Json => init(builder.json().finish()),
+    }
+}
+
+/// Returns true if the output on the terminal is colored.
+pub fn color_enabled(args: &Args) -> bool {
+    !args.no_color && env::var_os("NO_COLOR").is_none() && stderr().is_terminal()
+}
+
+// With the sentry feature log messages are added as breadcrumbs to error
+// reports and logged errors are reported as well.
+fn init<S>(subscriber: S)
+where
+    S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync + 'static,
+{
+    #[cfg(feature = "sentry")]
+    let subscriber = {
+        use tracing_subscriber::layer::SubscriberExt;
+        subscriber.with(sentry::integrations::tracing::layer())
+    };
+    subscriber.init();
+}
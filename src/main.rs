@@ -1,16 +1,9 @@
 use clap::Parser;
-use mastodon_twitter_sync::{args::Args, run};
-
-fn main() {
-    env_logger::init();
+use mastodon_twitter_sync::{args::Args, logging::logging_init, run_mode::run_main};
+use std::process::ExitCode;
 
+fn main() -> ExitCode {
     let args = Args::parse();
-
-    if let Err(err) = run(args) {
-        eprintln!("Error: {err}");
-        for cause in err.chain().skip(1) {
-            eprintln!("Because: {cause}");
-        }
-        std::process::exit(1);
-    }
+    logging_init(&args);
+    run_main(args)
 }
@@ -0,0 +1,428 @@
+// Without Twitter only the deletions use the Mastodon API.
+#![cfg_attr(not(feature = "twitter"), allow(dead_code))]
+
+use crate::api_budget::{api_call, ApiCallKind};
+use crate::config::save_dates_to_cache;
+use crate::delete_pacing::RateLimit;
+use crate::errors::SyncError;
+use crate::history::HistoryNetwork;
+use crate::progress::{Progress, ProgressUnit};
+use anyhow::Result;
+use chrono::prelude::*;
+use elefren::entities::status::Status;
+use elefren::status_builder::NewStatus as DraftStatus;
+use elefren::Error as ElefrenError;
+use elefren::Mastodon;
+use reqwest::header::{HeaderMap, LINK};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Returns true if the Mastodon API responded that the account is gone: HTTP
+/// 410 Gone or 404 Not Found for the own account, which was deleted or moved
+/// away from the instance, or the error of a suspended account.
+pub fn mastodon_account_gone(error: &ElefrenError) -> bool {
+    match error {
+        ElefrenError::Client(status) => matches!(status.as_u16(), 404 | 410),
+        ElefrenError::Http(error) => matches!(error.status().map(|s| s.as_u16()), Some(404 | 410)),
+        // Suspended accounts can still authenticate, but the API refuses to
+        // work for them.
+        ElefrenError::Api(error) => error.error.as_deref().is_some_and(|message| {
+            message == "Your login is currently disabled" || message.contains("suspended")
+        }),
+        _ => false,
+    }
+}
+
+/// Sends a request that removes something, like deleting a status, and
+/// returns the rate limit of the response and the text of the removed status.
+///
+/// The status could have been deleted already by the user, the API responds
+/// with an error in that case which is fine for us.
+pub fn mastodon_remove(
+    mastodon: &Mastodon,
+    method: Method,
+    url: &str,
+) -> Result<(RateLimit, Option<String>)> {
+    api_call(HistoryNetwork::Mastodon, ApiCallKind::Write);
+    let response = reqwest::blocking::Client::new()
+        .request(method, url)
+        .bearer_auth(&mastodon.token)
+        .send()?;
+    if response.status() == StatusCode::TOO_MANY_REQUESTS {
+        return Ok((RateLimit::Exceeded, None));
+    }
+    if response.status().is_client_error() {
+        return Ok((RateLimit::Remaining(None), None));
+    }
+    let rate_limit = RateLimit::Remaining(
+        response
+            .error_for_status_ref()?
+            .headers()
+            .get("x-ratelimit-remaining")
+            .and_then(|remaining| remaining.to_str().ok())
+            .and_then(|remaining| remaining.parse().ok()),
+    );
+    // Mastodon returns the source text of deleted statuses for "delete and
+    // redraft".
+    let text = response
+        .json::<serde_json::Value>()
+        .ok()
+        .and_then(|status| status["text"].as_str().map(str::to_string));
+    Ok((rate_limit, text))
+}
+
+/// Bookmarks a status, elefren has no API method for that.
+pub fn mastodon_bookmark(mastodon: &Mastodon, status_id: &str) -> Result<()> {
+    let url = format!("{}/api/v1/statuses/{status_id}/bookmark", mastodon.base);
+    api_call(HistoryNetwork::Mastodon, ApiCallKind::Write);
+    reqwest::blocking::Client::new()
+        .post(url)
+        .bearer_auth(&mastodon.token)
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Posts a status with an Idempotency-Key header, elefren does not support
+/// setting headers. Requests that fail because of network errors are sent
+/// again, the key makes sure that the status is only created once.
+pub fn mastodon_new_status(
+    mastodon: &Mastodon,
+    status: &DraftStatus,
+    idempotency_key: &str,
+) -> Result<Status> {
+    let url = format!("{}/api/v1/statuses", mastodon.base);
+    let client = reqwest::blocking::Client::new();
+    let mut attempt = 1;
+    loop {
+        api_call(HistoryNetwork::Mastodon, ApiCallKind::Write);
+        let result = client
+            .post(&url)
+            .bearer_auth(&mastodon.token)
+            .header("Idempotency-Key", idempotency_key)
+            .json(status)
+            .send();
+        match result {
+            Err(e) if attempt < POST_ATTEMPTS && (e.is_connect() || e.is_timeout()) => {
+                warn!("Posting to Mastodon failed, trying again: {e}");
+                attempt += 1;
+                sleep(PAGE_DELAY);
+            }
+            result => {
+                return result
+                    .and_then(|response| response.error_for_status()?.json())
+                    .map_err(|e| SyncError::MastodonApi(ElefrenError::Http(e)).into())
+            }
+        }
+    }
+}
+
+// The parts of a status that are needed for focal points of media.
+#[derive(Deserialize)]
+struct FocusStatus {
+    #[serde(default)]
+    media_attachments: Vec<FocusAttachment>,
+    reblog: Option<Box<FocusStatus>>,
+}
+
+#[derive(Deserialize)]
+struct FocusAttachment {
+    url: String,
+    meta: Option<FocusMeta>,
+}
+
+#[derive(Deserialize)]
+struct FocusMeta {
+    focus: Option<FocalPoint>,
+}
+
+#[derive(Deserialize)]
+struct FocalPoint {
+    x: f64,
+    y: f64,
+}
+
+/// Fetches the focal points of media in the recent statuses of an account by
+/// media URL, elefren does not know about them.
+pub fn mastodon_fetch_media_focus(
+    mastodon: &Mastodon,
+    account_id: &str,
+    limit: usize,
+) -> Result<BTreeMap<String, (f64, f64)>> {
+    let url = format!(
+        "{}/api/v1/accounts/{account_id}/statuses?limit={limit}",
+        mastodon.base
+    );
+    let client = reqwest::blocking::Client::new();
+    let json = mastodon_get_with_backoff(&client, mastodon, &url)?.text()?;
+    media_focus_from_json(&json)
+}
+
+fn media_focus_from_json(json: &str) -> Result<BTreeMap<String, (f64, f64)>> {
+    let statuses: Vec<FocusStatus> = serde_json::from_str(json)?;
+    let mut focus = BTreeMap::new();
+    let mut pending: Vec<&FocusStatus> = statuses.iter().collect();
+    while let Some(status) = pending.pop() {
+        for attachment in &status.media_attachments {
+            if let Some(point) = attachment
+                .meta
+                .as_ref()
+                .and_then(|meta| meta.focus.as_ref())
+            {
+                focus.insert(attachment.url.clone(), (point.x, point.y));
+            }
+        }
+        if let Some(reblog) = &status.reblog {
+            pending.push(reblog);
+        }
+    }
+    Ok(focus)
+}
+
+/// A status that is scheduled to be published later, elefren does not know
+/// about them.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduledStatus {
+    pub id: String,
+    pub scheduled_at: DateTime<Utc>,
+    pub params: ScheduledParams,
+    #[serde(default)]
+    pub media_attachments: Vec<ScheduledAttachment>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduledParams {
+    pub text: String,
+    pub visibility: Option<String>,
+    pub in_reply_to_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduledAttachment {
+    pub url: String,
+    pub description: Option<String>,
+}
+
+/// Fetches the scheduled statuses of the account, only the first page of 20.
+pub fn mastodon_scheduled_statuses(mastodon: &Mastodon) -> Result<Vec<ScheduledStatus>> {
+    let url = format!("{}/api/v1/scheduled_statuses", mastodon.base);
+    let client = reqwest::blocking::Client::new();
+    let json = mastodon_get_with_backoff(&client, mastodon, &url)?.text()?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+// Wait between fetching pages to stay below the rate limit of the instance.
+const PAGE_DELAY: Duration = Duration::from_secs(1);
+// How often posting a status is tried when the instance cannot be reached.
+const POST_ATTEMPTS: u32 = 3;
+// How often a request is repeated when the instance responds with HTTP 429.
+const MAX_RATE_LIMIT_RETRIES: u32 = 5;
+
+// Pagination state of an interrupted fetch, so that the next run can continue
+// where this one stopped.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FetchProgress {
+    next_url: Option<String>,
+    dates: BTreeMap<DateTime<Utc>, u64>,
+}
+
+/// Fetches dates and IDs of all statuses from a paginated Mastodon API
+/// endpoint and writes them to the cache file.
+///
+/// elefren does not expose pagination links, so the pages are requested
+/// directly. The progress is saved after every page, if fetching fails because
+/// of rate limits the next run continues from there.
+pub fn mastodon_fetch_dates(
+    mastodon: &Mastodon,
+    first_url: &str,
+    cache_file: &str,
+) -> Result<BTreeMap<DateTime<Utc>, u64>> {
+    let progress_file = format!("{cache_file}.progress");
+    let mut progress = match fs::read_to_string(&progress_file) {
+        Ok(json) => {
+            info!("Continuing to fetch Mastodon statuses from {progress_file}");
+            serde_json::from_str(&json)?
+        }
+        Err(_) => FetchProgress {
+            next_url: Some(first_url.to_string()),
+            dates: BTreeMap::new(),
+        },
+    };
+
+    let client = reqwest::blocking::Client::new();
+    let mut bar = Progress::new(
+        "Fetching Mastodon statuses",
+        None,
+        ProgressUnit::Items("statuses"),
+    );
+    while let Some(url) = progress.next_url.take() {
+        let response = mastodon_get_with_backoff(&client, mastodon, &url)?;
+        progress.next_url = response
+            .headers()
+            .get(LINK)
+            .and_then(|link| link.to_str().ok())
+            .and_then(next_link);
+        let statuses: Vec<Status> = response.json()?;
+        if statuses.is_empty() {
+            progress.next_url = None;
+        }
+        bar.inc(statuses.len() as u64);
+        for status in statuses {
+            progress
+                .dates
+                .insert(status.created_at, u64::from_str(&status.id)?);
+        }
+
+        if progress.next_url.is_some() {
+            fs::write(&progress_file, serde_json::to_string(&progress)?)?;
+            sleep(PAGE_DELAY);
+        }
+    }
+
+    save_dates_to_cache(cache_file, &progress.dates)?;
+    if Path::new(&progress_file).exists() {
+        fs::remove_file(&progress_file)?;
+    }
+    Ok(progress.dates)
+}
+
+// Sends a GET request and waits and repeats it if the rate limit is exceeded.
+fn mastodon_get_with_backoff(
+    client: &reqwest::blocking::Client,
+    mastodon: &Mastodon,
+    url: &str,
+) -> Result<reqwest::blocking::Response> {
+    let mut retries = 0;
+    loop {
+        api_call(HistoryNetwork::Mastodon, ApiCallKind::Read);
+        let response = client.get(url).bearer_auth(&mastodon.token).send()?;
+        if response.status() != StatusCode::TOO_MANY_REQUESTS || retries == MAX_RATE_LIMIT_RETRIES {
+            return Ok(response.error_for_status()?);
+        }
+        retries += 1;
+        let wait = rate_limit_wait(response.headers(), retries);
+        warn!(
+            "Mastodon rate limit exceeded, waiting {} seconds",
+            wait.as_secs()
+        );
+        sleep(wait);
+    }
+}
+
+// Mastodon tells when the rate limit is reset, otherwise wait longer with
+// every retry.
+fn rate_limit_wait(headers: &HeaderMap, retry: u32) -> Duration {
+    let reset = headers
+        .get("x-ratelimit-reset")
+        .and_then(|reset| reset.to_str().ok())
+        .and_then(|reset| DateTime::parse_from_rfc3339(reset).ok());
+    match reset {
+        Some(reset) => (reset.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default()
+            .max(Duration::from_secs(1)),
+        None => Duration::from_secs(5 * 2u64.pow(retry)),
+    }
+}
+
+// Returns the URL of the next page from a Link header like
+// <https://example.com/api/v1/favourites?max_id=1>; rel="next".
+fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|link| {
+        let (url, params) = link.split_once(';')?;
+        if params.contains("rel=\"next\"") {
+            Some(
+                url.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string(),
+            )
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elefren::errors::ApiError;
+
+    #[test]
+    fn parse_next_link() {
+        let header = "<https://mastodon.social/api/v1/favourites?max_id=20>; rel=\"next\", <https://mastodon.social/api/v1/favourites?min_id=30>; rel=\"prev\"";
+        assert_eq!(
+            next_link(header),
+            Some("https://mastodon.social/api/v1/favourites?max_id=20".to_string())
+        );
+        assert_eq!(
+            next_link("<https://mastodon.social/api/v1/favourites?min_id=30>; rel=\"prev\""),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_media_focus() {
+        let json = r#"[
+            {"media_attachments": [
+                {"url": "https://example.com/1.jpg", "meta": {"focus": {"x": -0.5, "y": 0.25}}},
+                {"url": "https://example.com/2.jpg", "meta": {"original": {"width": 10}}},
+                {"url": "https://example.com/3.mp4", "meta": null}
+            ]},
+            {"media_attachments": [], "reblog": {"media_attachments": [
+                {"url": "https://example.com/4.jpg", "meta": {"focus": {"x": 0.0, "y": 1.0}}}
+            ]}}
+        ]"#;
+        let focus = media_focus_from_json(json).unwrap();
+        assert_eq!(focus.len(), 2);
+        assert_eq!(focus["https://example.com/1.jpg"], (-0.5, 0.25));
+        assert_eq!(focus["https://example.com/4.jpg"], (0.0, 1.0));
+    }
+
+    #[test]
+    fn wait_for_rate_limit_reset() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(rate_limit_wait(&headers, 1), Duration::from_secs(10));
+        assert_eq!(rate_limit_wait(&headers, 2), Duration::from_secs(20));
+
+        let reset = (Utc::now() + chrono::Duration::seconds(60)).to_rfc3339();
+        headers.insert("x-ratelimit-reset", reset.parse().unwrap());
+        let wait = rate_limit_wait(&headers, 1);
+        assert!(wait > Duration::from_secs(50) && wait <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn account_gone() {
+        assert!(mastodon_account_gone(&ElefrenError::Client(
+            StatusCode::GONE
+        )));
+        assert!(mastodon_account_gone(&ElefrenError::Client(
+            StatusCode::NOT_FOUND
+        )));
+        let api_error = |message: &str| {
+            ElefrenError::Api(ApiError {
+                error: Some(message.to_string()),
+                error_description: None,
+            })
+        };
+        assert!(mastodon_account_gone(&api_error(
+            "Your login is currently disabled"
+        )));
+        assert!(mastodon_account_gone(&api_error("Account suspended")));
+        // Other errors can go away on the next run.
+        assert!(!mastodon_account_gone(&api_error("Record not found")));
+        assert!(!mastodon_account_gone(&ElefrenError::Client(
+            StatusCode::UNAUTHORIZED
+        )));
+        assert!(!mastodon_account_gone(&ElefrenError::Server(
+            StatusCode::BAD_GATEWAY
+        )));
+    }
+}
@@ -0,0 +1,494 @@
+// Without the media feature attachments are not uploaded, so nothing is
+// processed.
+#![cfg_attr(not(feature = "media"), allow(dead_code))]
+
+use crate::config::{WatermarkConfig, WatermarkPosition};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+#[cfg(feature = "media")]
+use {
+    anyhow::Context,
+    image::{imageops, DynamicImage, ImageFormat, ImageOutputFormat, RgbaImage},
+    std::io::Cursor,
+};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+// Distance of the watermark to the image border in pixels.
+const WATERMARK_MARGIN: i64 = 10;
+
+/// Processing steps for images before they are uploaded to the other network.
+#[derive(Debug, Clone, Default)]
+pub struct MediaOptions {
+    /// Remove EXIF and other metadata, for example GPS positions.
+    pub strip_metadata: bool,
+    /// Put an image on top, for example a logo.
+    pub watermark: Option<Arc<Watermark>>,
+    /// Downloaded attachments to reuse, shared by both networks.
+    pub cache: Option<Arc<MediaCache>>,
+}
+
+/// A watermark with its image, which is only opened once per run.
+#[derive(Debug)]
+pub struct Watermark {
+    position: WatermarkPosition,
+    #[cfg(feature = "media")]
+    image: RgbaImage,
+}
+
+impl Watermark {
+    /// Opens the image of the watermark. Without the media feature there is
+    /// nothing to put it on, so it is not opened.
+    pub fn load(config: &WatermarkConfig) -> Result<Watermark> {
+        Ok(Watermark {
+            position: config.position,
+            #[cfg(feature = "media")]
+            image: image::open(&config.image)
+                .with_context(|| format!("Failed to open watermark image {}", config.image))?
+                .to_rgba8(),
+        })
+    }
+}
+
+/// A downloaded attachment before processing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedMedia {
+    /// Last segment of the URL path, Mastodon needs a file name for uploads.
+    pub file_name: String,
+    pub content_type: Option<String>,
+    /// Checksum of the data, see `checksum()`.
+    pub checksum: String,
+    #[serde(skip)]
+    pub data: Vec<u8>,
+}
+
+/// Downloaded attachments by URL, so that media used in several posts is only
+/// downloaded once per run.
+///
+/// With a directory the downloads are kept for later runs, stored by the
+/// checksum of their content and listed in an index.json file.
+#[derive(Debug, Default)]
+pub struct MediaCache {
+    dir: Option<PathBuf>,
+    media: Mutex<BTreeMap<String, CachedMedia>>,
+}
+
+impl MediaCache {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        MediaCache {
+            dir,
+            media: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Returns the attachment downloaded from the URL, if any.
+    pub fn get(&self, url: &str) -> Option<CachedMedia> {
+        if let Some(media) = self.media.lock().unwrap().get(url) {
+            return Some(media.clone());
+        }
+        let dir = self.dir.as_ref()?;
+        let mut media = load_index(dir).remove(url)?;
+        media.data = fs::read(dir.join(&media.checksum)).ok()?;
+        // Ignore files that were changed or only partially written.
+        if checksum(&media.data) != media.checksum {
+            return None;
+        }
+        self.media
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), media.clone());
+        Some(media)
+    }
+
+    /// Remembers a downloaded attachment. Failing to write it to the cache
+    /// directory is only logged, the attachment is still reused in this run.
+    pub fn insert(&self, url: &str, media: CachedMedia) {
+        if let Some(dir) = &self.dir {
+            if let Err(e) = save_to_dir(dir, url, &media) {
+                warn!("Failed to write attachment {url} to the media cache: {e}");
+            }
+        }
+        self.media.lock().unwrap().insert(url.to_string(), media);
+    }
+
+    /// Removes all attachments except the ones of the given URLs from the
+    /// cache directory.
+    pub fn retain(&self, urls: &BTreeSet<String>) -> std::io::Result<()> {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        let (kept, removed): (BTreeMap<_, _>, BTreeMap<_, _>) = load_index(dir)
+            .into_iter()
+            .partition(|(url, _)| urls.contains(url));
+        if removed.is_empty() {
+            return Ok(());
+        }
+        for media in removed.values() {
+            // The same content can be downloaded from several URLs.
+            if !kept.values().any(|other| other.checksum == media.checksum) {
+                match fs::remove_file(dir.join(&media.checksum)) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+        }
+        fs::write(dir.join("index.json"), serde_json::to_string_pretty(&kept)?)
+    }
+}
+
+fn load_index(dir: &Path) -> BTreeMap<String, CachedMedia> {
+    fs::read_to_string(dir.join("index.json"))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn save_to_dir(dir: &Path, url: &str, media: &CachedMedia) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(&media.checksum), &media.data)?;
+    let mut index = load_index(dir);
+    index.insert(url.to_string(), media.clone());
+    fs::write(
+        dir.join("index.json"),
+        serde_json::to_string_pretty(&index)?,
+    )
+}
+
+/// Returns the SHA-256 hash of the data as hex string.
+pub fn checksum(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Applies the processing steps to an image. Data of other media types is
+/// returned unchanged.
+#[cfg(feature = "media")]
+pub fn process_image(mut data: Vec<u8>, options: &MediaOptions) -> Result<Vec<u8>> {
+    if let Some(watermark) = &options.watermark {
+        data = apply_watermark(data, watermark)?;
+    }
+    if options.strip_metadata {
+        data = strip_metadata(data);
+    }
+    Ok(data)
+}
+
+/// Puts the watermark image on top of JPEG and PNG images, except animated
+/// ones.
+#[cfg(feature = "media")]
+fn apply_watermark(data: Vec<u8>, watermark: &Watermark) -> Result<Vec<u8>> {
+    let format = match image::guess_format(&data) {
+        Ok(ImageFormat::Png) if is_animated_png(&data) => return Ok(data),
+        Ok(format @ (ImageFormat::Jpeg | ImageFormat::Png)) => format,
+        _ => return Ok(data),
+    };
+    let mut image = image::load_from_memory_with_format(&data, format)?.to_rgba8();
+    let mark = &watermark.image;
+    let (x, y) = watermark_offset(watermark.position, image.dimensions(), mark.dimensions());
+    imageops::overlay(&mut image, mark, x, y);
+
+    let mut output = Cursor::new(Vec::new());
+    match format {
+        // JPEG has no transparency.
+        ImageFormat::Jpeg => DynamicImage::ImageRgba8(image)
+            .to_rgb8()
+            .write_to(&mut output, ImageOutputFormat::Jpeg(90))?,
+        _ => image.write_to(&mut output, ImageOutputFormat::Png)?,
+    }
+    Ok(output.into_inner())
+}
+
+// Returns the top left corner of the watermark on the image.
+fn watermark_offset(
+    position: WatermarkPosition,
+    (width, height): (u32, u32),
+    (mark_width, mark_height): (u32, u32),
+) -> (i64, i64) {
+    let right = i64::from(width) - i64::from(mark_width) - WATERMARK_MARGIN;
+    let bottom = i64::from(height) - i64::from(mark_height) - WATERMARK_MARGIN;
+    match position {
+        WatermarkPosition::TopLeft => (WATERMARK_MARGIN, WATERMARK_MARGIN),
+        WatermarkPosition::TopRight => (right, WATERMARK_MARGIN),
+        WatermarkPosition::BottomLeft => (WATERMARK_MARGIN, bottom),
+        WatermarkPosition::BottomRight => (right, bottom),
+        WatermarkPosition::Center => (
+            (i64::from(width) - i64::from(mark_width)) / 2,
+            (i64::from(height) - i64::from(mark_height)) / 2,
+        ),
+    }
+}
+
+/// Removes metadata from JPEG and PNG images.
+fn strip_metadata(data: Vec<u8>) -> Vec<u8> {
+    let stripped = if data.starts_with(&[0xFF, 0xD8]) {
+        strip_jpeg_metadata(&data)
+    } else if data.starts_with(PNG_SIGNATURE) {
+        strip_png_metadata(&data)
+    } else {
+        return data;
+    };
+    match stripped {
+        Some(stripped) => stripped,
+        None => {
+            warn!("Could not remove metadata from broken image, uploading it unchanged");
+            data
+        }
+    }
+}
+
+// Drops APP1 (EXIF, XMP), APP13 (IPTC) and comment segments. Returns None if
+// the image is malformed.
+fn strip_jpeg_metadata(data: &[u8]) -> Option<Vec<u8>> {
+    let mut stripped = data[..2].to_vec();
+    let mut pos = 2;
+    while pos < data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        // Markers without a length field.
+        if marker == 0xD9 || marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            stripped.extend_from_slice(&data[pos..pos + 2]);
+            pos += 2;
+            continue;
+        }
+        let length = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]) as usize;
+        let end = pos + 2 + length;
+        if length < 2 || end > data.len() {
+            return None;
+        }
+        // Start of scan: the compressed image data follows until the end.
+        if marker == 0xDA {
+            stripped.extend_from_slice(&data[pos..]);
+            return Some(stripped);
+        }
+        if marker != 0xE1 && marker != 0xED && marker != 0xFE {
+            stripped.extend_from_slice(&data[pos..end]);
+        }
+        pos = end;
+    }
+    Some(stripped)
+}
+
+// Drops eXIf, text and time chunks. Returns None if the image is malformed.
+fn strip_png_metadata(data: &[u8]) -> Option<Vec<u8>> {
+    let mut stripped = PNG_SIGNATURE.to_vec();
+    for (chunk_type, chunk) in png_chunks(data)? {
+        if !matches!(chunk_type, b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME") {
+            stripped.extend_from_slice(chunk);
+        }
+    }
+    Some(stripped)
+}
+
+// Animated PNGs have an animation control chunk. The image crate only decodes
+// their first frame, so they are not changed.
+fn is_animated_png(data: &[u8]) -> bool {
+    png_chunks(data).map_or(false, |chunks| {
+        chunks.iter().any(|&(chunk_type, _)| chunk_type == b"acTL")
+    })
+}
+
+// Splits a PNG image into its chunks, as the chunk type and the whole chunk.
+// Returns None if the image is malformed.
+fn png_chunks(data: &[u8]) -> Option<Vec<(&[u8], &[u8])>> {
+    let mut chunks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < data.len() {
+        let length = u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        let chunk_type = data.get(pos + 4..pos + 8)?;
+        // Length, type, data and CRC.
+        let end = pos + 12 + length;
+        if end > data.len() {
+            return None;
+        }
+        chunks.push((chunk_type, &data[pos..end]));
+        pos = end;
+    }
+    Some(chunks)
+}
+
+#[cfg(all(test, feature = "media"))]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    const STRIP: MediaOptions = MediaOptions {
+        strip_metadata: true,
+        watermark: None,
+        cache: None,
+    };
+
+    fn contains(data: &[u8], needle: &[u8]) -> bool {
+        data.windows(needle.len()).any(|window| window == needle)
+    }
+
+    #[test]
+    fn strip_jpeg() {
+        let image = include_bytes!("../tests/fixtures/exif.jpg").to_vec();
+        assert!(contains(&image, b"GPS"));
+        let stripped = process_image(image.clone(), &STRIP).unwrap();
+        assert!(!contains(&stripped, b"Exif"));
+        assert!(!contains(&stripped, b"GPS"));
+        // Only the EXIF segment is gone.
+        assert_eq!(stripped.len(), image.len() - 41);
+        assert!(stripped.starts_with(&[0xFF, 0xD8, 0xFF, 0xE0]));
+        assert!(stripped.ends_with(&[0xFF, 0xD9]));
+    }
+
+    #[test]
+    fn strip_png() {
+        let image = include_bytes!("../tests/fixtures/exif.png").to_vec();
+        let stripped = process_image(image, &STRIP).unwrap();
+        assert!(!contains(&stripped, b"eXIf"));
+        assert!(!contains(&stripped, b"secret"));
+        assert!(contains(&stripped, b"IHDR"));
+        assert!(contains(&stripped, b"IDAT"));
+        assert!(contains(&stripped, b"IEND"));
+    }
+
+    #[test]
+    fn keep_metadata() {
+        let image = include_bytes!("../tests/fixtures/exif.jpg").to_vec();
+        let processed = process_image(image.clone(), &MediaOptions::default()).unwrap();
+        assert_eq!(processed, image);
+    }
+
+    // Other file types and broken images are not changed.
+    #[test]
+    fn unknown_data() {
+        let video = b"\x00\x00\x00\x18ftypmp42".to_vec();
+        assert_eq!(process_image(video.clone(), &STRIP).unwrap(), video);
+        let broken = vec![0xFF, 0xD8, 0xFF, 0xE1, 0xFF];
+        assert_eq!(process_image(broken.clone(), &STRIP).unwrap(), broken);
+    }
+
+    #[test]
+    fn watermark_positions() {
+        let image = (100, 50);
+        let mark = (20, 10);
+        assert_eq!(
+            watermark_offset(WatermarkPosition::TopLeft, image, mark),
+            (10, 10)
+        );
+        assert_eq!(
+            watermark_offset(WatermarkPosition::BottomRight, image, mark),
+            (70, 30)
+        );
+        assert_eq!(
+            watermark_offset(WatermarkPosition::Center, image, mark),
+            (40, 20)
+        );
+    }
+
+    #[test]
+    fn watermark_png() {
+        let dir = tempfile::tempdir().unwrap();
+        let mark_file = dir.path().join("mark.png");
+        RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]))
+            .save(&mark_file)
+            .unwrap();
+
+        let mut image = Cursor::new(Vec::new());
+        RgbaImage::from_pixel(20, 20, Rgba([255, 255, 255, 255]))
+            .write_to(&mut image, ImageOutputFormat::Png)
+            .unwrap();
+
+        let watermark = Watermark::load(&WatermarkConfig {
+            image: mark_file.to_string_lossy().into_owned(),
+            position: WatermarkPosition::BottomRight,
+        })
+        .unwrap();
+        let options = MediaOptions {
+            strip_metadata: false,
+            watermark: Some(Arc::new(watermark)),
+            cache: None,
+        };
+        let image = image.into_inner();
+        let processed = process_image(image.clone(), &options).unwrap();
+        let processed = image::load_from_memory(&processed).unwrap().to_rgba8();
+        assert_eq!(processed.get_pixel(6, 6), &Rgba([255, 0, 0, 255]));
+        assert_eq!(processed.get_pixel(0, 0), &Rgba([255, 255, 255, 255]));
+
+        // Animated PNGs are left alone, only their first frame would be kept.
+        let header_end = PNG_SIGNATURE.len() + 12 + 13;
+        let mut animated = image[..header_end].to_vec();
+        animated.extend_from_slice(b"\x00\x00\x00\x08acTL\x00\x00\x00\x02\x00\x00\x00\x00");
+        animated.extend_from_slice(&[0; 4]);
+        animated.extend_from_slice(&image[header_end..]);
+        assert!(is_animated_png(&animated));
+        assert_eq!(process_image(animated.clone(), &options).unwrap(), animated);
+        assert!(!is_animated_png(&image));
+    }
+
+    #[test]
+    fn sha256_checksum() {
+        assert_eq!(
+            checksum(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn media_cache_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = "https://example.com/image.png";
+        let media = CachedMedia {
+            file_name: "image.png".to_string(),
+            content_type: Some("image/png".to_string()),
+            checksum: checksum(b"image"),
+            data: b"image".to_vec(),
+        };
+        MediaCache::new(Some(dir.path().to_path_buf())).insert(url, media);
+
+        // A new cache as in the next run.
+        let cache = MediaCache::new(Some(dir.path().to_path_buf()));
+        let cached = cache.get(url).unwrap();
+        assert_eq!(cached.data, b"image");
+        assert_eq!(cached.content_type.as_deref(), Some("image/png"));
+        assert!(cache.get("https://example.com/other.png").is_none());
+
+        // Broken files are downloaded again.
+        fs::write(dir.path().join(checksum(b"image")), b"ima").unwrap();
+        assert!(MediaCache::new(Some(dir.path().to_path_buf()))
+            .get(url)
+            .is_none());
+        assert!(MediaCache::default().get(url).is_none());
+    }
+
+    #[test]
+    fn media_cache_retain() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MediaCache::new(Some(dir.path().to_path_buf()));
+        for (url, data) in [
+            ("https://example.com/a", "a"),
+            ("https://example.com/b", "b"),
+        ] {
+            cache.insert(
+                url,
+                CachedMedia {
+                    file_name: "file".to_string(),
+                    content_type: None,
+                    checksum: checksum(data.as_bytes()),
+                    data: data.as_bytes().to_vec(),
+                },
+            );
+        }
+        let keep = BTreeSet::from(["https://example.com/a".to_string()]);
+        cache.retain(&keep).unwrap();
+
+        let cache = MediaCache::new(Some(dir.path().to_path_buf()));
+        assert!(cache.get("https://example.com/a").is_some());
+        assert!(cache.get("https://example.com/b").is_none());
+        assert!(!dir.path().join(checksum(b"b")).exists());
+    }
+}
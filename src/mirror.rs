@@ -0,0 +1,154 @@
+use crate::config::{Config, TwitterFetchMode};
+use crate::id_map::IdMap;
+use crate::sync::{NewStatus, Platform, StatusUpdates};
+use std::mem;
+
+/// Returns the config for copying the statuses of the source network to the
+/// mirror account on the same network, None without a mirror account.
+///
+/// A mirror run is a sync run in which the mirror account takes the place of
+/// the other network: the posts are planned with the options of the source
+/// network and posted with the account of the mirror config. The options for
+/// posting statuses from the source network, like transform_cmd, are copied
+/// to the other network, because a sync posts to it with its own options.
+pub fn mirror_config(config: &Config, source: Platform) -> Option<Config> {
+    let mut mirror = config.clone();
+    match source {
+        Platform::Mastodon => {
+            mirror.mastodon.app = config.mastodon.mirror.as_ref()?.app.clone();
+            mirror.twitter.transform_cmd = config.mastodon.transform_cmd.clone();
+            mirror.twitter.posted_cmd = config.mastodon.posted_cmd.clone();
+            mirror.twitter.strip_metadata = config.mastodon.strip_metadata;
+            mirror.twitter.watermark = config.mastodon.watermark.clone();
+            // Statuses for Twitter are not planned when it is read-only.
+            mirror.twitter.fetch_mode = TwitterFetchMode::Timeline;
+        }
+        Platform::Twitter => {
+            let account = config.twitter.mirror.as_ref()?;
+            mirror.twitter.consumer_key = account.consumer_key.clone();
+            mirror.twitter.consumer_secret = account.consumer_secret.clone();
+            mirror.twitter.access_token = account.access_token.clone();
+            mirror.twitter.access_token_secret = account.access_token_secret.clone();
+            mirror.twitter.user_name = account.user_name.clone();
+            mirror.twitter.oauth2 = None;
+            mirror.twitter.backup = None;
+            mirror.mastodon.transform_cmd = config.twitter.transform_cmd.clone();
+            mirror.mastodon.posted_cmd = config.twitter.posted_cmd.clone();
+            mirror.mastodon.strip_metadata = config.twitter.strip_metadata;
+            mirror.mastodon.watermark = config.twitter.watermark.clone();
+        }
+    }
+    Some(mirror)
+}
+
+/// Moves the posts that were planned for the other network to the source
+/// network, where the mirror account is.
+pub fn mirror_posts(mut posts: StatusUpdates, source: Platform) -> StatusUpdates {
+    match source {
+        Platform::Mastodon => posts.toots = mem::take(&mut posts.tweets),
+        Platform::Twitter => posts.tweets = mem::take(&mut posts.toots),
+    }
+    posts
+}
+
+/// Marks the statuses and their thread replies as mirrored without posting
+/// them. Used on the first run, so that a new mirror account does not get a
+/// copy of the whole timeline.
+pub fn mark_mirrored(statuses: &[NewStatus], id_map: &mut IdMap) {
+    for status in statuses {
+        id_map.mark_synced(status.original_id.clone());
+        mark_mirrored(&status.replies, id_map);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::config_load;
+    use crate::sync::tests::new_status;
+    use crate::sync::StatusId;
+
+    const CONFIG: &str = r#"
+[mastodon]
+delete_older_statuses = false
+transform_cmd = "./mastodon-transform"
+
+[mastodon.app]
+base = "https://mastodon.social"
+client_id = "XXXXXXXXXXX"
+client_secret = "XXXXXXXXXXX"
+redirect = "urn:ietf:wg:oauth:2.0:oob"
+token = "XXXXXXXXXXX"
+
+[mastodon.mirror.app]
+base = "https://example.social"
+client_id = ""
+client_secret = ""
+redirect = ""
+token = "YYYYYYYYYYY"
+
+[twitter]
+consumer_key = "XXXXXXXXXXX"
+consumer_secret = "XXXXXXXXXXX"
+access_token = "XXXXXXXXXXX"
+access_token_secret = "XXXXXXXXXXX"
+user_id = 1234567890
+user_name = "klausi"
+transform_cmd = "./twitter-transform"
+fetch_mode = "rss"
+
+[twitter.mirror]
+consumer_key = "YYYYYYYYYYY"
+consumer_secret = "YYYYYYYYYYY"
+access_token = "YYYYYYYYYYY"
+access_token_secret = "YYYYYYYYYYY"
+user_name = "klausi_brand"
+"#;
+
+    #[test]
+    fn mastodon_mirror_config() {
+        let config = config_load(CONFIG).unwrap();
+        let mirror = mirror_config(&config, Platform::Mastodon).unwrap();
+        assert_eq!(mirror.mastodon.app.base, "https://example.social");
+        assert_eq!(
+            mirror.twitter.transform_cmd.as_deref(),
+            Some("./mastodon-transform")
+        );
+        assert_eq!(mirror.twitter.fetch_mode, TwitterFetchMode::Timeline);
+        // Twitter is not touched.
+        assert_eq!(mirror.twitter.access_token, "XXXXXXXXXXX");
+    }
+
+    #[test]
+    fn twitter_mirror_config() {
+        let mut config = config_load(CONFIG).unwrap();
+        let mirror = mirror_config(&config, Platform::Twitter).unwrap();
+        assert_eq!(mirror.twitter.access_token, "YYYYYYYYYYY");
+        assert_eq!(mirror.twitter.user_name, "klausi_brand");
+        assert_eq!(
+            mirror.mastodon.transform_cmd.as_deref(),
+            Some("./twitter-transform")
+        );
+        assert_eq!(mirror.mastodon.app.base, "https://mastodon.social");
+
+        config.twitter.mirror = None;
+        assert!(mirror_config(&config, Platform::Twitter).is_none());
+    }
+
+    #[test]
+    fn first_run() {
+        let mut posts = StatusUpdates::default();
+        let mut status = new_status(1, "Thread");
+        status.replies.push(new_status(2, "Reply"));
+        posts.tweets.push(status);
+        let posts = mirror_posts(posts, Platform::Mastodon);
+        assert!(posts.tweets.is_empty());
+        assert_eq!(posts.toots.len(), 1);
+
+        let mut id_map = IdMap::default();
+        mark_mirrored(&posts.toots, &mut id_map);
+        assert!(id_map.is_synced(&StatusId::Number(1)));
+        assert!(id_map.is_synced(&StatusId::Number(2)));
+        assert!(!id_map.is_synced(&StatusId::Number(3)));
+    }
+}
@@ -0,0 +1,404 @@
+use crate::args::Args;
+use crate::config::Config;
+use crate::plan::SyncAccount;
+use crate::post::{PostError, PostedStatus};
+use crate::status::RunStatus;
+use crate::sync::*;
+use crate::sync_accounts;
+use crate::twitter_json::{tweet_json, twitter_user_json, TweetPhoto};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use egg_mode::tweet::Tweet;
+use elefren::entities::status::Status;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+
+// Both fake accounts have the same name, the Mastodon one lives on a fake
+// instance.
+const MOCK_MASTODON_BASE: &str = "https://mastodon.example";
+const MOCK_USER_NAME: &str = "mock";
+const MOCK_ACCOUNT_ID: u64 = 1;
+
+/// A status of a fake account. Source statuses can be added to the account
+/// files by hand, only id and text are required.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockStatus {
+    pub id: u64,
+    pub text: String,
+    #[serde(default)]
+    pub in_reply_to_id: Option<u64>,
+    #[serde(default)]
+    pub attachments: Vec<NewMedia>,
+    #[serde(default = "Utc::now")]
+    pub created_at: DateTime<Utc>,
+    // The source status on the other network, for statuses that were posted
+    // by a mock run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synced_from: Option<StatusId>,
+}
+
+/// A fake account that keeps its statuses in a JSON file, oldest first.
+#[derive(Debug)]
+pub struct MockAccount {
+    file: String,
+    statuses: Vec<MockStatus>,
+}
+
+impl MockAccount {
+    /// Reads the statuses of the account, a missing file is an account
+    /// without statuses.
+    pub fn load(file: &str) -> Result<MockAccount> {
+        let statuses = match fs::read_to_string(file) {
+            Ok(json) => serde_json::from_str(&json)
+                .with_context(|| format!("Failed to parse mock account {file}"))?,
+            Err(_) => Vec::new(),
+        };
+        Ok(MockAccount {
+            file: file.to_string(),
+            statuses,
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let json = serde_json::to_string_pretty(&self.statuses)?;
+        fs::write(&self.file, json.as_bytes())
+            .with_context(|| format!("Failed to write mock account {}", self.file))?;
+        Ok(())
+    }
+
+    // Adds a status with the next free ID and returns the ID.
+    fn post(&mut self, status: &NewStatus, in_reply_to_id: Option<u64>) -> u64 {
+        let id = self
+            .statuses
+            .iter()
+            .map(|status| status.id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        self.statuses.push(MockStatus {
+            id,
+            text: status.text.clone(),
+            in_reply_to_id,
+            attachments: status.attachments.clone(),
+            created_at: Utc::now(),
+            synced_from: Some(status.original_id.clone()),
+        });
+        id
+    }
+
+    /// The statuses as Mastodon returns them, newest first.
+    pub fn toots(&self) -> Result<Vec<Status>> {
+        self.statuses.iter().rev().map(mock_toot).collect()
+    }
+
+    /// The statuses as Twitter returns them, newest first.
+    pub fn tweets(&self) -> Result<Vec<Tweet>> {
+        self.statuses.iter().rev().map(mock_tweet).collect()
+    }
+}
+
+/// The fake Mastodon account of mock runs.
+pub struct MockMastodon(pub MockAccount);
+
+impl SyncAccount for MockMastodon {
+    type Status = Status;
+
+    fn fetch(&self) -> Result<Vec<Status>> {
+        self.0.toots()
+    }
+
+    fn post(&mut self, status: &NewStatus, dry_run: bool) -> Result<Vec<PostedStatus>, PostError> {
+        if dry_run {
+            return Ok(Vec::new());
+        }
+        let url = |id: u64| format!("{MOCK_MASTODON_BASE}/@{MOCK_USER_NAME}/{id}");
+        Ok(mock_post(
+            &mut self.0,
+            std::slice::from_ref(status),
+            None,
+            &url,
+        ))
+    }
+}
+
+/// The fake Twitter account of mock runs.
+pub struct MockTwitter(pub MockAccount);
+
+impl SyncAccount for MockTwitter {
+    type Status = Tweet;
+
+    fn fetch(&self) -> Result<Vec<Tweet>> {
+        self.0.tweets()
+    }
+
+    fn post(&mut self, status: &NewStatus, dry_run: bool) -> Result<Vec<PostedStatus>, PostError> {
+        if dry_run {
+            return Ok(Vec::new());
+        }
+        let url = |id: u64| format!("https://twitter.com/{MOCK_USER_NAME}/status/{id}");
+        Ok(mock_post(
+            &mut self.0,
+            std::slice::from_ref(status),
+            None,
+            &url,
+        ))
+    }
+}
+
+/// Runs a sync between two fake accounts in local files instead of Mastodon
+/// and Twitter, with the sync options of the config. The statuses that are
+/// synced are added to the other account, so the next mock run sees them
+/// like a real run would. Nothing is written in a dry run.
+///
+/// The statuses are synced like in a sync run, see sync_accounts(). The ID
+/// map, queue and caches of mock runs are apart from the ones of the real
+/// accounts.
+pub fn mock_run(
+    args: &Args,
+    config: &Config,
+    mastodon_file: &str,
+    twitter_file: &str,
+    status: &mut RunStatus,
+) -> Result<()> {
+    let mut mastodon = MockMastodon(MockAccount::load(mastodon_file)?);
+    let mut twitter = MockTwitter(MockAccount::load(twitter_file)?);
+    let (toots, tweets) = (mastodon.0.statuses.len(), twitter.0.statuses.len());
+
+    // Links in the report point to the fake instance.
+    let mut config = config.clone();
+    config.mastodon.app.base = MOCK_MASTODON_BASE.into();
+    let result = sync_accounts(
+        &mut mastodon,
+        &mut twitter,
+        args,
+        &config,
+        &format!("{MOCK_MASTODON_BASE}/@{MOCK_USER_NAME}"),
+        status,
+    );
+    if args.dry_run {
+        return result;
+    }
+    // Statuses that were posted before a failure are kept as well.
+    mastodon.0.save()?;
+    twitter.0.save()?;
+    println!(
+        "Added {} status(es) to {mastodon_file} and {} to {twitter_file}.",
+        mastodon.0.statuses.len() - toots,
+        twitter.0.statuses.len() - tweets
+    );
+    result
+}
+
+// Posts the statuses with their thread replies and returns the created
+// statuses, with links made by url from their IDs.
+fn mock_post(
+    account: &mut MockAccount,
+    statuses: &[NewStatus],
+    in_reply_to_id: Option<u64>,
+    url: &impl Fn(u64) -> String,
+) -> Vec<PostedStatus> {
+    let mut created = Vec::new();
+    for status in statuses {
+        let parent =
+            in_reply_to_id.or_else(|| status.in_reply_to_id.as_ref().and_then(StatusId::as_u64));
+        let id = account.post(status, parent);
+        created.push(PostedStatus {
+            original_id: status.original_id.clone(),
+            id: StatusId::Number(id),
+            url: url(id),
+            text: status.text.clone(),
+            idempotency_key: None,
+        });
+        created.extend(mock_post(account, &status.replies, Some(id), url));
+    }
+    created
+}
+
+// The account of the mock statuses in the format of the Mastodon API.
+fn mock_mastodon_account() -> Value {
+    json!({
+        "id": MOCK_ACCOUNT_ID.to_string(),
+        "username": MOCK_USER_NAME,
+        "acct": MOCK_USER_NAME,
+        "display_name": "Mock",
+        "locked": false,
+        "created_at": "2022-01-01T00:00:00.000Z",
+        "note": "",
+        "url": format!("{MOCK_MASTODON_BASE}/@{MOCK_USER_NAME}"),
+        "avatar": format!("{MOCK_MASTODON_BASE}/avatars/original/missing.png"),
+        "avatar_static": format!("{MOCK_MASTODON_BASE}/avatars/original/missing.png"),
+        "header": format!("{MOCK_MASTODON_BASE}/headers/original/missing.png"),
+        "header_static": format!("{MOCK_MASTODON_BASE}/headers/original/missing.png"),
+        "followers_count": 0,
+        "following_count": 0,
+        "statuses_count": 0
+    })
+}
+
+fn mock_toot(status: &MockStatus) -> Result<Status> {
+    // Mastodon renders paragraphs and line breaks as HTML.
+    let content: String = status
+        .text
+        .split("\n\n")
+        .map(|paragraph| {
+            format!(
+                "<p>{}</p>",
+                html_escape::encode_text(paragraph).replace('\n', "<br />")
+            )
+        })
+        .collect();
+    let attachments: Vec<Value> = status
+        .attachments
+        .iter()
+        .enumerate()
+        .map(|(index, media)| {
+            json!({
+                "id": format!("{}{index}", status.id),
+                "type": "image",
+                "url": media.attachment_url,
+                "preview_url": media.attachment_url,
+                "remote_url": null,
+                "text_url": null,
+                "meta": null,
+                "description": media.alt_text
+            })
+        })
+        .collect();
+    let toot = json!({
+        "id": status.id.to_string(),
+        "created_at": status.created_at.to_rfc3339(),
+        "in_reply_to_id": status.in_reply_to_id.map(|id| id.to_string()),
+        "in_reply_to_account_id": status.in_reply_to_id.map(|_| MOCK_ACCOUNT_ID.to_string()),
+        "sensitive": false,
+        "spoiler_text": "",
+        "visibility": "public",
+        "language": "en",
+        "uri": format!("{MOCK_MASTODON_BASE}/users/{MOCK_USER_NAME}/statuses/{}", status.id),
+        "content": content,
+        "url": format!("{MOCK_MASTODON_BASE}/@{MOCK_USER_NAME}/{}", status.id),
+        "reblogs_count": 0,
+        "favourites_count": 0,
+        "favourited": false,
+        "reblogged": false,
+        "muted": false,
+        "pinned": false,
+        "reblog": null,
+        "application": {"name": "mock", "website": null},
+        "account": mock_mastodon_account(),
+        "media_attachments": attachments,
+        "mentions": [],
+        "tags": [],
+        "emojis": []
+    });
+    Ok(serde_json::from_value(toot)?)
+}
+
+fn mock_tweet(status: &MockStatus) -> Result<Tweet> {
+    let photos: Vec<TweetPhoto> = status
+        .attachments
+        .iter()
+        .enumerate()
+        .map(|(index, media)| {
+            let id = status.id * 10 + index as u64;
+            TweetPhoto {
+                id,
+                url: media.attachment_url.clone(),
+                short_url: format!("https://t.co/mock{id}"),
+                display_url: format!("pic.twitter.com/mock{id}"),
+                expanded_url: format!(
+                    "https://twitter.com/{MOCK_USER_NAME}/status/{}/photo/{}",
+                    status.id,
+                    index + 1
+                ),
+                alt_text: media.alt_text.clone(),
+            }
+        })
+        .collect();
+    let mut tweet = tweet_json(status.id, status.created_at, &status.text, &photos);
+    let extra = json!({
+        "source": "<a href=\"https://github.com/klausi/mastodon-twitter-sync\" rel=\"nofollow\">mock</a>",
+        "in_reply_to_status_id": status.in_reply_to_id,
+        "in_reply_to_status_id_str": status.in_reply_to_id.map(|id| id.to_string()),
+        "in_reply_to_user_id": status.in_reply_to_id.map(|_| MOCK_ACCOUNT_ID),
+        "in_reply_to_user_id_str": status.in_reply_to_id.map(|_| MOCK_ACCOUNT_ID.to_string()),
+        "in_reply_to_screen_name": status.in_reply_to_id.map(|_| MOCK_USER_NAME),
+        "user": twitter_user_json(MOCK_ACCOUNT_ID, MOCK_USER_NAME),
+        "geo": null,
+        "coordinates": null,
+        "place": null,
+        "contributors": null,
+        "is_quote_status": false,
+        "lang": "en"
+    });
+    if let (Some(tweet), Value::Object(extra)) = (tweet.as_object_mut(), extra) {
+        tweet.extend(extra);
+    }
+    Ok(serde_json::from_value(tweet)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::tests::DEFAULT_SYNC_OPTIONS;
+
+    fn status(id: u64, text: &str, in_reply_to_id: Option<u64>) -> MockStatus {
+        MockStatus {
+            id,
+            text: text.to_string(),
+            in_reply_to_id,
+            attachments: Vec::new(),
+            created_at: Utc::now(),
+            synced_from: None,
+        }
+    }
+
+    #[test]
+    fn mock_statuses_sync() {
+        let mut mastodon = MockAccount {
+            file: String::new(),
+            statuses: vec![
+                status(1, "A toot with <html> & a\n\nsecond paragraph", None),
+                status(2, "A reply", Some(1)),
+            ],
+        };
+        mastodon.statuses[0].attachments.push(NewMedia {
+            attachment_url: "https://example.com/image.jpg".to_string(),
+            alt_text: Some("An image".to_string()),
+            focus: None,
+        });
+        let mut twitter = MockAccount {
+            file: String::new(),
+            statuses: vec![status(5, "A tweet", None)],
+        };
+
+        let posts = determine_posts(
+            &mastodon.toots().unwrap(),
+            &twitter.tweets().unwrap(),
+            &DEFAULT_SYNC_OPTIONS,
+        )
+        .unwrap();
+        assert_eq!(posts.tweets.len(), 1);
+        let tweet = &posts.tweets[0];
+        assert_eq!(tweet.text, "A toot with <html> & a\n\nsecond paragraph");
+        assert_eq!(tweet.attachments[0].alt_text.as_deref(), Some("An image"));
+        assert_eq!(tweet.replies[0].text, "A reply");
+        assert_eq!(posts.toots[0].text, "A tweet");
+
+        // Posted statuses are found as synced on the next run.
+        let url = |id: u64| id.to_string();
+        let created = mock_post(&mut twitter, &posts.tweets, None, &url);
+        assert_eq!(created.len(), 2);
+        assert_eq!(created[1].original_id, StatusId::Number(2));
+        mock_post(&mut mastodon, &posts.toots, None, &url);
+        assert_eq!(twitter.statuses[2].in_reply_to_id, Some(6));
+        let posts = determine_posts(
+            &mastodon.toots().unwrap(),
+            &twitter.tweets().unwrap(),
+            &DEFAULT_SYNC_OPTIONS,
+        )
+        .unwrap();
+        assert!(posts.tweets.is_empty());
+        assert!(posts.toots.is_empty());
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::{bail, Context, Result};
+use egg_mode::user::TwitterUser;
+use elefren::{Mastodon, MastodonClient};
+use regex::Regex;
+use std::fmt::Write as _;
+use std::fs;
+use tracing::{info, warn};
+
+/// A blocked or muted Twitter account and the guessed Mastodon account of the
+/// same person, one row of the review file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModerationEntry {
+    pub action: ModerationAction,
+    pub twitter_user: String,
+    // Empty if no Mastodon account was found, can be filled in by hand.
+    pub mastodon_account: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModerationAction {
+    Block,
+    Mute,
+}
+
+impl ModerationAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ModerationAction::Block => "block",
+            ModerationAction::Mute => "mute",
+        }
+    }
+}
+
+const CSV_HEADER: &str = "action,twitter_user,mastodon_account";
+
+/// Writes all blocked and muted Twitter accounts to a CSV file for review.
+pub async fn twitter_export_moderation(token: &egg_mode::Token, file: &str) -> Result<()> {
+    let mut entries = Vec::new();
+    for user in twitter_fetch_users(egg_mode::user::blocks(token)).await? {
+        entries.push(moderation_entry(ModerationAction::Block, &user));
+    }
+    for user in twitter_fetch_users(egg_mode::user::mutes(token)).await? {
+        entries.push(moderation_entry(ModerationAction::Mute, &user));
+    }
+
+    let found = entries
+        .iter()
+        .filter(|e| !e.mastodon_account.is_empty())
+        .count();
+    fs::write(file, moderation_to_csv(&entries))
+        .with_context(|| format!("Failed to write {file}"))?;
+    println!(
+        "Exported {} blocked and muted Twitter accounts to {file}, found Mastodon accounts for {found} of them. Review the file and fill in missing Mastodon accounts before importing.",
+        entries.len()
+    );
+    Ok(())
+}
+
+/// Blocks and mutes the Mastodon accounts of the reviewed CSV file.
+pub fn mastodon_import_moderation(mastodon: &Mastodon, file: &str, dry_run: bool) -> Result<()> {
+    let csv = fs::read_to_string(file).with_context(|| format!("Failed to read {file}"))?;
+    for entry in moderation_from_csv(&csv)? {
+        if entry.mastodon_account.is_empty() {
+            continue;
+        }
+        info!(
+            "{} Mastodon account {} for Twitter user {}",
+            entry.action.as_str(),
+            entry.mastodon_account,
+            entry.twitter_user
+        );
+        if dry_run {
+            continue;
+        }
+
+        // Resolve the account on our instance, it might not be known there
+        // yet.
+        let results = mastodon.search_v2(&entry.mastodon_account, true)?;
+        let account = match results.accounts.into_iter().next() {
+            Some(account) => account,
+            None => {
+                warn!("Mastodon account {} not found", entry.mastodon_account);
+                continue;
+            }
+        };
+        match entry.action {
+            ModerationAction::Block => mastodon.block(&account.id)?,
+            ModerationAction::Mute => mastodon.mute(&account.id)?,
+        };
+    }
+    Ok(())
+}
+
+pub(crate) async fn twitter_fetch_users(
+    mut cursor: egg_mode::cursor::CursorIter<egg_mode::cursor::UserCursor>,
+) -> Result<Vec<TwitterUser>> {
+    let mut users = Vec::new();
+    loop {
+        let page = cursor.call().await?;
+        users.extend(page.response.users);
+        if page.response.next_cursor == 0 {
+            break;
+        }
+        cursor.next_cursor = page.response.next_cursor;
+    }
+    Ok(users)
+}
+
+fn moderation_entry(action: ModerationAction, user: &TwitterUser) -> ModerationEntry {
+    ModerationEntry {
+        action,
+        twitter_user: user.screen_name.clone(),
+        mastodon_account: twitter_user_mastodon_account(user).unwrap_or_default(),
+    }
+}
+
+/// Guesses the Mastodon account of a Twitter user from their profile.
+pub(crate) fn twitter_user_mastodon_account(user: &TwitterUser) -> Option<String> {
+    let profile = [
+        Some(user.name.as_str()),
+        user.description.as_deref(),
+        user.url.as_deref(),
+        user.location.as_deref(),
+    ];
+    guess_mastodon_account(&profile.into_iter().flatten().collect::<Vec<_>>())
+}
+
+/// Looks for a Mastodon handle like @user@example.com or a profile URL like
+/// https://example.com/@user in the Twitter profile texts.
+fn guess_mastodon_account(texts: &[&str]) -> Option<String> {
+    let handle = Regex::new(r"@([\w.]+)@([\w-]+(?:\.[\w-]+)+)").unwrap();
+    let url = Regex::new(r"https?://([\w-]+(?:\.[\w-]+)+)/@([\w.]+)").unwrap();
+    for text in texts {
+        if let Some(caps) = handle.captures(text) {
+            return Some(format!("@{}@{}", &caps[1], &caps[2]));
+        }
+        if let Some(caps) = url.captures(text) {
+            return Some(format!("@{}@{}", &caps[2], &caps[1]));
+        }
+    }
+    None
+}
+
+fn moderation_to_csv(entries: &[ModerationEntry]) -> String {
+    let mut csv = format!("{CSV_HEADER}\n");
+    for entry in entries {
+        // Account names cannot contain commas, no quoting needed.
+        let _ = writeln!(
+            csv,
+            "{},{},{}",
+            entry.action.as_str(),
+            entry.twitter_user,
+            entry.mastodon_account
+        );
+    }
+    csv
+}
+
+fn moderation_from_csv(csv: &str) -> Result<Vec<ModerationEntry>> {
+    let mut entries = Vec::new();
+    for (number, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line == CSV_HEADER {
+            continue;
+        }
+        let columns: Vec<&str> = line.split(',').map(str::trim).collect();
+        let action = match columns[0] {
+            "block" => ModerationAction::Block,
+            "mute" => ModerationAction::Mute,
+            other => bail!("Unknown action \"{other}\" in line {}", number + 1),
+        };
+        if columns.len() != 3 {
+            bail!("Expected 3 columns in line {}: {line}", number + 1);
+        }
+        entries.push(ModerationEntry {
+            action,
+            twitter_user: columns[1].to_string(),
+            mastodon_account: columns[2].to_string(),
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guess_account() {
+        assert_eq!(
+            guess_mastodon_account(&["Klaus", "Find me at @klausi@mastodon.social"]),
+            Some("@klausi@mastodon.social".to_string())
+        );
+        assert_eq!(
+            guess_mastodon_account(&["https://chaos.social/@example"]),
+            Some("@example@chaos.social".to_string())
+        );
+        assert_eq!(guess_mastodon_account(&["Just @twitter_user here"]), None);
+    }
+
+    #[test]
+    fn csv_roundtrip() {
+        let entries = vec![
+            ModerationEntry {
+                action: ModerationAction::Block,
+                twitter_user: "spammer".to_string(),
+                mastodon_account: "@spammer@example.com".to_string(),
+            },
+            ModerationEntry {
+                action: ModerationAction::Mute,
+                twitter_user: "loud".to_string(),
+                mastodon_account: String::new(),
+            },
+        ];
+        let csv = moderation_to_csv(&entries);
+        assert!(csv.starts_with(CSV_HEADER));
+        assert_eq!(moderation_from_csv(&csv).unwrap(), entries);
+    }
+
+    #[test]
+    fn csv_errors() {
+        assert!(moderation_from_csv("ban,spammer,").is_err());
+        assert!(moderation_from_csv("block,spammer").is_err());
+    }
+}
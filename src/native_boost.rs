@@ -0,0 +1,363 @@
+use crate::id_map::IdMap;
+use crate::moderation::twitter_user_mastodon_account;
+use crate::sync::*;
+use anyhow::Result;
+use egg_mode::tweet::Tweet;
+use elefren::entities::status::Status;
+use elefren::{Mastodon, MastodonClient, StatusesRequest};
+use std::collections::BTreeMap;
+use tokio::runtime::Runtime;
+use tracing::{info, warn};
+
+/// Boosts the original toot on Mastodon instead of posting an "RT" copy for
+/// retweets of accounts that are also on Mastodon. The boosted retweets are
+/// removed from the toots to post.
+///
+/// Returns the number of boosts.
+pub fn native_boosts(
+    mastodon: &Mastodon,
+    toots: &mut Vec<NewStatus>,
+    tweets: &[Tweet],
+    accounts: &BTreeMap<String, String>,
+    text_options: &TweetTextOptions,
+    id_map: &mut IdMap,
+    dry_run: bool,
+) -> usize {
+    let mut boosts = 0;
+    let mut remaining = Vec::new();
+    for toot in toots.drain(..) {
+        let retweet = tweets
+            .iter()
+            .find(|tweet| StatusId::from(tweet.id) == toot.original_id)
+            .and_then(|tweet| tweet.retweeted_status.as_deref());
+        let original = match retweet {
+            Some(retweet) if toot.in_reply_to_id.is_none() => retweet,
+            _ => {
+                remaining.push(toot);
+                continue;
+            }
+        };
+        let account = match retweet_mastodon_account(original, accounts) {
+            Some(account) => account,
+            None => {
+                remaining.push(toot);
+                continue;
+            }
+        };
+        // Any error falls back to posting a copy of the retweet.
+        match mastodon_find_original(mastodon, &account, original, text_options) {
+            Ok(Some(status)) => {
+                let url = status.url.as_deref().unwrap_or(&status.uri);
+                if dry_run {
+                    info!(
+                        "Would boost {url} instead of posting retweet {}",
+                        toot.original_id
+                    );
+                } else {
+                    info!(
+                        "Boosting {url} instead of posting retweet {}",
+                        toot.original_id
+                    );
+                    match mastodon.reblog(&status.id) {
+                        Ok(boost) => {
+                            if let Some(tweet_id) = toot.original_id.as_u64() {
+                                id_map.insert(StatusId::from(boost.id.as_str()), tweet_id);
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Failed to boost {url}, posting a copy instead: {e}");
+                            remaining.push(toot);
+                            continue;
+                        }
+                    }
+                }
+                boosts += 1;
+            }
+            Ok(None) => remaining.push(toot),
+            Err(e) => {
+                warn!("Failed to look up the retweeted tweet on Mastodon account {account}: {e:#}");
+                remaining.push(toot);
+            }
+        }
+    }
+    *toots = remaining;
+    boosts
+}
+
+/// Retweets the tweet that an own toot was synced to instead of posting an
+/// "RT" copy for boosts of own toots. Boosts of own toots that were not synced
+/// are dropped.
+///
+/// Returns the number of retweets.
+pub fn native_self_retweets(
+    rt: &Runtime,
+    token: &egg_mode::Token,
+    tweets: &mut Vec<NewStatus>,
+    mastodon_statuses: &[Status],
+    id_map: &mut IdMap,
+    dry_run: bool,
+) -> usize {
+    let mut retweets = 0;
+    let mut remaining = Vec::new();
+    for tweet in tweets.drain(..) {
+        let original = match self_boosted_toot(&tweet.original_id, mastodon_statuses) {
+            Some(original) => original,
+            None => {
+                remaining.push(tweet);
+                continue;
+            }
+        };
+        let tweet_id = match id_map.tweet_id(&original) {
+            Some(tweet_id) => tweet_id,
+            None => {
+                info!(
+                    "Not syncing boost {} of own toot {original}, the toot is not on Twitter",
+                    tweet.original_id
+                );
+                continue;
+            }
+        };
+        if dry_run {
+            info!(
+                "Would retweet {tweet_id} instead of posting boost {}",
+                tweet.original_id
+            );
+            retweets += 1;
+            continue;
+        }
+        info!(
+            "Retweeting {tweet_id} instead of posting boost {}",
+            tweet.original_id
+        );
+        // Failed retweets are not recorded, so they are tried again on the
+        // next run.
+        match rt.block_on(egg_mode::tweet::retweet(tweet_id, token)) {
+            Ok(retweet) => {
+                id_map.insert_created_tweet(tweet.original_id.clone(), retweet.id);
+                retweets += 1;
+            }
+            Err(e) => warn!("Failed to retweet {tweet_id}: {e}"),
+        }
+    }
+    *tweets = remaining;
+    retweets
+}
+
+/// Boosts the toot that an own tweet was synced to instead of posting an "RT"
+/// copy for retweets of own tweets. Retweets of own tweets that were not
+/// synced are dropped.
+///
+/// Returns the number of boosts.
+pub fn native_self_boosts(
+    mastodon: &Mastodon,
+    toots: &mut Vec<NewStatus>,
+    tweets: &[Tweet],
+    id_map: &mut IdMap,
+    dry_run: bool,
+) -> usize {
+    let mut boosts = 0;
+    let mut remaining = Vec::new();
+    for toot in toots.drain(..) {
+        let original = match self_retweeted_tweet(&toot.original_id, tweets) {
+            Some(original) => original,
+            None => {
+                remaining.push(toot);
+                continue;
+            }
+        };
+        let toot_id = match id_map.toot_id(original) {
+            Some(toot_id) => toot_id.clone(),
+            None => {
+                info!(
+                    "Not syncing retweet {} of own tweet {original}, the tweet is not on Mastodon",
+                    toot.original_id
+                );
+                continue;
+            }
+        };
+        if dry_run {
+            info!(
+                "Would boost {toot_id} instead of posting retweet {}",
+                toot.original_id
+            );
+            boosts += 1;
+            continue;
+        }
+        info!(
+            "Boosting {toot_id} instead of posting retweet {}",
+            toot.original_id
+        );
+        match mastodon.reblog(&toot_id.to_string()) {
+            Ok(boost) => {
+                if let Some(tweet_id) = toot.original_id.as_u64() {
+                    id_map.insert(StatusId::from(boost.id.as_str()), tweet_id);
+                }
+                boosts += 1;
+            }
+            Err(e) => warn!("Failed to boost {toot_id}: {e}"),
+        }
+    }
+    *toots = remaining;
+    boosts
+}
+
+// Returns the ID of the own toot that a toot boosted, None if it is not a
+// boost of an own toot.
+fn self_boosted_toot(boost_id: &StatusId, statuses: &[Status]) -> Option<StatusId> {
+    statuses
+        .iter()
+        .find(|toot| StatusId::from(toot.id.as_str()) == *boost_id)
+        .filter(|toot| is_self_boost(toot))
+        .and_then(|toot| toot.reblog.as_ref())
+        .map(|reblog| StatusId::from(reblog.id.as_str()))
+}
+
+// Returns the ID of the own tweet that a tweet retweeted, None if it is not a
+// retweet of an own tweet.
+fn self_retweeted_tweet(retweet_id: &StatusId, tweets: &[Tweet]) -> Option<u64> {
+    tweets
+        .iter()
+        .find(|tweet| StatusId::from(tweet.id) == *retweet_id)
+        .filter(|tweet| is_self_retweet(tweet))
+        .and_then(|tweet| tweet.retweeted_status.as_ref())
+        .map(|retweet| retweet.id)
+}
+
+// Returns the Mastodon account of the author of a retweeted tweet. The
+// configured accounts take precedence over the Twitter profile.
+fn retweet_mastodon_account(
+    original: &Tweet,
+    accounts: &BTreeMap<String, String>,
+) -> Option<String> {
+    let user = original.user.as_deref()?;
+    accounts
+        .iter()
+        .find(|(screen_name, _)| screen_name.eq_ignore_ascii_case(&user.screen_name))
+        .map(|(_, account)| account.clone())
+        .or_else(|| twitter_user_mastodon_account(user))
+}
+
+// Searches the recent toots of the Mastodon account for the retweeted tweet.
+fn mastodon_find_original(
+    mastodon: &Mastodon,
+    account: &str,
+    original: &Tweet,
+    text_options: &TweetTextOptions,
+) -> Result<Option<Status>> {
+    let results = mastodon.search_v2(account, true)?;
+    let account = match results.accounts.into_iter().next() {
+        Some(account) => account,
+        None => return Ok(None),
+    };
+    let statuses = mastodon
+        .statuses(&account.id, StatusesRequest::new().limit(40))?
+        .initial_items;
+    find_original(&statuses, original, text_options)
+}
+
+// Returns the toot with the same content as the tweet. Boosts are not boosted
+// again.
+fn find_original(
+    statuses: &[Status],
+    original: &Tweet,
+    text_options: &TweetTextOptions,
+) -> Result<Option<Status>> {
+    for status in statuses {
+        if status.reblog.is_none() && toot_and_tweet_are_equal(status, original, text_options)? {
+            return Ok(Some(status.clone()));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::tests::*;
+
+    static TEXT_OPTIONS: TweetTextOptions = TweetTextOptions {
+        expand_urls: true,
+        escape_mentions: true,
+        decode_html_entities: true,
+        removed_hashtags: Vec::new(),
+        hashtag_aliases: Vec::new(),
+        short_links: None,
+        shorten: DEFAULT_SHORTEN_OPTIONS,
+    };
+
+    #[test]
+    fn configured_account_before_profile() {
+        let mut tweet = get_twitter_status();
+        let mut user = get_twitter_user();
+        user.description = Some("Also at @test@example.com".to_string());
+        tweet.user = Some(Box::new(user));
+
+        let mut accounts = BTreeMap::new();
+        assert_eq!(
+            retweet_mastodon_account(&tweet, &accounts),
+            Some("@test@example.com".to_string())
+        );
+        accounts.insert("Test123".to_string(), "@other@example.org".to_string());
+        assert_eq!(
+            retweet_mastodon_account(&tweet, &accounts),
+            Some("@other@example.org".to_string())
+        );
+
+        tweet.user.as_mut().unwrap().description = None;
+        accounts.clear();
+        assert_eq!(retweet_mastodon_account(&tweet, &accounts), None);
+    }
+
+    #[test]
+    fn find_original_toot() {
+        let mut tweet = get_twitter_status();
+        tweet.text = "Hello from both networks".to_string();
+        let mut other = get_mastodon_status();
+        other.content = "<p>Something else</p>".to_string();
+        let mut original = get_mastodon_status();
+        original.content = "<p>Hello from both networks</p>".to_string();
+        let mut boost = get_mastodon_status();
+        boost.reblog = Some(Box::new(original.clone()));
+
+        let found = find_original(&[other.clone(), original], &tweet, &TEXT_OPTIONS).unwrap();
+        assert!(found.is_some());
+        assert!(find_original(&[other, boost], &tweet, &TEXT_OPTIONS)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn self_boost_originals() {
+        let original = get_mastodon_status();
+        let mut boost = get_mastodon_status();
+        boost.id = "1234".to_string();
+        boost.reblog = Some(Box::new(original.clone()));
+        let statuses = [original.clone(), boost.clone()];
+        assert_eq!(
+            self_boosted_toot(&StatusId::from("1234"), &statuses),
+            Some(StatusId::from(original.id.as_str()))
+        );
+        assert_eq!(
+            self_boosted_toot(&StatusId::from(original.id.as_str()), &statuses),
+            None
+        );
+
+        let mut original_tweet = get_twitter_status();
+        original_tweet.user = Some(Box::new(get_twitter_user()));
+        original_tweet.id = 1230906460160380928;
+        let mut retweet = get_twitter_status();
+        retweet.user = Some(Box::new(get_twitter_user()));
+        retweet.retweeted_status = Some(Box::new(original_tweet));
+        let tweets = [retweet.clone()];
+        assert_eq!(
+            self_retweeted_tweet(&StatusId::from(retweet.id), &tweets),
+            Some(1230906460160380928)
+        );
+        // Retweets of other users are no self retweets.
+        retweet.user.as_mut().unwrap().id += 1;
+        assert_eq!(
+            self_retweeted_tweet(&StatusId::from(retweet.id), &[retweet.clone()]),
+            None
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use crate::config::{EmailNotifyConfig, NotifyConfig, NtfyNotifyConfig, PushoverNotifyConfig};
+use anyhow::Result;
+use tracing::{info, warn};
+#[cfg(feature = "email")]
+use {
+    lettre::transport::smtp::authentication::Credentials,
+    lettre::{Message, SmtpTransport, Transport},
+};
+
+const TITLE: &str = "mastodon-twitter-sync failed";
+
+// Pushover rejects longer messages.
+const PUSHOVER_MAX_CHARS: usize = 1024;
+
+/// Sends the error of a failed run to all configured notification channels.
+/// A failing channel is only logged, it should not hide the original error.
+pub fn notify_failure(config: &NotifyConfig, error: &anyhow::Error) {
+    let message = error_message(error);
+    if let Some(email) = &config.email {
+        log_result("email", send_email(email, &message));
+    }
+    if let Some(ntfy) = &config.ntfy {
+        log_result("ntfy", send_ntfy(ntfy, &message));
+    }
+    if let Some(pushover) = &config.pushover {
+        log_result("Pushover", send_pushover(pushover, &message));
+    }
+}
+
+fn log_result(channel: &str, result: Result<()>) {
+    match result {
+        Ok(()) => info!("Sent failure notification by {channel}"),
+        Err(e) => warn!("Failed to send failure notification by {channel}: {e:#}"),
+    }
+}
+
+// The error with all its causes, one per line.
+fn error_message(error: &anyhow::Error) -> String {
+    let mut message = format!("Error: {error}");
+    for cause in error.chain().skip(1) {
+        message.push_str(&format!("\nBecause: {cause}"));
+    }
+    message
+}
+
+#[cfg(feature = "email")]
+fn send_email(config: &EmailNotifyConfig, message: &str) -> Result<()> {
+    let email = Message::builder()
+        .from(config.from.parse()?)
+        .to(config.to.parse()?)
+        .subject(TITLE)
+        .body(message.to_string())?;
+    let mut transport = SmtpTransport::starttls_relay(&config.smtp_server)?.port(config.smtp_port);
+    if let (Some(user), Some(password)) = (&config.smtp_user, &config.smtp_password) {
+        transport = transport.credentials(Credentials::new(user.clone(), password.clone()));
+    }
+    transport.build().send(&email)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "email"))]
+fn send_email(_config: &EmailNotifyConfig, _message: &str) -> Result<()> {
+    anyhow::bail!("the email feature is disabled")
+}
+
+fn send_ntfy(config: &NtfyNotifyConfig, message: &str) -> Result<()> {
+    let mut request = reqwest::blocking::Client::new()
+        .post(&config.url)
+        .header("Title", TITLE)
+        .body(message.to_string());
+    if let Some(token) = &config.token {
+        request = request.bearer_auth(token);
+    }
+    request.send()?.error_for_status()?;
+    Ok(())
+}
+
+fn send_pushover(config: &PushoverNotifyConfig, message: &str) -> Result<()> {
+    let message: String = message.chars().take(PUSHOVER_MAX_CHARS).collect();
+    reqwest::blocking::Client::new()
+        .post("https://api.pushover.net/1/messages.json")
+        .form(&[
+            ("token", config.token.as_str()),
+            ("user", config.user.as_str()),
+            ("title", TITLE),
+            ("message", message.as_str()),
+        ])
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn message_with_causes() {
+        let error: Result<()> = Err(anyhow!("Connection refused")).context("Failed to post tweet");
+        assert_eq!(
+            error_message(&error.unwrap_err()),
+            "Error: Failed to post tweet\nBecause: Connection refused"
+        );
+    }
+}
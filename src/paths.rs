@@ -0,0 +1,140 @@
+// Without both networks the paths are only used for the cache files.
+#![cfg_attr(not(all(feature = "mastodon", feature = "twitter")), allow(dead_code))]
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Name of the config file if none is given on the command line.
+pub const DEFAULT_CONFIG_FILE: &str = "mastodon-twitter-sync.toml";
+
+const APP_DIR: &str = "mastodon-twitter-sync";
+
+// Directory of the cache files, set when the run starts.
+static CACHE_DIR: Mutex<Option<String>> = Mutex::new(None);
+
+/// Where the config file and the cache files are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    pub config: String,
+    /// None for the current directory.
+    pub cache_dir: Option<String>,
+}
+
+/// Directories of the platform for config and cache files, only known on
+/// Windows. Elsewhere everything is kept in the current directory.
+#[derive(Debug, Clone, Default)]
+struct PlatformDirs {
+    config: Option<PathBuf>,
+    cache: Option<PathBuf>,
+}
+
+impl PlatformDirs {
+    fn new(windows: bool, var: impl Fn(&str) -> Option<String>) -> PlatformDirs {
+        if !windows {
+            return PlatformDirs::default();
+        }
+        let dir = |name| var(name).map(|dir| PathBuf::from(dir).join(APP_DIR));
+        PlatformDirs {
+            config: dir("APPDATA"),
+            cache: dir("LOCALAPPDATA"),
+        }
+    }
+}
+
+/// Finds the config file and the cache directory and keeps the cache
+/// directory for cache_file().
+///
+/// MTS_CACHE_DIR always wins. On Windows the config is looked up in
+/// %APPDATA% and the cache files are kept in %LOCALAPPDATA%, unless the config
+/// file is in the current directory where older versions kept everything.
+pub fn paths_init(config_arg: &str) -> Paths {
+    let paths = resolve_paths(
+        config_arg,
+        &PlatformDirs::new(cfg!(windows), |name| env::var(name).ok()),
+        env::var("MTS_CACHE_DIR").ok(),
+        |path| path.exists(),
+    );
+    *CACHE_DIR.lock().unwrap() = paths.cache_dir.clone();
+    paths
+}
+
+/// Returns the directory of the cache files, None for the current directory.
+pub fn cache_dir() -> Option<String> {
+    CACHE_DIR
+        .lock()
+        .unwrap()
+        .clone()
+        .or_else(|| env::var("MTS_CACHE_DIR").ok())
+}
+
+fn resolve_paths(
+    config_arg: &str,
+    dirs: &PlatformDirs,
+    env_cache_dir: Option<String>,
+    exists: impl Fn(&Path) -> bool,
+) -> Paths {
+    let legacy = config_arg == DEFAULT_CONFIG_FILE && exists(Path::new(DEFAULT_CONFIG_FILE));
+    let config = match &dirs.config {
+        Some(dir) if config_arg == DEFAULT_CONFIG_FILE && !legacy => {
+            dir.join(DEFAULT_CONFIG_FILE).to_string_lossy().into_owned()
+        }
+        _ => config_arg.to_string(),
+    };
+    let cache_dir = env_cache_dir.or_else(|| match &dirs.cache {
+        Some(dir) if !legacy => Some(dir.to_string_lossy().into_owned()),
+        _ => None,
+    });
+    Paths { config, cache_dir }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_paths() {
+        let dirs = PlatformDirs::new(true, |name| match name {
+            "APPDATA" => Some("C:\\Users\\me\\AppData\\Roaming".to_string()),
+            "LOCALAPPDATA" => Some("C:\\Users\\me\\AppData\\Local".to_string()),
+            _ => None,
+        });
+        let config_dir = dirs.config.clone().unwrap();
+        let cache_dir = dirs.cache.clone().unwrap();
+
+        let paths = resolve_paths(DEFAULT_CONFIG_FILE, &dirs, None, |_| false);
+        assert_eq!(
+            paths.config,
+            config_dir.join(DEFAULT_CONFIG_FILE).to_string_lossy()
+        );
+        assert_eq!(paths.cache_dir, Some(cache_dir.to_string_lossy().into()));
+
+        // A config file in the current directory keeps the old layout.
+        let paths = resolve_paths(DEFAULT_CONFIG_FILE, &dirs, None, |_| true);
+        assert_eq!(paths.config, DEFAULT_CONFIG_FILE);
+        assert_eq!(paths.cache_dir, None);
+
+        // A config file given on the command line is used as it is.
+        let paths = resolve_paths(
+            "D:\\sync\\work.toml",
+            &dirs,
+            Some("D:\\cache".into()),
+            |_| false,
+        );
+        assert_eq!(paths.config, "D:\\sync\\work.toml");
+        assert_eq!(paths.cache_dir, Some("D:\\cache".to_string()));
+    }
+
+    #[test]
+    fn current_directory_elsewhere() {
+        let dirs = PlatformDirs::new(false, |_| Some("/home/me".to_string()));
+        let paths = resolve_paths(DEFAULT_CONFIG_FILE, &dirs, None, |_| false);
+        assert_eq!(
+            paths,
+            Paths {
+                config: DEFAULT_CONFIG_FILE.to_string(),
+                cache_dir: None,
+            }
+        );
+    }
+}
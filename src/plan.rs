@@ -0,0 +1,182 @@
+use crate::attachment_size::{limit_attachment_size, RemoteMedia};
+use crate::config::{Config, TwitterFetchMode};
+use crate::filters::FilterPipeline;
+use crate::id_map::IdMap;
+use crate::post::{PostError, PostedStatus};
+use crate::sync::*;
+use crate::thread_replies::resolve_thread_replies;
+use anyhow::Result;
+use egg_mode::tweet::Tweet;
+use elefren::entities::status::Status;
+use std::mem;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// An account that statuses are fetched from and posted to. Sync runs talk to
+/// Mastodon and Twitter, mock runs use fake accounts in local files and tests
+/// replay recorded API responses.
+pub trait SyncAccount {
+    /// Statuses in the format of the network, Status for Mastodon and Tweet
+    /// for Twitter.
+    type Status;
+
+    /// Returns the recent statuses of the account, newest first.
+    fn fetch(&self) -> Result<Vec<Self::Status>>;
+
+    /// Posts a status with its thread replies and returns the created
+    /// statuses, nothing is sent in a dry run. See post_to_mastodon() for
+    /// errors.
+    fn post(&mut self, status: &NewStatus, dry_run: bool) -> Result<Vec<PostedStatus>, PostError>;
+}
+
+/// Plans the posts for the timelines of both accounts, including thread
+/// replies to statuses that were synced before. Statuses without required alt
+/// text, with too big attachments or rejected by a filter of the config are
+/// skipped.
+///
+/// The size of attachments is looked up with media, attachments of unknown
+/// size are kept.
+pub fn plan_posts(
+    mastodon_statuses: &[Status],
+    tweets: &[Tweet],
+    config: &Config,
+    options: &SyncOptions,
+    id_map: &IdMap,
+    media: &impl Fn(&str) -> Option<RemoteMedia>,
+) -> Result<StatusUpdates> {
+    let mut posts = determine_posts(mastodon_statuses, tweets, options)?;
+    resolve_thread_replies(&mut posts, id_map);
+    // Without API access tweets can only be read from an RSS feed.
+    if config.twitter.fetch_mode == TwitterFetchMode::Rss {
+        for status in mem::take(&mut posts.tweets) {
+            posts.skip(
+                Platform::Mastodon,
+                status.original_id,
+                &status.text,
+                SkipReason::ReadOnly,
+            );
+        }
+    }
+    posts.require_alt_text(
+        config.mastodon.require_alt_text,
+        config.twitter.require_alt_text,
+    );
+    let mut oversized = Vec::new();
+    if let Some(max_mb) = config.mastodon.max_attachment_mb {
+        let shorten = &options.tweet_text.shorten;
+        let max_chars = shorten
+            .max_characters
+            .saturating_sub(shorten.character_reserve);
+        let fits = |text: &str| egg_mode_text::character_count(text, 23, 23) <= max_chars;
+        posts.tweets = limit_attachment_size(
+            mem::take(&mut posts.tweets),
+            max_mb,
+            config.mastodon.oversized_attachments,
+            &media,
+            &fits,
+            &mut oversized,
+        );
+        for status in oversized.drain(..) {
+            posts.skip(
+                Platform::Mastodon,
+                status.original_id,
+                &status.text,
+                SkipReason::OversizedAttachment,
+            );
+        }
+    }
+    if let Some(max_mb) = config.twitter.max_attachment_mb {
+        let fits = |text: &str| text.graphemes(true).count() <= 500;
+        posts.toots = limit_attachment_size(
+            mem::take(&mut posts.toots),
+            max_mb,
+            config.twitter.oversized_attachments,
+            &media,
+            &fits,
+            &mut oversized,
+        );
+        for status in oversized {
+            posts.skip(
+                Platform::Twitter,
+                status.original_id,
+                &status.text,
+                SkipReason::OversizedAttachment,
+            );
+        }
+    }
+    // Filters run before the post cache check of a sync run, so that the
+    // cache contains the filtered texts that are posted.
+    let mut rejected = Vec::new();
+    posts.tweets = FilterPipeline::from_config(&config.mastodon.filters)?
+        .apply_rejected(posts.tweets, &mut rejected);
+    for status in rejected.drain(..) {
+        posts.skip(
+            Platform::Mastodon,
+            status.original_id,
+            &status.text,
+            SkipReason::Filtered,
+        );
+    }
+    posts.toots = FilterPipeline::from_config(&config.twitter.filters)?
+        .apply_rejected(posts.toots, &mut rejected);
+    for status in rejected {
+        posts.skip(
+            Platform::Twitter,
+            status.original_id,
+            &status.text,
+            SkipReason::Filtered,
+        );
+    }
+    Ok(posts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::config_load;
+    use crate::sync::tests::{get_mastodon_status, get_twitter_status, DEFAULT_SYNC_OPTIONS};
+
+    #[test]
+    fn read_only_twitter() {
+        let mut config = config_load(
+            r#"
+[mastodon.app]
+base = "https://mastodon.social"
+client_id = "XXXXXXXXXXX"
+client_secret = "XXXXXXXXXXX"
+redirect = "urn:ietf:wg:oauth:2.0:oob"
+token = "XXXXXXXXXXX"
+
+[twitter]
+user_id = 1234567890
+user_name = "klausi"
+"#,
+        )
+        .unwrap();
+        let mut status = get_mastodon_status();
+        status.content = "Hello from Mastodon".to_string();
+        let mut tweet = get_twitter_status();
+        tweet.text = "Hello from Twitter".to_string();
+        let statuses = vec![status];
+        let tweets = vec![tweet];
+        let plan = |config: &Config| {
+            plan_posts(
+                &statuses,
+                &tweets,
+                config,
+                &DEFAULT_SYNC_OPTIONS,
+                &IdMap::default(),
+                &|_| None,
+            )
+            .unwrap()
+        };
+        let posts = plan(&config);
+        assert_eq!(posts.tweets.len(), 1);
+        assert_eq!(posts.toots.len(), 1);
+
+        config.twitter.fetch_mode = TwitterFetchMode::Rss;
+        let posts = plan(&config);
+        assert!(posts.tweets.is_empty());
+        assert_eq!(posts.toots.len(), 1);
+        assert_eq!(posts.skipped[0].reason, SkipReason::ReadOnly);
+    }
+}
@@ -1,186 +1,577 @@
-use crate::sync::NewStatus;
-use anyhow::bail;
-use anyhow::format_err;
+use crate::api_budget::{api_call, api_write, ApiCallKind};
+use crate::config::ReplyVisibility;
+use crate::errors::SyncError;
+use crate::history::HistoryNetwork;
+use crate::id_map::idempotency_key;
+use crate::mastodon::mastodon_new_status;
+use crate::media::MediaOptions;
+use crate::sync::{NewMedia, NewStatus, Platform, StatusId};
 use anyhow::Context;
 use anyhow::Result;
-use egg_mode::media::ProgressInfo::{Failed, InProgress, Pending, Success};
-use egg_mode::media::{set_metadata, upload_media};
+use egg_mode::error::Error as EggModeError;
+use egg_mode::error::TwitterErrors;
+use egg_mode::media::MediaId;
 use egg_mode::tweet::DraftTweet;
 use egg_mode::Token;
-use elefren::media_builder::MediaBuilder;
-use elefren::status_builder::StatusBuilder;
+use elefren::status_builder::{StatusBuilder, Visibility};
 use elefren::Mastodon;
-use elefren::MastodonClient;
-use log::warn;
-use reqwest::header::CONTENT_TYPE;
-use std::fs::File;
-use std::io::Write;
-use std::path::Path;
-use std::time::Duration;
-use tempfile::tempdir;
-use tokio::time::sleep;
+use serde::Serialize;
+use thiserror::Error;
+use tracing::{error, info, instrument, warn};
+#[cfg(feature = "media")]
+use {
+    crate::media::{checksum, process_image, CachedMedia},
+    crate::progress::{Progress, ProgressUnit},
+    egg_mode::media::ProgressInfo::{Failed, InProgress, Pending, Success},
+    egg_mode::media::{set_metadata, upload_media},
+    elefren::media_builder::MediaBuilder,
+    elefren::MastodonClient,
+    reqwest::header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE},
+    reqwest::Url,
+    std::fs::File,
+    std::io::{Read, Write},
+    std::path::Path,
+    std::time::Duration,
+    tempfile::tempdir,
+    tokio::time::sleep,
+    tracing::debug,
+};
+
+// How often a failed or incomplete attachment download is tried.
+#[cfg(feature = "media")]
+const DOWNLOAD_ATTEMPTS: u32 = 3;
+
+/// A status that was created on the other network.
+#[derive(Debug, Clone, Serialize)]
+pub struct PostedStatus {
+    /// ID of the status on the source network.
+    pub original_id: StatusId,
+    pub id: StatusId,
+    pub url: String,
+    pub text: String,
+    /// Idempotency key of the request that posted a toot.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+}
+
+/// Error of posting a status with its thread replies. The statuses that were
+/// created before the error are returned with it, so that they are recorded
+/// and not posted again.
+#[derive(Debug, Error)]
+#[error("{error:#}")]
+pub struct PostError {
+    pub created: Vec<PostedStatus>,
+    // The status that failed, in reply to its created parent.
+    pub failed: NewStatus,
+    // Thread replies that were not tried because of the error, in reply to
+    // their created parents.
+    pub remaining: Vec<NewStatus>,
+    pub error: anyhow::Error,
+}
+
+impl PostError {
+    /// Error of a status that failed before anything was posted.
+    pub fn new(status: &NewStatus, error: anyhow::Error) -> PostError {
+        PostError {
+            created: Vec::new(),
+            failed: status.clone(),
+            remaining: Vec::new(),
+            error,
+        }
+    }
+}
+
+/// A status that could not be posted, collected for the summary at the end of a
+/// run.
+pub struct PostFailure {
+    pub target: Platform,
+    pub status: NewStatus,
+    // Thread replies that were not tried, they are queued with the status.
+    pub remaining: Vec<NewStatus>,
+    pub error: anyhow::Error,
+}
+
+/// Prints a table of all posts that failed to sync.
+pub fn print_post_failures(failures: &[PostFailure]) {
+    error!("{} post(s) failed to sync:", failures.len());
+    for failure in failures {
+        // Only show the beginning of the first line to keep the table readable.
+        let mut preview: String = failure
+            .status
+            .text
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .take(40)
+            .collect();
+        if preview.len() < failure.status.text.len() {
+            preview.push('…');
+        }
+        error!(
+            "  {:<8} | {:<41} | {:#}",
+            failure.target.to_string(),
+            preview,
+            failure.error
+        );
+    }
+}
 
 /// Send new status with any given replies to Mastodon.
-pub fn post_to_mastodon(mastodon: &Mastodon, toot: &NewStatus, dry_run: bool) -> Result<()> {
-    if let Some(reply_to) = toot.in_reply_to_id {
-        println!(
+///
+/// Returns all posted statuses. A failing reply stops posting the thread, the
+/// error contains what was posted and what is left.
+pub fn post_to_mastodon(
+    mastodon: &Mastodon,
+    toot: &NewStatus,
+    media_options: &MediaOptions,
+    reply_visibility: ReplyVisibility,
+    dry_run: bool,
+) -> Result<Vec<PostedStatus>, PostError> {
+    if let Some(reply_to) = &toot.in_reply_to_id {
+        info!(
             "Posting thread reply for {} to Mastodon: {}",
             reply_to, toot.text
         );
     } else {
-        println!("Posting to Mastodon: {}", toot.text);
+        info!("Posting to Mastodon: {}", toot.text);
     }
-    let mut status_id = 0;
+    let mut created = Vec::new();
+    let mut status_id = StatusId::Number(0);
     if !dry_run {
-        status_id = send_single_post_to_mastodon(mastodon, toot)?;
+        let posted = send_single_post_to_mastodon(mastodon, toot, media_options, reply_visibility)
+            .map_err(|e| PostError::new(toot, e))?;
+        status_id = posted.id.clone();
+        created.push(posted);
     }
 
     // Recursion does not work well with async functions, so we use iteration
     // here instead.
     let mut replies = Vec::new();
     for reply in &toot.replies {
-        replies.push((status_id, reply));
+        replies.push((status_id.clone(), reply));
     }
 
     while !replies.is_empty() {
         let (parent_id, reply) = replies.remove(0);
         let mut new_reply = reply.clone();
-        // Set the new ID of the parent status to reply to.
-        new_reply.in_reply_to_id = Some(parent_id);
-
-        println!(
+        info!(
             "Posting thread reply for {} to Mastodon: {}",
             parent_id, reply.text
         );
-        let mut parent_status_id = 0;
+        // Set the new ID of the parent status to reply to.
+        new_reply.in_reply_to_id = Some(parent_id);
+
+        let mut parent_status_id = StatusId::Number(0);
         if !dry_run {
-            parent_status_id = send_single_post_to_mastodon(mastodon, &new_reply)?;
+            match send_single_post_to_mastodon(
+                mastodon,
+                &new_reply,
+                media_options,
+                reply_visibility,
+            ) {
+                Ok(posted) => {
+                    parent_status_id = posted.id.clone();
+                    created.push(posted);
+                }
+                Err(error) => {
+                    return Err(PostError {
+                        created,
+                        failed: new_reply,
+                        remaining: unposted_replies(replies),
+                        error,
+                    })
+                }
+            }
         }
         for remaining_reply in &reply.replies {
-            replies.push((parent_status_id, remaining_reply));
+            replies.push((parent_status_id.clone(), remaining_reply));
         }
     }
 
-    Ok(())
+    Ok(created)
 }
 
 /// Sends the given new status to Mastodon.
-fn send_single_post_to_mastodon(mastodon: &Mastodon, toot: &NewStatus) -> Result<u64> {
+#[instrument(skip_all, fields(original_id = %toot.original_id))]
+fn send_single_post_to_mastodon(
+    mastodon: &Mastodon,
+    toot: &NewStatus,
+    media_options: &MediaOptions,
+    reply_visibility: ReplyVisibility,
+) -> Result<PostedStatus> {
+    let media_ids = mastodon_upload_attachments(mastodon, &toot.attachments, media_options)?;
+
+    let mut status_builder = StatusBuilder::new();
+    status_builder.status(&toot.text);
+    status_builder.media_ids(media_ids);
+    if let Some(parent_id) = &toot.in_reply_to_id {
+        status_builder.in_reply_to(parent_id.to_string());
+        if reply_visibility == ReplyVisibility::Unlisted {
+            status_builder.visibility(Visibility::Unlisted);
+        }
+    }
+
+    let draft_status = status_builder.build()?;
+    let key = idempotency_key(&toot.original_id, &toot.text);
+    let status = mastodon_new_status(mastodon, &draft_status, &key)?;
+
+    Ok(PostedStatus {
+        original_id: toot.original_id.clone(),
+        id: StatusId::from(status.id.as_str()),
+        url: status.url.unwrap_or(status.uri),
+        text: toot.text.clone(),
+        idempotency_key: Some(key),
+    })
+}
+
+/// Uploads media attachments to Mastodon and returns their IDs.
+#[cfg(feature = "media")]
+fn mastodon_upload_attachments(
+    mastodon: &Mastodon,
+    attachments: &[NewMedia],
+    media_options: &MediaOptions,
+) -> Result<Vec<String>> {
     let mut media_ids = Vec::new();
     // Temporary directory where we will download any file attachments to.
     let temp_dir = tempdir()?;
     // Post attachments first, if there are any.
-    for attachment in &toot.attachments {
-        // Because we use async for egg-mode we also need to use reqwest in
-        // async mode. Otherwise we get double async executor errors.
-        let response = reqwest::blocking::get(&attachment.attachment_url).context(format!(
-            "Failed downloading attachment {}",
-            attachment.attachment_url
-        ))?;
-        let file_name = match Path::new(response.url().path()).file_name() {
-            Some(f) => f,
-            None => bail!(
-                "Failed to create file name from attachment {}",
-                attachment.attachment_url
-            ),
-        };
-
-        let path = temp_dir.path().join(file_name);
+    for attachment in attachments {
+        let media = mastodon_download_attachment(&attachment.attachment_url, media_options)?;
+        let path = temp_dir.path().join(&media.file_name);
         let string_path = path.to_string_lossy().into_owned();
 
         let mut file = File::create(path)?;
-        file.write_all(&response.bytes()?)?;
+        let data = process_image(media.data, media_options)?;
+        file.write_all(&data)?;
 
-        let attachment = match &attachment.alt_text {
-            None => mastodon.media(string_path.into())?,
+        api_call(HistoryNetwork::Mastodon, ApiCallKind::Write);
+        // Mastodon supports descriptions for all media types.
+        let attachment = match attachment.description() {
+            None if attachment.focus.is_none() => mastodon.media(string_path.into()),
+            None => mastodon.media(MediaBuilder {
+                file: string_path.into(),
+                description: None,
+                focus: attachment.focus,
+            }),
             Some(description) => mastodon.media(MediaBuilder {
                 file: string_path.into(),
-                description: Some(description.clone().into()),
-                focus: None,
-            })?,
-        };
+                description: Some(description.to_string().into()),
+                focus: attachment.focus,
+            }),
+        }
+        .map_err(SyncError::MastodonApi)?;
 
         media_ids.push(attachment.id);
     }
 
-    let mut status_builder = StatusBuilder::new();
-    status_builder.status(&toot.text);
-    status_builder.media_ids(media_ids);
-    if let Some(parent_id) = toot.in_reply_to_id {
-        status_builder.in_reply_to(parent_id.to_string());
+    Ok(media_ids)
+}
+
+/// Returns the attachment from the media cache or downloads it.
+#[cfg(feature = "media")]
+fn mastodon_download_attachment(url: &str, media_options: &MediaOptions) -> Result<CachedMedia> {
+    if let Some(media) = media_options
+        .cache
+        .as_ref()
+        .and_then(|cache| cache.get(url))
+    {
+        debug!("Using cached attachment {url}");
+        return Ok(media);
+    }
+    let mut attempt = 1;
+    let media = loop {
+        match mastodon_download(url) {
+            Err(e) if attempt < DOWNLOAD_ATTEMPTS => {
+                warn!("Downloading attachment {url} failed, trying again: {e:#}");
+                attempt += 1;
+            }
+            result => break result?,
+        }
+    };
+    if let Some(cache) = &media_options.cache {
+        cache.insert(url, media.clone());
     }
+    Ok(media)
+}
 
-    let draft_status = status_builder.build()?;
-    let status = mastodon.new_status(draft_status)?;
-    let id = status
-        .id
-        .parse::<u64>()
-        .context(format!("Mastodon status ID is not u64: {}", status.id))?;
+#[cfg(feature = "media")]
+fn mastodon_download(url: &str) -> Result<CachedMedia> {
+    // Because we use async for egg-mode we also need to use reqwest in
+    // async mode. Otherwise we get double async executor errors.
+    let mut response =
+        reqwest::blocking::get(url).context(format!("Failed downloading attachment {url}"))?;
+    let final_url = response.url().clone();
+    let headers = response.headers().clone();
+    let mut bar = download_progress(&final_url, response.content_length());
+    let mut data = Vec::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = response.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        data.extend_from_slice(&buffer[..read]);
+        bar.inc(read as u64);
+    }
+    downloaded_media(url, &final_url, &headers, data)
+}
 
-    Ok(id)
+#[cfg(feature = "media")]
+fn download_progress(url: &Url, length: Option<u64>) -> Progress {
+    let file_name = url.path().rsplit('/').next().unwrap_or_default();
+    Progress::new(
+        format!("Downloading {file_name}"),
+        length,
+        ProgressUnit::Bytes,
+    )
+}
+
+// Collects what is needed for the upload from the response of an attachment
+// download.
+#[cfg(feature = "media")]
+fn downloaded_media(
+    url: &str,
+    final_url: &Url,
+    headers: &HeaderMap,
+    data: Vec<u8>,
+) -> Result<CachedMedia> {
+    let file_name = match Path::new(final_url.path()).file_name() {
+        Some(f) => f.to_string_lossy().into_owned(),
+        None => {
+            return Err(SyncError::Media {
+                url: url.to_string(),
+                reason: "Failed to create file name".to_string(),
+            }
+            .into())
+        }
+    };
+    // A connection that breaks off can end the body early without an error.
+    let expected_length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if let Some(length) = expected_length {
+        if data.len() != length {
+            return Err(SyncError::Media {
+                url: url.to_string(),
+                reason: format!("Incomplete download, got {} of {length} bytes", data.len()),
+            }
+            .into());
+        }
+    }
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    Ok(CachedMedia {
+        file_name,
+        content_type,
+        checksum: checksum(&data),
+        data,
+    })
+}
+
+#[cfg(not(feature = "media"))]
+fn mastodon_upload_attachments(
+    _mastodon: &Mastodon,
+    attachments: &[NewMedia],
+    _media_options: &MediaOptions,
+) -> Result<Vec<String>> {
+    if !attachments.is_empty() {
+        warn!("Skipping media attachments, the media feature is disabled");
+    }
+    Ok(Vec::new())
 }
 
 /// Send a new status update to Twitter, including thread replies and
 /// attachments.
-pub async fn post_to_twitter(token: &Token, tweet: &NewStatus, dry_run: bool) -> Result<()> {
-    if let Some(reply_to) = tweet.in_reply_to_id {
-        println!(
+///
+/// Returns all posted statuses, see post_to_mastodon() for errors.
+pub async fn post_to_twitter(
+    token: &Token,
+    tweet: &NewStatus,
+    media_options: &MediaOptions,
+    dry_run: bool,
+) -> Result<Vec<PostedStatus>, PostError> {
+    if let Some(reply_to) = &tweet.in_reply_to_id {
+        info!(
             "Posting thread reply for {} to Twitter: {}",
             reply_to, tweet.text
         );
     } else {
-        println!("Posting to Twitter: {}", tweet.text);
+        info!("Posting to Twitter: {}", tweet.text);
     }
-    let mut status_id = 0;
+    let mut created = Vec::new();
+    let mut status_id = StatusId::Number(0);
     if !dry_run {
-        status_id = send_single_post_to_twitter(token, tweet).await?;
+        let posted = send_single_post_to_twitter(token, tweet, media_options)
+            .await
+            .map_err(|e| PostError::new(tweet, e))?;
+        status_id = posted.id.clone();
+        created.push(posted);
     }
 
     // Recursion does not work well with async functions, so we use iteration
     // here instead.
     let mut replies = Vec::new();
     for reply in &tweet.replies {
-        replies.push((status_id, reply));
+        replies.push((status_id.clone(), reply));
     }
 
     while !replies.is_empty() {
         let (parent_id, reply) = replies.remove(0);
         let mut new_reply = reply.clone();
-        // Set the new ID of the parent status to reply to.
-        new_reply.in_reply_to_id = Some(parent_id);
-
-        println!(
+        info!(
             "Posting thread reply for {} to Twitter: {}",
             parent_id, reply.text
         );
-        let mut parent_status_id = 0;
+        // Set the new ID of the parent status to reply to.
+        new_reply.in_reply_to_id = Some(parent_id);
+
+        let mut parent_status_id = StatusId::Number(0);
         if !dry_run {
-            parent_status_id = send_single_post_to_twitter(token, &new_reply).await?;
+            match send_single_post_to_twitter(token, &new_reply, media_options).await {
+                Ok(posted) => {
+                    parent_status_id = posted.id.clone();
+                    created.push(posted);
+                }
+                Err(error) => {
+                    return Err(PostError {
+                        created,
+                        failed: new_reply,
+                        remaining: unposted_replies(replies),
+                        error,
+                    })
+                }
+            }
         }
         for remaining_reply in &reply.replies {
-            replies.push((parent_status_id, remaining_reply));
+            replies.push((parent_status_id.clone(), remaining_reply));
+        }
+    }
+
+    Ok(created)
+}
+
+// Thread replies that are left when posting a thread stops. Their parents are
+// posted already, so they are replies to them now.
+fn unposted_replies(replies: Vec<(StatusId, &NewStatus)>) -> Vec<NewStatus> {
+    replies
+        .into_iter()
+        .map(|(parent_id, reply)| NewStatus {
+            in_reply_to_id: Some(parent_id),
+            ..reply.clone()
+        })
+        .collect()
+}
+
+// Returns the error of the Twitter API, posting errors wrap it in SyncError.
+fn twitter_api_error(error: &anyhow::Error) -> Option<&EggModeError> {
+    match error.downcast_ref::<SyncError>() {
+        Some(SyncError::TwitterApi(e)) => Some(e),
+        _ => error.downcast_ref::<EggModeError>(),
+    }
+}
+
+/// Returns true if Twitter rejected a status because it is a duplicate of an
+/// already existing one.
+pub fn twitter_is_duplicate_error(error: &anyhow::Error) -> bool {
+    match twitter_api_error(error) {
+        // Error 187 is "Status is a duplicate".
+        Some(EggModeError::TwitterError(_, TwitterErrors { errors })) => {
+            errors.iter().any(|e| e.code == 187)
         }
+        _ => false,
     }
+}
 
-    Ok(())
+/// Returns true if Twitter does not allow posting with the account right now,
+/// for example because it is suspended or over the daily limit.
+pub fn twitter_is_rejected_error(error: &anyhow::Error) -> bool {
+    match twitter_api_error(error) {
+        // 64: account suspended, 88: rate limit exceeded, 185: over daily
+        // status update limit, 261: app cannot write, 326: account locked.
+        Some(EggModeError::TwitterError(_, TwitterErrors { errors })) => errors
+            .iter()
+            .any(|e| [64, 88, 185, 261, 326].contains(&e.code)),
+        Some(EggModeError::RateLimit(_)) => true,
+        _ => false,
+    }
 }
 
 /// Sends the given new status to Twitter.
-async fn send_single_post_to_twitter(token: &Token, tweet: &NewStatus) -> Result<u64> {
+#[instrument(skip_all, fields(original_id = %tweet.original_id))]
+async fn send_single_post_to_twitter(
+    token: &Token,
+    tweet: &NewStatus,
+    media_options: &MediaOptions,
+) -> Result<PostedStatus> {
     let mut draft = DraftTweet::new(tweet.text.clone());
-    'attachments: for attachment in &tweet.attachments {
-        let response = reqwest::get(&attachment.attachment_url).await?;
-        let media_type = response
-            .headers()
-            .get(CONTENT_TYPE)
-            .ok_or_else(|| format_err!("Missing content-type on response"))?
-            .to_str()?
+    for media_id in twitter_upload_attachments(token, &tweet.attachments, media_options).await? {
+        draft.add_media(media_id);
+    }
+
+    api_write(HistoryNetwork::Twitter)?;
+    let created_tweet = if let Some(parent_id) = &tweet.in_reply_to_id {
+        let parent_id = parent_id
+            .as_u64()
+            .with_context(|| format!("Tweet ID is not a number: {parent_id}"))?;
+        draft.in_reply_to(parent_id).send(token).await
+    } else {
+        draft.send(token).await
+    }
+    .map_err(SyncError::TwitterApi)?;
+
+    let user_name = match &created_tweet.user {
+        Some(user) => user.screen_name.clone(),
+        None => "twitter".to_string(),
+    };
+    Ok(PostedStatus {
+        original_id: tweet.original_id.clone(),
+        id: created_tweet.id.into(),
+        url: format!(
+            "https://twitter.com/{user_name}/status/{}",
+            created_tweet.id
+        ),
+        text: tweet.text.clone(),
+        idempotency_key: None,
+    })
+}
+
+/// Uploads media attachments to Twitter and returns their IDs once they are
+/// processed.
+#[cfg(feature = "media")]
+async fn twitter_upload_attachments(
+    token: &Token,
+    attachments: &[NewMedia],
+    media_options: &MediaOptions,
+) -> Result<Vec<MediaId>> {
+    let mut media_ids = Vec::new();
+    'attachments: for attachment in attachments {
+        let media = twitter_download_attachment(&attachment.attachment_url, media_options).await?;
+        let media_type = media
+            .content_type
+            .as_deref()
+            .ok_or_else(|| SyncError::Media {
+                url: attachment.attachment_url.clone(),
+                reason: "Missing content-type on response".to_string(),
+            })?
             .parse::<mime::Mime>()?;
 
-        let bytes = response.bytes().await?;
-        let mut media_handle = upload_media(&bytes, &media_type, token).await?;
+        let bytes = process_image(media.data, media_options)?;
+        api_write(HistoryNetwork::Twitter)?;
+        let mut media_handle = upload_media(&bytes, &media_type, token)
+            .await
+            .map_err(SyncError::TwitterApi)?;
 
-        // Now we need to wait and check until the media is ready.
+        // Now we need to wait and check until the media is ready, videos can
+        // take minutes.
+        let mut bar = Progress::new(
+            "Waiting for Twitter to process the media",
+            None,
+            ProgressUnit::Items("s"),
+        );
         loop {
             let wait_seconds = match media_handle.progress {
                 Some(progress) => match progress {
@@ -193,11 +584,11 @@ async fn send_single_post_to_twitter(token: &Token, tweet: &NewStatus) -> Result
                             );
                             continue 'attachments;
                         }
-                        return Err(format_err!(
-                            "Twitter media upload of {} failed: {}",
-                            attachment.attachment_url,
-                            error
-                        ));
+                        return Err(SyncError::Media {
+                            url: attachment.attachment_url.clone(),
+                            reason: format!("Twitter media upload failed: {error}"),
+                        }
+                        .into());
                     }
                     Success => 0,
                 },
@@ -207,23 +598,136 @@ async fn send_single_post_to_twitter(token: &Token, tweet: &NewStatus) -> Result
 
             if wait_seconds > 0 {
                 sleep(Duration::from_secs(wait_seconds)).await;
-                media_handle = egg_mode::media::get_status(media_handle.id, token).await?;
+                bar.inc(wait_seconds);
+                api_call(HistoryNetwork::Twitter, ApiCallKind::Read);
+                media_handle = egg_mode::media::get_status(media_handle.id, token)
+                    .await
+                    .map_err(SyncError::TwitterApi)?;
             } else {
                 break;
             }
         }
 
-        draft.add_media(media_handle.id.clone());
-        if let Some(alt_text) = &attachment.alt_text {
-            set_metadata(&media_handle.id, alt_text, token).await?;
+        media_ids.push(media_handle.id.clone());
+        if let Some(alt_text) = attachment.description() {
+            // Twitter does not accept alt text for every media type, the media
+            // is posted without it then.
+            api_write(HistoryNetwork::Twitter)?;
+            if let Err(e) = set_metadata(&media_handle.id, alt_text, token).await {
+                warn!(
+                    "Twitter rejected the alt text of the {media_type} attachment {}, posting it without alt text: {e}",
+                    attachment.attachment_url
+                );
+            }
         }
     }
 
-    let created_tweet = if let Some(parent_id) = tweet.in_reply_to_id {
-        draft.in_reply_to(parent_id).send(token).await?
-    } else {
-        draft.send(token).await?
+    Ok(media_ids)
+}
+
+/// Returns the attachment from the media cache or downloads it.
+#[cfg(feature = "media")]
+async fn twitter_download_attachment(
+    url: &str,
+    media_options: &MediaOptions,
+) -> Result<CachedMedia> {
+    if let Some(media) = media_options
+        .cache
+        .as_ref()
+        .and_then(|cache| cache.get(url))
+    {
+        debug!("Using cached attachment {url}");
+        return Ok(media);
+    }
+    let mut attempt = 1;
+    let media = loop {
+        match twitter_download(url).await {
+            Err(e) if attempt < DOWNLOAD_ATTEMPTS => {
+                warn!("Downloading attachment {url} failed, trying again: {e:#}");
+                attempt += 1;
+            }
+            result => break result?,
+        }
     };
+    if let Some(cache) = &media_options.cache {
+        cache.insert(url, media.clone());
+    }
+    Ok(media)
+}
 
-    Ok(created_tweet.id)
+#[cfg(feature = "media")]
+async fn twitter_download(url: &str) -> Result<CachedMedia> {
+    let mut response = reqwest::get(url).await?;
+    let final_url = response.url().clone();
+    let headers = response.headers().clone();
+    let mut bar = download_progress(&final_url, response.content_length());
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        data.extend_from_slice(&chunk);
+        bar.inc(chunk.len() as u64);
+    }
+    downloaded_media(url, &final_url, &headers, data)
+}
+
+#[cfg(not(feature = "media"))]
+async fn twitter_upload_attachments(
+    _token: &Token,
+    attachments: &[NewMedia],
+    _media_options: &MediaOptions,
+) -> Result<Vec<MediaId>> {
+    if !attachments.is_empty() {
+        warn!("Skipping media attachments, the media feature is disabled");
+    }
+    Ok(Vec::new())
+}
+
+#[cfg(all(test, feature = "media"))]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn incomplete_download() {
+        let url = "https://example.com/media/image.png?size=large";
+        let final_url = Url::parse(url).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("image/png"));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("5"));
+
+        let media = downloaded_media(url, &final_url, &headers, b"image".to_vec()).unwrap();
+        assert_eq!(media.file_name, "image.png");
+        assert_eq!(media.content_type.as_deref(), Some("image/png"));
+        assert_eq!(media.checksum, checksum(b"image"));
+
+        let error = downloaded_media(url, &final_url, &headers, b"ima".to_vec()).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Incomplete download, got 3 of 5 bytes"));
+    }
+
+    #[test]
+    fn wrapped_twitter_errors() {
+        let error = |code| -> anyhow::Error {
+            SyncError::TwitterApi(EggModeError::TwitterError(
+                HeaderMap::new(),
+                TwitterErrors {
+                    errors: vec![egg_mode::error::TwitterErrorCode {
+                        message: "Twitter error".to_string(),
+                        code,
+                    }],
+                },
+            ))
+            .into()
+        };
+        assert!(twitter_is_duplicate_error(&error(187)));
+        assert!(twitter_is_duplicate_error(
+            &error(187).context("Failed to post tweet")
+        ));
+        assert!(!twitter_is_duplicate_error(&error(185)));
+        assert!(twitter_is_rejected_error(&error(185)));
+        assert!(!twitter_is_rejected_error(&error(187)));
+        assert!(!twitter_is_rejected_error(&anyhow::anyhow!(
+            "Tweet ID is not a number"
+        )));
+    }
 }
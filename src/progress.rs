@@ -0,0 +1,88 @@
+// Without both networks only the deletions show progress.
+#![cfg_attr(not(all(feature = "mastodon", feature = "twitter")), allow(dead_code))]
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle};
+use std::io::{stderr, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Progress bars are only drawn after logging_init() enabled them.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enables progress bars if the log output goes to a terminal.
+pub fn progress_init(show: bool) {
+    ENABLED.store(show && stderr().is_terminal(), Ordering::Relaxed);
+}
+
+/// What the progress counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressUnit {
+    Bytes,
+    /// Items with their name, for example "statuses".
+    Items(&'static str),
+}
+
+/// A progress bar for a long download, upload or pagination loop, drawn on
+/// stderr and removed again when it is dropped.
+///
+/// Without a known total only the count is shown.
+#[derive(Debug)]
+pub struct Progress {
+    bar: ProgressBar,
+}
+
+impl Progress {
+    pub fn new(label: impl Into<String>, total: Option<u64>, unit: ProgressUnit) -> Progress {
+        let total = total.filter(|total| *total > 0);
+        let bar = if ENABLED.load(Ordering::Relaxed) {
+            ProgressBar::with_draw_target(total, ProgressDrawTarget::stderr())
+        } else {
+            ProgressBar::hidden()
+        };
+        Progress {
+            bar: bar
+                .with_style(progress_style(total.is_some(), unit))
+                .with_message(label.into())
+                // Clears the bar, so that log lines start at the beginning of
+                // the line again.
+                .with_finish(ProgressFinish::AndClear),
+        }
+    }
+
+    pub fn inc(&mut self, delta: u64) {
+        self.bar.inc(delta);
+    }
+}
+
+fn progress_style(has_total: bool, unit: ProgressUnit) -> ProgressStyle {
+    let template = match (has_total, unit) {
+        (true, ProgressUnit::Bytes) => {
+            "{msg} [{bar:30}] {percent:>3}% {bytes}/{total_bytes}".to_string()
+        }
+        (true, ProgressUnit::Items(name)) => {
+            format!("{{msg}} [{{bar:30}}] {{percent:>3}}% {{pos}}/{{len}} {name}")
+        }
+        (false, ProgressUnit::Bytes) => "{msg} {bytes}".to_string(),
+        (false, ProgressUnit::Items(name)) => format!("{{msg}} {{pos}} {name}"),
+    };
+    ProgressStyle::with_template(&template)
+        .expect("Valid progress bar template")
+        .progress_chars("#-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hidden_progress() {
+        for total in [None, Some(10 * 1024 * 1024)] {
+            for unit in [ProgressUnit::Bytes, ProgressUnit::Items("tweets")] {
+                let mut progress = Progress::new("Downloading video.mp4", total, unit);
+                progress.inc(5 * 1024 * 1024);
+                // Progress bars are only shown after logging_init().
+                assert!(progress.bar.is_hidden());
+                assert_eq!(progress.bar.position(), 5 * 1024 * 1024);
+            }
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use crate::config::PostingWindow;
+use crate::sync::{NewStatus, StatusId, StatusUpdates};
+use anyhow::Result;
+use chrono::NaiveTime;
+use std::fs;
+use tracing::{error, info};
+
+/// Posting a status is given up after it failed in this many runs, errors like
+/// a text that is too long do not go away by trying again.
+pub const MAX_POST_ATTEMPTS: u32 = 5;
+
+// Posts that were held back on a previous run, for example because they were
+// determined outside of the posting window. They are published on a later run.
+
+/// Reads the queue from the JSON file, a missing file is an empty queue.
+pub fn queue_load(file: &str) -> Result<StatusUpdates> {
+    match fs::read_to_string(file) {
+        Ok(json) => StatusUpdates::from_json(&json),
+        Err(_) => Ok(StatusUpdates::default()),
+    }
+}
+
+pub fn queue_save(queue: &StatusUpdates, file: &str) -> Result<()> {
+    fs::write(file, queue.to_json()?.as_bytes())?;
+    Ok(())
+}
+
+/// Adds newly determined posts after the queued ones. Posts that are still on
+/// the recent timelines are determined again on every run, they are only kept
+/// once.
+pub fn queue_merge(queue: StatusUpdates, posts: StatusUpdates) -> StatusUpdates {
+    StatusUpdates {
+        tweets: merge_statuses(queue.tweets, posts.tweets),
+        toots: merge_statuses(queue.toots, posts.toots),
+        skipped: posts.skipped,
+        ..StatusUpdates::default()
+    }
+}
+
+/// Sets the publishing time of new posts to the given number of minutes after
+/// their source status was created, or after now if that is not known. Posts
+/// that have a publishing time already keep it.
+pub fn queue_delay(
+    statuses: &mut [NewStatus],
+    minutes: u64,
+    created_at: impl Fn(&StatusId) -> Option<i64>,
+    now: i64,
+) {
+    let delay = i64::try_from(minutes.saturating_mul(60)).unwrap_or(i64::MAX);
+    for status in statuses {
+        if status.publish_at.is_none() {
+            let created = created_at(&status.original_id).unwrap_or(now);
+            status.publish_at = Some(created.saturating_add(delay));
+        }
+    }
+}
+
+/// Moves the posts to the queue when it is outside of the posting window of
+/// their source network, they are posted on a later run. The posts are added
+/// after the posts that are held in the queue already.
+pub fn queue_outside_window(
+    posts: &mut StatusUpdates,
+    queue: &mut StatusUpdates,
+    mastodon_window: Option<PostingWindow>,
+    twitter_window: Option<PostingWindow>,
+    now: NaiveTime,
+) {
+    if let Some(window) = mastodon_window {
+        if !window.contains(now) && !posts.tweets.is_empty() {
+            info!(
+                "Outside of the posting window for Twitter, queueing {} post(s)",
+                posts.tweets.len()
+            );
+            queue.tweets.append(&mut posts.tweets);
+        }
+    }
+    if let Some(window) = twitter_window {
+        if !window.contains(now) && !posts.toots.is_empty() {
+            info!(
+                "Outside of the posting window for Mastodon, queueing {} post(s)",
+                posts.toots.len()
+            );
+            queue.toots.append(&mut posts.toots);
+        }
+    }
+}
+
+/// Queues a status that failed to post for the next run, unless it failed too
+/// often already. Returns false if the status is given up.
+pub fn queue_retry(queue: &mut Vec<NewStatus>, mut status: NewStatus) -> bool {
+    status.attempts += 1;
+    if status.attempts >= MAX_POST_ATTEMPTS {
+        error!(
+            "Giving up status {} after {} failed attempts: {}",
+            status.original_id, status.attempts, status.text
+        );
+        return false;
+    }
+    queue.push(status);
+    true
+}
+
+fn merge_statuses(mut queued: Vec<NewStatus>, new: Vec<NewStatus>) -> Vec<NewStatus> {
+    for status in new {
+        if !queued.iter().any(|q| q.original_id == status.original_id) {
+            queued.push(status);
+        }
+    }
+    queued
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::tests::new_status;
+
+    // Queued posts come first and posts that are queued already are not
+    // duplicated.
+    #[test]
+    fn merge_queued_posts() {
+        let queue = StatusUpdates {
+            tweets: vec![new_status(1, "Status"), new_status(2, "Status")],
+            toots: Vec::new(),
+            skipped: Vec::new(),
+            ..StatusUpdates::default()
+        };
+        let posts = StatusUpdates {
+            tweets: vec![new_status(2, "Status"), new_status(3, "Status")],
+            toots: vec![new_status(4, "Status")],
+            skipped: Vec::new(),
+            ..StatusUpdates::default()
+        };
+        let merged = queue_merge(queue, posts);
+        let ids: Vec<String> = merged
+            .tweets
+            .iter()
+            .map(|t| t.original_id.to_string())
+            .collect();
+        assert_eq!(ids, vec!["1", "2", "3"]);
+        assert_eq!(merged.toots.len(), 1);
+    }
+
+    #[test]
+    fn delayed_posts() {
+        let mut statuses = vec![
+            new_status(1, "Status"),
+            new_status(2, "Status"),
+            new_status(3, "Status"),
+        ];
+        statuses[2].publish_at = Some(5);
+        let created_at = |id: &StatusId| (id == &StatusId::Number(1)).then_some(1000);
+        queue_delay(&mut statuses, 120, created_at, 2000);
+        assert_eq!(statuses[0].publish_at, Some(8200));
+        assert_eq!(statuses[1].publish_at, Some(9200));
+        assert_eq!(statuses[2].publish_at, Some(5));
+
+        let mut posts = StatusUpdates {
+            tweets: statuses,
+            ..StatusUpdates::default()
+        };
+        let held = posts.hold_until_publish(8200);
+        assert_eq!(posts.tweets.len(), 2);
+        assert_eq!(held.tweets[0].original_id, StatusId::Number(2));
+    }
+
+    // Scheduled posts that are held back stay in the queue when the new posts
+    // are queued outside of the posting window.
+    #[test]
+    fn held_posts_outside_window() {
+        let mut scheduled = new_status(1, "Scheduled");
+        scheduled.publish_at = Some(9000);
+        let mut posts = StatusUpdates {
+            tweets: vec![scheduled, new_status(2, "Status")],
+            toots: vec![new_status(3, "Status")],
+            ..StatusUpdates::default()
+        };
+        let mut queue = posts.hold_until_publish(8000);
+        let window = PostingWindow::try_from("08:00-22:00".to_string()).unwrap();
+        let night = NaiveTime::from_hms_opt(23, 0, 0).unwrap();
+        queue_outside_window(&mut posts, &mut queue, Some(window), None, night);
+        assert!(posts.tweets.is_empty());
+        let ids: Vec<String> = queue
+            .tweets
+            .iter()
+            .map(|t| t.original_id.to_string())
+            .collect();
+        assert_eq!(ids, vec!["1", "2"]);
+        // Posts to Mastodon have no window.
+        assert_eq!(posts.toots.len(), 1);
+        assert!(queue.toots.is_empty());
+
+        let noon = NaiveTime::from_hms_opt(12, 0, 0).unwrap();
+        let mut posts = StatusUpdates {
+            tweets: vec![new_status(4, "Status")],
+            ..StatusUpdates::default()
+        };
+        queue_outside_window(&mut posts, &mut queue, Some(window), None, noon);
+        assert_eq!(posts.tweets.len(), 1);
+        assert_eq!(queue.tweets.len(), 2);
+    }
+
+    #[test]
+    fn retry_failed_posts() {
+        let mut queue = Vec::new();
+        assert!(queue_retry(&mut queue, new_status(1, "Status")));
+        assert_eq!(queue[0].attempts, 1);
+        for _ in 2..MAX_POST_ATTEMPTS {
+            let status = queue.pop().unwrap();
+            assert!(queue_retry(&mut queue, status));
+        }
+        let status = queue.pop().unwrap();
+        assert!(!queue_retry(&mut queue, status));
+        assert!(queue.is_empty());
+
+        // Attempts are kept in the queue file.
+        let json = StatusUpdates {
+            tweets: vec![NewStatus {
+                attempts: 2,
+                ..new_status(1, "Status")
+            }],
+            ..StatusUpdates::default()
+        }
+        .to_json()
+        .unwrap();
+        let queue = StatusUpdates::from_json(&json).unwrap();
+        assert_eq!(queue.tweets[0].attempts, 2);
+    }
+
+    #[test]
+    fn queue_file_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("post_queue.json");
+        let file = file.to_str().unwrap();
+        assert!(queue_load(file).unwrap().tweets.is_empty());
+
+        let queue = StatusUpdates {
+            tweets: vec![new_status(1, "Status")],
+            toots: Vec::new(),
+            skipped: Vec::new(),
+            ..StatusUpdates::default()
+        };
+        queue_save(&queue, file).unwrap();
+        assert_eq!(
+            queue_load(file).unwrap().tweets[0].original_id,
+            StatusId::Number(1)
+        );
+    }
+}
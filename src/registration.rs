@@ -1,15 +1,150 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use elefren::data::Data;
+use elefren::entities::account::Account;
 use elefren::helpers::cli;
 use elefren::scopes::Scopes;
-use elefren::{Mastodon, Registration};
+use elefren::{Mastodon, MastodonClient, Registration};
 use std::io;
+use tokio::runtime::Runtime;
 
 use super::*;
+use crate::errors::SyncError;
+use crate::twitter_oauth2::{twitter_oauth2_register, twitter_oauth2_user};
+
+/// Registers Mastodon and Twitter accounts and checks that the credentials
+/// work. Other options of an existing config are kept.
+///
+/// A Mastodon instance URL and access token skip the Mastodon authorization.
+/// With a client ID the Twitter app is authorized with OAuth 2.0 instead of
+/// OAuth 1.0a.
+pub fn register(
+    rt: &Runtime,
+    existing: Option<Config>,
+    mastodon_token: Option<(&str, &str)>,
+    twitter_client_id: Option<&str>,
+) -> Result<Config> {
+    let mastodon = match mastodon_token {
+        Some((instance, token)) => mastodon_from_access_token(instance, token),
+        None => mastodon_register(),
+    }
+    .context("Failed to setup mastodon account")?;
+    let account = mastodon
+        .verify_credentials()
+        .context("Failed to verify Mastodon credentials")?;
+    println!("Mastodon account: {}", account.acct);
+
+    let twitter_config = match twitter_client_id {
+        Some(client_id) => {
+            let oauth2 =
+                twitter_oauth2_register(client_id).context("Failed to setup twitter account")?;
+            let user =
+                twitter_oauth2_user(&oauth2).context("Failed to verify Twitter credentials")?;
+            println!("Twitter account: {}", user.username);
+            TwitterConfig {
+                oauth2: Some(oauth2),
+                ..new_twitter_config(
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    user.id.parse()?,
+                    user.username,
+                )
+            }
+        }
+        None => {
+            let twitter_config = rt
+                .block_on(twitter_register())
+                .context("Failed to setup twitter account")?;
+            let user = rt
+                .block_on(egg_mode::auth::verify_tokens(&twitter_token(
+                    &twitter_config,
+                )))
+                .context("Failed to verify Twitter credentials")?;
+            println!("Twitter account: {}", user.screen_name);
+            twitter_config
+        }
+    };
+
+    let config = match existing {
+        Some(mut config) => {
+            config.mastodon.app = (*mastodon).clone();
+            config.twitter = TwitterConfig {
+                consumer_key: twitter_config.consumer_key,
+                consumer_secret: twitter_config.consumer_secret,
+                access_token: twitter_config.access_token,
+                access_token_secret: twitter_config.access_token_secret,
+                user_id: twitter_config.user_id,
+                user_name: twitter_config.user_name,
+                oauth2: twitter_config.oauth2,
+                ..config.twitter
+            };
+            config
+        }
+        None => Config {
+            mastodon: MastodonConfig {
+                app: (*mastodon).clone(),
+                // Do not delete older status per default, users should
+                // enable this explicitly.
+                ..MastodonConfig::default()
+            },
+            twitter: twitter_config,
+            notify: None,
+            sentry: None,
+            report: None,
+        },
+    };
+    Ok(config)
+}
+
+/// Creates the Twitter API access token from the config. Without OAuth 1.0a
+/// keys the OAuth 2.0 token is used.
+pub fn twitter_token(config: &TwitterConfig) -> egg_mode::Token {
+    if let (true, Some(oauth2)) = (config.access_token.is_empty(), &config.oauth2) {
+        return egg_mode::Token::Bearer(oauth2.access_token.clone());
+    }
+    access_token(
+        &config.consumer_key,
+        &config.consumer_secret,
+        &config.access_token,
+        &config.access_token_secret,
+    )
+}
+
+pub fn twitter_account_token(config: &TwitterAccountConfig) -> egg_mode::Token {
+    access_token(
+        &config.consumer_key,
+        &config.consumer_secret,
+        &config.access_token,
+        &config.access_token_secret,
+    )
+}
+
+fn access_token(
+    consumer_key: &str,
+    consumer_secret: &str,
+    access_token: &str,
+    access_token_secret: &str,
+) -> egg_mode::Token {
+    let con_token = egg_mode::KeyPair::new(consumer_key.to_string(), consumer_secret.to_string());
+    let access_token =
+        egg_mode::KeyPair::new(access_token.to_string(), access_token_secret.to_string());
+    egg_mode::Token::Access {
+        consumer: con_token,
+        access: access_token,
+    }
+}
 
 pub fn mastodon_register() -> Result<Mastodon> {
     let instance = console_input(
         "Provide the URL of your Mastodon instance, for example https://mastodon.social ",
     )?;
+    let token = console_input(
+        "Paste an access token with read and write scopes from Preferences -> Development on your instance, or leave empty to authorize in the browser",
+    )?;
+    if !token.is_empty() {
+        return mastodon_from_access_token(&instance, &token);
+    }
     let registration = Registration::new(instance)
         .client_name("mastodon-twitter-sync")
         .website("https://github.com/klausi/mastodon-twitter-sync")
@@ -20,6 +155,55 @@ pub fn mastodon_register() -> Result<Mastodon> {
     Ok(cli::authenticate(registration)?)
 }
 
+/// Uses an access token that was created in the Mastodon web interface
+/// instead of registering a new app.
+pub fn mastodon_from_access_token(instance: &str, token: &str) -> Result<Mastodon> {
+    let mastodon = Mastodon::from(Data {
+        base: instance.trim_end_matches('/').to_string().into(),
+        client_id: "".into(),
+        client_secret: "".into(),
+        redirect: "".into(),
+        token: token.to_string().into(),
+    });
+    // Fetching the own account and statuses needs the read scope. The write
+    // scope cannot be checked without posting something.
+    let account = mastodon
+        .verify_credentials()
+        .context("The access token is not valid or is missing the read scope")?;
+    mastodon
+        .statuses(&account.id, None)
+        .context("The access token is missing the read scope")?;
+    Ok(mastodon)
+}
+
+/// Tells the user where their Mastodon account has moved to and offers to
+/// register the new account in the config file.
+pub fn mastodon_handle_moved_account(
+    moved: &Account,
+    config: &mut Config,
+    config_file: &str,
+) -> Result<()> {
+    println!(
+        "Your Mastodon account has moved to {} ({})",
+        moved.acct, moved.url
+    );
+    let answer =
+        console_input("Do you want to register the new account in your config file? [y/N]")?;
+    if !answer.eq_ignore_ascii_case("y") {
+        return Err(SyncError::MastodonAccountMoved {
+            acct: moved.acct.clone(),
+        }
+        .into());
+    }
+
+    let mastodon = mastodon_register()?;
+    config.mastodon.app = (*mastodon).clone();
+    config_save(config, config_file)?;
+    println!("Config file updated, the next run will sync with your new Mastodon account.");
+
+    Ok(())
+}
+
 pub async fn twitter_register() -> Result<TwitterConfig> {
     println!("Go to https://developer.twitter.com/en/apps/create to create a new Twitter app.");
     println!("Name: Mastodon Twitter Sync (plus something unique, like your name)");
@@ -45,23 +229,39 @@ pub async fn twitter_register() -> Result<TwitterConfig> {
         egg_mode::Token::Access {
             access: ref access_token,
             ..
-        } => Ok(TwitterConfig {
+        } => Ok(new_twitter_config(
             consumer_key,
             consumer_secret,
-            access_token: access_token.key.to_string(),
-            access_token_secret: access_token.secret.to_string(),
+            access_token.key.to_string(),
+            access_token.secret.to_string(),
             user_id,
-            user_name: screen_name,
-            delete_older_statuses: false,
-            delete_older_favs: false,
-            sync_retweets: true,
-            sync_hashtag: None,
-        }),
+            screen_name,
+        )),
         _ => unreachable!(),
     }
 }
 
-fn console_input(prompt: &str) -> Result<String> {
+/// Returns the Twitter config of an account with the default options.
+fn new_twitter_config(
+    consumer_key: String,
+    consumer_secret: String,
+    access_token: String,
+    access_token_secret: String,
+    user_id: u64,
+    user_name: String,
+) -> TwitterConfig {
+    TwitterConfig {
+        consumer_key,
+        consumer_secret,
+        access_token,
+        access_token_secret,
+        user_id,
+        user_name,
+        ..TwitterConfig::default()
+    }
+}
+
+pub fn console_input(prompt: &str) -> Result<String> {
     println!("{prompt}: ");
     let mut line = String::new();
     let _ = io::stdin().read_line(&mut line)?;
@@ -0,0 +1,288 @@
+use crate::conflicts::Conflict;
+use crate::sync::{NewStatus, Platform, SkippedPost, StatusUpdates};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Builds a readable report of what a dry run would post.
+pub fn dry_run_report(
+    posts: &StatusUpdates,
+    conflicts: &[Conflict],
+    mastodon_base: &str,
+) -> String {
+    let mut report = format!(
+        "Dry run: {} post(s) would be synced to Mastodon, {} to Twitter. Use --print-json for \
+        machine readable output.\n",
+        posts.toots.len(),
+        posts.tweets.len()
+    );
+    for toot in &posts.toots {
+        let source_url = format!("https://twitter.com/twitter/status/{}", toot.original_id);
+        report_status(&mut report, toot, "Mastodon", &source_url, 0);
+    }
+    for tweet in &posts.tweets {
+        let source_url = format!(
+            "{}/web/statuses/{}",
+            mastodon_base.trim_end_matches('/'),
+            tweet.original_id
+        );
+        report_status(&mut report, tweet, "Twitter", &source_url, 0);
+    }
+    if !conflicts.is_empty() {
+        let _ = writeln!(
+            report,
+            "\n{} post(s) are not synced because they are similar to a recent status:",
+            conflicts.len()
+        );
+    }
+    for conflict in conflicts {
+        report_conflict(&mut report, conflict);
+    }
+    report
+}
+
+/// Summarizes why source posts were not synced, with the number of posts per
+/// reason and optionally each post.
+pub fn skipped_summary(skipped: &[SkippedPost], details: bool) -> String {
+    let mut summary = format!("Skipped {} source post(s):", skipped.len());
+    let mut counts = BTreeMap::new();
+    for post in skipped {
+        *counts.entry(post.reason).or_insert(0) += 1;
+    }
+    for (reason, count) in counts {
+        let _ = write!(summary, "\n  {count} × {reason}");
+    }
+    if details {
+        for post in skipped {
+            let first_line = post.text.lines().next().unwrap_or_default();
+            let _ = write!(
+                summary,
+                "\n  {} {}: {} | {first_line}",
+                post.source, post.original_id, post.reason
+            );
+        }
+    }
+    summary
+}
+
+/// Counts of one sync direction for the summary at the end of a run.
+#[derive(Debug, Clone, Default)]
+pub struct DirectionSummary {
+    pub direction: &'static str,
+    pub posted: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub queued: usize,
+}
+
+/// Builds a table of the counts per sync direction. With colors posted counts
+/// are green, failed ones red and skipped and queued ones yellow.
+pub fn summary_table(rows: &[DirectionSummary], color: bool) -> String {
+    let paint = |text: String, code: &str, count: usize| {
+        if color && count > 0 {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text
+        }
+    };
+    let header = format!(
+        "{:<20}{:>8}{:>8}{:>9}{:>8}",
+        "Direction", "Posted", "Failed", "Skipped", "Queued"
+    );
+    let mut table = if color {
+        format!("\x1b[1m{header}\x1b[0m\n")
+    } else {
+        format!("{header}\n")
+    };
+    for row in rows {
+        let _ = writeln!(
+            table,
+            "{:<20}{}{}{}{}",
+            row.direction,
+            paint(format!("{:>8}", row.posted), "32", row.posted),
+            paint(format!("{:>8}", row.failed), "31", row.failed),
+            paint(format!("{:>9}", row.skipped), "33", row.skipped),
+            paint(format!("{:>8}", row.queued), "33", row.queued),
+        );
+    }
+    table
+}
+
+fn report_conflict(report: &mut String, conflict: &Conflict) {
+    let _ = writeln!(report);
+    let _ = writeln!(
+        report,
+        "To {} (source ID {}), {:.0}% similar",
+        conflict.target,
+        conflict.status.original_id,
+        conflict.similarity * 100.0
+    );
+    let _ = writeln!(report, "  Text:");
+    for line in conflict.status.text.lines() {
+        let _ = writeln!(report, "  | {line}");
+    }
+    let _ = writeln!(report, "  Existing {} status:", conflict.target);
+    for line in conflict.existing.lines() {
+        let _ = writeln!(report, "  | {line}");
+    }
+}
+
+fn report_status(
+    report: &mut String,
+    status: &NewStatus,
+    target: &str,
+    source_url: &str,
+    depth: usize,
+) {
+    let indent = "    ".repeat(depth);
+    let reason = if depth > 0 {
+        "reply in a new thread".to_string()
+    } else if let Some(parent_id) = &status.in_reply_to_id {
+        format!("reply to already synced {target} status {parent_id}")
+    } else {
+        format!("not found among recent {target} statuses")
+    };
+    // Writing to a String cannot fail.
+    let _ = writeln!(report);
+    if depth == 0 {
+        let _ = writeln!(report, "{indent}To {target} from {source_url}");
+    } else {
+        let _ = writeln!(
+            report,
+            "{indent}Thread reply (source ID {})",
+            status.original_id
+        );
+    }
+    let _ = writeln!(report, "{indent}  Reason: {reason}");
+    let _ = writeln!(report, "{indent}  Text:");
+    for line in status.text.lines() {
+        let _ = writeln!(report, "{indent}  | {line}");
+    }
+    for attachment in &status.attachments {
+        match &attachment.alt_text {
+            Some(alt_text) => {
+                let _ = writeln!(
+                    report,
+                    "{indent}  Attachment: {} (alt text: {alt_text})",
+                    attachment.attachment_url
+                );
+            }
+            None => {
+                let _ = writeln!(
+                    report,
+                    "{indent}  Attachment: {} (no alt text)",
+                    attachment.attachment_url
+                );
+            }
+        }
+    }
+    for reply in &status.replies {
+        report_status(report, reply, target, source_url, depth + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::sync::tests::new_status;
+    use crate::sync::{NewMedia, SkipReason, StatusId};
+
+    // Test that the dry run report contains the thread structure.
+    #[test]
+    fn report_thread() {
+        let posts = StatusUpdates {
+            tweets: vec![NewStatus {
+                attachments: vec![NewMedia {
+                    attachment_url: "https://example.com/image.jpg".to_string(),
+                    alt_text: Some("An image".to_string()),
+                    focus: None,
+                }],
+                replies: vec![new_status(2, "Reply")],
+                ..new_status(1, "Original")
+            }],
+            toots: Vec::new(),
+            skipped: Vec::new(),
+            ..StatusUpdates::default()
+        };
+        let report = dry_run_report(&posts, &[], "https://mastodon.social/");
+        assert!(report.contains("To Twitter from https://mastodon.social/web/statuses/1"));
+        assert!(report.contains("  | Original"));
+        assert!(report.contains("  Attachment: https://example.com/image.jpg (alt text: An image)"));
+        assert!(report.contains("    Thread reply (source ID 2)"));
+        assert!(report.contains("      | Reply"));
+    }
+
+    #[test]
+    fn summary_counts() {
+        let rows = [
+            DirectionSummary {
+                direction: "Mastodon → Twitter",
+                posted: 3,
+                skipped: 2,
+                ..DirectionSummary::default()
+            },
+            DirectionSummary {
+                direction: "Twitter → Mastodon",
+                failed: 1,
+                queued: 1,
+                ..DirectionSummary::default()
+            },
+        ];
+        assert_eq!(
+            summary_table(&rows, false),
+            "Direction             Posted  Failed  Skipped  Queued\n\
+            Mastodon → Twitter         3       0        2       0\n\
+            Twitter → Mastodon         0       1        0       1\n"
+        );
+        let colored = summary_table(&rows, true);
+        assert!(colored.contains("\x1b[32m       3\x1b[0m"));
+        assert!(colored.contains("\x1b[31m       1\x1b[0m"));
+        // Zero counts are not colored.
+        assert!(colored.contains("\x1b[33m        2\x1b[0m       0\n"));
+    }
+
+    #[test]
+    fn report_conflicts() {
+        let conflict = Conflict {
+            target: Platform::Mastodon,
+            status: new_status(5, "Hello world!"),
+            existing: "Hello world".to_string(),
+            similarity: 0.92,
+        };
+        let report = dry_run_report(&StatusUpdates::default(), &[conflict], "");
+        assert!(report.contains("1 post(s) are not synced because they are similar"));
+        assert!(report.contains("To Mastodon (source ID 5), 92% similar"));
+        assert!(report.contains("  | Hello world!"));
+    }
+    #[test]
+    fn report_skipped() {
+        let skipped = vec![
+            SkippedPost {
+                source: Platform::Twitter,
+                original_id: StatusId::Number(1),
+                text: "@other Hi".to_string(),
+                reason: SkipReason::ReplyToOther,
+            },
+            SkippedPost {
+                source: Platform::Mastodon,
+                original_id: StatusId::Number(2),
+                text: "No tag".to_string(),
+                reason: SkipReason::MissingHashtag,
+            },
+            SkippedPost {
+                source: Platform::Twitter,
+                original_id: StatusId::Number(3),
+                text: "@someone Hello".to_string(),
+                reason: SkipReason::ReplyToOther,
+            },
+        ];
+        let summary = skipped_summary(&skipped, false);
+        assert!(summary.starts_with("Skipped 3 source post(s):"));
+        assert!(summary.contains("  2 × reply to another account"));
+        assert!(summary.contains("  1 × sync hashtag missing"));
+        assert!(!summary.contains("No tag"));
+
+        let summary = skipped_summary(&skipped, true);
+        assert!(summary.contains("  Mastodon 2: sync hashtag missing | No tag"));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::args::{Args, Command};
+use crate::run_with_status;
+use crate::shutdown::{shutdown_init, shutdown_requested};
+use crate::status::RunStatus;
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::process::ExitCode;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+/// Exit code if a run failed or posts could not be synced.
+pub const EXIT_ERROR: u8 = 1;
+/// Exit code with MTS_RUN_MODE=once if there was nothing to sync: nothing
+/// was posted and no posts are waiting in the queue. Argument errors exit with
+/// 2, so it is distinct from all errors.
+pub const EXIT_NOTHING_TO_DO: u8 = 10;
+
+// Seconds between two runs in daemon mode without MTS_INTERVAL.
+const DEFAULT_INTERVAL: u64 = 600;
+
+/// How the program runs, set with the MTS_RUN_MODE environment variable for
+/// containers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunMode {
+    /// One run as started from the command line or by cron.
+    Default,
+    /// One run that shuts down gracefully and tells with the exit code
+    /// whether there was anything to sync.
+    Once,
+    /// Syncs every MTS_INTERVAL seconds until the container is stopped.
+    Daemon(Duration),
+}
+
+impl RunMode {
+    fn from_env(mode: Option<&str>, interval: Option<&str>) -> Result<RunMode> {
+        match mode {
+            None | Some("") => Ok(RunMode::Default),
+            Some("once") => Ok(RunMode::Once),
+            Some("daemon") => {
+                let seconds = match interval {
+                    Some(interval) => interval
+                        .parse()
+                        .with_context(|| format!("Invalid MTS_INTERVAL {interval}"))?,
+                    None => DEFAULT_INTERVAL,
+                };
+                Ok(RunMode::Daemon(Duration::from_secs(seconds)))
+            }
+            Some(mode) => bail!("Unknown MTS_RUN_MODE {mode}, use \"once\" or \"daemon\""),
+        }
+    }
+}
+
+/// Runs the command line in the run mode of the environment and returns the
+/// exit code. The run mode only applies to sync runs, other commands run
+/// once.
+pub fn run_main(args: Args) -> ExitCode {
+    let mode = match RunMode::from_env(
+        env::var("MTS_RUN_MODE").ok().as_deref(),
+        env::var("MTS_INTERVAL").ok().as_deref(),
+    ) {
+        Ok(mode) if matches!(args.command, None | Some(Command::Flush)) => mode,
+        Ok(_) => RunMode::Default,
+        Err(e) => {
+            print_error(&e);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    if mode != RunMode::Default {
+        shutdown_init();
+    }
+
+    match mode {
+        RunMode::Default | RunMode::Once => {
+            let (result, status) = run_with_status(args);
+            if let Err(e) = &result {
+                print_error(e);
+            }
+            ExitCode::from(exit_code(&result, &status, mode))
+        }
+        RunMode::Daemon(interval) => {
+            info!("Syncing every {} seconds", interval.as_secs());
+            loop {
+                if let (Err(e), _) = run_with_status(args.clone()) {
+                    error!("Sync run failed: {e:#}");
+                }
+                if !wait(interval) {
+                    info!("Shutting down");
+                    return ExitCode::SUCCESS;
+                }
+            }
+        }
+    }
+}
+
+fn exit_code(result: &Result<()>, status: &RunStatus, mode: RunMode) -> u8 {
+    // Posts held back by the posting window, the write budget or a shutdown
+    // are work that is still pending.
+    let nothing_to_do = status.posted_toots + status.posted_tweets == 0 && status.queued_posts == 0;
+    match result {
+        Err(_) => EXIT_ERROR,
+        Ok(()) if mode == RunMode::Once && nothing_to_do => EXIT_NOTHING_TO_DO,
+        Ok(()) => 0,
+    }
+}
+
+// Waits for the next run, returns false if the program should shut down.
+fn wait(interval: Duration) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < interval {
+        if shutdown_requested() {
+            return false;
+        }
+        sleep(Duration::from_secs(1).min(interval.saturating_sub(start.elapsed())));
+    }
+    !shutdown_requested()
+}
+
+fn print_error(err: &anyhow::Error) {
+    eprintln!("Error: {err}");
+    for cause in err.chain().skip(1) {
+        eprintln!("Because: {cause}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn run_mode_from_env() {
+        assert_eq!(RunMode::from_env(None, None).unwrap(), RunMode::Default);
+        assert_eq!(
+            RunMode::from_env(Some("once"), None).unwrap(),
+            RunMode::Once
+        );
+        assert_eq!(
+            RunMode::from_env(Some("daemon"), Some("120")).unwrap(),
+            RunMode::Daemon(Duration::from_secs(120))
+        );
+        assert!(RunMode::from_env(Some("daemon"), Some("10m")).is_err());
+        assert!(RunMode::from_env(Some("forever"), None).is_err());
+    }
+
+    #[test]
+    fn nothing_to_do_exit_code() {
+        let mut status = RunStatus::default();
+        assert_eq!(
+            exit_code(&Ok(()), &status, RunMode::Once),
+            EXIT_NOTHING_TO_DO
+        );
+        // Without a run mode the exit code stays as it was.
+        assert_eq!(exit_code(&Ok(()), &status, RunMode::Default), 0);
+        // Queued posts are synced on a later run.
+        status.queued_posts = 2;
+        assert_eq!(exit_code(&Ok(()), &status, RunMode::Once), 0);
+        status.queued_posts = 0;
+        status.posted_tweets = 1;
+        assert_eq!(exit_code(&Ok(()), &status, RunMode::Once), 0);
+        assert_eq!(
+            exit_code(&Err(anyhow!("Twitter is down")), &status, RunMode::Once),
+            EXIT_ERROR
+        );
+    }
+}
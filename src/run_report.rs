@@ -0,0 +1,142 @@
+// Without both networks there are no sync runs to report.
+#![cfg_attr(not(all(feature = "mastodon", feature = "twitter")), allow(dead_code))]
+
+use crate::api_budget::ApiCalls;
+use crate::history::{DeletedKind, HistoryNetwork};
+use crate::status::RunStatus;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::Mutex;
+
+// The report of the current run, the parts are added where statuses are
+// posted, skipped or deleted.
+static RUN_REPORT: Mutex<Option<RunReport>> = Mutex::new(None);
+
+/// Machine readable report of a run, written to the configured report file
+/// to audit unattended installations.
+#[derive(Debug, Default, Serialize)]
+pub struct RunReport {
+    #[serde(flatten)]
+    pub status: RunStatus,
+    pub dry_run: bool,
+    // Posts that were selected to be synced in this run.
+    pub planned: Vec<ReportedPost>,
+    pub created: Vec<ReportedPost>,
+    pub failed: Vec<ReportedPost>,
+    pub skipped: Vec<ReportedPost>,
+    pub deleted: Vec<ReportedDeletion>,
+    // Number of read and write API calls by network.
+    pub api_calls: BTreeMap<HistoryNetwork, ApiCalls>,
+}
+
+/// A post with the network it was synced to.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportedPost {
+    pub target: HistoryNetwork,
+    pub source_id: String,
+    // URL of the created post.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    // Why the post was skipped or failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportedDeletion {
+    pub network: HistoryNetwork,
+    pub kind: DeletedKind,
+    pub count: usize,
+}
+
+impl ReportedPost {
+    pub fn new(target: HistoryNetwork, source_id: impl ToString, text: &str) -> ReportedPost {
+        ReportedPost {
+            target,
+            source_id: source_id.to_string(),
+            url: None,
+            reason: None,
+            text: text.to_string(),
+        }
+    }
+}
+
+/// Starts an empty report for a new run.
+pub fn run_report_start(dry_run: bool) {
+    *RUN_REPORT.lock().unwrap() = Some(RunReport {
+        dry_run,
+        ..RunReport::default()
+    });
+}
+
+/// Adds to the report of the current run, nothing is recorded outside of a
+/// run.
+pub fn run_report(update: impl FnOnce(&mut RunReport)) {
+    if let Some(report) = RUN_REPORT.lock().unwrap().as_mut() {
+        update(report);
+    }
+}
+
+/// Records deleted statuses or favourites.
+pub fn run_report_deleted(network: HistoryNetwork, kind: DeletedKind, count: usize) {
+    if count > 0 {
+        run_report(|report| {
+            report.deleted.push(ReportedDeletion {
+                network,
+                kind,
+                count,
+            })
+        });
+    }
+}
+
+/// Finishes the report of the current run with its result and writes it to
+/// the file.
+pub fn run_report_save(file: &str, status: &RunStatus) -> Result<()> {
+    let mut report = match RUN_REPORT.lock().unwrap().take() {
+        Some(report) => report,
+        None => return Ok(()),
+    };
+    report.status = status.clone();
+    let json = serde_json::to_string_pretty(&report)?;
+    fs::write(file, json.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn report_json() {
+        let mut report = RunReport {
+            dry_run: true,
+            ..RunReport::default()
+        };
+        let mut created = ReportedPost::new(HistoryNetwork::Twitter, 123, "Hello");
+        created.url = Some("https://twitter.com/test/status/456".to_string());
+        report.created.push(created);
+        report
+            .api_calls
+            .insert(HistoryNetwork::Mastodon, ApiCalls { read: 3, write: 1 });
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["success"], json!(false));
+        assert_eq!(
+            json["created"],
+            json!([{
+                "target": "twitter",
+                "source_id": "123",
+                "url": "https://twitter.com/test/status/456",
+                "text": "Hello",
+            }])
+        );
+        assert_eq!(
+            json["api_calls"],
+            json!({"mastodon": {"read": 3, "write": 1}})
+        );
+    }
+}
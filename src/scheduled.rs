@@ -0,0 +1,136 @@
+use crate::mastodon::ScheduledStatus;
+use crate::sync::*;
+use std::collections::BTreeSet;
+
+// Original IDs of scheduled statuses are prefixed, they change when Mastodon
+// publishes the status.
+const SCHEDULED_ID_PREFIX: &str = "scheduled-";
+
+/// Merges the scheduled Mastodon statuses as tweets that are published at the
+/// same time into the posts.
+///
+/// Scheduled statuses that are not due yet are replaced with the current
+/// list, so deleted or rescheduled statuses are dropped. Due ones are posted
+/// instead of the status that Mastodon published in the meantime.
+pub fn scheduled_merge(
+    posts: &mut StatusUpdates,
+    scheduled: &[ScheduledStatus],
+    options: &SyncOptions,
+    now: i64,
+) {
+    posts.tweets.retain(|status| {
+        !is_scheduled(status) || status.publish_at.map_or(true, |time| time <= now)
+    });
+    let due: BTreeSet<String> = posts
+        .tweets
+        .iter()
+        .filter(|status| is_scheduled(status))
+        .map(|status| unify_post_content(status.text.clone()))
+        .collect();
+    if !due.is_empty() {
+        posts.tweets.retain(|status| {
+            is_scheduled(status) || !due.contains(&unify_post_content(status.text.clone()))
+        });
+    }
+    posts.tweets.extend(
+        scheduled
+            .iter()
+            .filter_map(|status| scheduled_tweet(status, options)),
+    );
+}
+
+fn is_scheduled(status: &NewStatus) -> bool {
+    matches!(&status.original_id, StatusId::Text(id) if id.starts_with(SCHEDULED_ID_PREFIX))
+}
+
+// Returns the tweet for a scheduled status, None if it would not be synced.
+fn scheduled_tweet(status: &ScheduledStatus, options: &SyncOptions) -> Option<NewStatus> {
+    let visibility = status.params.visibility.as_deref().unwrap_or("public");
+    if status.params.in_reply_to_id.is_some() || !matches!(visibility, "public" | "unlisted") {
+        return None;
+    }
+    // Escape direct user mentions with @\ like published toots.
+    let fulltext = status
+        .params
+        .text
+        .replace(" @", " @\\")
+        .replace(" @\\\\", " @\\");
+    if fulltext.starts_with('@')
+        || options
+            .sync_hashtags_mastodon
+            .skip_reason(&fulltext)
+            .is_some()
+    {
+        return None;
+    }
+    let text = options.sync_hashtags_mastodon.remove_from(&fulltext);
+    // The URL of the toot is not known yet.
+    let text = tweet_shorten(&text, &None, &options.tweet_text.shorten);
+    Some(NewStatus {
+        attachments: status
+            .media_attachments
+            .iter()
+            .map(|attachment| NewMedia {
+                attachment_url: attachment.url.clone(),
+                alt_text: truncate_alt_text(attachment.description.clone(), TWITTER_ALT_TEXT_LIMIT),
+                focus: None,
+            })
+            .collect(),
+        publish_at: Some(status.scheduled_at.timestamp()),
+        ..NewStatus::new(
+            text,
+            StatusId::Text(format!("{SCHEDULED_ID_PREFIX}{}", status.id)),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::tests::{new_status, DEFAULT_SYNC_OPTIONS};
+
+    fn scheduled_status(id: &str, text: &str, scheduled_at: &str) -> ScheduledStatus {
+        serde_json::from_str(&format!(
+            r#"{{"id": "{id}", "scheduled_at": "{scheduled_at}", "params": {{"text": "{text}", "visibility": "public", "in_reply_to_id": null}}, "media_attachments": []}}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn merge_scheduled_statuses() {
+        // 2022-10-05T12:00:00Z
+        let now = 1664971200;
+        let mut posts = StatusUpdates::default();
+        let scheduled = vec![
+            scheduled_status("1", "Later", "2022-10-05T14:00:00.000Z"),
+            scheduled_status("2", "Deleted later", "2022-10-05T15:00:00.000Z"),
+        ];
+        scheduled_merge(&mut posts, &scheduled, &DEFAULT_SYNC_OPTIONS, now);
+        assert_eq!(posts.tweets.len(), 2);
+        assert_eq!(posts.tweets[0].publish_at, Some(now + 7200));
+        assert_eq!(
+            posts.tweets[0].original_id,
+            StatusId::Text("scheduled-1".to_string())
+        );
+
+        // The second status was deleted, the first one was published by
+        // Mastodon and also found on the timeline.
+        let published = new_status(100, "Later");
+        posts.tweets.push(published);
+        scheduled_merge(&mut posts, &[], &DEFAULT_SYNC_OPTIONS, now + 7200);
+        assert_eq!(posts.tweets.len(), 1);
+        assert_eq!(
+            posts.tweets[0].original_id,
+            StatusId::Text("scheduled-1".to_string())
+        );
+        let held = posts.hold_until_publish(now + 7200);
+        assert!(held.tweets.is_empty());
+    }
+
+    #[test]
+    fn private_scheduled_status() {
+        let mut status = scheduled_status("1", "Secret", "2022-10-05T14:00:00.000Z");
+        status.params.visibility = Some("private".to_string());
+        assert!(scheduled_tweet(&status, &DEFAULT_SYNC_OPTIONS).is_none());
+    }
+}
@@ -0,0 +1,259 @@
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What the installed service runs.
+#[derive(Debug, Clone)]
+struct ServiceSetup {
+    name: String,
+    exe: PathBuf,
+    config_file: PathBuf,
+    working_dir: PathBuf,
+    // Environment variables that are passed on to the service.
+    env: Vec<(String, String)>,
+    interval_minutes: u64,
+}
+
+/// A file of the service definition.
+#[derive(Debug)]
+struct ServiceFile {
+    path: PathBuf,
+    content: String,
+}
+
+// Variables of the current environment that change where files are or how
+// much is logged.
+const PASSED_ENV: &[&str] = &["MTS_CACHE_DIR", "RUST_LOG", "NO_COLOR"];
+
+/// Writes a systemd user service and timer on Linux or a launchd agent on
+/// macOS that runs the sync periodically with the current config file, prints
+/// them with dry_run.
+pub fn install_service(
+    config_file: &str,
+    name: &str,
+    interval_minutes: u64,
+    dry_run: bool,
+) -> Result<()> {
+    if interval_minutes == 0 {
+        bail!("The interval must be at least one minute");
+    }
+    let working_dir = env::current_dir()?;
+    let config_file = working_dir.join(config_file);
+    if !config_file.exists() {
+        bail!(
+            "Config file {} does not exist, create it with the init command first",
+            config_file.display()
+        );
+    }
+    let setup = ServiceSetup {
+        name: name.to_string(),
+        exe: env::current_exe()?,
+        config_file,
+        working_dir,
+        env: PASSED_ENV
+            .iter()
+            .filter_map(|var| Some((var.to_string(), env::var(var).ok()?)))
+            .collect(),
+        interval_minutes,
+    };
+    let home = PathBuf::from(env::var("HOME").context("HOME is not set")?);
+    let (files, enable) = if cfg!(target_os = "macos") {
+        let plist = launchd_plist(&setup, &home.join("Library/LaunchAgents"));
+        let enable = format!("launchctl load {}", plist.path.display());
+        (vec![plist], enable)
+    } else if cfg!(target_os = "linux") {
+        let config_home = env::var("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| home.join(".config"));
+        let units = systemd_units(&setup, &config_home.join("systemd/user"));
+        let enable =
+            format!("systemctl --user daemon-reload && systemctl --user enable --now {name}.timer");
+        (units, enable)
+    } else {
+        bail!("Services can only be installed with systemd on Linux and launchd on macOS, use the Task Scheduler on Windows");
+    };
+
+    for file in files {
+        if dry_run {
+            println!("# {}\n{}", file.path.display(), file.content);
+            continue;
+        }
+        if let Some(dir) = file.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+        }
+        fs::write(&file.path, file.content.as_bytes())
+            .with_context(|| format!("Failed to write {}", file.path.display()))?;
+        println!("Written {}", file.path.display());
+    }
+    println!("Start the periodic sync with:\n{enable}");
+    Ok(())
+}
+
+fn systemd_units(setup: &ServiceSetup, dir: &Path) -> Vec<ServiceFile> {
+    let mut service = format!(
+        "[Unit]
+Description=Sync Mastodon and Twitter
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+Type=oneshot
+WorkingDirectory={}
+ExecStart={} --config {}
+",
+        systemd_escape(&setup.working_dir.to_string_lossy()),
+        systemd_quote(&setup.exe.to_string_lossy()),
+        systemd_quote(&setup.config_file.to_string_lossy()),
+    );
+    for (name, value) in &setup.env {
+        service.push_str(&format!(
+            "Environment={}\n",
+            systemd_quote(&format!("{name}={value}"))
+        ));
+    }
+    let timer = format!(
+        "[Unit]
+Description=Sync Mastodon and Twitter every {minutes} minutes
+
+[Timer]
+OnBootSec=2min
+OnUnitActiveSec={minutes}min
+
+[Install]
+WantedBy=timers.target
+",
+        minutes = setup.interval_minutes
+    );
+    vec![
+        ServiceFile {
+            path: dir.join(format!("{}.service", setup.name)),
+            content: service,
+        },
+        ServiceFile {
+            path: dir.join(format!("{}.timer", setup.name)),
+            content: timer,
+        },
+    ]
+}
+
+// Escapes % which starts a specifier in systemd unit files.
+fn systemd_escape(value: &str) -> String {
+    value.replace('%', "%%")
+}
+
+// Quotes an argument of a command line or an environment variable for a
+// systemd unit file.
+fn systemd_quote(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        systemd_escape(&value.replace('\\', "\\\\").replace('"', "\\\""))
+    )
+}
+
+fn launchd_plist(setup: &ServiceSetup, dir: &Path) -> ServiceFile {
+    let label = format!("com.github.klausi.{}", setup.name);
+    let string = |value: &str| format!("<string>{}</string>", xml_escape(value));
+    let mut env = String::new();
+    for (name, value) in &setup.env {
+        env.push_str(&format!(
+            "        <key>{}</key>\n        {}\n",
+            xml_escape(name),
+            string(value)
+        ));
+    }
+    let log_file = setup.working_dir.join(format!("{}.log", setup.name));
+    let content = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    {}
+    <key>ProgramArguments</key>
+    <array>
+        {}
+        <string>--config</string>
+        {}
+    </array>
+    <key>WorkingDirectory</key>
+    {}
+    <key>EnvironmentVariables</key>
+    <dict>
+{env}    </dict>
+    <key>StartInterval</key>
+    <integer>{}</integer>
+    <key>StandardOutPath</key>
+    {log}
+    <key>StandardErrorPath</key>
+    {log}
+</dict>
+</plist>
+"#,
+        string(&label),
+        string(&setup.exe.to_string_lossy()),
+        string(&setup.config_file.to_string_lossy()),
+        string(&setup.working_dir.to_string_lossy()),
+        setup.interval_minutes * 60,
+        log = string(&log_file.to_string_lossy()),
+    );
+    ServiceFile {
+        path: dir.join(format!("{label}.plist")),
+        content,
+    }
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> ServiceSetup {
+        ServiceSetup {
+            name: "mastodon-twitter-sync".to_string(),
+            exe: PathBuf::from("/opt/mts/mastodon-twitter-sync"),
+            config_file: PathBuf::from("/home/me/sync & more/100%.toml"),
+            working_dir: PathBuf::from("/home/me/sync & more"),
+            env: vec![("MTS_CACHE_DIR".to_string(), "/var/cache/mts".to_string())],
+            interval_minutes: 15,
+        }
+    }
+
+    #[test]
+    fn systemd_service_and_timer() {
+        let units = systemd_units(&setup(), Path::new("/home/me/.config/systemd/user"));
+        assert_eq!(
+            units[0].path,
+            Path::new("/home/me/.config/systemd/user/mastodon-twitter-sync.service")
+        );
+        assert!(units[0].content.contains(
+            "WorkingDirectory=/home/me/sync & more\nExecStart=\"/opt/mts/mastodon-twitter-sync\" --config \"/home/me/sync & more/100%%.toml\"\nEnvironment=\"MTS_CACHE_DIR=/var/cache/mts\"\n"
+        ));
+        assert!(units[1].content.contains("OnUnitActiveSec=15min\n"));
+    }
+
+    #[test]
+    fn launchd_agent() {
+        let plist = launchd_plist(&setup(), Path::new("/Users/me/Library/LaunchAgents"));
+        assert_eq!(
+            plist.path,
+            Path::new(
+                "/Users/me/Library/LaunchAgents/com.github.klausi.mastodon-twitter-sync.plist"
+            )
+        );
+        assert!(plist
+            .content
+            .contains("<string>/home/me/sync &amp; more/100%.toml</string>"));
+        assert!(plist
+            .content
+            .contains("<key>MTS_CACHE_DIR</key>\n        <string>/var/cache/mts</string>\n"));
+        assert!(plist.content.contains("<integer>900</integer>"));
+    }
+}
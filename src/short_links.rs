@@ -0,0 +1,123 @@
+use anyhow::Result;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+// Link shorteners whose links are resolved before comparing posts. Twitter
+// expands them differently than they were posted on Mastodon.
+const SHORTENERS: &[&str] = &[
+    "bit.ly",
+    "buff.ly",
+    "dlvr.it",
+    "goo.gl",
+    "is.gd",
+    "ow.ly",
+    "t.co",
+    "tinyurl.com",
+];
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Resolves links of common link shorteners with HEAD requests.
+///
+/// Resolved links are kept in a JSON cache file, so each link is only
+/// requested once. Links that cannot be resolved are left as they are and
+/// tried again on the next run.
+#[derive(Debug)]
+pub struct ShortLinkResolver {
+    file: Option<String>,
+    links: Mutex<BTreeMap<String, String>>,
+    pattern: Regex,
+}
+
+impl ShortLinkResolver {
+    /// Reads the resolved links from the cache file, a missing file is an
+    /// empty cache. Without a file nothing is written.
+    pub fn load(file: Option<&str>) -> ShortLinkResolver {
+        let links = file
+            .and_then(|file| fs::read_to_string(file).ok())
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        let domains: Vec<_> = SHORTENERS
+            .iter()
+            .map(|domain| regex::escape(domain))
+            .collect();
+        ShortLinkResolver {
+            file: file.map(str::to_string),
+            links: Mutex::new(links),
+            pattern: Regex::new(&format!(
+                r"(?i)\bhttps?://(?:www\.)?(?:{})/[\w-]+",
+                domains.join("|")
+            ))
+            .unwrap(),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(file) = &self.file {
+            let json = serde_json::to_string_pretty(&*self.links.lock().unwrap())?;
+            fs::write(file, json.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the short links in a text with the URLs they redirect to.
+    pub fn resolve_text(&self, text: &str) -> String {
+        self.pattern
+            .replace_all(text, |captures: &regex::Captures| {
+                let link = &captures[0];
+                self.resolve(link).unwrap_or_else(|| link.to_string())
+            })
+            .into_owned()
+    }
+
+    fn resolve(&self, link: &str) -> Option<String> {
+        if let Some(resolved) = self.links.lock().unwrap().get(link) {
+            return Some(resolved.clone());
+        }
+        let resolved = match head_request(link) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                warn!("Failed to resolve short link {link}: {e}");
+                return None;
+            }
+        };
+        debug!("Resolved short link {link} to {resolved}");
+        self.links
+            .lock()
+            .unwrap()
+            .insert(link.to_string(), resolved.clone());
+        Some(resolved)
+    }
+}
+
+// Returns the URL after following all redirects.
+fn head_request(link: &str) -> Result<String> {
+    let response = reqwest::blocking::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?
+        .head(link)
+        .send()?
+        .error_for_status()?;
+    Ok(response.url().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_cached_links() {
+        let resolver = ShortLinkResolver::load(None);
+        resolver.links.lock().unwrap().insert(
+            "https://bit.ly/abc".to_string(),
+            "https://example.com/article".to_string(),
+        );
+        assert_eq!(
+            resolver.resolve_text("Read https://bit.ly/abc now, not https://example.com/bit.ly/x"),
+            "Read https://example.com/article now, not https://example.com/bit.ly/x"
+        );
+    }
+}
@@ -0,0 +1,37 @@
+// Without both networks only the deletions check for a shutdown.
+#![cfg_attr(not(all(feature = "mastodon", feature = "twitter")), allow(dead_code))]
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::flag;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use tracing::warn;
+
+// Set by the signal handler, checked between posts and deletions.
+static SHUTDOWN: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// Stops the run gracefully on SIGTERM and SIGINT: the current post or
+/// deletion is finished, the remaining posts are queued for the next run and
+/// the cache files are saved. A second signal exits immediately.
+///
+/// Only used in container mode, interactive commands keep the default signal
+/// handling.
+pub fn shutdown_init() {
+    let shutdown = SHUTDOWN.get_or_init(Default::default);
+    for signal in [SIGTERM, SIGINT] {
+        // The exit handler is registered first, so that it only sees the
+        // flag set by an earlier signal.
+        let registered = flag::register_conditional_shutdown(signal, 1, Arc::clone(shutdown))
+            .and_then(|_| flag::register(signal, Arc::clone(shutdown)));
+        if let Err(e) = registered {
+            warn!("Failed to handle signal {signal}, it stops the program immediately: {e}");
+        }
+    }
+}
+
+/// Returns true after a shutdown signal was received.
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN
+        .get()
+        .is_some_and(|shutdown| shutdown.load(Ordering::SeqCst))
+}
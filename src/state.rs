@@ -0,0 +1,218 @@
+use crate::id_map::IdMap;
+use crate::sync::*;
+use anyhow::{bail, Result};
+use chrono::{TimeZone, Utc};
+use egg_mode::tweet::Tweet;
+use elefren::entities::status::Status;
+use regex::Regex;
+use std::fmt::Write;
+use std::fs;
+
+// Reconstructs the ID map and the post cache by matching existing statuses on
+// both networks with the same equality check that is used for syncing.
+pub fn state_rebuild(
+    mastodon_statuses: &[Status],
+    twitter_statuses: &[Tweet],
+    text_options: &TweetTextOptions,
+) -> Result<(IdMap, PostCache)> {
+    let mut id_map = IdMap::default();
+    let mut post_cache = PostCache::default();
+    for toot in mastodon_statuses {
+        let toot_id = StatusId::from(toot.id.as_str());
+        for tweet in twitter_statuses {
+            if toot_and_tweet_are_equal(toot, tweet, text_options)? {
+                // The newer status is the synced copy.
+                if tweet.created_at > toot.created_at {
+                    id_map.insert_created_tweet(toot_id.clone(), tweet.id);
+                } else {
+                    id_map.insert(toot_id.clone(), tweet.id);
+                }
+                // We don't know in which direction the status was synced, so
+                // remember both.
+                let toot_text = mastodon_toot_get_text(toot);
+                post_cache.insert_text(
+                    Some(Platform::Twitter),
+                    toot_id,
+                    &tweet_shorten(&toot_text, &toot.url, &text_options.shorten),
+                );
+                post_cache.insert_text(
+                    Some(Platform::Mastodon),
+                    tweet.id.into(),
+                    &tweet_unshorten_decode(tweet, text_options)?,
+                );
+                break;
+            }
+        }
+    }
+    Ok((id_map, post_cache))
+}
+
+/// Describes what is recorded as synced in the ID map, post cache and queue.
+pub fn cache_show(id_map_file: &str, post_cache_file: &str, queue_file: &str) -> Result<String> {
+    let id_map = IdMap::load(id_map_file)?;
+    // Read the files directly, reading the post cache for syncing has side
+    // effects.
+    let post_cache = match fs::read_to_string(post_cache_file) {
+        Ok(json) => PostCache::from_json(&json)?,
+        Err(_) => PostCache::default(),
+    };
+    let queue = match fs::read_to_string(queue_file) {
+        Ok(json) => StatusUpdates::from_json(&json)?,
+        Err(_) => StatusUpdates::default(),
+    };
+
+    // Writing to a String cannot fail.
+    let mut output = String::new();
+    let _ = writeln!(
+        output,
+        "{id_map_file}: {} synced status(es)",
+        id_map.toots_to_tweets.len()
+    );
+    for (toot_id, tweet_id) in &id_map.toots_to_tweets {
+        let direction = if id_map.created_tweets.contains(tweet_id) {
+            "→"
+        } else {
+            "←"
+        };
+        let _ = writeln!(output, "  toot {toot_id} {direction} tweet {tweet_id}");
+    }
+    for id in &id_map.marked_synced {
+        let _ = writeln!(output, "  {id} marked as synced");
+    }
+    for (tweet_id, key) in &id_map.idempotency_keys {
+        let _ = writeln!(
+            output,
+            "  tweet {tweet_id} posted with idempotency key {key}"
+        );
+    }
+    for (id, tombstone) in &id_map.tombstones {
+        let kind = match tombstone.platform {
+            Platform::Mastodon => "toot",
+            Platform::Twitter => "tweet",
+        };
+        let deleted_at = match Utc.timestamp_opt(tombstone.deleted_at, 0).single() {
+            Some(date) => date.format("%Y-%m-%d %H:%M").to_string(),
+            None => tombstone.deleted_at.to_string(),
+        };
+        let _ = writeln!(output, "  {kind} {id} deleted at {deleted_at}");
+    }
+    let _ = writeln!(
+        output,
+        "{post_cache_file}: {} recently posted status(es)",
+        post_cache.len()
+    );
+    for entry in &post_cache.entries {
+        let platform = match entry.platform {
+            Some(Platform::Mastodon) => "Mastodon",
+            Some(Platform::Twitter) => "Twitter",
+            None => "unknown network",
+        };
+        let posted_at = match Utc.timestamp_opt(entry.posted_at, 0).single() {
+            Some(date) => date.format("%Y-%m-%d %H:%M").to_string(),
+            None => entry.posted_at.to_string(),
+        };
+        let _ = writeln!(
+            output,
+            "  {posted_at} to {platform}, source ID {}, text hash {:016x}",
+            entry.original_id, entry.text_hash
+        );
+    }
+    let _ = writeln!(
+        output,
+        "{queue_file}: {} queued post(s) for Twitter, {} for Mastodon",
+        queue.tweets.len(),
+        queue.toots.len()
+    );
+    Ok(output)
+}
+
+/// Removes the ID map and post cache files. The queue is kept because it
+/// contains posts that were not posted yet.
+pub fn cache_clear(files: &[&str], dry_run: bool) -> Result<()> {
+    for file in files {
+        if fs::metadata(file).is_err() {
+            continue;
+        }
+        println!("Removing {file}");
+        if !dry_run {
+            fs::remove_file(file)?;
+        }
+    }
+    Ok(())
+}
+
+/// Extracts the status ID from the URL of a tweet or toot.
+pub fn status_id_from_url(url: &str) -> Result<StatusId> {
+    // Tweets: https://twitter.com/user/status/123
+    // Toots: https://example.com/@user/123 or
+    // https://example.com/web/statuses/123, some servers use letters in IDs.
+    let regex = Regex::new(r"/(?:status|statuses|@[\w.]+)/([0-9A-Za-z]+)/?(?:[?#].*)?$").unwrap();
+    match regex.captures(url) {
+        Some(caps) => Ok(StatusId::from(&caps[1])),
+        None => bail!("No status ID found in URL {url}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::sync::tests::*;
+
+    // Test that equal statuses end up in the ID map and the post cache.
+    #[test]
+    fn rebuild_from_timelines() {
+        let mut status = get_mastodon_status();
+        status.id = "1".to_string();
+        status.content = "Same text".to_string();
+        let mut other_status = get_mastodon_status();
+        other_status.id = "2".to_string();
+        other_status.content = "Only on Mastodon".to_string();
+        let mut tweet = get_twitter_status();
+        tweet.id = 10;
+        tweet.text = "Same text".to_string();
+        tweet.created_at = status.created_at + chrono::Duration::minutes(1);
+
+        let text_options = TweetTextOptions {
+            expand_urls: true,
+            escape_mentions: true,
+            decode_html_entities: true,
+            removed_hashtags: Vec::new(),
+            hashtag_aliases: Vec::new(),
+            short_links: None,
+            shorten: DEFAULT_SHORTEN_OPTIONS,
+        };
+        let (id_map, post_cache) =
+            state_rebuild(&vec![status, other_status], &vec![tweet], &text_options).unwrap();
+        assert_eq!(id_map.tweet_id(&StatusId::Number(1)), Some(10));
+        assert_eq!(id_map.toot_id(10), Some(&StatusId::Number(1)));
+        assert_eq!(id_map.tweet_id(&StatusId::Number(2)), None);
+        assert!(id_map.created_tweets.contains(&10));
+        let status = new_status(1, "Same text");
+        assert!(post_cache.contains(Platform::Twitter, &status));
+        assert!(post_cache.contains(Platform::Mastodon, &status));
+    }
+    #[test]
+    fn status_urls() {
+        assert_eq!(
+            status_id_from_url("https://twitter.com/klausi/status/123").unwrap(),
+            StatusId::Number(123)
+        );
+        assert_eq!(
+            status_id_from_url("https://mastodon.social/@klausi/456").unwrap(),
+            StatusId::Number(456)
+        );
+        assert_eq!(
+            status_id_from_url("https://mastodon.social/web/statuses/789").unwrap(),
+            StatusId::Number(789)
+        );
+        assert_eq!(
+            status_id_from_url(
+                "https://gts.example.com/@klausi/statuses/01GQ8N4TWNFPZHBKTE6X9JBWCP"
+            )
+            .unwrap(),
+            StatusId::Text("01GQ8N4TWNFPZHBKTE6X9JBWCP".to_string())
+        );
+        assert!(status_id_from_url("https://mastodon.social/@klausi").is_err());
+    }
+}
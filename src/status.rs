@@ -0,0 +1,63 @@
+// Without both networks there are no sync runs to count.
+#![cfg_attr(not(all(feature = "mastodon", feature = "twitter")), allow(dead_code))]
+
+use anyhow::Result;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// Result and counts of the last sync run, written to a status file for
+/// monitoring.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunStatus {
+    pub started_at: String,
+    pub finished_at: String,
+    pub success: bool,
+    pub error: Option<String>,
+    pub posted_toots: usize,
+    pub posted_tweets: usize,
+    pub failed_posts: usize,
+    pub skipped_posts: usize,
+    pub queued_posts: usize,
+}
+
+impl RunStatus {
+    pub fn start() -> RunStatus {
+        RunStatus {
+            started_at: Local::now().to_rfc3339(),
+            ..RunStatus::default()
+        }
+    }
+
+    /// Records the result of the run.
+    pub fn finish(&mut self, result: &Result<()>) {
+        self.finished_at = Local::now().to_rfc3339();
+        self.success = result.is_ok();
+        self.error = result.as_ref().err().map(|e| format!("{e:#}"));
+    }
+
+    pub fn save(&self, file: &str) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(file, json.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn finish_with_error() {
+        let mut status = RunStatus::start();
+        status.finish(&Err(anyhow!("Twitter is down")));
+        assert!(!status.success);
+        assert_eq!(status.error.as_deref(), Some("Twitter is down"));
+        assert!(!status.finished_at.is_empty());
+
+        status.finish(&Ok(()));
+        assert!(status.success);
+        assert_eq!(status.error, None);
+    }
+}
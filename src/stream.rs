@@ -0,0 +1,96 @@
+use anyhow::{bail, Result};
+use elefren::{Mastodon, MastodonClient};
+use reqwest::header::AUTHORIZATION;
+use std::io::{BufRead, BufReader};
+use std::thread;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// Runs a sync whenever the Mastodon user posts a new status, as reported by
+/// the streaming API. While the stream is disconnected the sync runs every
+/// poll interval instead, until reconnecting works.
+pub fn stream_updates(
+    mastodon: &Mastodon,
+    poll_interval: Duration,
+    mut sync: impl FnMut() -> Result<()>,
+) -> Result<()> {
+    let account_id = mastodon.verify_credentials()?.id;
+    loop {
+        // Catch up with statuses that were posted while not connected.
+        run_sync(&mut sync);
+        match mastodon_stream(mastodon, &account_id, &mut sync) {
+            Ok(()) => warn!("Mastodon stream closed, polling until reconnected"),
+            Err(e) => warn!("Mastodon stream failed, polling until reconnected: {e:#}"),
+        }
+        thread::sleep(poll_interval);
+    }
+}
+
+// Reads the user stream until it is closed.
+fn mastodon_stream(
+    mastodon: &Mastodon,
+    account_id: &str,
+    sync: &mut impl FnMut() -> Result<()>,
+) -> Result<()> {
+    // The stream stays open, so it must not time out.
+    let client = reqwest::blocking::Client::builder().timeout(None).build()?;
+    let response = client
+        .get(format!("{}/api/v1/streaming/user", mastodon.base))
+        .header(AUTHORIZATION, format!("Bearer {}", mastodon.token))
+        .send()?;
+    if !response.status().is_success() {
+        bail!("Mastodon streaming API returned {}", response.status());
+    }
+    info!("Connected to the Mastodon stream, waiting for new statuses");
+
+    let mut event = String::new();
+    for line in BufReader::new(response).lines() {
+        let line = line?;
+        if let Some(name) = line.strip_prefix("event:") {
+            event = name.trim().to_string();
+        } else if let Some(data) = line.strip_prefix("data:") {
+            if is_own_new_status(&event, data, account_id) {
+                run_sync(sync);
+            }
+        } else if line.is_empty() {
+            event.clear();
+        }
+    }
+    Ok(())
+}
+
+// Returns true if the server-sent event is a new status of the account.
+fn is_own_new_status(event: &str, data: &str, account_id: &str) -> bool {
+    if event != "update" {
+        return false;
+    }
+    match serde_json::from_str::<serde_json::Value>(data) {
+        Ok(status) => status["account"]["id"].as_str() == Some(account_id),
+        Err(e) => {
+            warn!("Invalid status in the Mastodon stream: {e}");
+            false
+        }
+    }
+}
+
+// A failing sync should not end streaming, the next run retries it.
+fn run_sync(sync: &mut impl FnMut() -> Result<()>) {
+    if let Err(e) = sync() {
+        error!("Sync failed: {e:#}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn own_status_events() {
+        let own = r#"{"id":"1","account":{"id":"42"}}"#;
+        let other = r#"{"id":"2","account":{"id":"7"}}"#;
+        assert!(is_own_new_status("update", own, "42"));
+        assert!(!is_own_new_status("update", other, "42"));
+        assert!(!is_own_new_status("notification", own, "42"));
+        assert!(!is_own_new_status("update", "broken", "42"));
+    }
+}
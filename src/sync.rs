@@ -1,19 +1,111 @@
+use crate::config::{
+    AltTextPolicy, DirectPostMode, HashtagMatch, RetweetSyncMode, SelfBoostMode, ThreadStrategy,
+};
+use crate::history::HistoryNetwork;
+use crate::id_map::{content_hash, IdMap};
+use crate::short_links::ShortLinkResolver;
 use crate::thread_replies::*;
-use anyhow::Result;
+use anyhow::bail;
+use anyhow::{Context, Result};
+use chrono::Utc;
 use egg_mode::tweet::Tweet;
 use egg_mode_text::character_count;
+use elefren::entities::account::Account;
 use elefren::entities::status::Status;
 use regex::Regex;
-use std::collections::HashSet;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 use std::fs;
+use std::iter;
+use std::mem;
+use std::sync::Arc;
+use tracing::{info, warn};
+use unicode_normalization::UnicodeNormalization;
 use unicode_segmentation::UnicodeSegmentation;
 
+/// Version of the JSON representation of StatusUpdates. Increase it when
+/// making incompatible changes to StatusUpdates, NewStatus or NewMedia.
+pub const STATUS_UPDATES_SCHEMA_VERSION: u32 = 1;
+
 // Represents new status updates that should be posted to Twitter (tweets) and
 // Mastodon (toots).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct StatusUpdates {
     pub tweets: Vec<NewStatus>,
     pub toots: Vec<NewStatus>,
+    // Source statuses that were considered but are not synced, for the
+    // summary at the end of a run.
+    #[serde(skip)]
+    pub skipped: Vec<SkippedPost>,
+    // Thread replies whose parent is not in the fetched timelines, see
+    // resolve_thread_replies().
+    #[serde(skip)]
+    pub(crate) unresolved_tweets: Vec<Reply>,
+    #[serde(skip)]
+    pub(crate) unresolved_toots: Vec<Reply>,
+}
+
+/// Why a source status is not synced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SkipReason {
+    ReplyToOther,
+    Retweet,
+    Reblog,
+    DeniedInstance,
+    Direct,
+    MissingHashtag,
+    DeniedHashtag,
+    Filtered,
+    Similar,
+    MissingAltText,
+    SelfBoost,
+    OversizedAttachment,
+    Source,
+    Crosspost,
+    ReadOnly,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            SkipReason::ReplyToOther => "reply to another account",
+            SkipReason::Retweet => "retweet, sync_retweets is disabled",
+            SkipReason::Reblog => "boost, sync_reblogs is disabled",
+            SkipReason::DeniedInstance => "boost from an instance in boost_deny_instances",
+            SkipReason::Direct => "starts with a mention",
+            SkipReason::MissingHashtag => "sync hashtag missing",
+            SkipReason::DeniedHashtag => "has an excluded hashtag",
+            SkipReason::Filtered => "rejected by a filter",
+            SkipReason::Similar => "similar to a recent status",
+            SkipReason::MissingAltText => "media without alt text",
+            SkipReason::SelfBoost => "boost of an own status",
+            SkipReason::OversizedAttachment => "attachment larger than max_attachment_mb",
+            SkipReason::Source => "posted with an app in skip_sources",
+            SkipReason::Crosspost => "cross-posted by another tool",
+            SkipReason::ReadOnly => "Twitter is read-only with fetch_mode = \"rss\"",
+        };
+        f.write_str(reason)
+    }
+}
+
+/// A source status that is not synced.
+#[derive(Debug, Clone)]
+pub struct SkippedPost {
+    pub source: Platform,
+    pub original_id: StatusId,
+    pub text: String,
+    pub reason: SkipReason,
+}
+
+// The JSON document of status updates for external tools, see DEVELOPMENT.md.
+#[derive(Debug, Serialize, Deserialize)]
+struct StatusUpdatesDocument {
+    schema_version: u32,
+    #[serde(flatten)]
+    updates: StatusUpdates,
 }
 
 impl StatusUpdates {
@@ -22,11 +114,163 @@ impl StatusUpdates {
         self.tweets.reverse();
         self.toots.reverse();
     }
+
+    /// Records a source status that is not synced.
+    pub fn skip(
+        &mut self,
+        source: Platform,
+        original_id: StatusId,
+        text: &str,
+        reason: SkipReason,
+    ) {
+        self.skipped.push(SkippedPost {
+            source,
+            original_id,
+            text: text.to_string(),
+            reason,
+        });
+    }
+
+    /// Warns about or skips statuses with media that has no alt text. Skipped
+    /// thread replies take their own replies with them.
+    pub fn require_alt_text(&mut self, tweets_policy: AltTextPolicy, toots_policy: AltTextPolicy) {
+        let mut skipped = Vec::new();
+        self.tweets =
+            statuses_with_alt_text(mem::take(&mut self.tweets), tweets_policy, &mut skipped);
+        for status in skipped.drain(..) {
+            self.skip(
+                Platform::Mastodon,
+                status.original_id,
+                &status.text,
+                SkipReason::MissingAltText,
+            );
+        }
+        self.toots = statuses_with_alt_text(mem::take(&mut self.toots), toots_policy, &mut skipped);
+        for status in skipped {
+            self.skip(
+                Platform::Twitter,
+                status.original_id,
+                &status.text,
+                SkipReason::MissingAltText,
+            );
+        }
+    }
+
+    /// Sets the focal points of media attachments by their URL.
+    pub fn set_media_focus(&mut self, focus: &BTreeMap<String, (f64, f64)>) {
+        fn set_focus(statuses: &mut [NewStatus], focus: &BTreeMap<String, (f64, f64)>) {
+            for status in statuses {
+                for media in &mut status.attachments {
+                    if let Some(point) = focus.get(&media.attachment_url) {
+                        media.focus = Some(*point);
+                    }
+                }
+                set_focus(&mut status.replies, focus);
+            }
+        }
+        set_focus(&mut self.tweets, focus);
+        set_focus(&mut self.toots, focus);
+    }
+
+    /// Moves all statuses of other to the end of this one.
+    pub fn append(&mut self, other: &mut StatusUpdates) {
+        self.tweets.append(&mut other.tweets);
+        self.toots.append(&mut other.toots);
+    }
+
+    /// Keeps only the given number of statuses per direction. Statuses are
+    /// ordered oldest first, so newer statuses are left for the next run.
+    ///
+    /// Returns the statuses that were removed.
+    pub fn limit(&mut self, max_tweets: Option<usize>, max_toots: Option<usize>) -> StatusUpdates {
+        let mut removed = StatusUpdates::default();
+        if let Some(max_tweets) = max_tweets {
+            if self.tweets.len() > max_tweets {
+                removed.tweets = self.tweets.split_off(max_tweets);
+            }
+        }
+        if let Some(max_toots) = max_toots {
+            if self.toots.len() > max_toots {
+                removed.toots = self.toots.split_off(max_toots);
+            }
+        }
+        removed
+    }
+
+    /// Removes the statuses that must not be published before a later time.
+    ///
+    /// Returns the statuses that were removed.
+    pub fn hold_until_publish(&mut self, now: i64) -> StatusUpdates {
+        let is_due = |status: &NewStatus| status.publish_at.map_or(true, |time| time <= now);
+        let (tweets, held_tweets) = mem::take(&mut self.tweets).into_iter().partition(is_due);
+        let (toots, held_toots) = mem::take(&mut self.toots).into_iter().partition(is_due);
+        self.tweets = tweets;
+        self.toots = toots;
+        StatusUpdates {
+            tweets: held_tweets,
+            toots: held_toots,
+            ..StatusUpdates::default()
+        }
+    }
+
+    /// Serializes the status updates to JSON including the schema version.
+    pub fn to_json(&self) -> Result<String> {
+        let document = StatusUpdatesDocument {
+            schema_version: STATUS_UPDATES_SCHEMA_VERSION,
+            updates: self.clone(),
+        };
+        Ok(serde_json::to_string_pretty(&document)?)
+    }
+
+    /// Parses status updates from JSON, rejects unknown schema versions.
+    pub fn from_json(json: &str) -> Result<StatusUpdates> {
+        let document: StatusUpdatesDocument = serde_json::from_str(json)?;
+        if document.schema_version != STATUS_UPDATES_SCHEMA_VERSION {
+            bail!(
+                "Unsupported status updates schema version {}, expected {}",
+                document.schema_version,
+                STATUS_UPDATES_SCHEMA_VERSION
+            );
+        }
+        Ok(document.updates)
+    }
+}
+
+// Applies the alt text policy to statuses and their replies, skipped statuses
+// are moved to skipped.
+fn statuses_with_alt_text(
+    statuses: Vec<NewStatus>,
+    policy: AltTextPolicy,
+    skipped: &mut Vec<NewStatus>,
+) -> Vec<NewStatus> {
+    let mut kept = Vec::new();
+    for mut status in statuses {
+        let missing = status
+            .attachments
+            .iter()
+            .any(|media| media.description().is_none());
+        if missing {
+            match policy {
+                AltTextPolicy::Ignore => {}
+                AltTextPolicy::Warn => warn!(
+                    "Media without alt text in status {}: {}",
+                    status.original_id, status.text
+                ),
+                AltTextPolicy::Skip => {
+                    skipped.push(status);
+                    continue;
+                }
+            }
+        }
+        status.replies = statuses_with_alt_text(mem::take(&mut status.replies), policy, skipped);
+        kept.push(status);
+    }
+    kept
 }
 
 // A new status for posting. Optionally has links to media (images) that should
 // be attached.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NewStatus {
     pub text: String,
     pub attachments: Vec<NewMedia>,
@@ -35,23 +279,315 @@ pub struct NewStatus {
     pub replies: Vec<NewStatus>,
     // This new status could be part of a thread, post it in reply to an
     // existing already synced status.
-    pub in_reply_to_id: Option<u64>,
+    pub in_reply_to_id: Option<StatusId>,
     // The original post ID on the source status.
-    pub original_id: u64,
+    pub original_id: StatusId,
+    // Unix timestamp before which the status is not posted, it waits in the
+    // queue until then.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_at: Option<i64>,
+    // Number of runs in which posting the status failed.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub attempts: u32,
 }
 
-#[derive(Debug, Clone)]
+fn is_zero(number: &u32) -> bool {
+    *number == 0
+}
+
+impl NewStatus {
+    /// A status with the given text and nothing else, the other fields can be
+    /// set with struct update syntax.
+    pub fn new(text: impl Into<String>, original_id: StatusId) -> NewStatus {
+        NewStatus {
+            text: text.into(),
+            attachments: Vec::new(),
+            replies: Vec::new(),
+            in_reply_to_id: None,
+            original_id,
+            publish_at: None,
+            attempts: 0,
+        }
+    }
+}
+
+/// ID of a status on either network. Tweet IDs and the IDs of most Mastodon
+/// servers are numbers, but Mastodon IDs are strings by specification and
+/// servers like GoToSocial use ULIDs.
+///
+/// Numeric IDs are serialized as JSON numbers, so cache and queue files from
+/// older versions can still be read.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum StatusId {
+    Number(u64),
+    Text(String),
+}
+
+impl StatusId {
+    /// Returns the numeric ID, tweet IDs are always numbers.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            StatusId::Number(id) => Some(*id),
+            StatusId::Text(_) => None,
+        }
+    }
+}
+
+impl From<u64> for StatusId {
+    fn from(id: u64) -> Self {
+        StatusId::Number(id)
+    }
+}
+
+impl From<&str> for StatusId {
+    fn from(id: &str) -> Self {
+        match id.parse() {
+            Ok(number) => StatusId::Number(number),
+            Err(_) => StatusId::Text(id.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for StatusId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StatusId::Number(id) => write!(f, "{id}"),
+            StatusId::Text(id) => f.write_str(id),
+        }
+    }
+}
+
+impl Serialize for StatusId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            StatusId::Number(id) => serializer.serialize_u64(*id),
+            StatusId::Text(id) => serializer.serialize_str(id),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StatusId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(StatusIdVisitor)
+    }
+}
+
+// Accepts numbers and strings. JSON object keys are always strings, numeric
+// strings become numeric IDs to match the IDs of older files.
+struct StatusIdVisitor;
+
+impl<'de> Visitor<'de> for StatusIdVisitor {
+    type Value = StatusId;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a status ID number or string")
+    }
+
+    fn visit_u64<E: de::Error>(self, id: u64) -> Result<StatusId, E> {
+        Ok(StatusId::Number(id))
+    }
+
+    fn visit_str<E: de::Error>(self, id: &str) -> Result<StatusId, E> {
+        Ok(StatusId::from(id))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NewMedia {
     pub attachment_url: String,
     pub alt_text: Option<String>,
+    // Focal point of an image from Mastodon, x and y from -1.0 to 1.0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus: Option<(f64, f64)>,
+}
+
+impl NewMedia {
+    /// Returns the alt text if there is one, empty alt texts are rejected by
+    /// Twitter.
+    pub fn description(&self) -> Option<&str> {
+        self.alt_text
+            .as_deref()
+            .filter(|alt_text| !alt_text.trim().is_empty())
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct SyncOptions {
     pub sync_reblogs: bool,
     pub sync_retweets: bool,
-    pub sync_hashtag_twitter: Option<String>,
-    pub sync_hashtag_mastodon: Option<String>,
+    pub retweet_sync_mode_twitter: RetweetSyncMode,
+    pub retweet_sync_mode_mastodon: RetweetSyncMode,
+    pub sync_hashtags_twitter: HashtagFilter,
+    pub sync_hashtags_mastodon: HashtagFilter,
+    pub boost_deny_instances: Vec<String>,
+    pub skip_sources_twitter: Vec<String>,
+    pub skip_sources_mastodon: Vec<String>,
+    pub crosspost_patterns_twitter: Vec<Regex>,
+    pub crosspost_patterns_mastodon: Vec<Regex>,
+    pub sync_replies_to_others_twitter: bool,
+    pub sync_replies_to_others_mastodon: bool,
+    pub max_thread_depth_twitter: Option<usize>,
+    pub max_thread_depth_mastodon: Option<usize>,
+    pub thread_strategy_twitter: ThreadStrategy,
+    pub thread_strategy_mastodon: ThreadStrategy,
+    pub direct_posts_mastodon: DirectPostMode,
+    pub self_boosts_twitter: SelfBoostMode,
+    pub self_boosts_mastodon: SelfBoostMode,
+    pub link_preview_images_mastodon: bool,
+    pub tweet_text: TweetTextOptions,
+}
+
+// Normalization steps for tweet texts. They are applied before posting tweets
+// to Mastodon and when comparing them with toots.
+#[derive(Debug, Clone)]
+pub struct TweetTextOptions {
+    // Replace t.co links with the original URLs.
+    pub expand_urls: bool,
+    // Escape @username mentions so that nobody gets notified on Mastodon.
+    pub escape_mentions: bool,
+    // Decode HTML entities like &amp;.
+    pub decode_html_entities: bool,
+    // Sync hashtags that are removed from posts of either network, lowercase
+    // without "#". They are ignored when comparing tweets and toots.
+    pub removed_hashtags: Vec<String>,
+    // Hashtags that are replaced with another one for the other network, as
+    // pairs of lowercase names without "#". They count as the same hashtag
+    // when comparing tweets and toots.
+    pub hashtag_aliases: Vec<(String, String)>,
+    // Resolves links of link shorteners like bit.ly when comparing tweets and
+    // toots.
+    pub short_links: Option<Arc<ShortLinkResolver>>,
+    // How toots are shortened for Twitter, also when comparing them.
+    pub shorten: ShortenOptions,
+}
+
+/// How statuses that are too long are shortened for Twitter.
+#[derive(Debug, Clone)]
+pub struct ShortenOptions {
+    // Length limit of the target network, 280 for Twitter. Links count as 23
+    // characters on both networks.
+    pub max_characters: usize,
+    // Characters of the limit that are kept free, because the counting of
+    // Twitter is unpredictable.
+    pub character_reserve: usize,
+    // Appended to the shortened text.
+    pub ellipsis: Cow<'static, str>,
+    // Append the URL of the source status that has the full text.
+    pub link_to_source: bool,
+}
+
+pub const DEFAULT_SHORTEN_OPTIONS: ShortenOptions = ShortenOptions {
+    max_characters: 280,
+    character_reserve: 40,
+    ellipsis: Cow::Borrowed("…"),
+    link_to_source: true,
+};
+
+/// Hashtags that decide which statuses are synced. Without hashtags all
+/// statuses are synced.
+#[derive(Debug, Clone, Default)]
+pub struct HashtagFilter {
+    // At least one or all of these hashtags must be in a status. Hashtags are
+    // stored in lowercase without "#".
+    pub required: Vec<String>,
+    // Statuses with one of these hashtags are not synced.
+    pub denied: Vec<String>,
+    pub match_mode: HashtagMatch,
+    // Remove the required hashtags from synced posts.
+    pub remove: bool,
+}
+
+impl HashtagFilter {
+    /// Builds the filter from configured hashtags. Hashtags with a "-" prefix
+    /// like "-#private" exclude statuses.
+    pub fn new(hashtags: impl IntoIterator<Item = String>, match_mode: HashtagMatch) -> Self {
+        let mut filter = HashtagFilter {
+            match_mode,
+            ..HashtagFilter::default()
+        };
+        for hashtag in hashtags {
+            let (list, hashtag) = match hashtag.strip_prefix('-') {
+                Some(denied) => (&mut filter.denied, denied),
+                None => (&mut filter.required, hashtag.as_str()),
+            };
+            let hashtag = hashtag.trim().trim_start_matches('#').to_lowercase();
+            if !hashtag.is_empty() {
+                list.push(hashtag);
+            }
+        }
+        filter
+    }
+
+    /// Returns why a status with this text is not synced, None if it is
+    /// synced.
+    pub fn skip_reason(&self, text: &str) -> Option<SkipReason> {
+        let text_hashtags = text_hashtags(text);
+        let found = |hashtag: &String| text_hashtags.contains(hashtag);
+        if self.denied.iter().any(found) {
+            return Some(SkipReason::DeniedHashtag);
+        }
+        let matches = match self.match_mode {
+            HashtagMatch::Any => self.required.is_empty() || self.required.iter().any(found),
+            HashtagMatch::All => self.required.iter().all(found),
+        };
+        (!matches).then_some(SkipReason::MissingHashtag)
+    }
+
+    /// Returns the text for posting, without the required hashtags if they
+    /// should be removed.
+    pub fn remove_from(&self, text: &str) -> String {
+        if self.remove {
+            remove_hashtags(text, &self.required)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Removes hashtags (lowercase without "#") from a text and cleans up the
+/// whitespace they leave behind.
+pub fn remove_hashtags(text: &str, hashtags: &[String]) -> String {
+    if hashtags.is_empty() {
+        return text.to_string();
+    }
+    let mut result = text.to_string();
+    for hashtag in hashtags {
+        let pattern =
+            Regex::new(&format!(r"(?i)(^|[^\w&/])#{}\b", regex::escape(hashtag))).unwrap();
+        result = pattern.replace_all(&result, "${1}").to_string();
+    }
+    let spaces = Regex::new(r"[ \t]{2,}").unwrap();
+    let result = spaces.replace_all(&result, " ");
+    result
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+// Replaces the second hashtag of each pair with the first one, so that texts
+// with either of them are equal.
+fn unify_hashtag_aliases(text: &str, aliases: &[(String, String)]) -> String {
+    let mut result = text.to_string();
+    for (from, to) in aliases {
+        let pattern = Regex::new(&format!(r"(?i)(^|[^\w&/])#{}\b", regex::escape(to))).unwrap();
+        result = pattern
+            .replace_all(&result, format!("${{1}}#{from}").as_str())
+            .into_owned();
+    }
+    result
+}
+
+// Returns the hashtags of a text in lowercase without "#". A "#" inside of a
+// word or URL does not start a hashtag.
+pub(crate) fn text_hashtags(text: &str) -> BTreeSet<String> {
+    let hashtag = Regex::new(r"(?:^|[^\w&/])#(\w+)").unwrap();
+    hashtag
+        .captures_iter(text)
+        .map(|caps| caps[1].to_lowercase())
+        .collect()
 }
 
 /// This is the main synchronization function that can be tested without
@@ -69,11 +605,8 @@ pub fn determine_posts(
     mastodon_statuses: &[Status],
     twitter_statuses: &[Tweet],
     options: &SyncOptions,
-) -> StatusUpdates {
-    let mut updates = StatusUpdates {
-        tweets: Vec::new(),
-        toots: Vec::new(),
-    };
+) -> Result<StatusUpdates> {
+    let mut updates = StatusUpdates::default();
     'tweets: for tweet in twitter_statuses {
         // Skip replies, they are handled in determine_thread_replies().
         if let Some(_user_id) = &tweet.in_reply_to_user_id {
@@ -82,6 +615,31 @@ pub fn determine_posts(
 
         if tweet.retweeted == Some(true) && !options.sync_retweets {
             // Skip retweets when sync_retweets is disabled
+            updates.skip(
+                Platform::Twitter,
+                tweet.id.into(),
+                &tweet.text,
+                SkipReason::Retweet,
+            );
+            continue;
+        }
+        let source = tweet.source.as_ref().map(|source| source.name.as_str());
+        if is_skipped_source(source, &options.skip_sources_twitter) {
+            updates.skip(
+                Platform::Twitter,
+                tweet.id.into(),
+                &tweet.text,
+                SkipReason::Source,
+            );
+            continue;
+        }
+        if options.self_boosts_twitter == SelfBoostMode::Skip && is_self_retweet(tweet) {
+            updates.skip(
+                Platform::Twitter,
+                tweet.id.into(),
+                &tweet.text,
+                SkipReason::SelfBoost,
+            );
             continue;
         }
 
@@ -92,29 +650,56 @@ pub fn determine_posts(
             }
             // If the tweet already exists we can stop here and know that we are
             // synced.
-            if toot_and_tweet_are_equal(toot, tweet) {
+            if toot_and_tweet_are_equal(toot, tweet, &options.tweet_text)? {
                 break 'tweets;
             }
         }
 
         // The tweet is not on Mastodon yet, check if we should post it.
         // Fetch the tweet text into a String object
-        let decoded_tweet = tweet_unshorten_decode(tweet);
+        let decoded_tweet = tweet_unshorten_decode(tweet, &options.tweet_text)?;
 
         // Check if hashtag filtering is enabled and if the tweet matches.
-        if let Some(sync_hashtag) = &options.sync_hashtag_twitter {
-            if !sync_hashtag.is_empty() && !decoded_tweet.contains(sync_hashtag) {
-                // Skip if a sync hashtag is set and the string doesn't match.
+        if let Some(reason) = options.sync_hashtags_twitter.skip_reason(&decoded_tweet) {
+            updates.skip(Platform::Twitter, tweet.id.into(), &decoded_tweet, reason);
+            continue;
+        }
+        // Checked after the comparison, own synced tweets can link back to the
+        // toot.
+        let links = tweet
+            .entities
+            .urls
+            .iter()
+            .filter_map(|url| url.expanded_url.as_deref());
+        if is_crosspost(
+            iter::once(decoded_tweet.as_str()).chain(links),
+            &options.crosspost_patterns_twitter,
+        ) {
+            updates.skip(
+                Platform::Twitter,
+                tweet.id.into(),
+                &decoded_tweet,
+                SkipReason::Crosspost,
+            );
+            continue;
+        }
+
+        // The link to the retweeted tweet is enough, Mastodon shows a preview.
+        if options.retweet_sync_mode_twitter == RetweetSyncMode::Link {
+            if let Some(link_text) = tweet_retweet_link_text(tweet)? {
+                updates
+                    .toots
+                    .push(NewStatus::new(link_text, tweet.id.into()));
                 continue;
             }
         }
 
         updates.toots.push(NewStatus {
-            text: decoded_tweet,
             attachments: tweet_get_attachments(tweet),
-            replies: Vec::new(),
-            in_reply_to_id: None,
-            original_id: tweet.id,
+            ..NewStatus::new(
+                options.sync_hashtags_twitter.remove_from(&decoded_tweet),
+                tweet.id.into(),
+            )
         });
     }
 
@@ -124,93 +709,436 @@ pub fn determine_posts(
             continue;
         }
 
-        if toot.reblog.is_some() && !options.sync_reblogs {
-            // Skip reblogs when sync_reblogs is disabled
+        let toot_id = StatusId::from(toot.id.as_str());
+        let source = toot.application.as_ref().map(|app| app.name.as_str());
+        if is_skipped_source(source, &options.skip_sources_mastodon) {
+            updates.skip(
+                Platform::Mastodon,
+                toot_id,
+                &mastodon_toot_get_text(toot),
+                SkipReason::Source,
+            );
             continue;
         }
+        if let Some(reblog) = &toot.reblog {
+            if !options.sync_reblogs {
+                // Skip reblogs when sync_reblogs is disabled
+                updates.skip(
+                    Platform::Mastodon,
+                    toot_id.clone(),
+                    &mastodon_toot_get_text(toot),
+                    SkipReason::Reblog,
+                );
+                continue;
+            }
+            if options.self_boosts_mastodon == SelfBoostMode::Skip && is_self_boost(toot) {
+                updates.skip(
+                    Platform::Mastodon,
+                    toot_id.clone(),
+                    &mastodon_toot_get_text(toot),
+                    SkipReason::SelfBoost,
+                );
+                continue;
+            }
+            // Skip boosts of content from instances we don't want to amplify.
+            if let Some(domain) = mastodon_account_domain(&reblog.account) {
+                if options
+                    .boost_deny_instances
+                    .iter()
+                    .any(|instance| instance.eq_ignore_ascii_case(&domain))
+                {
+                    updates.skip(
+                        Platform::Mastodon,
+                        toot_id.clone(),
+                        &mastodon_toot_get_text(toot),
+                        SkipReason::DeniedInstance,
+                    );
+                    continue;
+                }
+            }
+        }
         let fulltext = mastodon_toot_get_text(toot);
         // If this is a reblog/boost then take the URL to the original toot.
+        let mut text = options.sync_hashtags_mastodon.remove_from(&fulltext);
+        if options.direct_posts_mastodon == DirectPostMode::Redact {
+            text = redact_leading_mentions(&text);
+        }
         let post = match &toot.reblog {
-            None => tweet_shorten(&fulltext, &toot.url),
-            Some(reblog) => tweet_shorten(&fulltext, &reblog.url),
+            None => tweet_shorten(&text, &toot.url, &options.tweet_text.shorten),
+            Some(reblog) => tweet_shorten(&text, &reblog.url, &options.tweet_text.shorten),
         };
         // Skip direct toots to other Mastodon users, even if they are public.
-        if post.starts_with('@') {
+        if post.starts_with('@') || post.is_empty() {
+            updates.skip(
+                Platform::Mastodon,
+                toot_id.clone(),
+                &fulltext,
+                SkipReason::Direct,
+            );
             continue;
         }
 
         for tweet in twitter_statuses {
             // If the toot already exists we can stop here and know that we are
             // synced.
-            if toot_and_tweet_are_equal(toot, tweet) {
+            if toot_and_tweet_are_equal(toot, tweet, &options.tweet_text)? {
                 break 'toots;
             }
         }
 
         // The toot is not on Twitter yet, check if we should post it.
         // Check if hashtag filtering is enabled and if the tweet matches.
-        if let Some(sync_hashtag) = &options.sync_hashtag_mastodon {
-            if !sync_hashtag.is_empty() && !fulltext.contains(sync_hashtag) {
-                // Skip if a sync hashtag is set and the string doesn't match.
+        if let Some(reason) = options.sync_hashtags_mastodon.skip_reason(&fulltext) {
+            updates.skip(Platform::Mastodon, toot_id.clone(), &fulltext, reason);
+            continue;
+        }
+        if is_crosspost([fulltext.as_str()], &options.crosspost_patterns_mastodon) {
+            updates.skip(
+                Platform::Mastodon,
+                toot_id.clone(),
+                &fulltext,
+                SkipReason::Crosspost,
+            );
+            continue;
+        }
+
+        // The link to the boosted status is enough, Twitter shows a preview.
+        if options.retweet_sync_mode_mastodon == RetweetSyncMode::Link {
+            if let Some(link_text) = toot_reblog_link_text(toot) {
+                updates.tweets.push(NewStatus::new(link_text, toot_id));
                 continue;
             }
         }
 
+        let mut attachments = toot_get_attachments(toot);
+        if attachments.is_empty() && options.link_preview_images_mastodon {
+            attachments.extend(toot_card_image(toot));
+        }
         updates.tweets.push(NewStatus {
-            text: post,
-            attachments: toot_get_attachments(toot),
-            replies: Vec::new(),
-            in_reply_to_id: None,
-            original_id: toot
-                .id
-                .parse()
-                .unwrap_or_else(|_| panic!("Mastodon status ID is not u64: {}", toot.id)),
+            attachments,
+            ..NewStatus::new(post, toot_id)
         });
     }
 
-    determine_thread_replies(mastodon_statuses, twitter_statuses, options, &mut updates);
+    determine_thread_replies(mastodon_statuses, twitter_statuses, options, &mut updates)?;
+
+    // Very long threads are synced over several runs to not run into API rate
+    // limits.
+    if let Some(max_depth) = options.max_thread_depth_twitter {
+        limit_thread_depth(&mut updates.toots, max_depth);
+    }
+    if let Some(max_depth) = options.max_thread_depth_mastodon {
+        limit_thread_depth(&mut updates.tweets, max_depth);
+    }
+
+    let toot_fits = |text: &str| text.graphemes(true).count() <= 500;
+    let tweet_url = |id: &StatusId| Some(format!("https://twitter.com/twitter/status/{id}"));
+    match options.thread_strategy_twitter {
+        ThreadStrategy::Mirror => {}
+        ThreadStrategy::FirstPostWithLink => {
+            link_to_thread_continuation(&mut updates.toots, &toot_fits, &tweet_url)
+        }
+        ThreadStrategy::Collapse => collapse_threads(&mut updates.toots, &toot_fits, &tweet_url),
+    }
+    let shorten = &options.tweet_text.shorten;
+    let max_chars = shorten
+        .max_characters
+        .saturating_sub(shorten.character_reserve);
+    let tweet_fits = |text: &str| character_count(text, 23, 23) <= max_chars;
+    let toot_url = |id: &StatusId| {
+        mastodon_statuses
+            .iter()
+            .find(|toot| StatusId::from(toot.id.as_str()) == *id)
+            .and_then(|toot| toot.url.clone())
+    };
+    match options.thread_strategy_mastodon {
+        ThreadStrategy::Mirror => {}
+        ThreadStrategy::FirstPostWithLink => {
+            link_to_thread_continuation(&mut updates.tweets, &tweet_fits, &toot_url)
+        }
+        ThreadStrategy::Collapse => collapse_threads(&mut updates.tweets, &tweet_fits, &toot_url),
+    }
 
     // Older posts should come first to preserve the ordering of posts to
     // synchronize.
     updates.reverse_order();
-    updates
+    Ok(updates)
+}
+
+// Removes thread replies that are nested deeper than the given depth. They will
+// be picked up on a later run once their parent has been synced.
+fn limit_thread_depth(statuses: &mut [NewStatus], max_depth: usize) {
+    for status in statuses {
+        if max_depth == 0 {
+            status.replies.clear();
+        } else {
+            limit_thread_depth(&mut status.replies, max_depth - 1);
+        }
+    }
+}
+
+// Marker that is appended to the first status of a thread when the rest of the
+// thread is not synced.
+const THREAD_CONTINUES: &str = "\n\n(thread continues) ";
+
+// Marker between the statuses of a thread that is joined into one status.
+const THREAD_COLLAPSED: &str = "\n\n🧵\n\n";
+
+// Both networks allow up to 4 images per status.
+const MAX_COLLAPSED_ATTACHMENTS: usize = 4;
+
+// Replaces the replies of threads with a link to the source thread, the text
+// is shortened if the link does not fit. Replies to already synced threads are
+// dropped.
+fn link_to_thread_continuation(
+    statuses: &mut Vec<NewStatus>,
+    fits: &impl Fn(&str) -> bool,
+    source_url: &impl Fn(&StatusId) -> Option<String>,
+) {
+    statuses.retain(|status| status.in_reply_to_id.is_none());
+    for status in statuses {
+        if status.replies.is_empty() {
+            continue;
+        }
+        status.replies.clear();
+        let suffix = match source_url(&status.original_id) {
+            Some(url) => format!("{THREAD_CONTINUES}{url}"),
+            None => continue,
+        };
+        if !fits(&format!("{}{suffix}", status.text)) {
+            let shortened = shorten_text(&status.text, |shortened| {
+                fits(&format!("{shortened}…{suffix}"))
+            });
+            status.text = format!("{shortened}…");
+        }
+        status.text = format!("{}{suffix}", status.text);
+    }
+}
+
+// Joins the replies of new threads into their first status. Threads that do
+// not fit into one status are linked like in link_to_thread_continuation().
+// Replies to already synced threads are dropped.
+fn collapse_threads(
+    statuses: &mut Vec<NewStatus>,
+    fits: &impl Fn(&str) -> bool,
+    source_url: &impl Fn(&StatusId) -> Option<String>,
+) {
+    statuses.retain(|status| status.in_reply_to_id.is_none());
+    for status in statuses.iter_mut() {
+        if status.replies.is_empty() {
+            continue;
+        }
+        let mut texts = vec![status.text.clone()];
+        let mut attachments = status.attachments.clone();
+        collect_thread(&status.replies, &mut texts, &mut attachments);
+        let text = texts.join(THREAD_COLLAPSED);
+        if fits(&text) && attachments.len() <= MAX_COLLAPSED_ATTACHMENTS {
+            status.text = text;
+            status.attachments = attachments;
+            status.replies.clear();
+        }
+    }
+    link_to_thread_continuation(statuses, fits, source_url);
+}
+
+// Collects the texts and attachments of thread replies in thread order.
+fn collect_thread(replies: &[NewStatus], texts: &mut Vec<String>, attachments: &mut Vec<NewMedia>) {
+    for reply in replies {
+        texts.push(reply.text.clone());
+        attachments.extend(reply.attachments.iter().cloned());
+        collect_thread(&reply.replies, texts, attachments);
+    }
 }
 
 // Returns true if a Mastodon toot and a Twitter tweet are considered equal.
-pub fn toot_and_tweet_are_equal(toot: &Status, tweet: &Tweet) -> bool {
+pub fn toot_and_tweet_are_equal(
+    toot: &Status,
+    tweet: &Tweet,
+    text_options: &TweetTextOptions,
+) -> Result<bool> {
     // Make sure the structure is the same: both must be replies or both must
     // not be replies.
     if (toot.in_reply_to_id.is_some() && tweet.in_reply_to_status_id.is_none())
         || (toot.in_reply_to_id.is_none() && tweet.in_reply_to_status_id.is_some())
     {
-        return false;
+        return Ok(false);
+    }
+    if links_to_source(toot, tweet) {
+        return Ok(true);
     }
 
-    // Strip markup from Mastodon toot and unify message for comparison.
-    let toot_text = unify_post_content(mastodon_toot_get_text(toot));
+    // Strip markup from Mastodon toot and unify message for comparison. A
+    // thread continuation link is not part of the original tweet.
+    let mut toot_text = mastodon_toot_get_text(toot);
+    if let Some(position) = toot_text.find(THREAD_CONTINUES) {
+        toot_text.truncate(position);
+    }
     // Replace those ugly t.co URLs in the tweet text.
-    let tweet_text = unify_post_content(tweet_unshorten_decode(tweet));
+    let mut tweet_text = tweet_unshorten_decode(tweet, text_options)?;
+    // A collapsed thread is compared by its first status.
+    for text in [&mut toot_text, &mut tweet_text] {
+        if let Some(position) = text.find(THREAD_COLLAPSED) {
+            text.truncate(position);
+        }
+    }
+    // Short links are case sensitive, so they are resolved before unifying.
+    if let Some(short_links) = &text_options.short_links {
+        toot_text = short_links.resolve_text(&toot_text);
+        tweet_text = short_links.resolve_text(&tweet_text);
+    }
+    if !text_options.hashtag_aliases.is_empty() {
+        toot_text = unify_hashtag_aliases(&toot_text, &text_options.hashtag_aliases);
+        tweet_text = unify_hashtag_aliases(&tweet_text, &text_options.hashtag_aliases);
+    }
+    let toot_text = unify_post_content(toot_text);
+    let tweet_text = unify_post_content(tweet_text);
 
     if toot_text == tweet_text {
-        return true;
+        return Ok(true);
+    }
+    // Retweets and boosts might have been synced as link only, independent
+    // of the current retweet_sync_mode.
+    if let Some(link_text) = tweet_retweet_link_text(tweet)? {
+        if toot_text == unify_post_content(link_text) {
+            return Ok(true);
+        }
+    }
+    if let Some(link_text) = toot_reblog_link_text(toot) {
+        if unify_post_content(link_text) == tweet_text {
+            return Ok(true);
+        }
     }
     // Mastodon allows up to 500 characters, so we might need to shorten the
     // toot. If this is a reblog/boost then take the URL to the original toot.
     let shortened_toot = unify_post_content(match &toot.reblog {
-        None => tweet_shorten(&toot_text, &toot.url),
-        Some(reblog) => tweet_shorten(&toot_text, &reblog.url),
+        None => tweet_shorten(&toot_text, &toot.url, &text_options.shorten),
+        Some(reblog) => tweet_shorten(&toot_text, &reblog.url, &text_options.shorten),
     });
-
     if shortened_toot == tweet_text {
-        return true;
+        return Ok(true);
     }
 
-    false
+    // Removed sync hashtags are only in the original post.
+    if text_options.removed_hashtags.is_empty() {
+        return Ok(false);
+    }
+    let toot_text = remove_hashtags(&toot_text, &text_options.removed_hashtags);
+    let tweet_text = remove_hashtags(&tweet_text, &text_options.removed_hashtags);
+    let shortened_toot = unify_post_content(match &toot.reblog {
+        None => tweet_shorten(&toot_text, &toot.url, &text_options.shorten),
+        Some(reblog) => tweet_shorten(&toot_text, &reblog.url, &text_options.shorten),
+    });
+    Ok(toot_text == tweet_text || shortened_toot == tweet_text)
+}
+
+// Shortened posts and thread beginnings are synced with a link to their
+// source status. Such a link marks the post as synced even if the texts are
+// different now, for example because Twitter changed the shortened text.
+fn links_to_source(toot: &Status, tweet: &Tweet) -> bool {
+    let without_scheme = |url: &str| {
+        url.trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .to_string()
+    };
+    let tweet_links_toot = toot.url.as_deref().map_or(false, |toot_url| {
+        let toot_url = without_scheme(toot_url);
+        tweet.entities.urls.iter().any(|url| {
+            url.expanded_url
+                .as_deref()
+                .map_or(false, |expanded| without_scheme(expanded) == toot_url)
+        })
+    });
+    let tweet_url = Regex::new(&format!(r"\btwitter\.com/\w+/status/{}\b", tweet.id)).unwrap();
+    tweet_links_toot || tweet_url.is_match(&mastodon_toot_get_text(toot))
+}
+
+// Returns the short "RT user: URL" text of a retweet, None for other tweets.
+fn tweet_retweet_link_text(tweet: &Tweet) -> Result<Option<String>> {
+    let retweet = match &tweet.retweeted_status {
+        Some(retweet) => retweet,
+        None => return Ok(None),
+    };
+    let screen_name = &retweet
+        .user
+        .as_ref()
+        .with_context(|| format!("Twitter user missing on retweet {}", retweet.id))?
+        .screen_name;
+    Ok(Some(format!(
+        "RT {screen_name}: https://twitter.com/{screen_name}/status/{}",
+        retweet.id
+    )))
+}
+
+// Removes the mentions at the beginning of a toot, escaped or not, so that
+// nobody is mentioned and the toot is not considered direct.
+fn redact_leading_mentions(text: &str) -> String {
+    let mentions = Regex::new(r"^(?:@\\?[\w.]+(?:@[\w.-]*\w)?\s*)+").unwrap();
+    mentions.replace(text, "").into_owned()
+}
+
+/// Compiles the crosspost patterns of a config section. A link to the profile
+/// of the account on the other network, given without scheme like
+/// "twitter.com/example", marks a crosspost as well.
+pub fn crosspost_patterns(patterns: &[String], other_profile: &str) -> Result<Vec<Regex>> {
+    let mut regexes = Vec::new();
+    // Without an account on the other network nothing links to it.
+    if !other_profile.is_empty() {
+        regexes.push(Regex::new(&format!(
+            r"(?i)\b{}\b",
+            regex::escape(other_profile)
+        ))?);
+    }
+    for pattern in patterns {
+        regexes.push(
+            Regex::new(pattern).with_context(|| format!("Invalid crosspost pattern {pattern}"))?,
+        );
+    }
+    Ok(regexes)
+}
+
+// Returns true if one of the texts of a status, its text and links, matches a
+// crosspost pattern.
+fn is_crosspost<'a>(texts: impl IntoIterator<Item = &'a str>, patterns: &[Regex]) -> bool {
+    texts
+        .into_iter()
+        .any(|text| patterns.iter().any(|pattern| pattern.is_match(text)))
+}
+
+/// Returns true if the status was posted with one of the apps, compared
+/// case-insensitive.
+pub(crate) fn is_skipped_source(app: Option<&str>, skip_sources: &[String]) -> bool {
+    app.map_or(false, |app| {
+        skip_sources
+            .iter()
+            .any(|source| source.trim().eq_ignore_ascii_case(app.trim()))
+    })
+}
+
+/// Returns true if the toot is a boost of a toot of the same account.
+pub(crate) fn is_self_boost(toot: &Status) -> bool {
+    toot.reblog
+        .as_ref()
+        .map_or(false, |reblog| reblog.account.id == toot.account.id)
+}
+
+/// Returns true if the tweet is a retweet of a tweet of the same user.
+pub(crate) fn is_self_retweet(tweet: &Tweet) -> bool {
+    let user_id = |tweet: &Tweet| tweet.user.as_ref().map(|user| user.id);
+    tweet.retweeted_status.as_deref().map_or(false, |retweet| {
+        user_id(retweet).is_some() && user_id(retweet) == user_id(tweet)
+    })
+}
+
+// Returns the short "RT user: URL" text of a boost, None for other toots.
+fn toot_reblog_link_text(toot: &Status) -> Option<String> {
+    let reblog = toot.reblog.as_ref()?;
+    let url = reblog.url.as_ref().unwrap_or(&reblog.uri);
+    Some(format!("RT {}: {url}", reblog.account.username))
 }
 
 // Unifies tweet text or toot text to a common format.
-fn unify_post_content(content: String) -> String {
-    let mut result = content.to_lowercase();
+pub(crate) fn unify_post_content(content: String) -> String {
+    let mut result = normalize_whitespace(&normalize_unicode(&content).to_lowercase());
     // Remove http:// and https:// for comparing because Twitter sometimes adds
     // those randomly.
     result = result.replace("http://", "");
@@ -234,9 +1162,32 @@ fn unify_post_content(content: String) -> String {
     result.replace(" @\\", " @")
 }
 
+// Converts line endings to "\n", collapses consecutive spaces and tabs and
+// removes whitespace at the start and end of lines. Twitter does that with
+// some posts, so they must not be considered different.
+fn normalize_whitespace(text: &str) -> String {
+    text.replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .split('\n')
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+// Brings text to the composed form (NFC) and removes variation selectors.
+// Mastodon and Twitter do not normalize posts the same way, so "é" can be one
+// or two code points and emoji can come with or without U+FE0F.
+fn normalize_unicode(text: &str) -> String {
+    text.nfc()
+        .filter(|c| !('\u{FE00}'..='\u{FE0F}').contains(c))
+        .collect()
+}
+
 // Replace t.co URLs and HTML entity decode &amp;.
 // Directly include quote tweets in the text.
-pub fn tweet_unshorten_decode(tweet: &Tweet) -> String {
+pub fn tweet_unshorten_decode(tweet: &Tweet, text_options: &TweetTextOptions) -> Result<String> {
     // We need to cleanup the tweet text while passing the tweet around.
     let mut tweet = tweet.clone();
 
@@ -244,11 +1195,11 @@ pub fn tweet_unshorten_decode(tweet: &Tweet) -> String {
         tweet.text = format!(
             "RT {}: {}",
             retweet
-                .clone()
                 .user
-                .unwrap_or_else(|| panic!("Twitter user missing on retweet {}", retweet.id))
+                .as_ref()
+                .with_context(|| format!("Twitter user missing on retweet {}", retweet.id))?
                 .screen_name,
-            tweet_get_text_with_quote(retweet)
+            tweet_get_text_with_quote(retweet, text_options)?
         );
         tweet.entities.urls = retweet.entities.urls.clone();
         tweet.extended_entities = retweet.extended_entities.clone();
@@ -262,39 +1213,45 @@ pub fn tweet_unshorten_decode(tweet: &Tweet) -> String {
         }
     }
     tweet.text = tweet.text.trim().to_string();
-    tweet.text = tweet_get_text_with_quote(&tweet);
+    tweet.text = tweet_get_text_with_quote(&tweet, text_options)?;
 
     // Replace t.co URLs with the real links in tweets.
-    for url in tweet.entities.urls {
-        if let Some(expanded_url) = &url.expanded_url {
-            tweet.text = tweet.text.replace(&url.url, expanded_url);
+    if text_options.expand_urls {
+        for url in tweet.entities.urls {
+            if let Some(expanded_url) = &url.expanded_url {
+                tweet.text = tweet.text.replace(&url.url, expanded_url);
+            }
         }
     }
 
     // Escape direct user mentions with @\.
-    tweet.text = tweet.text.replace(" @", " @\\").replace(" @\\\\", " @\\");
+    if text_options.escape_mentions {
+        tweet.text = tweet.text.replace(" @", " @\\").replace(" @\\\\", " @\\");
+    }
 
     // Twitterposts have HTML entities such as &amp;, we need to decode them.
-    let decoded = html_escape::decode_html_entities(&tweet.text);
+    if text_options.decode_html_entities {
+        tweet.text = html_escape::decode_html_entities(&tweet.text).to_string();
+    }
 
-    toot_shorten(&decoded, tweet.id)
+    Ok(toot_shorten(&tweet.text, tweet.id))
 }
 
 // If this is a quote tweet then include the original text.
-fn tweet_get_text_with_quote(tweet: &Tweet) -> String {
+fn tweet_get_text_with_quote(tweet: &Tweet, text_options: &TweetTextOptions) -> Result<String> {
     match tweet.quoted_status {
-        None => tweet.text.clone(),
+        None => Ok(tweet.text.clone()),
         Some(ref quoted_tweet) => {
             // Prevent infinite quote tweets. We only want to include
             // the first level, so make sure that the original has any
             // quote tweet removed.
             let mut original = quoted_tweet.clone();
             original.quoted_status = None;
-            let original_text = tweet_unshorten_decode(&original);
+            let original_text = tweet_unshorten_decode(&original, text_options)?;
             let screen_name = &original
                 .user
                 .as_ref()
-                .unwrap_or_else(|| panic!("Twitter user missing on tweet {}", original.id))
+                .with_context(|| format!("Twitter user missing on tweet {}", original.id))?
                 .screen_name;
             let mut tweet_text = tweet.text.clone();
 
@@ -317,55 +1274,125 @@ fn tweet_get_text_with_quote(tweet: &Tweet) -> String {
                 }
             }
 
-            format!(
+            Ok(format!(
                 "{tweet_text}
 
 QT {screen_name}: {original_text}"
-            )
+            ))
         }
     }
 }
 
-pub fn tweet_shorten(text: &str, toot_url: &Option<String>) -> String {
-    let mut char_count = character_count(text, 23, 23);
-    let re = Regex::new(r"[^\s]+$").unwrap();
-    let mut shortened = text.trim().to_string();
-    let mut with_link = shortened.clone();
-
+pub fn tweet_shorten(text: &str, toot_url: &Option<String>, options: &ShortenOptions) -> String {
+    let text = text.trim();
     // Twitter should allow 280 characters, but their counting is unpredictable.
-    // Use 40 characters less and hope it works ¯\_(ツ)_/¯
-    while char_count > 240 {
-        // Remove the last word.
-        shortened = re.replace_all(&shortened, "").trim().to_string();
-        if let Some(ref toot_url) = *toot_url {
-            // Add a link to the toot that has the full text.
-            with_link = shortened.clone() + "… " + toot_url;
-        } else {
-            with_link = shortened.clone();
+    // Use some characters less and hope it works ¯\_(ツ)_/¯
+    let max_chars = options
+        .max_characters
+        .saturating_sub(options.character_reserve);
+    if character_count(text, 23, 23) <= max_chars {
+        return text.to_string();
+    }
+    let suffix = match toot_url {
+        // Add a link to the toot that has the full text.
+        Some(toot_url) if options.link_to_source => format!("{} {toot_url}", options.ellipsis),
+        _ => options.ellipsis.to_string(),
+    };
+    let shortened = shorten_text(text, |shortened| {
+        character_count(&format!("{shortened}{suffix}"), 23, 23) <= max_chars
+    });
+    format!("{shortened}{suffix}")
+}
+
+/// Splits a text that is too long for a tweet into parts for a chain of
+/// replies, so that nothing is cut off. All parts but the last end with the
+/// ellipsis. A thread counter like "3/7" at the beginning or end of the text
+/// stays on the first part, so the numbering of the thread does not change.
+pub fn tweet_split(text: &str, options: &ShortenOptions) -> Vec<String> {
+    let text = text.trim();
+    let max_chars = options
+        .max_characters
+        .saturating_sub(options.character_reserve);
+    let fits = |text: &str| character_count(text, 23, 23) <= max_chars;
+    if fits(text) {
+        return vec![text.to_string()];
+    }
+    let counter = Regex::new(r"^\(?\d+/\d+\)?\s+|\s+\(?\d+/\d+\)?$").unwrap();
+    let (prefix, suffix, mut rest) = match counter.find(text) {
+        Some(m) if m.start() == 0 => (m.as_str(), "", &text[m.end()..]),
+        Some(m) => ("", m.as_str(), &text[..m.start()]),
+        None => ("", "", text),
+    };
+    let mut parts = Vec::new();
+    loop {
+        let (before, after) = match parts.is_empty() {
+            true => (prefix, suffix),
+            false => ("", ""),
+        };
+        let part = format!("{before}{rest}{after}");
+        if fits(&part) {
+            parts.push(part);
+            return parts;
+        }
+        let ellipsis = &options.ellipsis;
+        let chunk = shorten_text(rest, |chunk| {
+            fits(&format!("{before}{chunk}{ellipsis}{after}"))
+        });
+        if chunk.is_empty() {
+            // Without a word boundary that fits the text can only be cut off.
+            parts.push(tweet_shorten(&part, &None, options));
+            return parts;
         }
-        let new_count = character_count(&with_link, 23, 23);
-        char_count = new_count;
+        parts.push(format!("{before}{chunk}{ellipsis}{after}"));
+        rest = rest[chunk.len()..]
+            .trim_start_matches([',', ';', ':'])
+            .trim_start();
     }
-    with_link
 }
 
 // Mastodon has a 500 character post limit. With embedded quote tweets and long
 // links the content could get too long, shorten it to 500 characters.
 fn toot_shorten(text: &str, tweet_id: u64) -> String {
-    let mut char_count = text.graphemes(true).count();
-    let re = Regex::new(r"[^\s]+$").unwrap();
-    let mut shortened = text.trim().to_string();
-    let mut with_link = shortened.clone();
-
+    let text = text.trim();
     // Hard-coding a limit of 500 here for now, could be configurable.
-    while char_count > 500 {
-        // Remove the last word.
-        shortened = re.replace_all(&shortened, "").trim().to_string();
-        // Add a link to the full length tweet.
-        with_link = format!("{shortened}… https://twitter.com/twitter/status/{tweet_id}");
-        char_count = with_link.graphemes(true).count();
+    if text.graphemes(true).count() <= 500 {
+        return text.to_string();
     }
-    with_link
+    // Add a link to the full length tweet.
+    let suffix = format!("… https://twitter.com/twitter/status/{tweet_id}");
+    let shortened = shorten_text(text, |shortened| {
+        format!("{shortened}{suffix}").graphemes(true).count() <= 500
+    });
+    format!("{shortened}{suffix}")
+}
+
+// Returns the longest beginning of the text that fits. The text is only cut
+// between words, so URLs, hashtags and mentions stay intact. The end of a
+// sentence is preferred if at most a third of the text is lost by it.
+pub(crate) fn shorten_text(text: &str, fits: impl Fn(&str) -> bool) -> String {
+    let mut previous = ' ';
+    let cuts: Vec<usize> = text
+        .char_indices()
+        .filter_map(|(index, c)| {
+            let is_cut = c.is_whitespace() && !previous.is_whitespace();
+            previous = c;
+            is_cut.then_some(index)
+        })
+        .collect();
+    let best = match cuts.iter().rev().find(|cut| fits(&text[..**cut])) {
+        Some(best) => *best,
+        None => return String::new(),
+    };
+    let min_chars = text[..best].chars().count() * 2 / 3;
+    let sentence_end = cuts
+        .iter()
+        .rev()
+        .filter(|cut| **cut <= best)
+        .take_while(|cut| text[..**cut].chars().count() >= min_chars)
+        .find(|cut| text[..**cut].ends_with(['.', '!', '?']));
+    let cut = sentence_end.copied().unwrap_or(best);
+    // Separators are not needed in front of the ellipsis.
+    text[..cut].trim_end_matches([',', ';', ':']).to_string()
 }
 
 // Prefix boost toots with the author and strip HTML tags.
@@ -388,66 +1415,318 @@ pub fn mastodon_toot_get_text(toot: &Status) -> String {
     html_escape::decode_html_entities(&replaced).to_string()
 }
 
-// Ensure that sync posts have not been made before to prevent syncing loops.
-// Use a cache file to temporarily store posts and compare them on the next
-// invocation.
-pub fn filter_posted_before(
-    posts: StatusUpdates,
-    post_cache: &HashSet<String>,
-) -> Result<StatusUpdates> {
-    // If there are no status updates then we don't need to check anything.
-    if posts.toots.is_empty() && posts.tweets.is_empty() {
-        return Ok(posts);
+// Returns the instance domain of a Mastodon account. Remote accounts have it
+// in their "user@domain" acct, local accounts only in their profile URL.
+fn mastodon_account_domain(account: &Account) -> Option<String> {
+    if let Some((_, domain)) = account.acct.split_once('@') {
+        return Some(domain.to_lowercase());
     }
+    let url = account.url.split_once("://")?.1;
+    url.split('/').next().map(|host| host.to_lowercase())
+}
 
-    let mut filtered_posts = StatusUpdates {
-        tweets: Vec::new(),
-        toots: Vec::new(),
-    };
-    for tweet in posts.tweets {
-        if post_cache.contains(&tweet.text) {
-            eprintln!(
-                "Error: preventing double posting to Twitter: {}",
-                tweet.text
+// Entries of the post cache are removed after this many days, so that the
+// same text can be posted again at a later date.
+const POST_CACHE_MAX_AGE_DAYS: i64 = 30;
+
+/// Network a status was posted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Platform {
+    Mastodon,
+    Twitter,
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Platform::Mastodon => write!(f, "Mastodon"),
+            Platform::Twitter => write!(f, "Twitter"),
+        }
+    }
+}
+
+impl From<Platform> for HistoryNetwork {
+    fn from(platform: Platform) -> Self {
+        match platform {
+            Platform::Mastodon => HistoryNetwork::Mastodon,
+            Platform::Twitter => HistoryNetwork::Twitter,
+        }
+    }
+}
+
+/// A status that was posted by this tool, or found on both networks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PostCacheEntry {
+    // None for entries of old caches where the network is unknown, they
+    // apply to both networks.
+    pub platform: Option<Platform>,
+    // ID of the source status, 0 if unknown.
+    pub original_id: StatusId,
+    // Hash of the text with normalized whitespace and case.
+    pub text_hash: u64,
+    // Unix timestamp of posting.
+    pub posted_at: i64,
+}
+
+/// Remembers recently posted statuses to prevent double posting.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PostCache {
+    pub entries: Vec<PostCacheEntry>,
+}
+
+impl PostCache {
+    /// Parses the cache file, also from the old format that was a list of
+    /// posted texts.
+    pub fn from_json(json: &str) -> Result<PostCache> {
+        if let Ok(texts) = serde_json::from_str::<Vec<String>>(json) {
+            let mut cache = PostCache::default();
+            for text in texts {
+                cache.insert_text(None, StatusId::Number(0), &text);
+            }
+            return Ok(cache);
+        }
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Records a status that was posted to the network.
+    pub fn insert(&mut self, platform: Platform, status: &NewStatus) {
+        self.insert_text(Some(platform), status.original_id.clone(), &status.text);
+    }
+
+    pub fn insert_text(&mut self, platform: Option<Platform>, original_id: StatusId, text: &str) {
+        self.entries.push(PostCacheEntry {
+            platform,
+            original_id,
+            text_hash: text_hash(text),
+            posted_at: Utc::now().timestamp(),
+        });
+    }
+
+    /// Returns true if the status was posted to the network before, either
+    /// with the same text or from the same source status.
+    pub fn contains(&self, platform: Platform, status: &NewStatus) -> bool {
+        let hash = text_hash(&status.text);
+        self.entries.iter().any(|entry| {
+            entry.platform.map_or(true, |p| p == platform)
+                && (entry.text_hash == hash
+                    || (entry.original_id != StatusId::Number(0)
+                        && entry.original_id == status.original_id))
+        })
+    }
+
+    /// Removes entries that are older than the maximum age.
+    pub fn prune(&mut self, now: i64) {
+        let oldest = now - POST_CACHE_MAX_AGE_DAYS * 24 * 60 * 60;
+        self.entries.retain(|entry| entry.posted_at >= oldest);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+// Whitespace and case changes do not make a text different.
+fn text_hash(text: &str) -> u64 {
+    let normalized = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    content_hash(&normalized)
+}
+
+/// Removes posts whose source status was synced already according to the ID
+/// map. A changed text, for example because of a new template or signature,
+/// does not lead to posting the status again.
+pub fn filter_synced_before(mut posts: StatusUpdates, id_map: &IdMap) -> StatusUpdates {
+    for statuses in [&mut posts.tweets, &mut posts.toots] {
+        statuses.retain(|status| {
+            if !id_map.is_synced(&status.original_id) {
+                return true;
+            }
+            if id_map.content_changed(&status.original_id, &status.text) {
+                info!(
+                    "Status {} changed since it was synced, not posting it again: {}",
+                    status.original_id, status.text
+                );
+            } else {
+                warn!(
+                    "Preventing double posting of synced status: {}",
+                    status.text
+                );
+            }
+            false
+        });
+    }
+    posts
+}
+
+// Statuses that were deleted as old statuses are not recreated, neither from
+// their copies on the other network nor from their sources.
+pub fn filter_deleted_before(mut posts: StatusUpdates, id_map: &IdMap) -> StatusUpdates {
+    for (platform, statuses) in [
+        (Platform::Twitter, &mut posts.tweets),
+        (Platform::Mastodon, &mut posts.toots),
+    ] {
+        statuses.retain(|status| {
+            if id_map.tombstone(platform, status).is_none() {
+                return true;
+            }
+            info!(
+                "Status {} was deleted before, not recreating it: {}",
+                status.original_id, status.text
             );
+            false
+        });
+    }
+    posts
+}
+
+// Ensure that sync posts have not been made before to prevent syncing loops.
+// Use a cache file to store posts and compare them on the next invocation.
+pub fn filter_posted_before(posts: StatusUpdates, post_cache: &PostCache) -> StatusUpdates {
+    let mut filtered_posts = StatusUpdates::default();
+    for tweet in posts.tweets {
+        if post_cache.contains(Platform::Twitter, &tweet) {
+            warn!("Preventing double posting to Twitter: {}", tweet.text);
         } else {
-            filtered_posts.tweets.push(tweet.clone());
+            filtered_posts.tweets.push(tweet);
         }
     }
     for toot in posts.toots {
-        if post_cache.contains(&toot.text) {
-            eprintln!(
-                "Error: preventing double posting to Mastodon: {}",
-                toot.text
-            );
+        if post_cache.contains(Platform::Mastodon, &toot) {
+            warn!("Preventing double posting to Mastodon: {}", toot.text);
         } else {
-            filtered_posts.toots.push(toot.clone());
+            filtered_posts.toots.push(toot);
+        }
+    }
+    filtered_posts
+}
+
+/// Removes tweets to post that are on Twitter already, with the same text or a
+/// link to their source toot, and records them as synced in the ID map.
+/// Twitter rejects duplicates, so they are not even tried. Thread replies of
+/// such tweets are posted as replies to the existing tweet.
+///
+/// Returns true if the ID map changed.
+pub fn filter_existing_tweets(
+    tweets: &mut Vec<NewStatus>,
+    mastodon_statuses: &[Status],
+    twitter_statuses: &[Tweet],
+    text_options: &TweetTextOptions,
+    id_map: &mut IdMap,
+) -> Result<bool> {
+    let mut changed = false;
+    let mut remaining = Vec::new();
+    for mut status in tweets.drain(..) {
+        let source = mastodon_statuses
+            .iter()
+            .find(|toot| StatusId::from(toot.id.as_str()) == status.original_id);
+        let existing = match status.in_reply_to_id {
+            None => existing_tweet(&status, source, twitter_statuses, text_options)?,
+            Some(_) => None,
+        };
+        match existing {
+            Some(tweet_id) => {
+                warn!(
+                    "Tweet {tweet_id} exists already, marking it as synced: {}",
+                    status.text
+                );
+                id_map.insert(status.original_id.clone(), tweet_id);
+                changed = true;
+                for mut reply in mem::take(&mut status.replies) {
+                    reply.in_reply_to_id = Some(tweet_id.into());
+                    remaining.push(reply);
+                }
+            }
+            None => remaining.push(status),
         }
     }
+    *tweets = remaining;
+    Ok(changed)
+}
 
-    Ok(filtered_posts)
+// Returns the ID of a recent tweet with the same text as the status to post
+// or with a link to its source toot.
+fn existing_tweet(
+    status: &NewStatus,
+    source: Option<&Status>,
+    twitter_statuses: &[Tweet],
+    text_options: &TweetTextOptions,
+) -> Result<Option<u64>> {
+    let unify = |text: String| {
+        unify_post_content(match &text_options.short_links {
+            Some(short_links) => short_links.resolve_text(&text),
+            None => text,
+        })
+    };
+    let text = unify(status.text.clone());
+    for tweet in twitter_statuses {
+        if tweet.in_reply_to_status_id.is_some() {
+            continue;
+        }
+        if source.map_or(false, |toot| links_to_source(toot, tweet))
+            || unify(tweet_unshorten_decode(tweet, text_options)?) == text
+        {
+            return Ok(Some(tweet.id));
+        }
+    }
+    Ok(None)
 }
 
 // Read the JSON encoded cache file from disk or provide an empty default cache.
-pub fn read_post_cache(cache_file: &str) -> HashSet<String> {
+// Returns None if the cache file is broken, it is moved away to a backup file
+// in that case.
+pub fn read_post_cache(cache_file: &str) -> Option<PostCache> {
     match fs::read_to_string(cache_file) {
-        Ok(json) => {
-            match serde_json::from_str::<HashSet<String>>(&json) {
-                Ok(cache) => {
-                    // If the cache has more than 150 items already then empty it to not
-                    // accumulate too many items and allow posting the same text at a
-                    // later date.
-                    if cache.len() > 150 {
-                        HashSet::new()
-                    } else {
-                        cache
-                    }
+        Ok(json) => match PostCache::from_json(&json) {
+            Ok(mut cache) => {
+                cache.prune(Utc::now().timestamp());
+                Some(cache)
+            }
+            Err(e) => {
+                let backup_file = format!("{cache_file}.corrupt");
+                warn!("Post cache {cache_file} is broken ({e}), moving it to {backup_file}");
+                if let Err(e) = fs::rename(cache_file, &backup_file) {
+                    warn!("Failed to back up broken post cache {cache_file}: {e}");
                 }
-                Err(_) => HashSet::new(),
+                None
             }
+        },
+        Err(_) => Some(PostCache::default()),
+    }
+}
+
+// Builds a best-effort post cache from the texts of existing statuses. Used
+// when the cache file was broken to still prevent double posting of statuses
+// that exist already on the target network.
+pub fn rebuild_post_cache(
+    mastodon_statuses: &[Status],
+    twitter_statuses: &[Tweet],
+    text_options: &TweetTextOptions,
+) -> PostCache {
+    let mut cache = PostCache::default();
+    for tweet in twitter_statuses {
+        cache.insert_text(Some(Platform::Twitter), StatusId::Number(0), &tweet.text);
+        // Best effort, a tweet that cannot be decoded is only known by its
+        // raw text.
+        if let Ok(text) = tweet_unshorten_decode(tweet, text_options) {
+            cache.insert_text(Some(Platform::Twitter), StatusId::Number(0), &text);
         }
-        Err(_) => HashSet::new(),
     }
+    for toot in mastodon_statuses {
+        cache.insert_text(
+            Some(Platform::Mastodon),
+            StatusId::Number(0),
+            &mastodon_toot_get_text(toot),
+        );
+    }
+    cache
 }
 
 // Returns a list of direct links to attachments for download.
@@ -489,13 +1768,21 @@ pub fn tweet_get_attachments(tweet: &Tweet) -> Vec<NewMedia> {
                     }
                     links.push(NewMedia {
                         attachment_url: media_url,
-                        alt_text: attachment.ext_alt_text.clone(),
+                        alt_text: truncate_alt_text(
+                            attachment.ext_alt_text.clone(),
+                            MASTODON_ALT_TEXT_LIMIT,
+                        ),
+                        focus: None,
                     });
                 }
                 None => {
                     links.push(NewMedia {
                         attachment_url: attachment.media_url_https.clone(),
-                        alt_text: attachment.ext_alt_text.clone(),
+                        alt_text: truncate_alt_text(
+                            attachment.ext_alt_text.clone(),
+                            MASTODON_ALT_TEXT_LIMIT,
+                        ),
+                        focus: None,
                     });
                 }
             }
@@ -518,30 +1805,46 @@ pub fn toot_get_attachments(toot: &Status) -> Vec<NewMedia> {
     for attachment in attachments {
         links.push(NewMedia {
             attachment_url: attachment.url.clone(),
-            // Twitter only allows a max length of 1,000 characters for alt
-            // text, so we need to cut it off here.
-            alt_text: truncate_option_string(attachment.description.clone(), 1_000),
+            alt_text: truncate_alt_text(attachment.description.clone(), TWITTER_ALT_TEXT_LIMIT),
+            focus: None,
         });
     }
     links
 }
 
-/// Truncates a given string to a maximum number of characters.
-///
-/// I could not find a Rust core function that does this? We don't care about
-/// graphemes, please just cut off characters at a certain length. Copied from
-/// https://stackoverflow.com/a/38461750/2000435
-///
-/// No, I will not install the substring crate just to get a substring, are you
-/// kidding me????
-fn truncate_option_string(stringy: Option<String>, max_chars: usize) -> Option<String> {
-    match stringy {
-        Some(string) => match string.char_indices().nth(max_chars) {
-            None => Some(string),
-            Some((idx, _)) => Some(string[..idx].to_string()),
-        },
-        None => None,
+/// Returns the image of the link preview card of a toot or its boosted toot
+/// as attachment, with the title of the card as alt text.
+pub fn toot_card_image(toot: &Status) -> Option<NewMedia> {
+    let card = toot
+        .card
+        .as_ref()
+        .or_else(|| toot.reblog.as_ref().and_then(|boost| boost.card.as_ref()))?;
+    Some(NewMedia {
+        attachment_url: card.image.clone()?,
+        alt_text: truncate_alt_text(Some(card.title.clone()), TWITTER_ALT_TEXT_LIMIT),
+        focus: None,
+    })
+}
+
+// Maximum number of characters of alt texts, longer ones are cut off.
+pub(crate) const TWITTER_ALT_TEXT_LIMIT: usize = 1_000;
+pub(crate) const MASTODON_ALT_TEXT_LIMIT: usize = 1_500;
+
+/// Truncates an alt text to a maximum number of characters. Only whole
+/// graphemes are kept, so emoji that consist of several characters are not
+/// split.
+pub(crate) fn truncate_alt_text(alt_text: Option<String>, max_chars: usize) -> Option<String> {
+    let alt_text = alt_text?;
+    let mut chars = 0;
+    let mut end = 0;
+    for (index, grapheme) in alt_text.grapheme_indices(true) {
+        chars += grapheme.chars().count();
+        if chars > max_chars {
+            break;
+        }
+        end = index + grapheme.len();
     }
+    Some(alt_text[..end].to_string())
 }
 
 #[cfg(test)]
@@ -558,11 +1861,47 @@ pub mod tests {
     use egg_mode::tweet::{ExtendedTweetEntities, TweetEntities, TweetSource};
     use egg_mode::user::{TwitterUser, UserEntities, UserEntityDetail};
 
-    static DEFAULT_SYNC_OPTIONS: SyncOptions = SyncOptions {
+    pub static DEFAULT_SYNC_OPTIONS: SyncOptions = SyncOptions {
         sync_reblogs: true,
         sync_retweets: true,
-        sync_hashtag_twitter: None,
-        sync_hashtag_mastodon: None,
+        retweet_sync_mode_mastodon: RetweetSyncMode::Copy,
+        retweet_sync_mode_twitter: RetweetSyncMode::Copy,
+        sync_hashtags_twitter: HashtagFilter {
+            required: Vec::new(),
+            denied: Vec::new(),
+            match_mode: HashtagMatch::Any,
+            remove: false,
+        },
+        sync_hashtags_mastodon: HashtagFilter {
+            required: Vec::new(),
+            denied: Vec::new(),
+            match_mode: HashtagMatch::Any,
+            remove: false,
+        },
+        boost_deny_instances: Vec::new(),
+        skip_sources_twitter: Vec::new(),
+        skip_sources_mastodon: Vec::new(),
+        crosspost_patterns_twitter: Vec::new(),
+        crosspost_patterns_mastodon: Vec::new(),
+        sync_replies_to_others_twitter: false,
+        sync_replies_to_others_mastodon: false,
+        max_thread_depth_mastodon: None,
+        max_thread_depth_twitter: None,
+        thread_strategy_twitter: ThreadStrategy::Mirror,
+        thread_strategy_mastodon: ThreadStrategy::Mirror,
+        direct_posts_mastodon: DirectPostMode::Skip,
+        self_boosts_twitter: SelfBoostMode::Copy,
+        self_boosts_mastodon: SelfBoostMode::Copy,
+        link_preview_images_mastodon: false,
+        tweet_text: TweetTextOptions {
+            expand_urls: true,
+            escape_mentions: true,
+            decode_html_entities: true,
+            removed_hashtags: Vec::new(),
+            hashtag_aliases: Vec::new(),
+            short_links: None,
+            shorten: DEFAULT_SHORTEN_OPTIONS,
+        },
     };
 
     #[test]
@@ -595,6 +1934,7 @@ https://cybre.space/media/J-amFmXPvb_Mt7toGgs #tutorial #howto
         let shortened_for_twitter = tweet_shorten(
             toot,
             &Some("https://mastodon.social/@klausi/98999025586548863".to_string()),
+            &DEFAULT_SHORTEN_OPTIONS,
         );
         assert_eq!(
             shortened_for_twitter,
@@ -610,6 +1950,47 @@ UNLISTED 🔓 ✅ Tagged people
         );
     }
 
+    // Test that texts are cut between words and preferably at a sentence end.
+    #[test]
+    fn shorten_at_boundaries() {
+        let fits = |max: usize| move |text: &str| text.chars().count() <= max;
+        let text = "First sentence here. Second one with https://example.com/long/path #tag";
+        assert_eq!(shorten_text(text, fits(30)), "First sentence here.");
+        assert_eq!(
+            shorten_text(text, fits(60)),
+            "First sentence here. Second one with"
+        );
+        assert_eq!(shorten_text("One, two, three", fits(9)), "One, two");
+        assert_eq!(shorten_text("One, two three", fits(7)), "One");
+        assert_eq!(shorten_text("https://example.com/long", fits(10)), "");
+    }
+
+    // Test that the character reserve, ellipsis and link can be configured.
+    #[test]
+    fn tweet_shorten_options() {
+        let toot = "word ".repeat(60);
+        let url = Some("https://mastodon.social/@klausi/1".to_string());
+        let options = ShortenOptions {
+            character_reserve: 180,
+            ellipsis: Cow::Borrowed(" [...]"),
+            link_to_source: false,
+            ..DEFAULT_SHORTEN_OPTIONS
+        };
+        let shortened = tweet_shorten(&toot, &url, &options);
+        assert!(shortened.ends_with("word [...]"));
+        assert!(character_count(&shortened, 23, 23) <= 100);
+
+        // A reserve larger than the limit does not loop forever.
+        let options = ShortenOptions {
+            character_reserve: 300,
+            ..DEFAULT_SHORTEN_OPTIONS
+        };
+        assert_eq!(
+            tweet_shorten(&toot, &url, &options),
+            "… https://mastodon.social/@klausi/1"
+        );
+    }
+
     // Test that if a long Mastodon toot already exists as short version on
     // Twitter that it is not posted again.
     #[test]
@@ -623,11 +2004,11 @@ UNLISTED 🔓 ✅ Tagged people
         status.content = long_toot.to_string();
 
         let mut tweet = get_twitter_status();
-        tweet.text = tweet_shorten(long_toot, &status.url);
+        tweet.text = tweet_shorten(long_toot, &status.url, &DEFAULT_SHORTEN_OPTIONS);
 
         let tweets = vec![tweet];
         let statuses = vec![status];
-        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
         assert!(posts.toots.is_empty());
         assert!(posts.tweets.is_empty());
     }
@@ -649,7 +2030,7 @@ UNLISTED 🔓 ✅ Tagged people
 
         let tweets = vec![tweet];
         let statuses = vec![status];
-        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
         assert!(posts.toots.is_empty());
         assert!(posts.tweets.is_empty());
     }
@@ -660,7 +2041,7 @@ UNLISTED 🔓 ✅ Tagged people
     fn mastodon_html_decode() {
         let mut status = get_mastodon_status();
         status.content = "<p>You &amp; me!</p>".to_string();
-        let posts = determine_posts(&vec![status], &Vec::new(), &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&vec![status], &Vec::new(), &DEFAULT_SYNC_OPTIONS).unwrap();
         assert_eq!(posts.tweets[0].text, "You & me!");
     }
 
@@ -670,7 +2051,7 @@ UNLISTED 🔓 ✅ Tagged people
     fn twitter_html_decode() {
         let mut status = get_twitter_status();
         status.text = "You &amp; me!".to_string();
-        let posts = determine_posts(&Vec::new(), &vec![status], &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&Vec::new(), &vec![status], &DEFAULT_SYNC_OPTIONS).unwrap();
         assert_eq!(posts.toots[0].text, "You & me!");
     }
 
@@ -684,7 +2065,7 @@ UNLISTED 🔓 ✅ Tagged people
         status.reblog = Some(Box::new(reblog));
         status.reblogged = Some(true);
 
-        let posts = determine_posts(&vec![status], &Vec::new(), &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&vec![status], &Vec::new(), &DEFAULT_SYNC_OPTIONS).unwrap();
         assert_eq!(posts.tweets[0].text, "RT example: Some example toooot!");
     }
 
@@ -698,7 +2079,7 @@ UNLISTED 🔓 ✅ Tagged people
         status.reblog = Some(Box::new(reblog));
         status.reblogged = Some(true);
 
-        let posts = determine_posts(&vec![status], &Vec::new(), &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&vec![status], &Vec::new(), &DEFAULT_SYNC_OPTIONS).unwrap();
         assert_eq!(posts.tweets[0].text, "RT example: longer than 280 characters longer than 280 characters longer than 280 characters longer than 280 characters longer than 280 characters longer than 280 characters longer than 280 characters longer than… https://example.com/a/b/c/5");
     }
 
@@ -717,7 +2098,7 @@ UNLISTED 🔓 ✅ Tagged people
 
         let tweets = vec![tweet];
         let statuses = vec![status];
-        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
         assert!(posts.toots.is_empty());
         assert!(posts.tweets.is_empty());
     }
@@ -729,7 +2110,9 @@ UNLISTED 🔓 ✅ Tagged people
         status.content = "Casing different @Yes".to_string();
         let mut tweet = get_twitter_status();
         tweet.text = "casing Different @yes".to_string();
-        assert!(toot_and_tweet_are_equal(&status, &tweet));
+        assert!(
+            toot_and_tweet_are_equal(&status, &tweet, &DEFAULT_SYNC_OPTIONS.tweet_text).unwrap()
+        );
 
         let long_toot = "Test test test test test test test test test test test test test
         test test test test test test test test test test test test test
@@ -737,8 +2120,10 @@ UNLISTED 🔓 ✅ Tagged people
         test test test test test test test test test test test test test
         test test test test";
         status.content = long_toot.to_string();
-        tweet.text = tweet_shorten(long_toot, &status.url).to_lowercase();
-        assert!(toot_and_tweet_are_equal(&status, &tweet));
+        tweet.text = tweet_shorten(long_toot, &status.url, &DEFAULT_SHORTEN_OPTIONS).to_lowercase();
+        assert!(
+            toot_and_tweet_are_equal(&status, &tweet, &DEFAULT_SYNC_OPTIONS.tweet_text).unwrap()
+        );
     }
 
     // Test that @username mentions are escaped, because we don't want to mention completely unrelated users on the other network.
@@ -748,18 +2133,20 @@ UNLISTED 🔓 ✅ Tagged people
         status.content = "I will mention <span class=\"h-card\"><a href=\"https://example.com/@klausi\" class=\"u-url mention\">@<span>klausi</span></a></span> here".to_string();
         let mut tweet = get_twitter_status();
         tweet.text = "I will mention @\\klausi here".to_string();
-        assert!(toot_and_tweet_are_equal(&status, &tweet));
+        assert!(
+            toot_and_tweet_are_equal(&status, &tweet, &DEFAULT_SYNC_OPTIONS.tweet_text).unwrap()
+        );
 
         let tweets = Vec::new();
         let statuses = vec![status];
-        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
         assert!(posts.toots.is_empty());
         assert_eq!(posts.tweets[0].text, "I will mention @\\klausi here");
 
         tweet.text = "I will mention @klausi here".to_string();
         let tweets = vec![tweet];
         let statuses = Vec::new();
-        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
         assert!(posts.tweets.is_empty());
         assert_eq!(posts.toots[0].text, "I will mention @\\klausi here");
     }
@@ -772,20 +2159,24 @@ UNLISTED 🔓 ✅ Tagged people
         status.content = "I will mention <span class=\"h-card\"><a href=\"https://example.com/@klausi\" class=\"u-url mention\">@<span>klausi</span></a></span> here".to_string();
         let mut tweet = get_twitter_status();
         tweet.text = "I will mention \\@klausi here".to_string();
-        assert!(toot_and_tweet_are_equal(&status, &tweet));
+        assert!(
+            toot_and_tweet_are_equal(&status, &tweet, &DEFAULT_SYNC_OPTIONS.tweet_text).unwrap()
+        );
 
         let tweets = vec![tweet.clone()];
         let statuses = vec![status.clone()];
-        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
         assert!(posts.toots.is_empty());
         assert!(posts.tweets.is_empty());
 
         tweet.text = "I will mention @klausi here".to_string();
         status.content = "I will mention \\@klausi here".to_string();
-        assert!(toot_and_tweet_are_equal(&status, &tweet));
+        assert!(
+            toot_and_tweet_are_equal(&status, &tweet, &DEFAULT_SYNC_OPTIONS.tweet_text).unwrap()
+        );
         let tweets = vec![tweet];
         let statuses = vec![status];
-        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
         assert!(posts.toots.is_empty());
         assert!(posts.tweets.is_empty());
     }
@@ -797,11 +2188,29 @@ UNLISTED 🔓 ✅ Tagged people
         status.content = "@Test Hello! http://example.com".to_string();
         let tweets = Vec::new();
         let statuses = vec![status];
-        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
         assert!(posts.toots.is_empty());
         assert!(posts.tweets.is_empty());
     }
 
+    // Test that the mentions of direct toots can be removed instead of skipping
+    // the toots.
+    #[test]
+    fn direct_toot_redacted() {
+        let mut status = get_mastodon_status();
+        status.content = "@Test @other@example.com Hello @you!".to_string();
+        let mut options = DEFAULT_SYNC_OPTIONS.clone();
+        options.direct_posts_mastodon = DirectPostMode::Redact;
+        let posts = determine_posts(&vec![status.clone()], &Vec::new(), &options).unwrap();
+        assert_eq!(posts.tweets[0].text, "Hello @\\you!");
+
+        // Nothing left to post.
+        status.content = "@Test".to_string();
+        let posts = determine_posts(&vec![status], &Vec::new(), &options).unwrap();
+        assert!(posts.tweets.is_empty());
+        assert_eq!(posts.skipped[0].reason, SkipReason::Direct);
+    }
+
     // Test that toots starting with umlauts like Ö do not panic.
     #[test]
     fn umlaut_toot() {
@@ -809,11 +2218,45 @@ UNLISTED 🔓 ✅ Tagged people
         status.content = "Österreich".to_string();
         let tweets = Vec::new();
         let statuses = vec![status];
-        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
         assert!(posts.toots.is_empty());
         assert_eq!(posts.tweets[0].text, "Österreich");
     }
 
+    // Test that line endings and repeated whitespace do not make posts
+    // different.
+    #[test]
+    fn whitespace_normalization() {
+        let mut status = get_mastodon_status();
+        status.content = "<p>First  line <br />second\tline </p>".to_string();
+        let mut tweet = get_twitter_status();
+        tweet.text = "First line\r\nsecond line".to_string();
+        assert!(
+            toot_and_tweet_are_equal(&status, &tweet, &DEFAULT_SYNC_OPTIONS.tweet_text).unwrap()
+        );
+        assert_eq!(
+            unify_post_content(" a \u{a0} b \r\n\r\n c\r".to_string()),
+            "a b\n\nc"
+        );
+    }
+
+    // Test that decomposed accents and emoji variation selectors do not make
+    // posts different.
+    #[test]
+    fn unicode_normalization() {
+        let mut status = get_mastodon_status();
+        status.content = "Caf\u{e9} in \u{d6}sterreich \u{2764}\u{fe0f}".to_string();
+        let mut tweet = get_twitter_status();
+        tweet.text = "Cafe\u{301} in O\u{308}sterreich \u{2764}".to_string();
+        assert!(
+            toot_and_tweet_are_equal(&status, &tweet, &DEFAULT_SYNC_OPTIONS.tweet_text).unwrap()
+        );
+        assert_eq!(
+            unify_post_content("c\u{327}a\u{300} \u{301}x".to_string()),
+            "\u{e7}\u{e0} \u{301}x"
+        );
+    }
+
     // Test that posting something looking like a URL/domain is considered
     // equal coming back from Twitter.
     #[test]
@@ -838,7 +2281,246 @@ UNLISTED 🔓 ✅ Tagged people
             media: None,
         };
 
-        assert!(toot_and_tweet_are_equal(&status, &tweet));
+        assert!(
+            toot_and_tweet_are_equal(&status, &tweet, &DEFAULT_SYNC_OPTIONS.tweet_text).unwrap()
+        );
+    }
+
+    // Test that a link to the source status marks posts as equal even if their
+    // texts are different.
+    #[test]
+    fn source_url_marker() {
+        let mut status = get_mastodon_status();
+        status.content = "<p>Long text that was shortened</p>".to_string();
+        status.url = Some("https://mastodon.social/@klausi/123".to_string());
+        let mut tweet = get_twitter_status();
+        tweet.text = "Long text that… https://t.co/abc".to_string();
+        tweet.entities.urls = vec![UrlEntity {
+            display_url: "mastodon.social/@klausi/123".to_string(),
+            expanded_url: Some("http://mastodon.social/@klausi/123".to_string()),
+            range: (16, 32),
+            url: "https://t.co/abc".to_string(),
+        }];
+        assert!(
+            toot_and_tweet_are_equal(&status, &tweet, &DEFAULT_SYNC_OPTIONS.tweet_text).unwrap()
+        );
+
+        // Thread beginning synced from Twitter.
+        tweet.entities.urls.clear();
+        tweet.id = 1234;
+        status.content = "<p>Thread start</p><p>(thread continues) <a href=\"https://twitter.com/twitter/status/1234\">https://twitter.com/twitter/status/1234</a></p>".to_string();
+        assert!(
+            toot_and_tweet_are_equal(&status, &tweet, &DEFAULT_SYNC_OPTIONS.tweet_text).unwrap()
+        );
+        tweet.id = 123;
+        assert!(
+            !toot_and_tweet_are_equal(&status, &tweet, &DEFAULT_SYNC_OPTIONS.tweet_text).unwrap()
+        );
+    }
+
+    #[test]
+    fn tweet_split_parts() {
+        let sentence = "This is a sentence with some words. ";
+        let text = format!("{}4/7", sentence.repeat(12));
+        let parts = tweet_split(&text, &DEFAULT_SHORTEN_OPTIONS);
+        assert_eq!(parts.len(), 2);
+        for part in &parts {
+            assert!(part.chars().count() <= 240);
+        }
+        assert!(parts[0].ends_with(".… 4/7"));
+        assert!(!parts[1].contains("4/7"));
+        let joined = format!(
+            "{} {}",
+            parts[0].trim_end_matches("… 4/7"),
+            parts[1].trim_end_matches('…')
+        );
+        assert_eq!(joined, sentence.repeat(12).trim());
+
+        assert_eq!(
+            tweet_split("Short 1/2", &DEFAULT_SHORTEN_OPTIONS),
+            vec!["Short 1/2"]
+        );
+    }
+
+    // Test that tweets that are on Twitter already are not posted again.
+    #[test]
+    fn existing_tweets_marked_synced() {
+        let mut existing = get_twitter_status();
+        existing.id = 1000;
+        existing.text = "Already on  Twitter".to_string();
+        let mut source = get_mastodon_status();
+        source.id = "3".to_string();
+        source.url = Some("https://mastodon.social/@klausi/3".to_string());
+        let mut linked = get_twitter_status();
+        linked.id = 1001;
+        linked.text = "Changed text… https://t.co/abc".to_string();
+        linked.entities.urls = vec![UrlEntity {
+            display_url: "mastodon.social/@klausi/3".to_string(),
+            expanded_url: Some("https://mastodon.social/@klausi/3".to_string()),
+            range: (14, 30),
+            url: "https://t.co/abc".to_string(),
+        }];
+
+        let mut thread = new_status(1, "already on twitter");
+        thread.replies.push(new_status(2, "New reply"));
+        let mut tweets = vec![thread, new_status(3, "Long text"), new_status(4, "New")];
+        let mut id_map = IdMap::default();
+        let changed = filter_existing_tweets(
+            &mut tweets,
+            &[source],
+            &[existing, linked],
+            &DEFAULT_SYNC_OPTIONS.tweet_text,
+            &mut id_map,
+        )
+        .unwrap();
+        assert!(changed);
+        assert_eq!(tweets.len(), 2);
+        assert_eq!(tweets[0].original_id, StatusId::Number(2));
+        assert_eq!(tweets[0].in_reply_to_id, Some(StatusId::Number(1000)));
+        assert_eq!(tweets[1].original_id, StatusId::Number(4));
+        assert_eq!(id_map.tweet_id(&StatusId::Number(1)), Some(1000));
+        assert_eq!(id_map.tweet_id(&StatusId::Number(3)), Some(1001));
+    }
+
+    // Test that statuses posted with other cross-posting apps are skipped.
+    #[test]
+    fn skip_sources() {
+        let mut status = get_mastodon_status();
+        status.application.as_mut().unwrap().name = "IFTTT".to_string();
+        let mut tweet = get_twitter_status();
+        tweet.source = Some(TweetSource {
+            name: "Buffer".to_string(),
+            url: "https://buffer.com".to_string(),
+        });
+        let mut options = DEFAULT_SYNC_OPTIONS.clone();
+        options.skip_sources_mastodon = vec!["ifttt".to_string()];
+        options.skip_sources_twitter = vec!["Buffer".to_string()];
+        let posts = determine_posts(&[status.clone()], &[tweet.clone()], &options).unwrap();
+        assert!(posts.tweets.is_empty());
+        assert!(posts.toots.is_empty());
+        assert_eq!(posts.skipped.len(), 2);
+        assert!(posts
+            .skipped
+            .iter()
+            .all(|skipped| skipped.reason == SkipReason::Source));
+
+        options.skip_sources_mastodon = vec!["Buffer".to_string()];
+        options.skip_sources_twitter = Vec::new();
+        let posts = determine_posts(&[status], &[tweet], &options).unwrap();
+        assert_eq!(posts.tweets.len(), 1);
+        assert_eq!(posts.toots.len(), 1);
+    }
+
+    // Test that statuses cross-posted by other tools are not synced back.
+    #[test]
+    fn skip_crossposts() {
+        let mut status = get_mastodon_status();
+        status.content = "Hello (from birdsite)".to_string();
+        let mut tweet = get_twitter_status();
+        tweet.text = "Hello, see https://mastodon.social/@test/123".to_string();
+        let mut options = DEFAULT_SYNC_OPTIONS.clone();
+        let patterns = vec![r"(?i)\(from birdsite\)".to_string()];
+        options.crosspost_patterns_mastodon =
+            crosspost_patterns(&patterns, "twitter.com/test").unwrap();
+        options.crosspost_patterns_twitter =
+            crosspost_patterns(&patterns, "mastodon.social/@test").unwrap();
+        let posts = determine_posts(&[status.clone()], &[tweet.clone()], &options).unwrap();
+        assert!(posts.tweets.is_empty());
+        assert!(posts.toots.is_empty());
+        assert!(posts
+            .skipped
+            .iter()
+            .all(|skipped| skipped.reason == SkipReason::Crosspost));
+
+        // Other accounts on the same instance are no crossposts.
+        tweet.text = "Hello, see https://mastodon.social/@test_other/123".to_string();
+        let posts = determine_posts(&[], &[tweet], &options).unwrap();
+        assert_eq!(posts.toots.len(), 1);
+        assert!(crosspost_patterns(&["(".to_string()], "twitter.com/test").is_err());
+        // Without an account on the other network only the patterns are left.
+        assert!(crosspost_patterns(&[], "").unwrap().is_empty());
+    }
+
+    // Test that the image of a link preview is attached if enabled.
+    #[test]
+    fn link_preview_image() {
+        let mut status = get_mastodon_status();
+        status.card = Some(
+            serde_json::from_value(serde_json::json!({
+                "url": "https://example.com/article",
+                "title": "An article",
+                "description": "About something",
+                "image": "https://example.com/preview.jpg",
+            }))
+            .unwrap(),
+        );
+        let posts = determine_posts(&[status.clone()], &[], &DEFAULT_SYNC_OPTIONS).unwrap();
+        assert!(posts.tweets[0].attachments.is_empty());
+
+        let mut options = DEFAULT_SYNC_OPTIONS.clone();
+        options.link_preview_images_mastodon = true;
+        let posts = determine_posts(&[status.clone()], &[], &options).unwrap();
+        let attachment = &posts.tweets[0].attachments[0];
+        assert_eq!(attachment.attachment_url, "https://example.com/preview.jpg");
+        assert_eq!(attachment.alt_text.as_deref(), Some("An article"));
+
+        // Media of the toot take precedence.
+        let mut status_media = get_mastodon_status_media();
+        status_media.card = status.card;
+        let posts = determine_posts(&[status_media], &[], &options).unwrap();
+        assert!(posts.tweets[0]
+            .attachments
+            .iter()
+            .all(|media| media.attachment_url != "https://example.com/preview.jpg"));
+    }
+
+    // Test that hashtags that are replaced for the other network and appended
+    // hashtags do not make statuses different.
+    #[test]
+    fn hashtag_aliases_are_equal() {
+        let mut status = get_mastodon_status();
+        status.content = "<p>Hello #Fediverse</p>".to_string();
+        let mut tweet = get_twitter_status();
+        tweet.text = "Hello #mastodon\n\n#sync".to_string();
+        let mut options = DEFAULT_SYNC_OPTIONS.tweet_text.clone();
+        assert!(!toot_and_tweet_are_equal(&status, &tweet, &options).unwrap());
+        options.hashtag_aliases = vec![("fediverse".to_string(), "mastodon".to_string())];
+        options.removed_hashtags = vec!["sync".to_string()];
+        assert!(toot_and_tweet_are_equal(&status, &tweet, &options).unwrap());
+    }
+
+    // Test that tweet text normalization steps can be switched off.
+    #[test]
+    fn tweet_text_options() {
+        let mut tweet = get_twitter_status();
+        tweet.text = "Tom &amp; @Jerry at https://t.co/OxEvHBajwd".to_string();
+        tweet.entities.urls = vec![UrlEntity {
+            display_url: "bofa.lol".to_string(),
+            expanded_url: Some("http://bofa.lol".to_string()),
+            range: (20, 43),
+            url: "https://t.co/OxEvHBajwd".to_string(),
+        }];
+
+        let mut options = DEFAULT_SYNC_OPTIONS.clone();
+        let posts = determine_posts(&Vec::new(), &vec![tweet.clone()], &options).unwrap();
+        assert_eq!(posts.toots[0].text, "Tom & @\\Jerry at http://bofa.lol");
+
+        options.tweet_text = TweetTextOptions {
+            expand_urls: false,
+            escape_mentions: false,
+            decode_html_entities: false,
+            removed_hashtags: Vec::new(),
+            hashtag_aliases: Vec::new(),
+            short_links: None,
+            shorten: DEFAULT_SHORTEN_OPTIONS,
+        };
+        let posts = determine_posts(&Vec::new(), &vec![tweet.clone()], &options).unwrap();
+        assert_eq!(posts.toots[0].text, tweet.text);
+
+        // The equality check uses the same normalization.
+        let mut status = get_mastodon_status();
+        status.content = html_escape::encode_text(&posts.toots[0].text).to_string();
+        assert!(toot_and_tweet_are_equal(&status, &tweet, &options.tweet_text).unwrap());
     }
 
     // Test that if there are pictures in a tweet that they are attached as
@@ -847,7 +2529,7 @@ UNLISTED 🔓 ✅ Tagged people
     fn pictures_in_tweet() {
         let tweets = vec![get_twitter_status_media()];
         let statuses = Vec::new();
-        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         let status = &posts.toots[0];
         assert_eq!(status.text, "Verhalten bei #Hausdurchsuchung");
@@ -867,7 +2549,7 @@ UNLISTED 🔓 ✅ Tagged people
         let tweet = get_twitter_status_video();
         let tweets = vec![tweet];
         let statuses = Vec::new();
-        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         let status = &posts.toots[0];
         assert_eq!(status.text, "Verhalten bei #Hausdurchsuchung");
@@ -887,7 +2569,7 @@ UNLISTED 🔓 ✅ Tagged people
     fn pictures_in_toot() {
         let statuses = vec![get_mastodon_status_media()];
         let tweets = Vec::new();
-        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&statuses, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         let tweet = &posts.tweets[0];
         assert_eq!(tweet.text, "test image");
@@ -912,7 +2594,7 @@ UNLISTED 🔓 ✅ Tagged people
 
         let tweets = vec![retweet];
         let toots = Vec::new();
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         let sync_toot = &posts.toots[0];
         assert_eq!(
@@ -925,6 +2607,21 @@ UNLISTED 🔓 ✅ Tagged people
         );
     }
 
+    // Test that a malformed retweet without user is an error, not a panic.
+    #[test]
+    fn retweet_without_user() {
+        let mut retweet = get_twitter_status();
+        retweet.retweeted = Some(true);
+        let mut original_tweet = get_twitter_status();
+        original_tweet.user = None;
+        retweet.retweeted_status = Some(Box::new(original_tweet));
+
+        let error = determine_posts(&Vec::new(), &vec![retweet], &DEFAULT_SYNC_OPTIONS)
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Twitter user missing on retweet"));
+    }
+
     // Test boosts that have attachments.
     #[test]
     fn picture_in_boost() {
@@ -934,7 +2631,7 @@ UNLISTED 🔓 ✅ Tagged people
 
         let tweets = Vec::new();
         let toots = vec![boost];
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         let sync_tweet = &posts.tweets[0];
         assert_eq!(sync_tweet.text, "RT example: test image");
@@ -969,7 +2666,7 @@ UNLISTED 🔓 ✅ Tagged people
 
         let tweets = vec![quote_tweet];
         let toots = Vec::new();
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         let sync_toot = &posts.toots[0];
         assert_eq!(
@@ -1009,7 +2706,7 @@ QT test123: Original text"
 
         let tweets = vec![quote_tweet];
         let toots = Vec::new();
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         let sync_toot = &posts.toots[0];
         assert_eq!(
@@ -1051,7 +2748,7 @@ QT test123: Original text"
 
         let tweets = vec![quote_tweet];
         let toots = Vec::new();
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         let sync_toot = &posts.toots[0];
         assert_eq!(
@@ -1095,7 +2792,7 @@ QT test123: Verhalten bei #Hausdurchsuchung"
 
         let tweets = vec![quote_tweet];
         let toots = Vec::new();
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         let sync_toot = &posts.toots[0];
         assert_eq!(
@@ -1159,7 +2856,7 @@ QT test123: Original text"
 
         let tweets = vec![quote_tweet];
         let toots = Vec::new();
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         let sync_toot = &posts.toots[0];
         assert_eq!(
@@ -1172,7 +2869,7 @@ QT test123: Reminder that there's a *very* small group of maintainers on SQLite
         // Also test that a shortened toot is detected as equal.
         let mut status = get_mastodon_status();
         status.content = sync_toot.text.clone();
-        let posts = determine_posts(&vec![status], &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&vec![status], &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
         assert!(posts.toots.is_empty());
         assert!(posts.tweets.is_empty());
     }
@@ -1194,7 +2891,7 @@ QT test123: Reminder that there's a *very* small group of maintainers on SQLite
         let mut options = DEFAULT_SYNC_OPTIONS.clone();
         options.sync_retweets = false;
 
-        let posts = determine_posts(&toots, &tweets, &options);
+        let posts = determine_posts(&toots, &tweets, &options).unwrap();
         assert!(posts.toots.is_empty());
         assert!(posts.tweets.is_empty());
     }
@@ -1216,7 +2913,7 @@ QT test123: Reminder that there's a *very* small group of maintainers on SQLite
         let mut options = DEFAULT_SYNC_OPTIONS.clone();
         options.sync_retweets = false;
 
-        let posts = determine_posts(&toots, &tweets, &options);
+        let posts = determine_posts(&toots, &tweets, &options).unwrap();
 
         let sync_toot = &posts.toots[0];
 
@@ -1240,9 +2937,129 @@ QT test123: Original text"
         let mut options = DEFAULT_SYNC_OPTIONS.clone();
         options.sync_reblogs = false;
 
-        let posts = determine_posts(&toots, &tweets, &options);
+        let posts = determine_posts(&toots, &tweets, &options).unwrap();
+        assert!(posts.toots.is_empty());
+        assert!(posts.tweets.is_empty());
+    }
+
+    // Test that boosts and retweets of own statuses are skipped when
+    // configured.
+    #[test]
+    fn skip_self_boosts() {
+        let mut original_toot = get_mastodon_status();
+        original_toot.content = "<p>My own toot</p>".to_string();
+        let mut boost = get_mastodon_status();
+        boost.id = "1234".to_string();
+        boost.reblog = Some(Box::new(original_toot.clone()));
+        let mut original_tweet = get_twitter_status();
+        original_tweet.user = Some(Box::new(get_twitter_user()));
+        original_tweet.id = 1230906460160380928;
+        let mut retweet = get_twitter_status();
+        retweet.user = Some(Box::new(get_twitter_user()));
+        retweet.retweeted = Some(true);
+        retweet.retweeted_status = Some(Box::new(original_tweet));
+
+        let mut options = DEFAULT_SYNC_OPTIONS.clone();
+        options.self_boosts_mastodon = SelfBoostMode::Skip;
+        options.self_boosts_twitter = SelfBoostMode::Skip;
+        let posts = determine_posts(&[boost.clone()], &[retweet.clone()], &options).unwrap();
+        assert!(posts.tweets.is_empty());
         assert!(posts.toots.is_empty());
+        assert_eq!(posts.skipped.len(), 2);
+        assert!(posts
+            .skipped
+            .iter()
+            .all(|skipped| skipped.reason == SkipReason::SelfBoost));
+
+        // Boosts of other accounts are still synced.
+        original_toot.account.id = "999".to_string();
+        boost.reblog = Some(Box::new(original_toot));
+        let posts = determine_posts(&[boost], &[], &options).unwrap();
+        assert_eq!(posts.tweets.len(), 1);
+    }
+
+    // Test that retweets are synced as link in the link retweet_sync_mode.
+    #[test]
+    fn retweet_link_mode() {
+        let mut original_tweet = get_twitter_status_media();
+        original_tweet.user = Some(Box::new(get_twitter_user()));
+        original_tweet.id = 1230906460160380928;
+        let mut retweet = get_twitter_status();
+        retweet.user = Some(Box::new(get_twitter_user()));
+        retweet.retweeted = Some(true);
+        retweet.retweeted_status = Some(Box::new(original_tweet));
+
+        let mut options = DEFAULT_SYNC_OPTIONS.clone();
+        options.retweet_sync_mode_twitter = RetweetSyncMode::Link;
+        let tweets = vec![retweet];
+        let posts = determine_posts(&Vec::new(), &tweets, &options).unwrap();
+        assert_eq!(
+            posts.toots[0].text,
+            "RT test123: https://twitter.com/test123/status/1230906460160380928"
+        );
+        assert!(posts.toots[0].attachments.is_empty());
+
+        // The synced link is recognized in both modes.
+        let mut status = get_mastodon_status();
+        status.content = format!("<p>{}</p>", posts.toots[0].text);
+        let toots = vec![status];
+        assert!(determine_posts(&toots, &tweets, &options)
+            .unwrap()
+            .toots
+            .is_empty());
+        assert!(determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS)
+            .unwrap()
+            .toots
+            .is_empty());
+    }
+
+    // Test that boosts are synced as link in the link retweet_sync_mode.
+    #[test]
+    fn boost_link_mode() {
+        let mut original_toot = get_mastodon_status();
+        original_toot.content = "<p>Some example toooot!</p>".to_string();
+        let mut boost = get_mastodon_status();
+        boost.reblog = Some(Box::new(original_toot));
+
+        let mut options = DEFAULT_SYNC_OPTIONS.clone();
+        options.retweet_sync_mode_mastodon = RetweetSyncMode::Link;
+        let toots = vec![boost];
+        let posts = determine_posts(&toots, &Vec::new(), &options).unwrap();
+        assert_eq!(
+            posts.tweets[0].text,
+            "RT example: https://mastodon.social/@example/99009862234659599"
+        );
+
+        let mut tweet = get_twitter_status();
+        tweet.text = posts.tweets[0].text.clone();
+        let posts = determine_posts(&toots, &vec![tweet], &options).unwrap();
+        assert!(posts.tweets.is_empty());
+    }
+
+    // Test that boosts of content from denied instances are not synced.
+    #[test]
+    fn boost_deny_instances() {
+        let mut original_toot = get_mastodon_status();
+        original_toot.account.acct = "example@example.bad".to_string();
+        let mut boost = get_mastodon_status();
+        boost.reblog = Some(Box::new(original_toot.clone()));
+
+        let mut options = DEFAULT_SYNC_OPTIONS.clone();
+        options.boost_deny_instances = vec!["Example.bad".to_string()];
+
+        let posts = determine_posts(&vec![boost.clone()], &Vec::new(), &options).unwrap();
+        assert!(posts.tweets.is_empty());
+
+        // Local accounts are matched by their profile URL.
+        original_toot.account.acct = "example".to_string();
+        boost.reblog = Some(Box::new(original_toot));
+        options.boost_deny_instances = vec!["mastodon.social".to_string()];
+        let posts = determine_posts(&vec![boost.clone()], &Vec::new(), &options).unwrap();
         assert!(posts.tweets.is_empty());
+
+        options.boost_deny_instances = vec!["example.bad".to_string()];
+        let posts = determine_posts(&vec![boost], &Vec::new(), &options).unwrap();
+        assert_eq!(posts.tweets.len(), 1);
     }
 
     // Test tagged posts are sent when hashtag is set
@@ -1254,13 +3071,15 @@ QT test123: Original text"
         tweet.text = "Let's #toot!".to_string();
 
         let mut options = DEFAULT_SYNC_OPTIONS.clone();
-        options.sync_hashtag_twitter = Some("#toot".to_string());
-        options.sync_hashtag_mastodon = Some("#tweet".to_string());
+        options.sync_hashtags_twitter =
+            HashtagFilter::new(vec!["#toot".to_string()], HashtagMatch::Any);
+        options.sync_hashtags_mastodon =
+            HashtagFilter::new(vec!["#tweet".to_string()], HashtagMatch::Any);
 
         let tweets = vec![tweet];
         let toots = vec![status];
 
-        let posts = determine_posts(&toots, &tweets, &options);
+        let posts = determine_posts(&toots, &tweets, &options).unwrap();
         assert!(!posts.toots.is_empty());
         assert!(!posts.tweets.is_empty());
     }
@@ -1274,17 +3093,169 @@ QT test123: Original text"
         tweet.text = "Let's NOT toot!".to_string();
 
         let mut options = DEFAULT_SYNC_OPTIONS.clone();
-        options.sync_hashtag_twitter = Some("#toot".to_string());
-        options.sync_hashtag_mastodon = Some("#tweet".to_string());
+        options.sync_hashtags_twitter =
+            HashtagFilter::new(vec!["#toot".to_string()], HashtagMatch::Any);
+        options.sync_hashtags_mastodon =
+            HashtagFilter::new(vec!["#tweet".to_string()], HashtagMatch::Any);
 
         let tweets = vec![tweet];
         let toots = vec![status];
 
-        let posts = determine_posts(&toots, &tweets, &options);
+        let posts = determine_posts(&toots, &tweets, &options).unwrap();
         assert!(posts.toots.is_empty());
         assert!(posts.tweets.is_empty());
     }
 
+    // Test any and all matching of several hashtags and excluded hashtags.
+    #[test]
+    fn multiple_sync_hashtags() {
+        let hashtags = vec![
+            "#blog".to_string(),
+            "#release".to_string(),
+            "-#private".to_string(),
+            String::new(),
+        ];
+        let any = HashtagFilter::new(hashtags.clone(), HashtagMatch::Any);
+        assert_eq!(any.skip_reason("New #blog post"), None);
+        assert_eq!(any.skip_reason("New #release"), None);
+        assert_eq!(
+            any.skip_reason("Nothing to see"),
+            Some(SkipReason::MissingHashtag)
+        );
+        assert_eq!(
+            any.skip_reason("New #blog post #private"),
+            Some(SkipReason::DeniedHashtag)
+        );
+
+        let all = HashtagFilter::new(hashtags, HashtagMatch::All);
+        assert_eq!(all.skip_reason("New #blog post about the #release"), None);
+        assert_eq!(
+            all.skip_reason("New #blog post"),
+            Some(SkipReason::MissingHashtag)
+        );
+
+        // Only excluded hashtags sync everything else.
+        let deny = HashtagFilter::new(vec!["-#private".to_string()], HashtagMatch::Any);
+        assert_eq!(deny.skip_reason("Hello"), None);
+    }
+
+    // Test that hashtags match case-insensitive and only whole hashtags.
+    #[test]
+    fn sync_hashtag_word_boundary() {
+        let filter = HashtagFilter::new(vec!["#Art".to_string()], HashtagMatch::Any);
+        assert_eq!(filter.skip_reason("My new #art"), None);
+        assert_eq!(filter.skip_reason("#ART, finally"), None);
+        assert_eq!(
+            filter.skip_reason("Follow this #artist"),
+            Some(SkipReason::MissingHashtag)
+        );
+        assert_eq!(
+            filter.skip_reason("See https://example.com/#art and C#art"),
+            Some(SkipReason::MissingHashtag)
+        );
+        assert_eq!(
+            text_hashtags("#Rust, #rust and #Über!"),
+            BTreeSet::from(["rust".to_string(), "über".to_string()])
+        );
+    }
+
+    // Test that the sync hashtag is removed from posts and the posts are
+    // still recognized as synced.
+    #[test]
+    fn remove_sync_hashtag() {
+        let mut status = get_mastodon_status();
+        status.content = "<p>New #Sync release #rust</p>".to_string();
+        let mut tweet = get_twitter_status();
+        tweet.text = "#sync\nHello  world".to_string();
+
+        let mut options = DEFAULT_SYNC_OPTIONS.clone();
+        options.sync_hashtags_mastodon =
+            HashtagFilter::new(vec!["#sync".to_string()], HashtagMatch::Any);
+        options.sync_hashtags_mastodon.remove = true;
+        options.sync_hashtags_twitter = options.sync_hashtags_mastodon.clone();
+        options.tweet_text.removed_hashtags = vec!["sync".to_string()];
+
+        let posts = determine_posts(&vec![status.clone()], &vec![tweet.clone()], &options).unwrap();
+        assert_eq!(posts.tweets[0].text, "New release #rust");
+        assert_eq!(posts.toots[0].text, "Hello world");
+
+        let mut synced_tweet = get_twitter_status();
+        synced_tweet.text = posts.tweets[0].text.clone();
+        let mut synced_status = get_mastodon_status();
+        synced_status.content = posts.toots[0].text.clone();
+        let posts = determine_posts(
+            &vec![synced_status, status],
+            &vec![synced_tweet, tweet],
+            &options,
+        )
+        .unwrap();
+        assert!(posts.tweets.is_empty());
+        assert!(posts.toots.is_empty());
+    }
+
+    // Test that empty alt texts are not used as media description.
+    #[test]
+    fn media_description() {
+        let mut media = NewMedia {
+            attachment_url: "https://example.com/animation.gif".to_string(),
+            alt_text: Some("A dancing cat".to_string()),
+            focus: None,
+        };
+        assert_eq!(media.description(), Some("A dancing cat"));
+        media.alt_text = Some(" \n".to_string());
+        assert_eq!(media.description(), None);
+        media.alt_text = None;
+        assert_eq!(media.description(), None);
+    }
+
+    // Test that statuses and replies with media without alt text are skipped.
+    #[test]
+    fn require_alt_text_skip() {
+        let media = |alt_text: Option<&str>| NewMedia {
+            attachment_url: "https://example.com/image.jpg".to_string(),
+            alt_text: alt_text.map(str::to_string),
+            focus: None,
+        };
+        let status = |id: u64, attachments: Vec<NewMedia>, replies: Vec<NewStatus>| NewStatus {
+            attachments,
+            replies,
+            ..new_status(id, &format!("Status {id}"))
+        };
+        let mut updates = StatusUpdates {
+            tweets: vec![
+                status(
+                    1,
+                    vec![media(Some("A cat"))],
+                    vec![status(
+                        2,
+                        vec![media(Some(" "))],
+                        vec![status(3, Vec::new(), Vec::new())],
+                    )],
+                ),
+                status(4, vec![media(None)], Vec::new()),
+            ],
+            toots: vec![status(5, vec![media(None)], Vec::new())],
+            ..StatusUpdates::default()
+        };
+
+        updates.require_alt_text(AltTextPolicy::Skip, AltTextPolicy::Warn);
+        assert_eq!(updates.tweets.len(), 1);
+        assert!(updates.tweets[0].replies.is_empty());
+        assert_eq!(updates.toots.len(), 1);
+        let skipped: Vec<_> = updates
+            .skipped
+            .iter()
+            .map(|s| (s.original_id.clone(), s.reason))
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                (StatusId::Number(2), SkipReason::MissingAltText),
+                (StatusId::Number(4), SkipReason::MissingAltText)
+            ]
+        );
+    }
+
     // Test that a retweet of a quote tweet also includes the quoted text.
     #[test]
     fn retweet_quote_tweet() {
@@ -1319,7 +3290,7 @@ QT test123: Original text"
 
         let tweets = vec![retweet];
         let toots = Vec::new();
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         let sync_toot = &posts.toots[0];
         assert_eq!(
@@ -1337,7 +3308,7 @@ QT test123: Original text"
         status.in_reply_to_id = Some("1234".to_string());
         let toots = vec![status];
 
-        let posts = determine_posts(&toots, &Vec::new(), &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &Vec::new(), &DEFAULT_SYNC_OPTIONS).unwrap();
         assert!(posts.toots.is_empty());
         assert!(posts.tweets.is_empty());
     }
@@ -1359,7 +3330,8 @@ QT test123: Original text"
             &vec![toot1, toot2],
             &vec![tweet1, tweet2],
             &DEFAULT_SYNC_OPTIONS,
-        );
+        )
+        .unwrap();
         assert_eq!(
             vec!["tweet #2", "tweet #1"],
             posts
@@ -1378,23 +3350,155 @@ QT test123: Original text"
         );
     }
 
+    // Test that only the oldest posts are synced when a limit is set.
+    #[test]
+    fn post_limit() {
+        let mut toot1 = get_mastodon_status();
+        toot1.content = "toot #1".to_string();
+        let mut toot2 = get_mastodon_status();
+        toot2.content = "toot #2".to_string();
+
+        let mut posts =
+            determine_posts(&vec![toot2, toot1], &Vec::new(), &DEFAULT_SYNC_OPTIONS).unwrap();
+        let removed = posts.limit(Some(1), None);
+        assert_eq!(posts.tweets.len(), 1);
+        assert_eq!(posts.tweets[0].text, "toot #1");
+        assert_eq!(removed.tweets.len(), 1);
+        assert_eq!(removed.tweets[0].text, "toot #2");
+    }
+
     // Test that long image alt text on Mastodon is shortened to the Twitter
     // 1000 character limit.
     #[test]
     fn tweet_alt_text_length() {
         let mut toot = get_mastodon_status_media();
         toot.media_attachments[0].description = Some("a".repeat(1_001));
-        let posts = determine_posts(&vec![toot], &Vec::new(), &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&vec![toot], &Vec::new(), &DEFAULT_SYNC_OPTIONS).unwrap();
 
         let tweet = &posts.tweets[0];
         assert_eq!(tweet.attachments[0].alt_text, Some("a".repeat(1_000)));
+
+        // The family emoji consists of 5 characters, it is not split.
+        let alt_text = format!("{}👨‍👩‍👧", "a".repeat(997));
+        assert_eq!(
+            truncate_alt_text(Some(alt_text.clone()), TWITTER_ALT_TEXT_LIMIT),
+            Some("a".repeat(997))
+        );
+        assert_eq!(
+            truncate_alt_text(Some(alt_text.clone()), MASTODON_ALT_TEXT_LIMIT),
+            Some(alt_text)
+        );
+    }
+
+    // Test that status updates survive a JSON round trip and that other
+    // schema versions are rejected.
+    #[test]
+    fn status_updates_json() {
+        let statuses = vec![get_mastodon_status_media()];
+        let posts = determine_posts(&statuses, &Vec::new(), &DEFAULT_SYNC_OPTIONS).unwrap();
+        let json = posts.to_json().unwrap();
+        assert!(json.contains("\"schema_version\": 1"));
+
+        let parsed = StatusUpdates::from_json(&json).unwrap();
+        assert_eq!(parsed.tweets[0].text, "test image");
+        assert_eq!(
+            parsed.tweets[0].attachments[0].alt_text,
+            Some("Test image from a TV screen".to_string())
+        );
+
+        let json = json.replace("\"schema_version\": 1", "\"schema_version\": 999");
+        assert!(StatusUpdates::from_json(&json).is_err());
+    }
+
+    // Test that servers with non-numeric IDs like GoToSocial are supported and
+    // numeric IDs stay JSON numbers.
+    #[test]
+    fn text_status_ids() {
+        let mut status = get_mastodon_status();
+        status.id = "01GQ8N4TWNFPZHBKTE6X9JBWCP".to_string();
+        let posts = determine_posts(&vec![status], &Vec::new(), &DEFAULT_SYNC_OPTIONS).unwrap();
+        assert_eq!(
+            posts.tweets[0].original_id,
+            StatusId::Text("01GQ8N4TWNFPZHBKTE6X9JBWCP".to_string())
+        );
+
+        let json = posts.to_json().unwrap();
+        assert!(json.contains("\"original_id\": \"01GQ8N4TWNFPZHBKTE6X9JBWCP\""));
+        let parsed = StatusUpdates::from_json(&json).unwrap();
+        assert_eq!(parsed.tweets[0].original_id, posts.tweets[0].original_id);
+
+        assert_eq!(
+            serde_json::to_string(&StatusId::from("123")).unwrap(),
+            "123"
+        );
+        assert_eq!(
+            serde_json::from_str::<StatusId>("\"123\"").unwrap(),
+            StatusId::Number(123)
+        );
+    }
+
+    // Test that a broken post cache file is moved away.
+    #[test]
+    fn broken_post_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_file = dir.path().join("post_cache.json");
+        let cache_file = cache_file.to_str().unwrap();
+        fs::write(cache_file, "[\"broken").unwrap();
+
+        assert!(read_post_cache(cache_file).is_none());
+        assert!(fs::metadata(format!("{cache_file}.corrupt")).is_ok());
+        assert_eq!(read_post_cache(cache_file), Some(PostCache::default()));
+
+        let mut tweet = get_twitter_status();
+        tweet.text = "You &amp; me!".to_string();
+        let cache = rebuild_post_cache(&Vec::new(), &vec![tweet], &DEFAULT_SYNC_OPTIONS.tweet_text);
+        let status = new_status(1, "You & me!");
+        assert!(cache.contains(Platform::Twitter, &status));
+        assert!(!cache.contains(Platform::Mastodon, &status));
+    }
+
+    // Test that the post cache matches by text hash or source ID and forgets
+    // old entries.
+    #[test]
+    fn post_cache_entries() {
+        let mut status = new_status(5, "Hello  World");
+        let mut cache = PostCache::default();
+        cache.insert(Platform::Twitter, &status);
+
+        status.text = "hello world".to_string();
+        assert!(cache.contains(Platform::Twitter, &status));
+        assert!(!cache.contains(Platform::Mastodon, &status));
+        // A changed text from the same source status was posted already.
+        status.text = "Hello World, new signature".to_string();
+        assert!(cache.contains(Platform::Twitter, &status));
+        status.original_id = StatusId::Number(6);
+        assert!(!cache.contains(Platform::Twitter, &status));
+
+        cache.prune(Utc::now().timestamp() + 31 * 24 * 60 * 60);
+        assert!(cache.is_empty());
+    }
+
+    // Test that post caches of older versions with texts are still read.
+    #[test]
+    fn old_post_cache_format() {
+        let cache = PostCache::from_json("[\"Old post\"]").unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.entries[0].platform, None);
+        let status = new_status(1, "Old post");
+        assert!(cache.contains(Platform::Mastodon, &status));
+    }
+
+    /// A new status with a numeric source ID, shared by the tests of the
+    /// other modules.
+    pub fn new_status(original_id: u64, text: &str) -> NewStatus {
+        NewStatus::new(text, StatusId::Number(original_id))
     }
 
     pub fn get_mastodon_status() -> Status {
         read_mastodon_status("src/mastodon_status.json")
     }
 
-    fn get_mastodon_status_media() -> Status {
+    pub fn get_mastodon_status_media() -> Status {
         read_mastodon_status("src/mastodon_attach.json")
     }
 
@@ -1,14 +1,39 @@
+use crate::id_map::IdMap;
 use crate::sync::*;
+use anyhow::{Context, Result};
 use egg_mode::tweet::Tweet;
 use elefren::entities::status::Status;
+use regex::Regex;
+use std::mem;
 
 // A reply to a post that has the ID to the parent post.
-#[derive(Debug)]
-struct Reply {
-    pub id: u64,
+#[derive(Debug, Clone)]
+pub(crate) struct Reply {
+    pub id: StatusId,
     pub text: String,
     pub attachments: Vec<NewMedia>,
-    pub in_reply_to_id: u64,
+    pub in_reply_to_id: StatusId,
+    // Further parts of a reply that was split because it is too long.
+    pub parts: Vec<String>,
+}
+
+impl Reply {
+    // Returns the status to post. The further parts follow as a chain of
+    // replies with the same original ID.
+    fn new_status(&self, in_reply_to_id: Option<StatusId>) -> NewStatus {
+        let parts = self.parts.iter().rev().fold(None, |next, part: &String| {
+            Some(NewStatus {
+                replies: Vec::from_iter(next),
+                ..NewStatus::new(part.clone(), self.id.clone())
+            })
+        });
+        NewStatus {
+            attachments: self.attachments.clone(),
+            replies: Vec::from_iter(parts),
+            in_reply_to_id,
+            ..NewStatus::new(self.text.clone(), self.id.clone())
+        }
+    }
 }
 
 // Check if there are thread replies that we want to sync.
@@ -17,7 +42,7 @@ pub fn determine_thread_replies(
     twitter_statuses: &[Tweet],
     options: &SyncOptions,
     sync_statuses: &mut StatusUpdates,
-) {
+) -> Result<()> {
     // Collect replies in reverse order to post the oldest first.
     let mut twitter_replies = Vec::new();
     'tweets: for tweet in twitter_statuses {
@@ -27,65 +52,150 @@ pub fn determine_thread_replies(
                 != &tweet
                     .user
                     .as_ref()
-                    .unwrap_or_else(|| panic!("Twitter user missing on tweet {}", tweet.id))
+                    .with_context(|| format!("Twitter user missing on tweet {}", tweet.id))?
                     .id
             {
+                if !options.sync_replies_to_others_twitter {
+                    sync_statuses.skip(
+                        Platform::Twitter,
+                        tweet.id.into(),
+                        &tweet.text,
+                        SkipReason::ReplyToOther,
+                    );
+                    continue;
+                }
+                let parent_url = format!(
+                    "https://twitter.com/{}/status/{}",
+                    tweet
+                        .in_reply_to_screen_name
+                        .as_deref()
+                        .unwrap_or("twitter"),
+                    tweet.in_reply_to_status_id.with_context(|| format!(
+                        "Twitter reply ID missing on tweet {}",
+                        tweet.id
+                    ))?
+                );
+                let text = reply_to_other_text(
+                    &parent_url,
+                    &tweet_unshorten_decode(tweet, &options.tweet_text)?,
+                );
+                let post = options.sync_hashtags_twitter.remove_from(&text);
+                // If the standalone post already exists we are synced.
+                for toot in mastodon_statuses {
+                    if unify_post_content(mastodon_toot_get_text(toot))
+                        == unify_post_content(post.clone())
+                    {
+                        break 'tweets;
+                    }
+                }
+                if let Some(reason) = options.sync_hashtags_twitter.skip_reason(&text) {
+                    sync_statuses.skip(Platform::Twitter, tweet.id.into(), &text, reason);
+                    continue;
+                }
+                sync_statuses.toots.push(NewStatus {
+                    attachments: tweet_get_attachments(tweet),
+                    ..NewStatus::new(post, tweet.id.into())
+                });
                 continue;
             }
 
             for toot in mastodon_statuses {
                 // If the tweet already exists we can stop here and know that we are
                 // synced.
-                if toot_and_tweet_are_equal(toot, tweet) {
+                if toot_and_tweet_are_equal(toot, tweet, &options.tweet_text)? {
                     break 'tweets;
                 }
             }
 
             // The tweet is not on Mastodon yet, check if we should post it.
             // Fetch the tweet text into a String object
-            let decoded_tweet = tweet_unshorten_decode(tweet);
+            let decoded_tweet = tweet_unshorten_decode(tweet, &options.tweet_text)?;
 
             // Check if hashtag filtering is enabled and if the tweet matches.
-            if let Some(sync_hashtag) = &options.sync_hashtag_twitter {
-                if !sync_hashtag.is_empty() && !decoded_tweet.contains(sync_hashtag) {
-                    // Skip if a sync hashtag is set and the string doesn't match.
-                    continue;
-                }
+            if let Some(reason) = options.sync_hashtags_twitter.skip_reason(&decoded_tweet) {
+                sync_statuses.skip(Platform::Twitter, tweet.id.into(), &decoded_tweet, reason);
+                continue;
             }
 
             // Insert this reply in the beginning to reverse order.
             twitter_replies.insert(
                 0,
                 Reply {
-                    id: tweet.id,
-                    text: decoded_tweet,
+                    id: tweet.id.into(),
+                    text: options.sync_hashtags_twitter.remove_from(&decoded_tweet),
                     attachments: tweet_get_attachments(tweet),
-                    in_reply_to_id: tweet.in_reply_to_status_id.unwrap_or_else(|| {
-                        panic!("Twitter reply ID missing on tweet {}", tweet.id)
-                    }),
+                    in_reply_to_id: tweet
+                        .in_reply_to_status_id
+                        .with_context(|| format!("Twitter reply ID missing on tweet {}", tweet.id))?
+                        .into(),
+                    parts: Vec::new(),
                 },
             );
         }
     }
     insert_twitter_replies(
         &mut sync_statuses.toots,
+        &mut sync_statuses.unresolved_toots,
         twitter_replies,
         twitter_statuses,
         mastodon_statuses,
-    );
+        options,
+    )?;
 
     let mut mastodon_replies = Vec::new();
     'toots: for toot in mastodon_statuses {
         // Check if this is a reply to a toot of this user.
         if let Some(user_id) = &toot.in_reply_to_account_id {
             if user_id != &toot.account.id {
+                if !options.sync_replies_to_others_mastodon {
+                    sync_statuses.skip(
+                        Platform::Mastodon,
+                        StatusId::from(toot.id.as_str()),
+                        &mastodon_toot_get_text(toot),
+                        SkipReason::ReplyToOther,
+                    );
+                    continue;
+                }
+                let in_reply_to_id = toot
+                    .in_reply_to_id
+                    .as_ref()
+                    .with_context(|| format!("Mastodon reply ID missing on status {}", toot.id))?;
+                // The replied status might be on another instance, the web
+                // interface of the own instance can show it.
+                let parent_url = format!("{}/web/statuses/{in_reply_to_id}", url_origin(&toot.uri));
+                let fulltext = reply_to_other_text(&parent_url, &mastodon_toot_get_text(toot));
+                let post = tweet_shorten(
+                    &options.sync_hashtags_mastodon.remove_from(&fulltext),
+                    &toot.url,
+                    &options.tweet_text.shorten,
+                );
+                for tweet in twitter_statuses {
+                    if unify_post_content(tweet_unshorten_decode(tweet, &options.tweet_text)?)
+                        == unify_post_content(post.clone())
+                    {
+                        break 'toots;
+                    }
+                }
+                if let Some(reason) = options.sync_hashtags_mastodon.skip_reason(&fulltext) {
+                    sync_statuses.skip(
+                        Platform::Mastodon,
+                        StatusId::from(toot.id.as_str()),
+                        &fulltext,
+                        reason,
+                    );
+                    continue;
+                }
+                sync_statuses.tweets.push(NewStatus {
+                    attachments: toot_get_attachments(toot),
+                    ..NewStatus::new(post, StatusId::from(toot.id.as_str()))
+                });
                 continue;
             }
 
             for tweet in twitter_statuses {
                 // If the toot already exists we can stop here and know that we are
                 // synced.
-                if toot_and_tweet_are_equal(toot, tweet) {
+                if toot_and_tweet_are_equal(toot, tweet, &options.tweet_text)? {
                     break 'toots;
                 }
             }
@@ -94,42 +204,49 @@ pub fn determine_thread_replies(
 
             // The toot is not on Twitter yet, check if we should post it.
             // Check if hashtag filtering is enabled and if the tweet matches.
-            if let Some(sync_hashtag) = &options.sync_hashtag_mastodon {
-                if !sync_hashtag.is_empty() && !fulltext.contains(sync_hashtag) {
-                    // Skip if a sync hashtag is set and the string doesn't match.
-                    continue;
-                }
+            if let Some(reason) = options.sync_hashtags_mastodon.skip_reason(&fulltext) {
+                sync_statuses.skip(
+                    Platform::Mastodon,
+                    StatusId::from(toot.id.as_str()),
+                    &fulltext,
+                    reason,
+                );
+                continue;
             }
 
             let in_reply_to_id = toot
                 .in_reply_to_id
                 .as_ref()
-                .unwrap_or_else(|| panic!("Mastodon reply ID missing on status: {}", toot.id));
-            let post = tweet_shorten(&fulltext, &toot.url);
+                .with_context(|| format!("Mastodon reply ID missing on status {}", toot.id))?;
+            // Long replies are split instead of shortened, so that the rest of
+            // the thread does not lose its context.
+            let mut parts = tweet_split(
+                &options.sync_hashtags_mastodon.remove_from(&fulltext),
+                &options.tweet_text.shorten,
+            );
+            let post = parts.remove(0);
 
             // Insert this reply in the beginning to reverse order.
             mastodon_replies.insert(
                 0,
                 Reply {
-                    id: toot
-                        .id
-                        .parse::<u64>()
-                        .unwrap_or_else(|_| panic!("Mastodon status ID is not u64: {}", toot.id)),
+                    id: StatusId::from(toot.id.as_str()),
                     text: post,
                     attachments: toot_get_attachments(toot),
-                    in_reply_to_id: in_reply_to_id.parse::<u64>().unwrap_or_else(|_| {
-                        panic!("Mastodon reply ID is not u64: {in_reply_to_id}")
-                    }),
+                    in_reply_to_id: StatusId::from(in_reply_to_id.as_str()),
+                    parts,
                 },
             );
         }
     }
     insert_mastodon_replies(
         &mut sync_statuses.tweets,
+        &mut sync_statuses.unresolved_tweets,
         mastodon_replies,
         twitter_statuses,
         mastodon_statuses,
-    );
+        options,
+    )
 }
 
 // Insert Twitter replies with the correct Mastodon parent status ID.
@@ -137,10 +254,12 @@ pub fn determine_thread_replies(
 // correctly.
 fn insert_twitter_replies(
     sync_statuses: &mut Vec<NewStatus>,
+    unresolved: &mut Vec<Reply>,
     replies: Vec<Reply>,
     twitter_statuses: &[Tweet],
     mastodon_statuses: &[Status],
-) {
+    options: &SyncOptions,
+) -> Result<()> {
     'reply_loop: for reply in replies {
         // Check new statuses first if it is a reply to that.
         for sync_status in &mut *sync_statuses {
@@ -150,26 +269,22 @@ fn insert_twitter_replies(
         }
         // Check existing statuses if the parent is there.
         for tweet in twitter_statuses {
-            if tweet.id == reply.in_reply_to_id {
+            if reply.in_reply_to_id == StatusId::from(tweet.id) {
                 for toot in mastodon_statuses {
                     // If we get a status with the same text then we assume this
                     // must be the corresponding parent.
-                    if toot_and_tweet_are_equal(toot, tweet) {
-                        sync_statuses.push(NewStatus {
-                            text: reply.text.clone(),
-                            attachments: reply.attachments.clone(),
-                            replies: Vec::new(),
-                            in_reply_to_id: Some(toot.id.parse().unwrap_or_else(|_| {
-                                panic!("Mastodon status ID is not u64: {}", toot.id)
-                            })),
-                            original_id: reply.id,
-                        });
+                    if toot_and_tweet_are_equal(toot, tweet, &options.tweet_text)? {
+                        sync_statuses
+                            .push(reply.new_status(Some(StatusId::from(toot.id.as_str()))));
                         continue 'reply_loop;
                     }
                 }
             }
         }
+        // The parent might have been synced on an earlier run.
+        unresolved.push(reply);
     }
+    Ok(())
 }
 
 // Insert Mastodon replies with the correct Twitter parent status ID.
@@ -177,10 +292,12 @@ fn insert_twitter_replies(
 // correctly.
 fn insert_mastodon_replies(
     sync_statuses: &mut Vec<NewStatus>,
+    unresolved: &mut Vec<Reply>,
     replies: Vec<Reply>,
     twitter_statuses: &[Tweet],
     mastodon_statuses: &[Status],
-) {
+    options: &SyncOptions,
+) -> Result<()> {
     'reply_loop: for reply in replies {
         // Check new statuses first if it is a reply to that.
         for sync_status in &mut *sync_statuses {
@@ -190,23 +307,71 @@ fn insert_mastodon_replies(
         }
         // Check existing statuses if the parent is there.
         for toot in mastodon_statuses {
-            if toot.id == reply.in_reply_to_id.to_string() {
+            if reply.in_reply_to_id == StatusId::from(toot.id.as_str()) {
                 for tweet in twitter_statuses {
                     // If we get a status with the same text then we assume this
                     // must be the corresponding parent.
-                    if toot_and_tweet_are_equal(toot, tweet) {
-                        sync_statuses.push(NewStatus {
-                            text: reply.text.clone(),
-                            attachments: reply.attachments.clone(),
-                            replies: Vec::new(),
-                            in_reply_to_id: Some(tweet.id),
-                            original_id: reply.id,
-                        });
+                    if toot_and_tweet_are_equal(toot, tweet, &options.tweet_text)? {
+                        sync_statuses.push(reply.new_status(Some(tweet.id.into())));
                         continue 'reply_loop;
                     }
                 }
             }
         }
+        // The parent might have been synced on an earlier run.
+        unresolved.push(reply);
+    }
+    Ok(())
+}
+
+/// Attaches thread replies whose parent was not found in the fetched
+/// timelines to the synced parent from the ID map. This way replies that are
+/// written long after their parent still continue the thread. Replies without
+/// synced parent are dropped.
+pub fn resolve_thread_replies(updates: &mut StatusUpdates, id_map: &IdMap) {
+    'twitter_replies: for reply in mem::take(&mut updates.unresolved_toots) {
+        for toot in &mut updates.toots {
+            if insert_reply_on_status(toot, &reply) {
+                continue 'twitter_replies;
+            }
+        }
+        let parent = reply
+            .in_reply_to_id
+            .as_u64()
+            .and_then(|tweet_id| id_map.toot_id(tweet_id));
+        if let Some(parent) = parent {
+            updates.toots.push(reply.new_status(Some(parent.clone())));
+        }
+    }
+    'mastodon_replies: for reply in mem::take(&mut updates.unresolved_tweets) {
+        for tweet in &mut updates.tweets {
+            if insert_reply_on_status(tweet, &reply) {
+                continue 'mastodon_replies;
+            }
+        }
+        if let Some(parent) = id_map.tweet_id(&reply.in_reply_to_id) {
+            updates.tweets.push(reply.new_status(Some(parent.into())));
+        }
+    }
+}
+
+// Text of a standalone post for a reply to another account. The mentions at
+// the beginning are replaced by a link to the replied status and all other
+// mentions are escaped, so that nobody is notified on the other network.
+fn reply_to_other_text(parent_url: &str, text: &str) -> String {
+    let leading_mentions = Regex::new(r"^(?:@\\?[\w.@-]+\s+)+").unwrap();
+    let mention = Regex::new(r"(^|\s)@(\w)").unwrap();
+    let text = leading_mentions.replace(text.trim(), "");
+    let text = mention.replace_all(&text, "${1}@\\${2}");
+    format!("Replying to {parent_url}\n\n{text}")
+}
+
+// Scheme and host of a URL, for example https://mastodon.social.
+fn url_origin(url: &str) -> &str {
+    let host_start = url.find("://").map_or(0, |i| i + 3);
+    match url[host_start..].find('/') {
+        Some(path_start) => &url[..host_start + path_start],
+        None => url,
     }
 }
 
@@ -214,13 +379,7 @@ fn insert_mastodon_replies(
 // replies.
 fn insert_reply_on_status(status: &mut NewStatus, reply: &Reply) -> bool {
     if reply.in_reply_to_id == status.original_id {
-        status.replies.push(NewStatus {
-            text: reply.text.clone(),
-            attachments: reply.attachments.clone(),
-            replies: Vec::new(),
-            in_reply_to_id: None,
-            original_id: reply.id,
-        });
+        last_part(status).replies.push(reply.new_status(None));
         return true;
     }
     for existing_reply in &mut status.replies {
@@ -231,17 +390,69 @@ fn insert_reply_on_status(status: &mut NewStatus, reply: &Reply) -> bool {
     false
 }
 
+// Returns the last part of a split status, replies continue the thread from
+// there.
+fn last_part(status: &mut NewStatus) -> &mut NewStatus {
+    match status
+        .replies
+        .iter()
+        .position(|reply| reply.original_id == status.original_id)
+    {
+        Some(index) => last_part(&mut status.replies[index]),
+        None => status,
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::config::{
+        DirectPostMode, HashtagMatch, RetweetSyncMode, SelfBoostMode, ThreadStrategy,
+    };
     use crate::sync::tests::*;
 
     static DEFAULT_SYNC_OPTIONS: SyncOptions = SyncOptions {
         sync_reblogs: true,
         sync_retweets: true,
-        sync_hashtag_twitter: None,
-        sync_hashtag_mastodon: None,
+        retweet_sync_mode_mastodon: RetweetSyncMode::Copy,
+        retweet_sync_mode_twitter: RetweetSyncMode::Copy,
+        sync_hashtags_twitter: HashtagFilter {
+            required: Vec::new(),
+            denied: Vec::new(),
+            match_mode: HashtagMatch::Any,
+            remove: false,
+        },
+        sync_hashtags_mastodon: HashtagFilter {
+            required: Vec::new(),
+            denied: Vec::new(),
+            match_mode: HashtagMatch::Any,
+            remove: false,
+        },
+        boost_deny_instances: Vec::new(),
+        skip_sources_twitter: Vec::new(),
+        skip_sources_mastodon: Vec::new(),
+        crosspost_patterns_twitter: Vec::new(),
+        crosspost_patterns_mastodon: Vec::new(),
+        sync_replies_to_others_twitter: false,
+        sync_replies_to_others_mastodon: false,
+        max_thread_depth_mastodon: None,
+        max_thread_depth_twitter: None,
+        thread_strategy_twitter: ThreadStrategy::Mirror,
+        thread_strategy_mastodon: ThreadStrategy::Mirror,
+        direct_posts_mastodon: DirectPostMode::Skip,
+        self_boosts_twitter: SelfBoostMode::Copy,
+        self_boosts_mastodon: SelfBoostMode::Copy,
+        link_preview_images_mastodon: false,
+        tweet_text: TweetTextOptions {
+            expand_urls: true,
+            escape_mentions: true,
+            decode_html_entities: true,
+            removed_hashtags: Vec::new(),
+            hashtag_aliases: Vec::new(),
+            short_links: None,
+            shorten: DEFAULT_SHORTEN_OPTIONS,
+        },
     };
 
     // Tests that a reply to your own tweet is synced as thread reply to
@@ -259,7 +470,7 @@ mod tests {
 
         let tweets = vec![reply_tweet, original_tweet];
         let toots = Vec::new();
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         assert_eq!(posts.toots.len(), 1);
         let sync_toot = &posts.toots[0];
@@ -280,7 +491,7 @@ mod tests {
 
         let tweets = Vec::new();
         let toots = vec![reply_toot, original_toot];
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         assert_eq!(posts.tweets.len(), 1);
         let sync_tweet = &posts.tweets[0];
@@ -288,6 +499,210 @@ mod tests {
         assert_eq!(sync_tweet.replies[0].text, "Reply");
     }
 
+    // Tests that media attachments of Mastodon thread replies are synced to
+    // the Twitter thread.
+    #[test]
+    fn sync_mastodon_thread_reply_media() {
+        let mut original_toot = get_mastodon_status();
+        original_toot.content = "Original".to_string();
+        let mut reply_toot = get_mastodon_status_media();
+        reply_toot.id = "2".to_string();
+        reply_toot.account = original_toot.account.clone();
+        reply_toot.in_reply_to_account_id = Some(original_toot.account.id.clone());
+        reply_toot.in_reply_to_id = Some(original_toot.id.clone());
+
+        let tweets = Vec::new();
+        let toots = vec![reply_toot.clone(), original_toot];
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
+
+        assert_eq!(posts.tweets.len(), 1);
+        let reply = &posts.tweets[0].replies[0];
+        assert_eq!(reply.original_id, StatusId::Number(2));
+        assert_eq!(
+            reply.attachments[0].attachment_url,
+            reply_toot.media_attachments[0].url
+        );
+    }
+
+    // Tests that replies to a parent outside of the fetched timelines are
+    // attached to the synced parent from the ID map.
+    #[test]
+    fn resolve_replies_with_id_map() {
+        let mut reply_tweet = get_twitter_status();
+        reply_tweet.id = 20;
+        reply_tweet.user = Some(Box::new(get_twitter_user()));
+        reply_tweet.text = "Late reply".to_string();
+        reply_tweet.in_reply_to_user_id = Some(reply_tweet.user.clone().unwrap().id);
+        reply_tweet.in_reply_to_status_id = Some(10);
+        let mut reply2_tweet = reply_tweet.clone();
+        reply2_tweet.id = 21;
+        reply2_tweet.text = "Even later reply".to_string();
+        reply2_tweet.in_reply_to_status_id = Some(20);
+        let mut reply_toot = get_mastodon_status();
+        reply_toot.id = "30".to_string();
+        reply_toot.content = "Late toot reply".to_string();
+        reply_toot.in_reply_to_account_id = Some(reply_toot.account.id.clone());
+        reply_toot.in_reply_to_id = Some("5".to_string());
+
+        let tweets = vec![reply2_tweet, reply_tweet];
+        let toots = vec![reply_toot];
+        let mut posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
+        assert!(posts.toots.is_empty());
+        assert!(posts.tweets.is_empty());
+
+        let mut id_map = IdMap::default();
+        id_map.insert(StatusId::Number(1), 10);
+        id_map.insert(StatusId::Number(5), 50);
+        resolve_thread_replies(&mut posts, &id_map);
+        assert_eq!(posts.toots.len(), 1);
+        assert_eq!(posts.toots[0].in_reply_to_id, Some(StatusId::Number(1)));
+        assert_eq!(posts.toots[0].replies[0].text, "Even later reply");
+        assert_eq!(posts.tweets.len(), 1);
+        assert_eq!(posts.tweets[0].in_reply_to_id, Some(StatusId::Number(50)));
+        assert!(posts.unresolved_toots.is_empty());
+    }
+
+    // Tests that long thread replies are split and the thread continues after
+    // the last part.
+    #[test]
+    fn split_long_mastodon_replies() {
+        let mut long_toot = get_mastodon_status();
+        long_toot.id = "30".to_string();
+        long_toot.content = format!("<p>{}2/3</p>", "A sentence with a few words. ".repeat(12));
+        long_toot.in_reply_to_account_id = Some(long_toot.account.id.clone());
+        long_toot.in_reply_to_id = Some("5".to_string());
+        let mut next_toot = long_toot.clone();
+        next_toot.id = "31".to_string();
+        next_toot.content = "<p>The end 3/3</p>".to_string();
+        next_toot.in_reply_to_id = Some("30".to_string());
+
+        let toots = vec![next_toot, long_toot];
+        let mut posts = determine_posts(&toots, &[], &DEFAULT_SYNC_OPTIONS).unwrap();
+        let mut id_map = IdMap::default();
+        id_map.insert(StatusId::Number(5), 50);
+        resolve_thread_replies(&mut posts, &id_map);
+
+        assert_eq!(posts.tweets.len(), 1);
+        let first = &posts.tweets[0];
+        assert_eq!(first.in_reply_to_id, Some(StatusId::Number(50)));
+        assert!(first.text.ends_with("… 2/3"));
+        let second = &first.replies[0];
+        assert_eq!(second.original_id, StatusId::Number(30));
+        assert!(second.text.ends_with("words."));
+        assert_eq!(second.replies[0].text, "The end 3/3");
+    }
+
+    // Tests that thread replies deeper than the configured limit are left for
+    // a later run.
+    #[test]
+    fn thread_depth_limit() {
+        let mut original_toot = get_mastodon_status();
+        original_toot.id = "1".to_string();
+        original_toot.content = "Original".to_string();
+        let mut reply_toot = get_mastodon_status();
+        reply_toot.id = "2".to_string();
+        reply_toot.content = "Reply".to_string();
+        reply_toot.in_reply_to_account_id = Some(original_toot.account.id.clone());
+        reply_toot.in_reply_to_id = Some(original_toot.id.clone());
+        let mut second_reply_toot = get_mastodon_status();
+        second_reply_toot.id = "3".to_string();
+        second_reply_toot.content = "Second reply".to_string();
+        second_reply_toot.in_reply_to_account_id = Some(original_toot.account.id.clone());
+        second_reply_toot.in_reply_to_id = Some(reply_toot.id.clone());
+
+        let toots = vec![second_reply_toot, reply_toot, original_toot];
+        let mut options = DEFAULT_SYNC_OPTIONS.clone();
+        options.max_thread_depth_mastodon = Some(1);
+        let posts = determine_posts(&toots, &Vec::new(), &options).unwrap();
+
+        assert_eq!(posts.tweets.len(), 1);
+        let sync_tweet = &posts.tweets[0];
+        assert_eq!(sync_tweet.replies.len(), 1);
+        assert_eq!(sync_tweet.replies[0].text, "Reply");
+        assert!(sync_tweet.replies[0].replies.is_empty());
+    }
+
+    // Tests that only the first tweet of a thread is synced with a link when
+    // the thread strategy says so.
+    #[test]
+    fn thread_first_post_with_link() {
+        let mut original_tweet = get_twitter_status();
+        original_tweet.user = Some(Box::new(get_twitter_user()));
+        original_tweet.text = "Original".to_string();
+        let mut reply_tweet = get_twitter_status();
+        reply_tweet.id = original_tweet.id + 1;
+        reply_tweet.user = Some(Box::new(get_twitter_user()));
+        reply_tweet.text = "Reply".to_string();
+        reply_tweet.in_reply_to_user_id = Some(original_tweet.user.clone().unwrap().id);
+        reply_tweet.in_reply_to_status_id = Some(original_tweet.id);
+
+        let mut options = DEFAULT_SYNC_OPTIONS.clone();
+        options.thread_strategy_twitter = ThreadStrategy::FirstPostWithLink;
+        let tweets = vec![reply_tweet, original_tweet.clone()];
+        let posts = determine_posts(&Vec::new(), &tweets, &options).unwrap();
+
+        assert_eq!(posts.toots.len(), 1);
+        let sync_toot = &posts.toots[0];
+        assert_eq!(
+            sync_toot.text,
+            format!(
+                "Original\n\n(thread continues) https://twitter.com/twitter/status/{}",
+                original_tweet.id
+            )
+        );
+        assert!(sync_toot.replies.is_empty());
+
+        // The synced toot is considered equal to the first tweet.
+        let mut status = get_mastodon_status();
+        status.content = sync_toot.text.clone();
+        assert!(toot_and_tweet_are_equal(
+            &status,
+            &original_tweet,
+            &DEFAULT_SYNC_OPTIONS.tweet_text
+        )
+        .unwrap());
+    }
+
+    // Tests that a Mastodon thread is joined into one tweet if it fits and
+    // linked otherwise.
+    #[test]
+    fn thread_collapse() {
+        let mut original_toot = get_mastodon_status();
+        original_toot.id = "1".to_string();
+        original_toot.content = "Original thread".to_string();
+        original_toot.url = Some("https://mastodon.social/@example/1".to_string());
+        let mut reply_toot = get_mastodon_status();
+        reply_toot.id = "2".to_string();
+        reply_toot.content = "Reply".to_string();
+        reply_toot.in_reply_to_account_id = Some(original_toot.account.id.clone());
+        reply_toot.in_reply_to_id = Some(original_toot.id.clone());
+
+        let mut options = DEFAULT_SYNC_OPTIONS.clone();
+        options.thread_strategy_mastodon = ThreadStrategy::Collapse;
+        let toots = vec![reply_toot.clone(), original_toot.clone()];
+        let posts = determine_posts(&toots, &Vec::new(), &options).unwrap();
+        assert_eq!(posts.tweets.len(), 1);
+        assert_eq!(posts.tweets[0].text, "Original thread\n\n🧵\n\nReply");
+        assert!(posts.tweets[0].replies.is_empty());
+
+        // The collapsed tweet is considered equal to the first toot.
+        let mut tweet = get_twitter_status();
+        tweet.text = posts.tweets[0].text.clone();
+        assert!(
+            toot_and_tweet_are_equal(&original_toot, &tweet, &DEFAULT_SYNC_OPTIONS.tweet_text)
+                .unwrap()
+        );
+
+        reply_toot.content = "A sentence with a few words. ".repeat(9);
+        let toots = vec![reply_toot, original_toot];
+        let posts = determine_posts(&toots, &Vec::new(), &options).unwrap();
+        assert_eq!(
+            posts.tweets[0].text,
+            "Original thread\n\n(thread continues) https://mastodon.social/@example/1"
+        );
+        assert!(posts.tweets[0].replies.is_empty());
+    }
+
     // Tests that a reply for a tweet that has already been synced is also
     // synced on a subsequent run.
     #[test]
@@ -306,15 +721,15 @@ mod tests {
 
         let tweets = vec![reply_tweet, original_tweet];
         let toots = vec![status];
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         assert_eq!(posts.toots.len(), 1);
         let sync_toot = &posts.toots[0];
         assert_eq!(sync_toot.text, "Reply");
         assert!(sync_toot.in_reply_to_id.is_some());
         assert_eq!(
-            sync_toot.in_reply_to_id.unwrap(),
-            toots[0].id.parse::<u64>().unwrap()
+            sync_toot.in_reply_to_id,
+            Some(StatusId::from(toots[0].id.as_str()))
         );
         assert!(sync_toot.replies.is_empty());
     }
@@ -335,13 +750,13 @@ mod tests {
 
         let tweets = vec![tweet];
         let toots = vec![reply_toot, original_toot];
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         assert_eq!(posts.tweets.len(), 1);
         let sync_tweet = &posts.tweets[0];
         assert_eq!(sync_tweet.text, "Reply");
         assert!(sync_tweet.in_reply_to_id.is_some());
-        assert_eq!(sync_tweet.in_reply_to_id.unwrap(), tweets[0].id);
+        assert_eq!(sync_tweet.in_reply_to_id, Some(tweets[0].id.into()));
         assert!(sync_tweet.replies.is_empty());
     }
 
@@ -377,15 +792,15 @@ mod tests {
 
         let tweets = vec![reply3_tweet, reply2_tweet, reply1_tweet, original_tweet];
         let toots = vec![status];
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         assert_eq!(posts.toots.len(), 1);
         let reply1_toot = &posts.toots[0];
         assert_eq!(reply1_toot.text, "Reply1");
         assert!(reply1_toot.in_reply_to_id.is_some());
         assert_eq!(
-            reply1_toot.in_reply_to_id.unwrap(),
-            toots[0].id.parse::<u64>().unwrap()
+            reply1_toot.in_reply_to_id,
+            Some(StatusId::from(toots[0].id.as_str()))
         );
         assert_eq!(reply1_toot.replies.len(), 1);
 
@@ -428,13 +843,13 @@ mod tests {
 
         let tweets = vec![tweet];
         let toots = vec![reply3_toot, reply2_toot, reply1_toot, original_toot];
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         assert_eq!(posts.tweets.len(), 1);
         let reply1_tweet = &posts.tweets[0];
         assert_eq!(reply1_tweet.text, "Reply1");
         assert!(reply1_tweet.in_reply_to_id.is_some());
-        assert_eq!(reply1_tweet.in_reply_to_id.unwrap(), tweets[0].id);
+        assert_eq!(reply1_tweet.in_reply_to_id, Some(tweets[0].id.into()));
         assert_eq!(reply1_tweet.replies.len(), 1);
 
         let reply2_tweet = &reply1_tweet.replies[0];
@@ -459,7 +874,7 @@ mod tests {
 
         let tweets = vec![reply_tweet];
         let toots = Vec::new();
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         assert!(posts.toots.is_empty());
     }
@@ -474,11 +889,63 @@ mod tests {
 
         let tweets = Vec::new();
         let toots = vec![reply_toot];
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         assert!(posts.toots.is_empty());
     }
 
+    // Test that a reply to another user is synced as standalone toot if enabled.
+    #[test]
+    fn sync_twitter_replies_to_others() {
+        let mut reply_tweet = get_twitter_status();
+        reply_tweet.user = Some(Box::new(get_twitter_user()));
+        reply_tweet.text = "@other Reply to @someone".to_string();
+        reply_tweet.in_reply_to_user_id = Some(999);
+        reply_tweet.in_reply_to_status_id = Some(999);
+        reply_tweet.in_reply_to_screen_name = Some("other".to_string());
+
+        let mut options = DEFAULT_SYNC_OPTIONS.clone();
+        options.sync_replies_to_others_twitter = true;
+        let tweets = vec![reply_tweet];
+        let toots = Vec::new();
+        let posts = determine_posts(&toots, &tweets, &options).unwrap();
+
+        assert_eq!(posts.toots.len(), 1);
+        assert_eq!(
+            posts.toots[0].text,
+            "Replying to https://twitter.com/other/status/999\n\nReply to @\\someone"
+        );
+        assert!(posts.toots[0].in_reply_to_id.is_none());
+
+        // Already synced replies are not posted again.
+        let mut status = get_mastodon_status();
+        status.content = posts.toots[0].text.clone();
+        let posts = determine_posts(&[status], &tweets, &options).unwrap();
+        assert!(posts.toots.is_empty());
+    }
+
+    // Test that a reply to another user is synced as standalone tweet if enabled.
+    #[test]
+    fn sync_mastodon_replies_to_others() {
+        let mut reply_toot = get_mastodon_status();
+        reply_toot.content = "@other Reply".to_string();
+        reply_toot.in_reply_to_account_id = Some("999".to_string());
+        reply_toot.in_reply_to_id = Some("999".to_string());
+
+        let mut options = DEFAULT_SYNC_OPTIONS.clone();
+        options.sync_replies_to_others_mastodon = true;
+        let tweets = Vec::new();
+        let toots = vec![reply_toot];
+        let posts = determine_posts(&toots, &tweets, &options).unwrap();
+
+        assert_eq!(posts.tweets.len(), 1);
+        assert_eq!(
+            posts.tweets[0].text,
+            "Replying to https://mastodon.social/web/statuses/999\n\nReply"
+        );
+        assert!(posts.tweets[0].in_reply_to_id.is_none());
+    }
+
     // Tests that replies to oneself are not synced if they are in a
     // conversation with other users.
     #[test]
@@ -508,7 +975,7 @@ mod tests {
 
         let tweets = vec![reply3_tweet, reply2_tweet, original_tweet];
         let toots = vec![status];
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         assert!(posts.toots.is_empty());
         assert!(posts.tweets.is_empty());
@@ -540,7 +1007,7 @@ mod tests {
 
         let tweets = vec![tweet];
         let toots = vec![reply3_toot, reply2_toot, original_toot];
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         assert!(posts.toots.is_empty());
         assert!(posts.tweets.is_empty());
@@ -558,7 +1025,7 @@ mod tests {
 
         let tweets = Vec::new();
         let toots = vec![reply_toot, original_toot];
-        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS);
+        let posts = determine_posts(&toots, &tweets, &DEFAULT_SYNC_OPTIONS).unwrap();
 
         assert_eq!(posts.tweets.len(), 1);
         let sync_tweet = &posts.tweets[0];
@@ -0,0 +1,56 @@
+use crate::sync::NewStatus;
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs the external transform command with the status as JSON on stdin and
+/// returns the changed status the command printed to stdout.
+pub fn transform_status(cmd: &str, status: &NewStatus) -> Result<NewStatus> {
+    let output = run_status_command(cmd, status)?;
+    serde_json::from_slice(&output)
+        .with_context(|| format!("Transform command {cmd} returned invalid JSON"))
+}
+
+/// Runs the command with the status as JSON on stdin and returns its output.
+pub fn run_status_command(cmd: &str, status: &impl Serialize) -> Result<Vec<u8>> {
+    let mut child = Command::new(cmd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run command {cmd}"))?;
+
+    let json = serde_json::to_string(status)?;
+    // Write in a block so that stdin is closed and the command sees the end of
+    // the input.
+    {
+        let mut stdin = child.stdin.take().context("Failed to open stdin")?;
+        stdin.write_all(json.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!("Command {cmd} failed with {}", output.status);
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::sync::StatusId;
+
+    // cat returns the input unchanged.
+    #[test]
+    fn transform_unchanged() {
+        let status = transform_status("cat", &new_status(1, "Hello")).unwrap();
+        assert_eq!(status.text, "Hello");
+        assert_eq!(status.original_id, StatusId::Number(1));
+    }
+
+    #[test]
+    fn transform_failed() {
+        assert!(transform_status("false", &new_status(1, "Hello")).is_err());
+        assert!(transform_status("true", &new_status(1, "Hello")).is_err());
+    }
+}
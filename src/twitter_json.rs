@@ -0,0 +1,110 @@
+//! Builds tweets and users like the Twitter API returns them, for tweets that
+//! do not come from the API.
+
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+/// A photo attached to a tweet.
+pub struct TweetPhoto {
+    pub id: u64,
+    pub url: String,
+    // The t.co link and its display text in the tweet.
+    pub short_url: String,
+    pub display_url: String,
+    pub expanded_url: String,
+    pub alt_text: Option<String>,
+}
+
+/// Returns the JSON of a tweet without user. The text is HTML escaped like
+/// Twitter does it.
+pub fn tweet_json(id: u64, created_at: DateTime<Utc>, text: &str, photos: &[TweetPhoto]) -> Value {
+    let text = html_escape::encode_text(text).to_string();
+    let text_length = text.chars().count();
+    let media: Vec<Value> = photos
+        .iter()
+        .map(|photo| {
+            let size = |w: u32, h: u32, resize: &str| json!({"w": w, "h": h, "resize": resize});
+            json!({
+                "id": photo.id,
+                "id_str": photo.id.to_string(),
+                "indices": [text_length, text_length],
+                "media_url": photo.url,
+                "media_url_https": photo.url,
+                "url": photo.short_url,
+                "display_url": photo.display_url,
+                "expanded_url": photo.expanded_url,
+                "type": "photo",
+                "sizes": {
+                    "thumb": size(150, 150, "crop"),
+                    "small": size(680, 680, "fit"),
+                    "medium": size(1200, 1200, "fit"),
+                    "large": size(2048, 2048, "fit")
+                },
+                "ext_alt_text": photo.alt_text
+            })
+        })
+        .collect();
+    let mut tweet = json!({
+        "created_at": created_at.format("%a %b %d %H:%M:%S +0000 %Y").to_string(),
+        "id": id,
+        "id_str": id.to_string(),
+        "full_text": text,
+        "truncated": false,
+        "display_text_range": [0, text_length],
+        "entities": {"hashtags": [], "symbols": [], "user_mentions": [], "urls": []},
+        "retweet_count": 0,
+        "favorite_count": 0,
+        "favorited": false,
+        "retweeted": false
+    });
+    if !media.is_empty() {
+        tweet["entities"]["media"] = json!(media);
+        tweet["extended_entities"] = json!({ "media": media });
+    }
+    tweet
+}
+
+/// Returns the JSON of a user with default profile settings.
+pub fn twitter_user_json(id: u64, screen_name: &str) -> Value {
+    json!({
+        "id": id,
+        "id_str": id.to_string(),
+        "name": screen_name,
+        "screen_name": screen_name,
+        "location": null,
+        "description": null,
+        "url": null,
+        "entities": {"description": {"urls": []}},
+        "protected": false,
+        "followers_count": 0,
+        "friends_count": 0,
+        "listed_count": 0,
+        "created_at": "Sat Jan 01 00:00:00 +0000 2022",
+        "favourites_count": 0,
+        "utc_offset": null,
+        "time_zone": null,
+        "geo_enabled": false,
+        "verified": false,
+        "statuses_count": 0,
+        "lang": null,
+        "contributors_enabled": false,
+        "is_translator": false,
+        "profile_background_color": "F5F8FA",
+        "profile_background_image_url": null,
+        "profile_background_image_url_https": null,
+        "profile_background_tile": false,
+        "profile_image_url": "http://abs.twimg.com/sticky/default_profile_images/default_profile_normal.png",
+        "profile_image_url_https": "https://abs.twimg.com/sticky/default_profile_images/default_profile_normal.png",
+        "profile_link_color": "1DA1F2",
+        "profile_sidebar_border_color": "C0DEED",
+        "profile_sidebar_fill_color": "DDEEF6",
+        "profile_text_color": "333333",
+        "profile_use_background_image": true,
+        "default_profile": true,
+        "default_profile_image": true,
+        "following": false,
+        "follow_request_sent": false,
+        "notifications": false,
+        "withheld_in_countries": []
+    })
+}
@@ -0,0 +1,253 @@
+use crate::config::TwitterOAuth2Config;
+use crate::registration::console_input;
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+const AUTHORIZE_URL: &str = "https://twitter.com/i/oauth2/authorize";
+const TOKEN_URL: &str = "https://api.twitter.com/2/oauth2/token";
+// offline.access is needed to get a refresh token.
+const SCOPES: &str = "tweet.read tweet.write users.read like.read like.write offline.access";
+// Access tokens are refreshed this many seconds before they expire.
+const REFRESH_MARGIN: i64 = 300;
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserResponse {
+    data: TwitterUser,
+}
+
+/// The account of an OAuth 2.0 token.
+#[derive(Debug, Deserialize)]
+pub struct TwitterUser {
+    pub id: String,
+    pub username: String,
+}
+
+/// Authorizes the Twitter app with OAuth 2.0 and PKCE on the console.
+///
+/// The redirect URL must be one of the callback URLs of the app, the user
+/// pastes the URL the browser was redirected to.
+pub fn twitter_oauth2_register(client_id: &str) -> Result<TwitterOAuth2Config> {
+    println!("Enable OAuth 2.0 in the user authentication settings of your Twitter app.");
+    let secret = console_input("Paste your client secret, leave empty for a public client")?;
+    let client_secret = Some(secret).filter(|secret| !secret.is_empty());
+    let redirect_uri = console_input("Paste one of the callback URLs of your app")?;
+
+    let verifier = random_string()?;
+    let state = random_string()?;
+    println!(
+        "Click this link to authorize on Twitter: {}",
+        authorize_url(client_id, &redirect_uri, &verifier, &state)?
+    );
+    let redirected = console_input("Paste the URL you were redirected to")?;
+    let code = authorization_code(&redirected, &state)?;
+
+    let params = [
+        ("grant_type", "authorization_code"),
+        ("code", code.as_str()),
+        ("redirect_uri", redirect_uri.as_str()),
+        ("code_verifier", verifier.as_str()),
+        ("client_id", client_id),
+    ];
+    let response = token_request(client_id, client_secret.as_deref(), &params)?;
+    token_config(
+        client_id,
+        client_secret,
+        response,
+        None,
+        Utc::now().timestamp(),
+    )
+}
+
+/// Refreshes the access token if it expires soon. Returns true if the token
+/// changed, the config must be saved then because Twitter only accepts a
+/// refresh token once.
+pub fn twitter_oauth2_refresh(config: &mut TwitterOAuth2Config) -> Result<bool> {
+    let now = Utc::now().timestamp();
+    if config.expires_at - REFRESH_MARGIN > now {
+        return Ok(false);
+    }
+    info!("Refreshing the Twitter OAuth 2.0 access token");
+    let params = [
+        ("grant_type", "refresh_token"),
+        ("refresh_token", config.refresh_token.as_str()),
+        ("client_id", config.client_id.as_str()),
+    ];
+    let response = token_request(&config.client_id, config.client_secret.as_deref(), &params)
+        .context("Failed to refresh the Twitter OAuth 2.0 token, run the register command again")?;
+    *config = token_config(
+        &config.client_id,
+        config.client_secret.clone(),
+        response,
+        Some(&config.refresh_token),
+        now,
+    )?;
+    Ok(true)
+}
+
+/// Returns the account that authorized the token.
+pub fn twitter_oauth2_user(config: &TwitterOAuth2Config) -> Result<TwitterUser> {
+    let response = reqwest::blocking::Client::new()
+        .get("https://api.twitter.com/2/users/me")
+        .bearer_auth(&config.access_token)
+        .send()?
+        .error_for_status()?;
+    let user: UserResponse = serde_json::from_str(&response.text()?)?;
+    Ok(user.data)
+}
+
+fn token_request(
+    client_id: &str,
+    client_secret: Option<&str>,
+    params: &[(&str, &str)],
+) -> Result<TokenResponse> {
+    let mut request = reqwest::blocking::Client::new()
+        .post(TOKEN_URL)
+        .form(params);
+    // Confidential clients authenticate with their secret.
+    if let Some(secret) = client_secret {
+        request = request.basic_auth(client_id, Some(secret));
+    }
+    let response = request.send()?;
+    let status = response.status();
+    let body = response.text()?;
+    if !status.is_success() {
+        bail!("Twitter token request failed with HTTP {status}: {body}");
+    }
+    Ok(serde_json::from_str(&body)?)
+}
+
+fn token_config(
+    client_id: &str,
+    client_secret: Option<String>,
+    response: TokenResponse,
+    previous_refresh_token: Option<&str>,
+    now: i64,
+) -> Result<TwitterOAuth2Config> {
+    let refresh_token = match (response.refresh_token, previous_refresh_token) {
+        (Some(token), _) => token,
+        (None, Some(previous)) => previous.to_string(),
+        (None, None) => bail!("Twitter did not return a refresh token, is offline.access allowed?"),
+    };
+    Ok(TwitterOAuth2Config {
+        client_id: client_id.to_string(),
+        client_secret,
+        access_token: response.access_token,
+        refresh_token,
+        expires_at: now + response.expires_in,
+    })
+}
+
+// The PKCE challenge is the SHA-256 hash of the verifier ("S256" method).
+fn authorize_url(client_id: &str, redirect_uri: &str, verifier: &str, state: &str) -> Result<Url> {
+    Ok(Url::parse_with_params(
+        AUTHORIZE_URL,
+        &[
+            ("response_type", "code"),
+            ("client_id", client_id),
+            ("redirect_uri", redirect_uri),
+            ("scope", SCOPES),
+            ("state", state),
+            ("code_challenge", &code_challenge(verifier)),
+            ("code_challenge_method", "S256"),
+        ],
+    )?)
+}
+
+// Returns the code of the redirect URL after checking its state.
+fn authorization_code(redirected: &str, state: &str) -> Result<String> {
+    let url = Url::parse(redirected).context("Invalid redirect URL")?;
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+    if param("state").as_deref() != Some(state) {
+        bail!("The redirect URL does not belong to this authorization");
+    }
+    param("code").context("The redirect URL has no code, was the app authorized?")
+}
+
+fn code_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+// Random string of 64 hex characters from the random number generator of the
+// operating system.
+fn random_string() -> Result<String> {
+    let mut bytes = [0; 32];
+    getrandom::getrandom(&mut bytes).context("Failed to get random bytes")?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn authorization() {
+        let url = authorize_url("client", "https://example.com/cb", "verifier", "state").unwrap();
+        let url = url.as_str();
+        assert!(url.starts_with("https://twitter.com/i/oauth2/authorize?response_type=code"));
+        assert!(url.contains("redirect_uri=https%3A%2F%2Fexample.com%2Fcb"));
+        assert!(url.contains(&format!(
+            "code_challenge={}&code_challenge_method=S256",
+            code_challenge("verifier")
+        )));
+        assert!(url.contains("offline.access"));
+
+        assert_eq!(
+            authorization_code("https://example.com/cb?state=state&code=abc", "state").unwrap(),
+            "abc"
+        );
+        assert!(
+            authorization_code("https://example.com/cb?state=other&code=abc", "state").is_err()
+        );
+        assert!(authorization_code("https://example.com/cb?state=state", "state").is_err());
+
+        let verifier = random_string().unwrap();
+        assert_eq!(verifier.len(), 64);
+        assert_ne!(verifier, random_string().unwrap());
+    }
+
+    // Example of RFC 7636, appendix B.
+    #[test]
+    fn s256_challenge() {
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn refreshed_token() {
+        let response = TokenResponse {
+            access_token: "new".to_string(),
+            refresh_token: None,
+            expires_in: 7200,
+        };
+        let config = token_config("client", None, response, Some("refresh"), 1000).unwrap();
+        assert_eq!(config.access_token, "new");
+        assert_eq!(config.refresh_token, "refresh");
+        assert_eq!(config.expires_at, 8200);
+
+        let response = TokenResponse {
+            access_token: "new".to_string(),
+            refresh_token: None,
+            expires_in: 7200,
+        };
+        assert!(token_config("client", None, response, None, 1000).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+use crate::config::TwitterConfig;
+use crate::twitter_json::{tweet_json, twitter_user_json, TweetPhoto};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use egg_mode::tweet::Tweet;
+use regex::Regex;
+use rss::Channel;
+use serde_json::{json, Value};
+use tracing::debug;
+
+/// A tweet from the RSS feed.
+#[derive(Debug, Clone, PartialEq)]
+struct FeedTweet {
+    id: u64,
+    // Account that posted the tweet, the original author of retweets.
+    author: String,
+    text: String,
+    images: Vec<String>,
+    created_at: DateTime<Utc>,
+    retweet: bool,
+}
+
+/// Fetches the public tweets of the user from the RSS feed of a Nitter
+/// instance, newest first. No Twitter API access is needed for this.
+///
+/// The feed has no IDs of replied tweets and no videos, so replies are left
+/// out and videos are not attached.
+pub async fn twitter_rss_tweets(config: &TwitterConfig) -> Result<Vec<Tweet>> {
+    let url = match &config.rss_url {
+        Some(url) => url,
+        None => bail!(
+            "twitter.fetch_mode = \"rss\" needs twitter.rss_url, for example \"https://nitter.net/{}/rss\"",
+            config.user_name
+        ),
+    };
+    let response = reqwest::Client::new().get(url).send().await?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        bail!("Fetching the RSS feed {url} failed with HTTP {status}");
+    }
+    let mut tweets = feed_tweets(&body)
+        .with_context(|| format!("Failed to parse the RSS feed {url}"))?
+        .iter()
+        .map(|tweet| api_tweet(tweet, config))
+        .collect::<Result<Vec<_>>>()?;
+    tweets.sort_by(|a, b| b.id.cmp(&a.id));
+    Ok(tweets)
+}
+
+fn feed_tweets(xml: &str) -> Result<Vec<FeedTweet>> {
+    let channel = Channel::read_from(xml.as_bytes())?;
+    let status_id = Regex::new(r"/status/(\d+)").unwrap();
+    let mut tweets = Vec::new();
+    for item in channel.items() {
+        let title = item.title().unwrap_or_default();
+        if title.starts_with("R to @") {
+            continue;
+        }
+        let link = item.link().context("RSS item without link")?;
+        let id = match status_id.captures(link) {
+            Some(id) => id[1].parse()?,
+            None => bail!("RSS item without tweet ID in the link {link}"),
+        };
+        let pub_date = item.pub_date().context("RSS item without pubDate")?;
+        let (text, images) = description_text(item.description().unwrap_or_default());
+        let author = item
+            .dublin_core_ext()
+            .and_then(|dc| dc.creators().first())
+            .map_or("", |creator| creator.trim_start_matches('@'));
+        tweets.push(FeedTweet {
+            id,
+            author: author.to_string(),
+            text,
+            images,
+            created_at: DateTime::parse_from_rfc2822(pub_date)
+                .with_context(|| format!("Invalid pubDate {pub_date}"))?
+                .with_timezone(&Utc),
+            retweet: title.starts_with("RT by @"),
+        });
+    }
+    Ok(tweets)
+}
+
+// Converts the HTML of a tweet in the feed to plain text with full links and
+// returns it with the URLs of the images.
+fn description_text(html: &str) -> (String, Vec<String>) {
+    // A quoted tweet follows after a line, only its link is kept.
+    let (body, quote) = html.split_once("<hr").unwrap_or((html, ""));
+    let image = Regex::new(r#"<img [^>]*src="([^"]+)""#).unwrap();
+    let images = image
+        .captures_iter(body)
+        .filter_map(|image| {
+            let url = twimg_url(&image[1]);
+            if url.is_none() {
+                debug!("Skipping attachment {} of the RSS feed", &image[1]);
+            }
+            url
+        })
+        .collect();
+    let link = Regex::new(r#"(?s)<a [^>]*href="([^"]*)"[^>]*>(.*?)</a>"#).unwrap();
+    // Mentions and hashtags keep their text, other links are written out.
+    let text = link.replace_all(body, |link: &regex::Captures| {
+        let text = &link[2];
+        if text.starts_with('@') || text.starts_with('#') || !link[1].starts_with("http") {
+            text.to_string()
+        } else {
+            link[1].to_string()
+        }
+    });
+    let text = Regex::new(r"<br\s*/?>")
+        .unwrap()
+        .replace_all(&text, "\n")
+        .replace("</p><p>", "\n\n");
+    let text = Regex::new(r"<[^>]+>").unwrap().replace_all(&text, "");
+    let mut text = html_escape::decode_html_entities(&text).trim().to_string();
+    if let Some(quoted) = Regex::new(r"/(\w+)/status/(\d+)").unwrap().captures(quote) {
+        text.push_str(&format!(
+            "\n\nhttps://twitter.com/{}/status/{}",
+            &quoted[1], &quoted[2]
+        ));
+    }
+    (text, images)
+}
+
+// Nitter proxies images, the original is downloaded from Twitter. Video
+// thumbnails and other media return None.
+fn twimg_url(url: &str) -> Option<String> {
+    let (_, path) = url.split_once("/pic/")?;
+    let path = percent_decode(path.trim_start_matches("orig/"));
+    path.starts_with("media/")
+        .then(|| format!("https://pbs.twimg.com/{path}"))
+}
+
+fn percent_decode(value: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let hex = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(decoded) if byte == b'%' => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+// Builds a tweet like the Twitter API returns it, so that the sync does not
+// need to know where it came from.
+fn api_tweet(tweet: &FeedTweet, config: &TwitterConfig) -> Result<Tweet> {
+    let mut original = feed_tweet_json(tweet, &tweet.author, &tweet.text);
+    if tweet.retweet {
+        // Only the name of other accounts is in the feed.
+        let author_id = if tweet.author.eq_ignore_ascii_case(&config.user_name) {
+            config.user_id
+        } else {
+            0
+        };
+        original["user"] = twitter_user_json(author_id, &tweet.author);
+        let text = format!("RT @{}: {}", tweet.author, tweet.text);
+        let mut retweet = feed_tweet_json(tweet, &config.user_name, &text);
+        retweet["user"] = twitter_user_json(config.user_id, &config.user_name);
+        retweet["retweeted"] = json!(true);
+        retweet["retweeted_status"] = original;
+        return Ok(serde_json::from_value(retweet)?);
+    }
+    original["user"] = twitter_user_json(config.user_id, &config.user_name);
+    Ok(serde_json::from_value(original)?)
+}
+
+fn feed_tweet_json(tweet: &FeedTweet, user_name: &str, text: &str) -> Value {
+    let id = tweet.id;
+    let photos: Vec<TweetPhoto> = tweet
+        .images
+        .iter()
+        .enumerate()
+        .map(|(index, url)| TweetPhoto {
+            id: id + index as u64,
+            url: url.clone(),
+            short_url: url.clone(),
+            display_url: url.clone(),
+            expanded_url: format!(
+                "https://twitter.com/{user_name}/status/{id}/photo/{}",
+                index + 1
+            ),
+            alt_text: None,
+        })
+        .collect();
+    tweet_json(id, tweet.created_at, text, &photos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::config_load;
+
+    const FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss xmlns:atom="http://www.w3.org/2005/Atom" xmlns:dc="http://purl.org/dc/elements/1.1/" version="2.0">
+  <channel>
+    <title>Klaus Purer / @klausi</title>
+    <item>
+      <title>New blog post about Rust &amp; Mastodon: https://klau.si/blog</title>
+      <dc:creator>@klausi</dc:creator>
+      <description><![CDATA[<p>New blog post about Rust &amp; Mastodon: <a href="https://klau.si/blog">klau.si/blog</a> <a href="/search?q=%23rust">#rust</a><br>Thanks <a href="/rustlang">@rustlang</a></p><img src="https://nitter.net/pic/media%2FFrJ5ZtUaAAEfFmV.jpg" style="max-width:250px;" />]]></description>
+      <pubDate>Tue, 14 Mar 2023 10:21:22 GMT</pubDate>
+      <guid>https://nitter.net/klausi/status/1635595453359828993#m</guid>
+      <link>https://nitter.net/klausi/status/1635595453359828993#m</link>
+    </item>
+    <item>
+      <title>RT by @klausi: Rust 1.68 is out</title>
+      <dc:creator>@rustlang</dc:creator>
+      <description><![CDATA[<p>Rust 1.68 is out</p><img src="https://nitter.net/pic/ext_tw_video_thumb%2F1635%2Fpu%2Fimg%2Fthumb.jpg" />]]></description>
+      <pubDate>Thu, 09 Mar 2023 18:00:00 GMT</pubDate>
+      <link>https://nitter.net/rustlang/status/1633882318564311040#m</link>
+    </item>
+    <item>
+      <title>R to @rustlang: Congrats!</title>
+      <dc:creator>@klausi</dc:creator>
+      <description><![CDATA[<p>Congrats!</p>]]></description>
+      <pubDate>Thu, 09 Mar 2023 19:00:00 GMT</pubDate>
+      <link>https://nitter.net/klausi/status/1633897419283202048#m</link>
+    </item>
+  </channel>
+</rss>"#;
+
+    #[test]
+    fn nitter_feed() {
+        let tweets = feed_tweets(FEED).unwrap();
+        assert_eq!(tweets.len(), 2);
+        assert_eq!(tweets[0].id, 1635595453359828993);
+        assert_eq!(tweets[0].author, "klausi");
+        assert_eq!(
+            tweets[0].text,
+            "New blog post about Rust & Mastodon: https://klau.si/blog #rust\nThanks @rustlang"
+        );
+        assert_eq!(
+            tweets[0].images,
+            vec!["https://pbs.twimg.com/media/FrJ5ZtUaAAEfFmV.jpg"]
+        );
+        assert_eq!(
+            tweets[0].created_at.to_rfc3339(),
+            "2023-03-14T10:21:22+00:00"
+        );
+        assert!(!tweets[0].retweet);
+
+        assert_eq!(tweets[1].author, "rustlang");
+        assert!(tweets[1].retweet);
+        // Video thumbnails are not attached.
+        assert!(tweets[1].images.is_empty());
+    }
+
+    #[test]
+    fn retweet_from_feed() {
+        let config = config_load(
+            r#"
+[mastodon.app]
+base = "https://mastodon.social"
+client_id = "XXXXXXXXXXX"
+client_secret = "XXXXXXXXXXX"
+redirect = "urn:ietf:wg:oauth:2.0:oob"
+token = "XXXXXXXXXXX"
+
+[twitter]
+user_id = 1234567890
+user_name = "klausi"
+fetch_mode = "rss"
+rss_url = "https://nitter.net/klausi/rss"
+"#,
+        )
+        .unwrap()
+        .twitter;
+        let tweets = feed_tweets(FEED).unwrap();
+        let tweet = api_tweet(&tweets[0], &config).unwrap();
+        assert_eq!(tweet.user.unwrap().id, 1234567890);
+        assert_eq!(tweet.extended_entities.unwrap().media.len(), 1);
+        assert_eq!(tweet.text, tweets[0].text.replace('&', "&amp;"));
+
+        let retweet = api_tweet(&tweets[1], &config).unwrap();
+        assert_eq!(retweet.retweeted, Some(true));
+        assert_eq!(retweet.text, "RT @rustlang: Rust 1.68 is out");
+        let original = retweet.retweeted_status.unwrap();
+        assert_eq!(original.id, 1633882318564311040);
+        assert_eq!(original.user.unwrap().screen_name, "rustlang");
+    }
+
+    #[test]
+    fn quoted_tweet_link() {
+        let (text, _) = description_text(
+            r#"<p>So true</p><hr/><blockquote><b>Rust (@rustlang)</b><p>Fearless concurrency</p><a href="https://nitter.net/rustlang/status/123#m">nitter.net/rustlang/status/123#m</a></blockquote>"#,
+        );
+        assert_eq!(text, "So true\n\nhttps://twitter.com/rustlang/status/123");
+    }
+}
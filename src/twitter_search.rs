@@ -0,0 +1,157 @@
+use crate::api_budget::{api_call, ApiCallKind};
+use crate::config::{HashtagMatch, TwitterConfig};
+use crate::history::HistoryNetwork;
+use anyhow::{bail, Result};
+use egg_mode::tweet::Tweet;
+use egg_mode::Token;
+use serde::Deserialize;
+
+const SEARCH_URL: &str = "https://api.twitter.com/2/tweets/search/recent";
+// Maximum of the recent search endpoint, also the limit of one lookup call.
+const MAX_RESULTS: usize = 100;
+
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    // Missing if nothing was found.
+    #[serde(default)]
+    data: Vec<SearchTweet>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchTweet {
+    id: String,
+}
+
+/// Returns the recent search query for the tweets of the user with the sync
+/// hashtags, None if no hashtag restricts the sync.
+pub fn twitter_search_query(config: &TwitterConfig) -> Option<String> {
+    let hashtags: Vec<String> = config
+        .sync_hashtag
+        .iter()
+        .chain(&config.sync_hashtags)
+        .filter(|hashtag| !hashtag.starts_with('-'))
+        .map(|hashtag| format!("#{}", hashtag.trim_start_matches('#')))
+        .collect();
+    if hashtags.is_empty() {
+        return None;
+    }
+    let mut query = format!("from:{} ", config.user_name);
+    match config.sync_hashtags_match {
+        HashtagMatch::Any if hashtags.len() > 1 => {
+            query.push_str(&format!("({})", hashtags.join(" OR ")))
+        }
+        _ => query.push_str(&hashtags.join(" ")),
+    }
+    for excluded in config
+        .sync_hashtags
+        .iter()
+        .filter_map(|hashtag| hashtag.strip_prefix('-'))
+    {
+        query.push_str(&format!(" -#{}", excluded.trim_start_matches('#')));
+    }
+    Some(query)
+}
+
+/// Fetches the tweets of the last 7 days that match the search query, newest
+/// first.
+///
+/// Only the IDs come from the v2 search, which needs the OAuth 2.0 token. The
+/// tweets are looked up with the v1.1 API because the sync works with those.
+pub async fn twitter_search_tweets(
+    config: &TwitterConfig,
+    query: &str,
+    token: &Token,
+) -> Result<Vec<Tweet>> {
+    let oauth2 = match &config.oauth2 {
+        Some(oauth2) => oauth2,
+        None => bail!(
+            "twitter.fetch_mode = \"search\" needs an OAuth 2.0 token, run the register command with --twitter-client-id"
+        ),
+    };
+    api_call(HistoryNetwork::Twitter, ApiCallKind::Read);
+    let response = reqwest::Client::new()
+        .get(SEARCH_URL)
+        .bearer_auth(&oauth2.access_token)
+        .query(&[
+            ("query", query.to_string()),
+            ("max_results", MAX_RESULTS.to_string()),
+        ])
+        .send()
+        .await?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        bail!("Twitter search failed with HTTP {status}: {body}");
+    }
+    let ids = search_ids(&body)?;
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    api_call(HistoryNetwork::Twitter, ApiCallKind::Read);
+    let mut tweets = egg_mode::tweet::lookup(ids, token).await?.response;
+    tweets.sort_by(|a, b| b.id.cmp(&a.id));
+    Ok(tweets)
+}
+
+fn search_ids(body: &str) -> Result<Vec<u64>> {
+    let response: SearchResponse = serde_json::from_str(body)?;
+    Ok(response
+        .data
+        .iter()
+        .map(|tweet| tweet.id.parse())
+        .collect::<Result<_, _>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::config_load;
+
+    #[test]
+    fn search_query() {
+        let mut config = config_load(
+            r#"
+[mastodon.app]
+base = "https://mastodon.social"
+client_id = "XXXXXXXXXXX"
+client_secret = "XXXXXXXXXXX"
+redirect = "urn:ietf:wg:oauth:2.0:oob"
+token = "XXXXXXXXXXX"
+
+[twitter]
+consumer_key = "XXXXXXXXXXX"
+consumer_secret = "XXXXXXXXXXX"
+access_token = "XXXXXXXXXXX"
+access_token_secret = "XXXXXXXXXXX"
+user_id = 1234567890
+user_name = "klausi"
+"#,
+        )
+        .unwrap()
+        .twitter;
+        assert_eq!(twitter_search_query(&config), None);
+
+        config.sync_hashtags = vec![
+            "#blog".to_string(),
+            "release".to_string(),
+            "-#private".to_string(),
+        ];
+        assert_eq!(
+            twitter_search_query(&config).as_deref(),
+            Some("from:klausi (#blog OR #release) -#private")
+        );
+        config.sync_hashtags_match = HashtagMatch::All;
+        assert_eq!(
+            twitter_search_query(&config).as_deref(),
+            Some("from:klausi #blog #release -#private")
+        );
+    }
+
+    #[test]
+    fn search_response() {
+        let body = r##"{"data":[{"id":"1445880548472328192","text":"#blog New post"}],"meta":{"result_count":1}}"##;
+        assert_eq!(search_ids(body).unwrap(), vec![1445880548472328192]);
+        let body = r#"{"meta":{"result_count":0}}"#;
+        assert!(search_ids(body).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,153 @@
+//! Replays recorded Mastodon and Twitter API responses through the sync
+//! pipeline and compares the result with a snapshot.
+//!
+//! Each directory in tests/fixtures/cassettes is a cassette with:
+//! - config.toml: the config of the run, credentials are not used.
+//! - account.json: the Mastodon account from verify_credentials.
+//! - mastodon_statuses.json: the Mastodon timeline of the account.
+//! - tweets.json: the Twitter user timeline in extended tweet mode.
+//! - expected.json: the snapshot of the statuses that were posted to each
+//!   network and the counts of the run status.
+//!
+//! A cassette without a snapshot fails. Run with UPDATE_SNAPSHOTS=1 to write
+//! the snapshots of new cassettes or after an intended change, and review the
+//! diff.
+#![cfg(all(feature = "mastodon", feature = "twitter"))]
+
+use anyhow::Result;
+use clap::Parser;
+use egg_mode::tweet::Tweet;
+use elefren::entities::account::Account;
+use elefren::entities::status::Status;
+use mastodon_twitter_sync::args::Args;
+use mastodon_twitter_sync::config::config_load;
+use mastodon_twitter_sync::paths::{paths_init, DEFAULT_CONFIG_FILE};
+use mastodon_twitter_sync::plan::SyncAccount;
+use mastodon_twitter_sync::post::{PostError, PostedStatus};
+use mastodon_twitter_sync::status::RunStatus;
+use mastodon_twitter_sync::sync::{NewStatus, StatusId, StatusUpdates};
+use mastodon_twitter_sync::sync_accounts;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+
+const CASSETTES_DIR: &str = "tests/fixtures/cassettes";
+
+#[test]
+fn replay_cassettes() {
+    let mut cassettes: Vec<_> = fs::read_dir(CASSETTES_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect();
+    cassettes.sort();
+    assert!(!cassettes.is_empty(), "No cassettes in {CASSETTES_DIR}");
+    for cassette in cassettes {
+        let snapshot = replay(&cassette);
+        let expected_file = cassette.join("expected.json");
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            let json = serde_json::to_string_pretty(&snapshot).unwrap();
+            fs::write(&expected_file, format!("{json}\n")).unwrap();
+            continue;
+        }
+        assert!(
+            expected_file.exists(),
+            "Cassette {} has no snapshot, run with UPDATE_SNAPSHOTS=1 to write it",
+            cassette.display()
+        );
+        let expected: Value = read_json(&expected_file);
+        assert_eq!(
+            snapshot,
+            expected,
+            "Cassette {} does not match its snapshot, run with UPDATE_SNAPSHOTS=1 if the change is intended",
+            cassette.display()
+        );
+    }
+}
+
+// An account that returns the recorded timeline and remembers what is posted
+// to it.
+struct Recording<S> {
+    statuses: Vec<S>,
+    posted: Vec<NewStatus>,
+}
+
+impl<S: Clone> SyncAccount for Recording<S> {
+    type Status = S;
+
+    fn fetch(&self) -> Result<Vec<S>> {
+        Ok(self.statuses.clone())
+    }
+
+    fn post(&mut self, status: &NewStatus, dry_run: bool) -> Result<Vec<PostedStatus>, PostError> {
+        if dry_run {
+            return Ok(Vec::new());
+        }
+        self.posted.push(status.clone());
+        Ok(posted_statuses(status))
+    }
+}
+
+// The created statuses of a thread, with made up IDs.
+fn posted_statuses(status: &NewStatus) -> Vec<PostedStatus> {
+    let mut posted = vec![PostedStatus {
+        original_id: status.original_id.clone(),
+        id: StatusId::Text(format!("replayed-{}", status.original_id)),
+        url: String::new(),
+        text: status.text.clone(),
+        idempotency_key: None,
+    }];
+    for reply in &status.replies {
+        posted.extend(posted_statuses(reply));
+    }
+    posted
+}
+
+// Runs the recorded timelines through a sync run and posts the statuses to
+// recordings. The cache files of the run are kept in a temporary directory.
+fn replay(cassette: &Path) -> Value {
+    let config = config_load(&fs::read_to_string(cassette.join("config.toml")).unwrap()).unwrap();
+    let account: Account = read_json(&cassette.join("account.json"));
+    let mut mastodon = Recording::<Status> {
+        statuses: read_json(&cassette.join("mastodon_statuses.json")),
+        posted: Vec::new(),
+    };
+    let mut twitter = Recording::<Tweet> {
+        statuses: read_json(&cassette.join("tweets.json")),
+        posted: Vec::new(),
+    };
+
+    let cache_dir = tempfile::tempdir().unwrap();
+    std::env::set_var("MTS_CACHE_DIR", cache_dir.path());
+    paths_init(DEFAULT_CONFIG_FILE);
+    let args = Args::parse_from(["mastodon-twitter-sync", "--quiet"]);
+    let mut status = RunStatus::default();
+    sync_accounts(
+        &mut mastodon,
+        &mut twitter,
+        &args,
+        &config,
+        &account.url,
+        &mut status,
+    )
+    .unwrap();
+
+    let mut posted = StatusUpdates::default();
+    posted.toots = mastodon.posted;
+    posted.tweets = twitter.posted;
+    let mut snapshot: Value = serde_json::from_str(&posted.to_json().unwrap()).unwrap();
+    snapshot["status"] = json!({
+        "posted_toots": status.posted_toots,
+        "posted_tweets": status.posted_tweets,
+        "skipped_posts": status.skipped_posts,
+        "queued_posts": status.queued_posts,
+        "failed_posts": status.failed_posts,
+    });
+    snapshot
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(file: &Path) -> T {
+    let json = fs::read_to_string(file).unwrap();
+    serde_json::from_str(&json)
+        .unwrap_or_else(|e| panic!("Failed to parse {}: {e}", file.display()))
+}